@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE sync_checkpoints (
+    market_pubkey TEXT PRIMARY KEY,         -- Base58 market address the cursor belongs to
+    last_signature TEXT NOT NULL,           -- Newest transaction signature that has been processed
+    last_slot BIGINT NOT NULL,              -- Slot the newest processed transaction landed in
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+SELECT diesel_manage_updated_at('sync_checkpoints');
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+CREATE TABLE backfill_progress (
+    market_pubkey TEXT NOT NULL,                -- Base58 market address being backfilled
+    range_from TEXT NOT NULL,                   -- Oldest bound of the range, e.g. "slot:1000" or "time:1740956436"
+    range_to TEXT NOT NULL,                     -- Newest bound of the range
+    before_signature TEXT,                      -- Oldest signature processed so far, the walk resumes before it
+    signatures_processed BIGINT NOT NULL DEFAULT 0,
+    fills_inserted BIGINT NOT NULL DEFAULT 0,
+    completed BOOLEAN NOT NULL DEFAULT FALSE,
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (market_pubkey, range_from, range_to)
+);
+
+SELECT diesel_manage_updated_at('backfill_progress');
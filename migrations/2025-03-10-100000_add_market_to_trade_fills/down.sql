@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX trade_fills_market_pubkey_event_timestamp_idx;
+ALTER TABLE trade_fills DROP COLUMN market_pubkey;
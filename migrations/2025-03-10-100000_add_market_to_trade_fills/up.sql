@@ -0,0 +1,7 @@
+-- Your SQL goes here
+-- Every fill stored before markets were tracked came from the SOL/USDC market
+ALTER TABLE trade_fills
+    ADD COLUMN market_pubkey TEXT NOT NULL DEFAULT '4DoNfFBfF7UokCC2FQzriy7yHK6DY6NVdYpuekQ5pRgg';
+ALTER TABLE trade_fills ALTER COLUMN market_pubkey DROP DEFAULT;
+
+CREATE INDEX trade_fills_market_pubkey_event_timestamp_idx ON trade_fills (market_pubkey, event_timestamp);
@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE time_in_force_events;
+DROP TABLE fee_events;
+DROP TABLE fill_summaries;
+DROP TABLE order_evictions;
+DROP TABLE order_reduces;
+DROP TABLE order_places;
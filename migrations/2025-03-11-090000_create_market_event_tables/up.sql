@@ -0,0 +1,67 @@
+-- Your SQL goes here
+-- One table per Phoenix `MarketEventDetails` variant, fills stay in `trade_fills`
+CREATE TABLE order_places (
+    id SERIAL PRIMARY KEY,
+    market_pubkey TEXT NOT NULL,            -- Base58 market address
+    event_timestamp BIGINT NOT NULL,        -- UNIX timestamp (in seconds)
+    order_sequence_number BIGINT NOT NULL,  -- Sequence number of the placed order
+    client_order_id TEXT NOT NULL,          -- u128 client order id, in decimal
+    maker TEXT NOT NULL,                    -- Base58 address of the order owner
+    price_in_ticks BIGINT NOT NULL,
+    base_lots_placed BIGINT NOT NULL
+);
+
+CREATE TABLE order_reduces (
+    id SERIAL PRIMARY KEY,
+    market_pubkey TEXT NOT NULL,
+    event_timestamp BIGINT NOT NULL,
+    order_sequence_number BIGINT NOT NULL,
+    maker TEXT NOT NULL,
+    price_in_ticks BIGINT NOT NULL,
+    base_lots_removed BIGINT NOT NULL,
+    base_lots_remaining BIGINT NOT NULL,
+    is_full_cancel BOOLEAN NOT NULL
+);
+
+CREATE TABLE order_evictions (
+    id SERIAL PRIMARY KEY,
+    market_pubkey TEXT NOT NULL,
+    event_timestamp BIGINT NOT NULL,
+    order_sequence_number BIGINT NOT NULL,
+    maker TEXT NOT NULL,
+    price_in_ticks BIGINT NOT NULL,
+    base_lots_evicted BIGINT NOT NULL
+);
+
+CREATE TABLE fill_summaries (
+    id SERIAL PRIMARY KEY,
+    market_pubkey TEXT NOT NULL,
+    event_timestamp BIGINT NOT NULL,
+    client_order_id TEXT NOT NULL,                  -- u128 client order id, in decimal
+    total_base_lots_filled BIGINT NOT NULL,
+    total_quote_lots_filled_including_fees BIGINT NOT NULL,
+    total_quote_lots_fees BIGINT NOT NULL
+);
+
+CREATE TABLE fee_events (
+    id SERIAL PRIMARY KEY,
+    market_pubkey TEXT NOT NULL,
+    event_timestamp BIGINT NOT NULL,
+    fees_collected_in_quote_lots BIGINT NOT NULL
+);
+
+CREATE TABLE time_in_force_events (
+    id SERIAL PRIMARY KEY,
+    market_pubkey TEXT NOT NULL,
+    event_timestamp BIGINT NOT NULL,
+    order_sequence_number BIGINT NOT NULL,
+    last_valid_slot BIGINT NOT NULL,                -- 0 when the order has no slot expiry
+    last_valid_unix_timestamp_in_seconds BIGINT NOT NULL  -- 0 when the order has no time expiry
+);
+
+CREATE INDEX order_places_market_pubkey_event_timestamp_idx ON order_places (market_pubkey, event_timestamp);
+CREATE INDEX order_reduces_market_pubkey_event_timestamp_idx ON order_reduces (market_pubkey, event_timestamp);
+CREATE INDEX order_evictions_market_pubkey_event_timestamp_idx ON order_evictions (market_pubkey, event_timestamp);
+CREATE INDEX fill_summaries_market_pubkey_event_timestamp_idx ON fill_summaries (market_pubkey, event_timestamp);
+CREATE INDEX fee_events_market_pubkey_event_timestamp_idx ON fee_events (market_pubkey, event_timestamp);
+CREATE INDEX time_in_force_events_market_pubkey_event_timestamp_idx ON time_in_force_events (market_pubkey, event_timestamp);
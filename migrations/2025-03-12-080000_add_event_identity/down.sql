@@ -0,0 +1,49 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE time_in_force_events
+    DROP CONSTRAINT time_in_force_events_event_identity_key,
+    DROP COLUMN event_index,
+    DROP COLUMN sequence_number,
+    DROP COLUMN slot,
+    DROP COLUMN signature;
+
+ALTER TABLE fee_events
+    DROP CONSTRAINT fee_events_event_identity_key,
+    DROP COLUMN event_index,
+    DROP COLUMN sequence_number,
+    DROP COLUMN slot,
+    DROP COLUMN signature;
+
+ALTER TABLE fill_summaries
+    DROP CONSTRAINT fill_summaries_event_identity_key,
+    DROP COLUMN event_index,
+    DROP COLUMN sequence_number,
+    DROP COLUMN slot,
+    DROP COLUMN signature;
+
+ALTER TABLE order_evictions
+    DROP CONSTRAINT order_evictions_event_identity_key,
+    DROP COLUMN event_index,
+    DROP COLUMN sequence_number,
+    DROP COLUMN slot,
+    DROP COLUMN signature;
+
+ALTER TABLE order_reduces
+    DROP CONSTRAINT order_reduces_event_identity_key,
+    DROP COLUMN event_index,
+    DROP COLUMN sequence_number,
+    DROP COLUMN slot,
+    DROP COLUMN signature;
+
+ALTER TABLE order_places
+    DROP CONSTRAINT order_places_event_identity_key,
+    DROP COLUMN event_index,
+    DROP COLUMN sequence_number,
+    DROP COLUMN slot,
+    DROP COLUMN signature;
+
+ALTER TABLE trade_fills
+    DROP CONSTRAINT trade_fills_event_identity_key,
+    DROP COLUMN event_index,
+    DROP COLUMN sequence_number,
+    DROP COLUMN slot,
+    DROP COLUMN signature;
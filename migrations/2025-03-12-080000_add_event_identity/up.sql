@@ -0,0 +1,98 @@
+-- Your SQL goes here
+-- Every event is identified by the transaction it was emitted in, the market's sequence number of the
+-- instruction and the index of the event inside it. Phoenix events don't carry their instruction index,
+-- but every Phoenix instruction on a market takes the next sequence number, so it stands in for the
+-- instruction index and event_index tells the events of one instruction apart. A transaction lands in a
+-- single slot, the slot is stored but adds nothing to the key. Rows stored before that get a unique
+-- placeholder signature so the unique constraint holds, new events are inserted with ON CONFLICT DO NOTHING.
+
+ALTER TABLE trade_fills
+    ADD COLUMN signature TEXT NOT NULL DEFAULT '',
+    ADD COLUMN slot BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN sequence_number BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN event_index BIGINT NOT NULL DEFAULT 0;
+UPDATE trade_fills SET signature = 'legacy:' || id;
+ALTER TABLE trade_fills
+    ALTER COLUMN signature DROP DEFAULT,
+    ALTER COLUMN slot DROP DEFAULT,
+    ALTER COLUMN sequence_number DROP DEFAULT,
+    ALTER COLUMN event_index DROP DEFAULT,
+    ADD CONSTRAINT trade_fills_event_identity_key UNIQUE (market_pubkey, signature, sequence_number, event_index);
+
+ALTER TABLE order_places
+    ADD COLUMN signature TEXT NOT NULL DEFAULT '',
+    ADD COLUMN slot BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN sequence_number BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN event_index BIGINT NOT NULL DEFAULT 0;
+UPDATE order_places SET signature = 'legacy:' || id;
+ALTER TABLE order_places
+    ALTER COLUMN signature DROP DEFAULT,
+    ALTER COLUMN slot DROP DEFAULT,
+    ALTER COLUMN sequence_number DROP DEFAULT,
+    ALTER COLUMN event_index DROP DEFAULT,
+    ADD CONSTRAINT order_places_event_identity_key UNIQUE (market_pubkey, signature, sequence_number, event_index);
+
+ALTER TABLE order_reduces
+    ADD COLUMN signature TEXT NOT NULL DEFAULT '',
+    ADD COLUMN slot BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN sequence_number BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN event_index BIGINT NOT NULL DEFAULT 0;
+UPDATE order_reduces SET signature = 'legacy:' || id;
+ALTER TABLE order_reduces
+    ALTER COLUMN signature DROP DEFAULT,
+    ALTER COLUMN slot DROP DEFAULT,
+    ALTER COLUMN sequence_number DROP DEFAULT,
+    ALTER COLUMN event_index DROP DEFAULT,
+    ADD CONSTRAINT order_reduces_event_identity_key UNIQUE (market_pubkey, signature, sequence_number, event_index);
+
+ALTER TABLE order_evictions
+    ADD COLUMN signature TEXT NOT NULL DEFAULT '',
+    ADD COLUMN slot BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN sequence_number BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN event_index BIGINT NOT NULL DEFAULT 0;
+UPDATE order_evictions SET signature = 'legacy:' || id;
+ALTER TABLE order_evictions
+    ALTER COLUMN signature DROP DEFAULT,
+    ALTER COLUMN slot DROP DEFAULT,
+    ALTER COLUMN sequence_number DROP DEFAULT,
+    ALTER COLUMN event_index DROP DEFAULT,
+    ADD CONSTRAINT order_evictions_event_identity_key UNIQUE (market_pubkey, signature, sequence_number, event_index);
+
+ALTER TABLE fill_summaries
+    ADD COLUMN signature TEXT NOT NULL DEFAULT '',
+    ADD COLUMN slot BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN sequence_number BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN event_index BIGINT NOT NULL DEFAULT 0;
+UPDATE fill_summaries SET signature = 'legacy:' || id;
+ALTER TABLE fill_summaries
+    ALTER COLUMN signature DROP DEFAULT,
+    ALTER COLUMN slot DROP DEFAULT,
+    ALTER COLUMN sequence_number DROP DEFAULT,
+    ALTER COLUMN event_index DROP DEFAULT,
+    ADD CONSTRAINT fill_summaries_event_identity_key UNIQUE (market_pubkey, signature, sequence_number, event_index);
+
+ALTER TABLE fee_events
+    ADD COLUMN signature TEXT NOT NULL DEFAULT '',
+    ADD COLUMN slot BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN sequence_number BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN event_index BIGINT NOT NULL DEFAULT 0;
+UPDATE fee_events SET signature = 'legacy:' || id;
+ALTER TABLE fee_events
+    ALTER COLUMN signature DROP DEFAULT,
+    ALTER COLUMN slot DROP DEFAULT,
+    ALTER COLUMN sequence_number DROP DEFAULT,
+    ALTER COLUMN event_index DROP DEFAULT,
+    ADD CONSTRAINT fee_events_event_identity_key UNIQUE (market_pubkey, signature, sequence_number, event_index);
+
+ALTER TABLE time_in_force_events
+    ADD COLUMN signature TEXT NOT NULL DEFAULT '',
+    ADD COLUMN slot BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN sequence_number BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN event_index BIGINT NOT NULL DEFAULT 0;
+UPDATE time_in_force_events SET signature = 'legacy:' || id;
+ALTER TABLE time_in_force_events
+    ALTER COLUMN signature DROP DEFAULT,
+    ALTER COLUMN slot DROP DEFAULT,
+    ALTER COLUMN sequence_number DROP DEFAULT,
+    ALTER COLUMN event_index DROP DEFAULT,
+    ADD CONSTRAINT time_in_force_events_event_identity_key UNIQUE (market_pubkey, signature, sequence_number, event_index);
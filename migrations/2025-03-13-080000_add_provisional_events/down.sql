@@ -0,0 +1,21 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX time_in_force_events_provisional_idx;
+ALTER TABLE time_in_force_events DROP COLUMN provisional;
+
+DROP INDEX fee_events_provisional_idx;
+ALTER TABLE fee_events DROP COLUMN provisional;
+
+DROP INDEX fill_summaries_provisional_idx;
+ALTER TABLE fill_summaries DROP COLUMN provisional;
+
+DROP INDEX order_evictions_provisional_idx;
+ALTER TABLE order_evictions DROP COLUMN provisional;
+
+DROP INDEX order_reduces_provisional_idx;
+ALTER TABLE order_reduces DROP COLUMN provisional;
+
+DROP INDEX order_places_provisional_idx;
+ALTER TABLE order_places DROP COLUMN provisional;
+
+DROP INDEX trade_fills_provisional_idx;
+ALTER TABLE trade_fills DROP COLUMN provisional;
@@ -0,0 +1,24 @@
+-- Your SQL goes here
+-- Events extracted below the finalized commitment are provisional until the reconciliation pass sees
+-- their transaction finalized, or deletes them when it was rolled back. Existing rows are final.
+
+ALTER TABLE trade_fills ADD COLUMN provisional BOOLEAN NOT NULL DEFAULT FALSE;
+CREATE INDEX trade_fills_provisional_idx ON trade_fills (slot) WHERE provisional;
+
+ALTER TABLE order_places ADD COLUMN provisional BOOLEAN NOT NULL DEFAULT FALSE;
+CREATE INDEX order_places_provisional_idx ON order_places (slot) WHERE provisional;
+
+ALTER TABLE order_reduces ADD COLUMN provisional BOOLEAN NOT NULL DEFAULT FALSE;
+CREATE INDEX order_reduces_provisional_idx ON order_reduces (slot) WHERE provisional;
+
+ALTER TABLE order_evictions ADD COLUMN provisional BOOLEAN NOT NULL DEFAULT FALSE;
+CREATE INDEX order_evictions_provisional_idx ON order_evictions (slot) WHERE provisional;
+
+ALTER TABLE fill_summaries ADD COLUMN provisional BOOLEAN NOT NULL DEFAULT FALSE;
+CREATE INDEX fill_summaries_provisional_idx ON fill_summaries (slot) WHERE provisional;
+
+ALTER TABLE fee_events ADD COLUMN provisional BOOLEAN NOT NULL DEFAULT FALSE;
+CREATE INDEX fee_events_provisional_idx ON fee_events (slot) WHERE provisional;
+
+ALTER TABLE time_in_force_events ADD COLUMN provisional BOOLEAN NOT NULL DEFAULT FALSE;
+CREATE INDEX time_in_force_events_provisional_idx ON time_in_force_events (slot) WHERE provisional;
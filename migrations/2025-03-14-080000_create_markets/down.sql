@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE trade_fills DROP CONSTRAINT trade_fills_market_pubkey_fkey;
+DROP TABLE markets;
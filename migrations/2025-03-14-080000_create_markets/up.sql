@@ -0,0 +1,22 @@
+-- Your SQL goes here
+CREATE TABLE markets (
+    market_pubkey TEXT PRIMARY KEY,                         -- Base58 market address
+    base_mint TEXT NOT NULL,                                -- Mint of the base token
+    quote_mint TEXT NOT NULL,                               -- Mint of the quote token
+    base_decimals INTEGER NOT NULL,                         -- Decimals of the base mint
+    quote_decimals INTEGER NOT NULL,                        -- Decimals of the quote mint
+    base_lot_size BIGINT NOT NULL,                          -- Base atoms per base lot
+    quote_lot_size BIGINT NOT NULL,                         -- Quote atoms per quote lot
+    tick_size_in_quote_atoms_per_base_unit BIGINT NOT NULL, -- Price increment of one tick
+    raw_base_units_per_base_unit INTEGER NOT NULL,          -- Raw base units in one (possibly scaled) base unit
+    taker_fee_bps INTEGER NOT NULL,                         -- Taker fee in basis points
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+SELECT diesel_manage_updated_at('markets');
+
+-- Fills stored before markets were tracked have no market row until the daemon reads it,
+-- so only new fills are checked
+ALTER TABLE trade_fills
+    ADD CONSTRAINT trade_fills_market_pubkey_fkey
+    FOREIGN KEY (market_pubkey) REFERENCES markets (market_pubkey) NOT VALID;
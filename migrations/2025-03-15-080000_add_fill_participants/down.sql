@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX trade_fills_taker_event_timestamp_idx;
+DROP INDEX trade_fills_maker_event_timestamp_idx;
+
+ALTER TABLE trade_fills
+    DROP CONSTRAINT trade_fills_side_filled_check,
+    DROP COLUMN side_filled,
+    DROP COLUMN taker,
+    DROP COLUMN maker,
+    DROP COLUMN order_sequence_number;
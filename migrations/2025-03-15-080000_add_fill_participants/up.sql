@@ -0,0 +1,11 @@
+-- Your SQL goes here
+-- The order, maker, taker and filled side of every fill. Fills stored before they were tracked keep NULLs.
+ALTER TABLE trade_fills
+    ADD COLUMN order_sequence_number BIGINT,    -- Sequence number of the maker order that was filled
+    ADD COLUMN maker TEXT,                      -- Base58 address of the maker order owner
+    ADD COLUMN taker TEXT,                      -- Base58 address of the taker
+    ADD COLUMN side_filled TEXT,                -- Side of the maker order, 'bid' when the taker sold
+    ADD CONSTRAINT trade_fills_side_filled_check CHECK (side_filled IN ('bid', 'ask'));
+
+CREATE INDEX trade_fills_maker_event_timestamp_idx ON trade_fills (maker, event_timestamp);
+CREATE INDEX trade_fills_taker_event_timestamp_idx ON trade_fills (taker, event_timestamp);
@@ -0,0 +1,17 @@
+-- Your SQL goes here
+-- Transactions of a market that couldn't be fetched, decoded or inserted, kept until they are retried
+-- successfully or discarded so no event is lost without anyone knowing.
+CREATE TABLE dead_letters (
+    id SERIAL PRIMARY KEY,
+    market_pubkey TEXT NOT NULL,            -- Base58 market address the transaction was extracted for
+    signature TEXT NOT NULL,                -- Base58 transaction signature
+    error_kind TEXT NOT NULL,               -- Stage that failed, 'fetch', 'decode' or 'insert'
+    error_message TEXT NOT NULL,            -- Error of the latest attempt
+    attempts INTEGER NOT NULL DEFAULT 1,    -- Failed attempts since the transaction was recorded or requeued
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    CONSTRAINT dead_letters_market_signature_key UNIQUE (market_pubkey, signature),
+    CONSTRAINT dead_letters_error_kind_check CHECK (error_kind IN ('fetch', 'decode', 'insert'))
+);
+
+SELECT diesel_manage_updated_at('dead_letters');
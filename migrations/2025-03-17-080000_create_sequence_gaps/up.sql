@@ -0,0 +1,20 @@
+-- Your SQL goes here
+-- Ranges of market sequence numbers the daemon never saw. A gap stays open until a targeted backfill of
+-- its slots ran, which either closes it or leaves it unresolved as a data quality signal.
+CREATE TABLE sequence_gaps (
+    id SERIAL PRIMARY KEY,
+    market_pubkey TEXT NOT NULL,                -- Base58 market address
+    first_missing BIGINT NOT NULL,              -- First missing sequence number
+    last_missing BIGINT NOT NULL,               -- Last missing sequence number
+    from_slot BIGINT NOT NULL,                  -- Slot of the sequence number seen before the gap
+    to_slot BIGINT NOT NULL,                    -- Slot of the sequence number seen after the gap
+    status TEXT NOT NULL DEFAULT 'open',        -- 'open', 'closed' or 'unresolved'
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    CONSTRAINT sequence_gaps_market_range_key UNIQUE (market_pubkey, first_missing, last_missing),
+    CONSTRAINT sequence_gaps_status_check CHECK (status IN ('open', 'closed', 'unresolved'))
+);
+
+CREATE INDEX sequence_gaps_status_idx ON sequence_gaps (status);
+
+SELECT diesel_manage_updated_at('sequence_gaps');
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE orderbook_levels;
+DROP TABLE orderbook_snapshots;
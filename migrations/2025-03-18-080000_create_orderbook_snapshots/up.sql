@@ -0,0 +1,23 @@
+-- Your SQL goes here
+-- Periodic snapshots of the best levels of a market's order book, read from the market account at a slot
+CREATE TABLE orderbook_snapshots (
+    id SERIAL PRIMARY KEY,
+    market_pubkey TEXT NOT NULL REFERENCES markets (market_pubkey), -- Base58 market address
+    slot BIGINT NOT NULL,                                           -- Slot the market account was read at
+    snapshot_timestamp BIGINT NOT NULL,                             -- Unix time (seconds) the snapshot was taken
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    CONSTRAINT orderbook_snapshots_market_slot_key UNIQUE (market_pubkey, slot)
+);
+
+CREATE INDEX orderbook_snapshots_market_timestamp_idx ON orderbook_snapshots (market_pubkey, snapshot_timestamp);
+
+-- The price levels of a snapshot, level 0 is the best bid or ask
+CREATE TABLE orderbook_levels (
+    snapshot_id INTEGER NOT NULL REFERENCES orderbook_snapshots (id) ON DELETE CASCADE,
+    side TEXT NOT NULL,                 -- 'bid' or 'ask'
+    level INTEGER NOT NULL,             -- Distance from the best price, in levels
+    price_in_ticks BIGINT NOT NULL,     -- Price of the level in ticks
+    size_in_base_lots BIGINT NOT NULL,  -- Resting size at the price in base lots
+    PRIMARY KEY (snapshot_id, side, level),
+    CONSTRAINT orderbook_levels_side_check CHECK (side IN ('bid', 'ask'))
+);
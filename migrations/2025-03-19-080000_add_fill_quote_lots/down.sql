@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE trade_fills
+    DROP COLUMN quote_lots_filled;
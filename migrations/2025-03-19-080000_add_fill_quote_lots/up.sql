@@ -0,0 +1,19 @@
+-- Your SQL goes here
+-- Quote lots exchanged by every fill, derived from its market's header like the Phoenix program
+-- does, rounded down. Fills of markets the daemon hasn't read yet keep NULLs.
+ALTER TABLE trade_fills
+    ADD COLUMN quote_lots_filled BIGINT;    -- price_in_ticks * base_lots * tick size / base lot size
+
+UPDATE trade_fills
+SET quote_lots_filled = FLOOR(
+    trade_fills.price_in_ticks::NUMERIC
+        * markets.tick_size_in_quote_atoms_per_base_unit
+        * trade_fills.base_lots_filled
+        * markets.base_lot_size
+        / (markets.quote_lot_size
+            * POWER(10::NUMERIC, markets.base_decimals)
+            * GREATEST(markets.raw_base_units_per_base_unit, 1))
+)::BIGINT
+FROM markets
+WHERE markets.market_pubkey = trade_fills.market_pubkey
+    AND markets.quote_lot_size > 0;
@@ -0,0 +1,30 @@
+//! Configuration errors
+
+use {std::path::PathBuf, thiserror::Error};
+
+/// Everything that can go wrong while loading or validating the configuration
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    /// The configuration file couldn't be read
+    #[error("Failed to read {path}: {source}")]
+    Read {
+        /// Path of the configuration file
+        path: PathBuf,
+        /// Why it couldn't be read
+        source: std::io::Error,
+    },
+    /// The configuration file isn't valid TOML or has unknown keys
+    #[error(transparent)]
+    Toml(#[from] toml::de::Error),
+    /// An environment variable override can't be parsed into its setting
+    #[error("Invalid value '{value}' for environment variable {key}")]
+    InvalidEnv {
+        /// Name of the environment variable
+        key: String,
+        /// Value it holds
+        value: String,
+    },
+    /// A setting is missing or out of range
+    #[error("Invalid configuration, {0}")]
+    Invalid(String),
+}
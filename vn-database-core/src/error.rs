@@ -1,23 +1,35 @@
 //! Database connection errors
 
 use {
-    diesel::{result::Error, ConnectionError},
+    diesel::{r2d2::PoolError, result::Error, ConnectionError},
     thiserror::Error,
 };
 
 /// Encapsulate 3rd party and std lib errors for this crate
 #[derive(Error, Debug)]
 pub enum VybeDatabaseError {
-    /// Encapsulate var error
+    /// Neither `DATABASE_URL` nor the `[database]` section of the configuration set a url
     #[error("DATABASE_URL env variable not found")]
     EnvVar,
     /// Encapsulates Diesel database `diesel::ConnectionError`
     #[error(transparent)]
     Connection(#[from] ConnectionError),
+    /// A pool couldn't establish its connections, or none freed up in time
+    #[error(transparent)]
+    Pool(#[from] PoolError),
     /// Encapsulates the actual database errors from diesel
     #[error(transparent)]
     Diesel(#[from] Error),
-    /// Represents an unexpected `PhoenixEvent::MarketDetails::Fill` variant.
-    #[error("PhoenixEvent does not contain a Fill event")]
+    /// Represents an unexpected `PhoenixEvent::MarketDetails` variant.
+    #[error("PhoenixEvent does not contain the expected event kind")]
     InvalidPhoenixEvent,
+    /// A market event kind name is not one of `MarketEventKind`
+    #[error("Unknown market event kind '{0}'")]
+    InvalidEventKind(String),
+    /// A dead letter kind name is not one of `DeadLetterKind`
+    #[error("Unknown dead letter kind '{0}'")]
+    InvalidDeadLetterKind(String),
+    /// A sequence gap status name is not one of `SequenceGapStatus`
+    #[error("Unknown sequence gap status '{0}'")]
+    InvalidSequenceGapStatus(String),
 }
@@ -5,29 +5,165 @@
 mod error;
 pub mod models;
 pub mod schema;
+pub mod units;
 
 pub use error::VybeDatabaseError;
 
 use {
-    diesel::prelude::*,
-    dotenvy::dotenv,
-    models::{NewTradeFill, TradeFill},
-    schema::trade_fills,
-    std::env,
+    diesel::{
+        prelude::*,
+        r2d2::{ConnectionManager, Pool, PooledConnection},
+        upsert::excluded,
+    },
+    models::{
+        BackfillProgress, DeadLetter, Market, NewBackfillProgress, NewDeadLetter, NewMarket,
+        NewMarketEvents, NewOrderEviction, NewOrderPlace, NewOrderReduce, NewOrderbookSnapshot,
+        NewSequenceGap, NewSyncCheckpoint, NewTradeFill, Orderbook, OrderbookLevel,
+        OrderbookSnapshot, ProvisionalTransaction, SequenceGap, SequenceGapStatus, SyncCheckpoint,
+        TradeFill,
+    },
+    schema::{
+        backfill_progress, dead_letters, fee_events, fill_summaries, markets, order_evictions,
+        order_places, order_reduces, orderbook_levels, orderbook_snapshots, sequence_gaps,
+        sync_checkpoints, time_in_force_events, trade_fills,
+    },
+    std::{
+        collections::{HashMap, HashSet},
+        ops::{Deref, DerefMut},
+    },
     tracing::debug,
+    vn_config::DatabaseConfig,
 };
 
+/// Expands `$body` once for every market event table, with `$table` naming the table's
+/// schema module, for statements that are the same on every event table.
+macro_rules! for_each_event_table {
+    ($table:ident => $body:block) => {{
+        {
+            use schema::trade_fills as $table;
+            $body
+        }
+        {
+            use schema::order_places as $table;
+            $body
+        }
+        {
+            use schema::order_reduces as $table;
+            $body
+        }
+        {
+            use schema::order_evictions as $table;
+            $body
+        }
+        {
+            use schema::fill_summaries as $table;
+            $body
+        }
+        {
+            use schema::fee_events as $table;
+            $body
+        }
+        {
+            use schema::time_in_force_events as $table;
+            $body
+        }
+    }};
+}
+
+/// Postgres rejects statements with more than 65535 bind parameters
+const MAX_BIND_PARAMETERS: usize = 0xFFFF;
+/// Bind parameters per row of a `trade_fills` insert
+const TRADE_FILL_COLUMNS: usize = 14;
+/// Bind parameters per row of an `order_places` insert
+const ORDER_PLACE_COLUMNS: usize = 12;
+/// Bind parameters per row of an `order_reduces` insert
+const ORDER_REDUCE_COLUMNS: usize = 13;
+/// Bind parameters per row of an `order_evictions` insert
+const ORDER_EVICTION_COLUMNS: usize = 11;
+/// Bind parameters per row of a `fill_summaries` insert
+const FILL_SUMMARY_COLUMNS: usize = 11;
+/// Bind parameters per row of a `fee_events` insert
+const FEE_EVENT_COLUMNS: usize = 8;
+/// Bind parameters per row of a `time_in_force_events` insert
+const TIME_IN_FORCE_COLUMNS: usize = 10;
+
+/// Inserts `$rows` into `$table` in chunks that keep each statement below
+/// [`MAX_BIND_PARAMETERS`], given `$columns` bind parameters per row. Rows that are
+/// already stored are skipped, evaluates to the number of newly inserted rows.
+macro_rules! insert_chunked {
+    ($conn:expr, $table:path, $rows:expr, $columns:expr) => {{
+        let mut inserted = 0_usize;
+        for chunk in $rows.chunks(MAX_BIND_PARAMETERS.div_euclid($columns)) {
+            inserted += diesel::insert_into($table)
+                .values(chunk)
+                .on_conflict_do_nothing()
+                .execute($conn)?;
+        }
+        inserted
+    }};
+}
+
+/// Market events newly inserted by a commit, events that were already stored aren't counted
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct InsertedEvents {
+    /// Newly inserted trade fills
+    pub fills: usize,
+    /// Newly inserted events of every kind, fills included
+    pub total: usize,
+}
+
+/// Connections to the database shared by every `VybeDatabase` made with
+/// `VybeDatabase::from_pool`
+pub type VybePool = Pool<ConnectionManager<PgConnection>>;
+
+/// Where a `VybeDatabase` gets its connection from
+enum ConnectionSource {
+    /// A connection of its own, held for as long as the `VybeDatabase` lives
+    Owned(Box<PgConnection>),
+    /// A pool shared with other `VybeDatabase`s, a connection is checked out per operation
+    Pooled(VybePool),
+}
+
+/// The connection a single operation runs on, a pooled connection goes back to its pool
+/// once this is dropped
+pub enum VybeConnection<'a> {
+    /// The connection owned by the `VybeDatabase`
+    Owned(&'a mut PgConnection),
+    /// A connection checked out of the pool
+    Pooled(Box<PooledConnection<ConnectionManager<PgConnection>>>),
+}
+
+impl Deref for VybeConnection<'_> {
+    type Target = PgConnection;
+
+    fn deref(&self) -> &PgConnection {
+        match self {
+            Self::Owned(conn) => conn,
+            Self::Pooled(conn) => conn,
+        }
+    }
+}
+
+impl DerefMut for VybeConnection<'_> {
+    fn deref_mut(&mut self) -> &mut PgConnection {
+        match self {
+            Self::Owned(conn) => conn,
+            Self::Pooled(conn) => conn,
+        }
+    }
+}
+
 /// PG Database abstraction/interface
 pub struct VybeDatabase {
-    /// Connection to our db
-    conn: PgConnection,
+    /// Connection to our db, or the pool connections are checked out of
+    source: ConnectionSource,
 }
 
 impl VybeDatabase {
     /// Creates a new instance of the database connection,
     /// loads `DATABASE_URL` using dotenv from root .env file
     /// and immediately atttempts to connect.
-    /// Should only be called once per application.
+    /// Applications reading a configuration file use `VybeDatabase::connect` instead.
     ///
     /// # Errors
     ///
@@ -45,20 +181,61 @@ impl VybeDatabase {
     /// }
     /// ```
     pub fn new() -> Result<Self, VybeDatabaseError> {
-        dotenv().ok();
-        match env::var("DATABASE_URL") {
-            Ok(database_url) => {
-                let conn = PgConnection::establish(&database_url)?;
-                debug!("Established connection to {database_url}");
-                Ok(Self { conn })
-            }
-            Err(_) => Err(VybeDatabaseError::EnvVar),
+        Self::connect(&DatabaseConfig::from_env())
+    }
+
+    /// Connects to the database configured in the `[database]` section of a `VybeConfig`
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::EnvVar` if no database url is configured
+    /// `vn_database_core::VybeDatabaseError::Connection`
+    pub fn connect(config: &DatabaseConfig) -> Result<Self, VybeDatabaseError> {
+        let database_url = config.url.as_deref().ok_or(VybeDatabaseError::EnvVar)?;
+        let conn = PgConnection::establish(database_url)?;
+        debug!("Established connection to {database_url}");
+        Ok(Self {
+            source: ConnectionSource::Owned(Box::new(conn)),
+        })
+    }
+
+    /// Opens a pool of at most `pool_size` connections to the database configured in the
+    /// `[database]` section of a `VybeConfig`, to be shared with `VybeDatabase::from_pool`
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::EnvVar` if no database url is configured
+    /// `vn_database_core::VybeDatabaseError::Pool` if the connections can't be established
+    pub fn pool(config: &DatabaseConfig) -> Result<VybePool, VybeDatabaseError> {
+        let database_url = config.url.as_deref().ok_or(VybeDatabaseError::EnvVar)?;
+        let pool = VybePool::builder()
+            .max_size(config.pool_size)
+            .build(ConnectionManager::new(database_url))?;
+        debug!(
+            "Opened a pool of {} connection(s) to {database_url}",
+            config.pool_size
+        );
+        Ok(pool)
+    }
+
+    /// Shares `pool` with every other `VybeDatabase` made from it, each operation checks out
+    /// a connection and returns it once done, so a transaction never spans two operations
+    pub fn from_pool(pool: &VybePool) -> Self {
+        Self {
+            source: ConnectionSource::Pooled(pool.clone()),
         }
     }
 
-    /// Get a mutable reference to the underlying connection
-    pub fn conn(&mut self) -> &mut PgConnection {
-        &mut self.conn
+    /// Get the connection the next operation runs on
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Pool` if no pooled connection frees up in time
+    pub fn conn(&mut self) -> Result<VybeConnection<'_>, VybeDatabaseError> {
+        Ok(match &mut self.source {
+            ConnectionSource::Owned(conn) => VybeConnection::Owned(conn),
+            ConnectionSource::Pooled(pool) => VybeConnection::Pooled(Box::new(pool.get()?)),
+        })
     }
 
     /// Gets a trade fill by the id number
@@ -71,7 +248,7 @@ impl VybeDatabase {
             .filter(trade_fills::id.eq(id))
             .limit(5)
             .select(TradeFill::as_select())
-            .load(self.conn())?)
+            .load(&mut *self.conn()?)?)
     }
 
     /// Gets all trade fill records from the database.
@@ -82,24 +259,839 @@ impl VybeDatabase {
     pub fn get_all_trade_fills(&mut self) -> Result<Vec<TradeFill>, VybeDatabaseError> {
         Ok(trade_fills::table
             .select(TradeFill::as_select())
-            .load(self.conn())?)
+            .load(&mut *self.conn()?)?)
+    }
+
+    /// Gets all trade fill records of a single market from the database.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `VybeDatabaseError::Diesel` if the Diesel query fails.
+    pub fn get_trade_fills_by_market(
+        &mut self,
+        market_pubkey: &str,
+    ) -> Result<Vec<TradeFill>, VybeDatabaseError> {
+        Ok(trade_fills::table
+            .filter(trade_fills::market_pubkey.eq(market_pubkey))
+            .select(TradeFill::as_select())
+            .load(&mut *self.conn()?)?)
+    }
+
+    /// Gets every trade fill a trader took part in, as maker or taker, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `VybeDatabaseError::Diesel` if the Diesel query fails.
+    pub fn get_trade_fills_by_trader(
+        &mut self,
+        trader: &str,
+    ) -> Result<Vec<TradeFill>, VybeDatabaseError> {
+        Ok(trade_fills::table
+            .filter(
+                trade_fills::maker
+                    .eq(trader)
+                    .or(trade_fills::taker.eq(trader)),
+            )
+            .order(trade_fills::event_timestamp.asc())
+            .select(TradeFill::as_select())
+            .load(&mut *self.conn()?)?)
     }
 
-    /// Create a new trade fill entry in the database
+    /// Create a new trade fill entry in the database, a fill that is already stored
+    /// (same market, signature, sequence number and event index) is left untouched.
     ///
     /// # Params
     ///
+    /// - `new_trade_fill`: the fill to store
     ///
     /// # Errors
     ///
     /// `vn_database_core::VybeDatabaseError::Diesel`
+    ///
+    /// # Returns
+    ///
+    /// The stored fill, `None` if it was already stored
     pub fn create_trade_fill(
         &mut self,
         new_trade_fill: &NewTradeFill,
-    ) -> Result<TradeFill, VybeDatabaseError> {
+    ) -> Result<Option<TradeFill>, VybeDatabaseError> {
         Ok(diesel::insert_into(trade_fills::table)
             .values(new_trade_fill)
+            .on_conflict_do_nothing()
             .returning(TradeFill::as_returning())
-            .get_result(self.conn())?)
+            .get_result(&mut *self.conn()?)
+            .optional()?)
+    }
+
+    /// Gets the metadata of a market, `None` if the daemon has never read the market
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Diesel` if the Diesel query fails
+    pub fn get_market(&mut self, market_pubkey: &str) -> Result<Option<Market>, VybeDatabaseError> {
+        Ok(markets::table
+            .find(market_pubkey)
+            .select(Market::as_select())
+            .first(&mut *self.conn()?)
+            .optional()?)
+    }
+
+    /// Gets the metadata of every market the daemon has read
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Diesel` if the Diesel query fails
+    pub fn get_markets(&mut self) -> Result<Vec<Market>, VybeDatabaseError> {
+        Ok(markets::table
+            .select(Market::as_select())
+            .load(&mut *self.conn()?)?)
+    }
+
+    /// Stores the metadata of a market, replacing what was stored before since a market's
+    /// fee can change. Fills reference their market's row, so it is stored before any fill.
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Diesel`
+    ///
+    /// # Returns
+    ///
+    /// The number of rows written
+    pub fn upsert_market(&mut self, new_market: &NewMarket) -> Result<usize, VybeDatabaseError> {
+        Ok(diesel::insert_into(markets::table)
+            .values(new_market)
+            .on_conflict(markets::market_pubkey)
+            .do_update()
+            .set(new_market)
+            .execute(&mut *self.conn()?)?)
+    }
+
+    /// Gets the signature cursor of a market, `None` if the market has never been extracted
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Diesel` if the Diesel query fails
+    pub fn get_checkpoint(
+        &mut self,
+        market_pubkey: &str,
+    ) -> Result<Option<SyncCheckpoint>, VybeDatabaseError> {
+        Ok(sync_checkpoints::table
+            .find(market_pubkey)
+            .select(SyncCheckpoint::as_select())
+            .first(&mut *self.conn()?)
+            .optional()?)
+    }
+
+    /// Inserts a batch of market events and moves the market's signature cursor forward
+    /// inside a single transaction, so a crash can never store events without the cursor
+    /// that covers them (or the other way around).
+    ///
+    /// # Params
+    ///
+    /// - `new_events`: events extracted from the transactions up to the checkpoint
+    /// - `checkpoint`: the newest signature those events were extracted from
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Diesel`, nothing is written when this is returned
+    ///
+    /// # Returns
+    ///
+    /// The number of inserted events, and how many of them are fills
+    pub fn commit_market_events(
+        &mut self,
+        new_events: &NewMarketEvents,
+        checkpoint: &NewSyncCheckpoint,
+    ) -> Result<InsertedEvents, VybeDatabaseError> {
+        self.conn()?.transaction(|conn| {
+            let inserted = Self::insert_market_events(conn, new_events)?;
+
+            diesel::insert_into(sync_checkpoints::table)
+                .values(checkpoint)
+                .on_conflict(sync_checkpoints::market_pubkey)
+                .do_update()
+                .set(checkpoint)
+                .execute(conn)?;
+
+            Ok(inserted)
+        })
+    }
+
+    /// Gets the progress of a backfill, `None` if it has never been started
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Diesel` if the Diesel query fails
+    pub fn get_backfill_progress(
+        &mut self,
+        market_pubkey: &str,
+        range_from: &str,
+        range_to: &str,
+    ) -> Result<Option<BackfillProgress>, VybeDatabaseError> {
+        Ok(backfill_progress::table
+            .find((market_pubkey, range_from, range_to))
+            .select(BackfillProgress::as_select())
+            .first(&mut *self.conn()?)
+            .optional()?)
+    }
+
+    /// Inserts one page of backfilled market events together with the backfill's progress
+    /// inside a single transaction. A resumed backfill continues from the stored progress,
+    /// so a page is never inserted twice. `progress.fills_inserted` is advanced by the fills
+    /// the page actually inserted before it is stored, replayed fills are not counted again.
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Diesel`, nothing is written when this is returned
+    ///
+    /// # Returns
+    ///
+    /// The number of inserted events
+    pub fn commit_backfill_page(
+        &mut self,
+        new_events: &NewMarketEvents,
+        progress: &mut NewBackfillProgress,
+    ) -> Result<usize, VybeDatabaseError> {
+        self.conn()?.transaction(|conn| {
+            let inserted = Self::insert_market_events(conn, new_events)?;
+            progress.fills_inserted += inserted.fills as i64;
+
+            diesel::insert_into(backfill_progress::table)
+                .values(&*progress)
+                .on_conflict((
+                    backfill_progress::market_pubkey,
+                    backfill_progress::range_from,
+                    backfill_progress::range_to,
+                ))
+                .do_update()
+                .set(&*progress)
+                .execute(conn)?;
+
+            Ok(inserted.total)
+        })
+    }
+
+    /// Gets the transactions that still have provisional events, oldest slot first.
+    ///
+    /// # Params
+    ///
+    /// - `limit`: maximum number of transactions returned
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Diesel` if a Diesel query fails
+    pub fn get_provisional_transactions(
+        &mut self,
+        limit: i64,
+    ) -> Result<Vec<ProvisionalTransaction>, VybeDatabaseError> {
+        let mut transactions: Vec<ProvisionalTransaction> = vec![];
+        let mut conn = self.conn()?;
+        let conn = &mut *conn;
+        for_each_event_table!(table => {
+            transactions.extend(
+                table::table
+                    .filter(table::provisional.eq(true))
+                    .select((table::signature, table::slot))
+                    .distinct()
+                    .order(table::slot.asc())
+                    .limit(limit)
+                    .load::<ProvisionalTransaction>(conn)?,
+            );
+        });
+
+        transactions.sort_by_key(|transaction| transaction.slot);
+        let mut seen = HashSet::new();
+        transactions.retain(|transaction| seen.insert(transaction.signature.clone()));
+        transactions.truncate(usize::try_from(limit).unwrap_or_default());
+        Ok(transactions)
+    }
+
+    /// Marks the events of transactions that reached the finalized commitment as final,
+    /// inside a single transaction. The slot is updated as well, a transaction dropped from
+    /// a skipped slot can land again in a later one.
+    ///
+    /// # Params
+    ///
+    /// - `finalized`: the finalized transactions and the slot they were finalized in
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Diesel`, nothing is written when this is returned
+    ///
+    /// # Returns
+    ///
+    /// The number of events that became final
+    pub fn finalize_transactions(
+        &mut self,
+        finalized: &[ProvisionalTransaction],
+    ) -> Result<usize, VybeDatabaseError> {
+        self.conn()?.transaction(|conn| {
+            let mut updated = 0_usize;
+            for transaction in finalized {
+                for_each_event_table!(table => {
+                    updated += diesel::update(
+                        table::table
+                            .filter(table::signature.eq(&transaction.signature))
+                            .filter(table::provisional.eq(true)),
+                    )
+                    .set((
+                        table::provisional.eq(false),
+                        table::slot.eq(transaction.slot),
+                    ))
+                    .execute(conn)?;
+                });
+            }
+            Ok(updated)
+        })
+    }
+
+    /// Deletes the provisional events of transactions that were rolled back,
+    /// inside a single transaction. Final events are never deleted.
+    ///
+    /// # Params
+    ///
+    /// - `signatures`: base58 encoded signatures of the rolled back transactions
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Diesel`, nothing is deleted when this is returned
+    ///
+    /// # Returns
+    ///
+    /// The number of deleted events
+    pub fn delete_provisional_transactions(
+        &mut self,
+        signatures: &[String],
+    ) -> Result<usize, VybeDatabaseError> {
+        self.conn()?.transaction(|conn| {
+            let mut deleted = 0_usize;
+            for_each_event_table!(table => {
+                deleted += diesel::delete(
+                    table::table
+                        .filter(table::signature.eq_any(signatures))
+                        .filter(table::provisional.eq(true)),
+                )
+                .execute(conn)?;
+            });
+            Ok(deleted)
+        })
+    }
+
+    /// Records transactions that failed to be extracted. A transaction that is already
+    /// recorded for the market gets the new error and one more attempt.
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Diesel`, nothing is written when this is returned
+    ///
+    /// # Returns
+    ///
+    /// The number of recorded transactions
+    pub fn record_dead_letters(
+        &mut self,
+        letters: &[NewDeadLetter],
+    ) -> Result<usize, VybeDatabaseError> {
+        self.conn()?
+            .transaction(|conn| Self::insert_dead_letters(conn, letters))
+    }
+
+    /// Records transactions that failed to be extracted and moves the market's signature
+    /// cursor past them inside a single transaction, so the cursor never skips a
+    /// transaction that is neither stored nor recorded as a dead letter.
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Diesel`, nothing is written when this is returned
+    ///
+    /// # Returns
+    ///
+    /// The number of recorded transactions
+    pub fn commit_dead_letters(
+        &mut self,
+        letters: &[NewDeadLetter],
+        checkpoint: &NewSyncCheckpoint,
+    ) -> Result<usize, VybeDatabaseError> {
+        self.conn()?.transaction(|conn| {
+            let recorded = Self::insert_dead_letters(conn, letters)?;
+
+            diesel::insert_into(sync_checkpoints::table)
+                .values(checkpoint)
+                .on_conflict(sync_checkpoints::market_pubkey)
+                .do_update()
+                .set(checkpoint)
+                .execute(conn)?;
+
+            Ok(recorded)
+        })
+    }
+
+    /// Gets the dead letters, oldest first.
+    ///
+    /// # Params
+    ///
+    /// - `market_pubkey`: only the dead letters of this market, all markets when `None`
+    /// - `limit`: maximum number of dead letters returned
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Diesel` if the Diesel query fails
+    pub fn get_dead_letters(
+        &mut self,
+        market_pubkey: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<DeadLetter>, VybeDatabaseError> {
+        let mut query = dead_letters::table
+            .select(DeadLetter::as_select())
+            .order(dead_letters::id.asc())
+            .limit(limit)
+            .into_boxed();
+        if let Some(market_pubkey) = market_pubkey {
+            query = query.filter(dead_letters::market_pubkey.eq(market_pubkey));
+        }
+        Ok(query.load(&mut *self.conn()?)?)
+    }
+
+    /// Gets the dead letters that failed fewer than `max_attempts` times, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Diesel` if the Diesel query fails
+    pub fn get_retryable_dead_letters(
+        &mut self,
+        max_attempts: i32,
+        limit: i64,
+    ) -> Result<Vec<DeadLetter>, VybeDatabaseError> {
+        Ok(dead_letters::table
+            .filter(dead_letters::attempts.lt(max_attempts))
+            .select(DeadLetter::as_select())
+            .order(dead_letters::id.asc())
+            .limit(limit)
+            .load(&mut *self.conn()?)?)
+    }
+
+    /// Inserts the events of a retried dead letter and deletes the dead letter
+    /// inside a single transaction.
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Diesel`, nothing is written when this is returned
+    ///
+    /// # Returns
+    ///
+    /// The number of inserted events
+    pub fn resolve_dead_letter(
+        &mut self,
+        id: i32,
+        new_events: &NewMarketEvents,
+    ) -> Result<usize, VybeDatabaseError> {
+        self.conn()?.transaction(|conn| {
+            let inserted = Self::insert_market_events(conn, new_events)?.total;
+            diesel::delete(dead_letters::table.find(id)).execute(conn)?;
+            Ok(inserted)
+        })
+    }
+
+    /// Resets the attempts of dead letters so the daemon retries them again.
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Diesel` if the Diesel query fails
+    ///
+    /// # Returns
+    ///
+    /// The number of requeued dead letters
+    pub fn requeue_dead_letters(&mut self, ids: &[i32]) -> Result<usize, VybeDatabaseError> {
+        Ok(
+            diesel::update(dead_letters::table.filter(dead_letters::id.eq_any(ids)))
+                .set(dead_letters::attempts.eq(0))
+                .execute(&mut *self.conn()?)?,
+        )
+    }
+
+    /// Deletes dead letters without retrying them, their events are given up on.
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Diesel` if the Diesel query fails
+    ///
+    /// # Returns
+    ///
+    /// The number of discarded dead letters
+    pub fn delete_dead_letters(&mut self, ids: &[i32]) -> Result<usize, VybeDatabaseError> {
+        Ok(
+            diesel::delete(dead_letters::table.filter(dead_letters::id.eq_any(ids)))
+                .execute(&mut *self.conn()?)?,
+        )
+    }
+
+    /// Gets the highest sequence number stored for a market across every event table,
+    /// with the slot of its transaction, `None` before the market's first event.
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Diesel` if a Diesel query fails
+    ///
+    /// # Returns
+    ///
+    /// `(sequence_number, slot)` of the newest stored event
+    pub fn get_last_sequence_number(
+        &mut self,
+        market_pubkey: &str,
+    ) -> Result<Option<(i64, i64)>, VybeDatabaseError> {
+        let mut last: Option<(i64, i64)> = None;
+        let mut conn = self.conn()?;
+        let conn = &mut *conn;
+        for_each_event_table!(table => {
+            let newest = table::table
+                .filter(table::market_pubkey.eq(market_pubkey))
+                .select((table::sequence_number, table::slot))
+                .order(table::sequence_number.desc())
+                .first::<(i64, i64)>(conn)
+                .optional()?;
+            last = last.max(newest);
+        });
+        Ok(last)
+    }
+
+    /// Gets the signature of the oldest stored event of a market emitted after `sequence_number`,
+    /// across every event table, `None` when nothing newer is stored. A backfill of the gap in
+    /// front of it can start walking history from that transaction instead of the chain tip.
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Diesel` if a Diesel query fails
+    ///
+    /// # Returns
+    ///
+    /// `(sequence_number, signature)` of the oldest newer event
+    pub fn get_next_sequence_signature(
+        &mut self,
+        market_pubkey: &str,
+        sequence_number: i64,
+    ) -> Result<Option<(i64, String)>, VybeDatabaseError> {
+        let mut next: Option<(i64, String)> = None;
+        let mut conn = self.conn()?;
+        let conn = &mut *conn;
+        for_each_event_table!(table => {
+            let oldest = table::table
+                .filter(table::market_pubkey.eq(market_pubkey))
+                .filter(table::sequence_number.gt(sequence_number))
+                .select((table::sequence_number, table::signature))
+                .order(table::sequence_number.asc())
+                .first::<(i64, String)>(conn)
+                .optional()?;
+            next = match (next, oldest) {
+                (Some(next), Some(oldest)) => Some(next.min(oldest)),
+                (next, oldest) => next.or(oldest),
+            };
+        });
+        Ok(next)
+    }
+
+    /// Records newly detected sequence gaps as open, gaps that are already recorded are skipped.
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Diesel` if the Diesel query fails
+    ///
+    /// # Returns
+    ///
+    /// The number of recorded gaps
+    pub fn record_sequence_gaps(
+        &mut self,
+        gaps: &[NewSequenceGap],
+    ) -> Result<usize, VybeDatabaseError> {
+        Ok(diesel::insert_into(sequence_gaps::table)
+            .values(gaps)
+            .on_conflict_do_nothing()
+            .execute(&mut *self.conn()?)?)
+    }
+
+    /// Gets the sequence gaps, oldest first.
+    ///
+    /// # Params
+    ///
+    /// - `market_pubkey`: only the gaps of this market, all markets when `None`
+    /// - `status`: only the gaps with this status, every status when `None`
+    /// - `limit`: maximum number of gaps returned
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Diesel` if the Diesel query fails
+    pub fn get_sequence_gaps(
+        &mut self,
+        market_pubkey: Option<&str>,
+        status: Option<SequenceGapStatus>,
+        limit: i64,
+    ) -> Result<Vec<SequenceGap>, VybeDatabaseError> {
+        let mut query = sequence_gaps::table
+            .select(SequenceGap::as_select())
+            .order(sequence_gaps::id.asc())
+            .limit(limit)
+            .into_boxed();
+        if let Some(market_pubkey) = market_pubkey {
+            query = query.filter(sequence_gaps::market_pubkey.eq(market_pubkey));
+        }
+        if let Some(status) = status {
+            query = query.filter(sequence_gaps::status.eq(status.as_str()));
+        }
+        Ok(query.load(&mut *self.conn()?)?)
+    }
+
+    /// Sets the status of a sequence gap once its targeted backfill ran.
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Diesel` if the Diesel query fails
+    pub fn set_sequence_gap_status(
+        &mut self,
+        id: i32,
+        status: SequenceGapStatus,
+    ) -> Result<usize, VybeDatabaseError> {
+        Ok(diesel::update(sequence_gaps::table.find(id))
+            .set(sequence_gaps::status.eq(status.as_str()))
+            .execute(&mut *self.conn()?)?)
+    }
+
+    /// Stores an order book snapshot with its price levels in a single transaction,
+    /// a snapshot of a market at a slot that is already stored is skipped.
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Diesel`, nothing is written when this is returned
+    ///
+    /// # Returns
+    ///
+    /// The id of the stored snapshot, `None` if it was already stored
+    pub fn record_orderbook_snapshot(
+        &mut self,
+        snapshot: &NewOrderbookSnapshot,
+    ) -> Result<Option<i32>, VybeDatabaseError> {
+        self.conn()?.transaction(|conn| {
+            let id = diesel::insert_into(orderbook_snapshots::table)
+                .values((
+                    orderbook_snapshots::market_pubkey.eq(&snapshot.market_pubkey),
+                    orderbook_snapshots::slot.eq(snapshot.slot),
+                    orderbook_snapshots::snapshot_timestamp.eq(snapshot.snapshot_timestamp),
+                ))
+                .on_conflict_do_nothing()
+                .returning(orderbook_snapshots::id)
+                .get_result::<i32>(conn)
+                .optional()?;
+            if let Some(id) = id {
+                diesel::insert_into(orderbook_levels::table)
+                    .values(snapshot.levels(id))
+                    .execute(conn)?;
+            }
+            Ok(id)
+        })
+    }
+
+    /// Gets the newest order book snapshot of a market, `None` if none was taken yet
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Diesel` if a Diesel query fails
+    pub fn get_latest_orderbook(
+        &mut self,
+        market_pubkey: &str,
+    ) -> Result<Option<Orderbook>, VybeDatabaseError> {
+        Ok(self
+            .get_orderbook_history(market_pubkey, None, None, 1)?
+            .pop())
+    }
+
+    /// Gets the order book snapshots of a market, newest first.
+    ///
+    /// # Params
+    ///
+    /// - `from`: only the snapshots taken at or after this Unix timestamp
+    /// - `to`: only the snapshots taken at or before this Unix timestamp
+    /// - `limit`: maximum number of snapshots returned
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Diesel` if a Diesel query fails
+    pub fn get_orderbook_history(
+        &mut self,
+        market_pubkey: &str,
+        from: Option<i64>,
+        to: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<Orderbook>, VybeDatabaseError> {
+        let mut query = orderbook_snapshots::table
+            .filter(orderbook_snapshots::market_pubkey.eq(market_pubkey))
+            .select(OrderbookSnapshot::as_select())
+            .order(orderbook_snapshots::snapshot_timestamp.desc())
+            .then_order_by(orderbook_snapshots::slot.desc())
+            .limit(limit)
+            .into_boxed();
+        if let Some(from) = from {
+            query = query.filter(orderbook_snapshots::snapshot_timestamp.ge(from));
+        }
+        if let Some(to) = to {
+            query = query.filter(orderbook_snapshots::snapshot_timestamp.le(to));
+        }
+        let snapshots: Vec<OrderbookSnapshot> = query.load(&mut *self.conn()?)?;
+
+        let ids: Vec<i32> = snapshots.iter().map(|snapshot| snapshot.id).collect();
+        let mut levels = HashMap::new();
+        for level in orderbook_levels::table
+            .filter(orderbook_levels::snapshot_id.eq_any(&ids))
+            .select(OrderbookLevel::as_select())
+            .load::<OrderbookLevel>(&mut *self.conn()?)?
+        {
+            levels.entry(level.snapshot_id).or_default().push(level);
+        }
+
+        Ok(snapshots
+            .into_iter()
+            .map(|snapshot| {
+                let own = levels.remove(&snapshot.id).unwrap_or_default();
+                Orderbook::new(snapshot, own)
+            })
+            .collect())
+    }
+
+    /// Gets the stored events that change a market's order book (fills of maker orders,
+    /// places, reduces and evictions), each in sequence number order.
+    ///
+    /// # Params
+    ///
+    /// - `from_sequence_number`: only the events of this instruction and later ones
+    /// - `until_timestamp`: only the events that happened at or before this Unix timestamp
+    ///
+    /// # Errors
+    ///
+    /// `vn_database_core::VybeDatabaseError::Diesel` if a Diesel query fails
+    pub fn get_order_book_events(
+        &mut self,
+        market_pubkey: &str,
+        from_sequence_number: i64,
+        until_timestamp: i64,
+    ) -> Result<NewMarketEvents, VybeDatabaseError> {
+        let mut conn = self.conn()?;
+        let conn = &mut *conn;
+        let fills = trade_fills::table
+            .filter(trade_fills::market_pubkey.eq(market_pubkey))
+            .filter(trade_fills::sequence_number.ge(from_sequence_number))
+            .filter(trade_fills::event_timestamp.le(until_timestamp))
+            .order((
+                trade_fills::sequence_number.asc(),
+                trade_fills::event_index.asc(),
+            ))
+            .select(TradeFill::as_select())
+            .load::<TradeFill>(conn)?;
+        let places = order_places::table
+            .filter(order_places::market_pubkey.eq(market_pubkey))
+            .filter(order_places::sequence_number.ge(from_sequence_number))
+            .filter(order_places::event_timestamp.le(until_timestamp))
+            .order((
+                order_places::sequence_number.asc(),
+                order_places::event_index.asc(),
+            ))
+            .select(NewOrderPlace::as_select())
+            .load(conn)?;
+        let reduces = order_reduces::table
+            .filter(order_reduces::market_pubkey.eq(market_pubkey))
+            .filter(order_reduces::sequence_number.ge(from_sequence_number))
+            .filter(order_reduces::event_timestamp.le(until_timestamp))
+            .order((
+                order_reduces::sequence_number.asc(),
+                order_reduces::event_index.asc(),
+            ))
+            .select(NewOrderReduce::as_select())
+            .load(conn)?;
+        let evictions = order_evictions::table
+            .filter(order_evictions::market_pubkey.eq(market_pubkey))
+            .filter(order_evictions::sequence_number.ge(from_sequence_number))
+            .filter(order_evictions::event_timestamp.le(until_timestamp))
+            .order((
+                order_evictions::sequence_number.asc(),
+                order_evictions::event_index.asc(),
+            ))
+            .select(NewOrderEviction::as_select())
+            .load(conn)?;
+        Ok(NewMarketEvents {
+            fills: fills
+                .into_iter()
+                .filter_map(TradeFill::into_maker_fill)
+                .collect(),
+            places,
+            reduces,
+            evictions,
+            ..NewMarketEvents::default()
+        })
+    }
+
+    /// Upserts dead letters on an existing connection (or transaction),
+    /// bumping the attempts of the ones already recorded.
+    fn insert_dead_letters(
+        conn: &mut PgConnection,
+        letters: &[NewDeadLetter],
+    ) -> Result<usize, VybeDatabaseError> {
+        let mut recorded = 0_usize;
+        for letter in letters {
+            recorded += diesel::insert_into(dead_letters::table)
+                .values(letter)
+                .on_conflict((dead_letters::market_pubkey, dead_letters::signature))
+                .do_update()
+                .set((
+                    dead_letters::error_kind.eq(excluded(dead_letters::error_kind)),
+                    dead_letters::error_message.eq(excluded(dead_letters::error_message)),
+                    dead_letters::attempts.eq(dead_letters::attempts + 1),
+                ))
+                .execute(conn)?;
+        }
+        Ok(recorded)
+    }
+
+    /// Batch insert market events on an existing connection (or transaction),
+    /// in as few inserts per table as the bind parameter limit allows. Events that are
+    /// already stored are skipped, so replaying a transaction never duplicates its events.
+    /// Returns how many fills, and how many events in total, were newly inserted.
+    fn insert_market_events(
+        conn: &mut PgConnection,
+        new_events: &NewMarketEvents,
+    ) -> Result<InsertedEvents, VybeDatabaseError> {
+        let fills = insert_chunked!(
+            conn,
+            trade_fills::table,
+            new_events.fills,
+            TRADE_FILL_COLUMNS
+        );
+        let mut total = fills;
+        total += insert_chunked!(
+            conn,
+            order_places::table,
+            new_events.places,
+            ORDER_PLACE_COLUMNS
+        );
+        total += insert_chunked!(
+            conn,
+            order_reduces::table,
+            new_events.reduces,
+            ORDER_REDUCE_COLUMNS
+        );
+        total += insert_chunked!(
+            conn,
+            order_evictions::table,
+            new_events.evictions,
+            ORDER_EVICTION_COLUMNS
+        );
+        total += insert_chunked!(
+            conn,
+            fill_summaries::table,
+            new_events.fill_summaries,
+            FILL_SUMMARY_COLUMNS
+        );
+        total += insert_chunked!(conn, fee_events::table, new_events.fees, FEE_EVENT_COLUMNS);
+        total += insert_chunked!(
+            conn,
+            time_in_force_events::table,
+            new_events.time_in_force,
+            TIME_IN_FORCE_COLUMNS
+        );
+        Ok(InsertedEvents { fills, total })
     }
 }
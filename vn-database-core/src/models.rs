@@ -1,11 +1,12 @@
 //! Diesel Models
 
 use {
-    crate::VybeDatabaseError,
+    crate::{units::MarketUnits, VybeDatabaseError},
     diesel::prelude::*,
+    phoenix::state::Side,
     phoenix_sdk::sdk_client::{MarketEventDetails, PhoenixEvent},
     serde::Serialize,
-    std::convert::TryFrom,
+    std::{convert::TryFrom, fmt, str::FromStr},
 };
 
 /// Represents a trade fill event as stored in the database.
@@ -24,11 +25,59 @@ pub struct TradeFill {
     pub price_in_ticks: i64,
     /// The volume of the base token (in lots) that was filled in this trade event.
     pub base_lots_filled: i64,
+    /// Base58 encoded public key of the market the fill happened on.
+    pub market_pubkey: String,
+    /// Base58 encoded signature of the transaction that emitted the event.
+    pub signature: String,
+    /// The slot the transaction landed in.
+    pub slot: i64,
+    /// The market's sequence number of the instruction that emitted the event.
+    pub sequence_number: i64,
+    /// Index of the event among the events of its instruction.
+    pub event_index: i64,
+    /// Extracted below the finalized commitment, the transaction may still be rolled back.
+    pub provisional: bool,
+    /// Sequence number of the maker order that was filled, `None` for fills stored before
+    /// it was tracked.
+    pub order_sequence_number: Option<i64>,
+    /// Base58 encoded public key of the maker order owner.
+    pub maker: Option<String>,
+    /// Base58 encoded public key of the taker.
+    pub taker: Option<String>,
+    /// Side of the maker order that was filled, `bid` when the taker sold and `ask` when
+    /// the taker bought.
+    pub side_filled: Option<String>,
+    /// Quote lots exchanged, derived from the price, the size and the market's header.
+    /// `None` for fills of markets that weren't read yet.
+    pub quote_lots_filled: Option<i64>,
+}
+
+impl TradeFill {
+    /// The fill as a new event of its maker order, `None` for fills stored before the maker
+    /// order was tracked
+    pub fn into_maker_fill(self) -> Option<NewTradeFill> {
+        Some(NewTradeFill {
+            event_timestamp: self.event_timestamp,
+            price_in_ticks: self.price_in_ticks,
+            base_lots_filled: self.base_lots_filled,
+            market_pubkey: self.market_pubkey,
+            signature: self.signature,
+            slot: self.slot,
+            sequence_number: self.sequence_number,
+            event_index: self.event_index,
+            provisional: self.provisional,
+            order_sequence_number: self.order_sequence_number?,
+            maker: self.maker?,
+            taker: self.taker.unwrap_or_default(),
+            side_filled: self.side_filled.unwrap_or_default(),
+            quote_lots_filled: self.quote_lots_filled,
+        })
+    }
 }
 
 /// Represents a new trade fill event to be inserted into the database.
 /// Used to post new trade fill records.
-#[derive(Insertable)]
+#[derive(Debug, Insertable, Serialize, Clone)]
 #[diesel(table_name = crate::schema::trade_fills)]
 pub struct NewTradeFill {
     /// The Unix timestamp (in seconds) when the trade fill event occurred.
@@ -38,6 +87,1076 @@ pub struct NewTradeFill {
     pub price_in_ticks: i64,
     /// The volume of the base token (in lots) that was filled in this trade event.
     pub base_lots_filled: i64,
+    /// Base58 encoded public key of the market the fill happened on.
+    pub market_pubkey: String,
+    /// Base58 encoded signature of the transaction that emitted the event.
+    pub signature: String,
+    /// The slot the transaction landed in.
+    pub slot: i64,
+    /// The market's sequence number of the instruction that emitted the event.
+    pub sequence_number: i64,
+    /// Index of the event among the events of its instruction.
+    pub event_index: i64,
+    /// Extracted below the finalized commitment, the transaction may still be rolled back.
+    pub provisional: bool,
+    /// Sequence number of the maker order that was filled.
+    pub order_sequence_number: i64,
+    /// Base58 encoded public key of the maker order owner.
+    pub maker: String,
+    /// Base58 encoded public key of the taker.
+    pub taker: String,
+    /// Side of the maker order that was filled, `bid` when the taker sold and `ask` when
+    /// the taker bought.
+    pub side_filled: String,
+    /// Quote lots exchanged, set by `NewMarketEvents::derive_quote_lots` once the market's
+    /// header is known.
+    pub quote_lots_filled: Option<i64>,
+}
+
+/// Represents an order placed on the book as stored in the database.
+#[derive(Debug, Queryable, Selectable, Eq, PartialEq, Serialize, Clone)]
+#[diesel(table_name = crate::schema::order_places)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct OrderPlace {
+    /// Primary key of the record.
+    pub id: i32,
+    /// Base58 encoded public key of the market the order was placed on.
+    pub market_pubkey: String,
+    /// The Unix timestamp (in seconds) when the event occurred.
+    pub event_timestamp: i64,
+    /// Sequence number identifying the order on the book.
+    pub order_sequence_number: i64,
+    /// Client chosen u128 order id, in decimal.
+    pub client_order_id: String,
+    /// Base58 encoded public key of the order owner.
+    pub maker: String,
+    /// The price of the order expressed in ticks.
+    pub price_in_ticks: i64,
+    /// The volume of the base token (in lots) placed on the book.
+    pub base_lots_placed: i64,
+    /// Base58 encoded signature of the transaction that emitted the event.
+    pub signature: String,
+    /// The slot the transaction landed in.
+    pub slot: i64,
+    /// The market's sequence number of the instruction that emitted the event.
+    pub sequence_number: i64,
+    /// Index of the event among the events of its instruction.
+    pub event_index: i64,
+    /// Extracted below the finalized commitment, the transaction may still be rolled back.
+    pub provisional: bool,
+}
+
+/// Represents a new placed order to be inserted into the database.
+#[derive(Debug, Insertable, Queryable, Selectable, Serialize, Clone)]
+#[diesel(table_name = crate::schema::order_places)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewOrderPlace {
+    /// Base58 encoded public key of the market the order was placed on.
+    pub market_pubkey: String,
+    /// The Unix timestamp (in seconds) when the event occurred.
+    pub event_timestamp: i64,
+    /// Sequence number identifying the order on the book.
+    pub order_sequence_number: i64,
+    /// Client chosen u128 order id, in decimal.
+    pub client_order_id: String,
+    /// Base58 encoded public key of the order owner.
+    pub maker: String,
+    /// The price of the order expressed in ticks.
+    pub price_in_ticks: i64,
+    /// The volume of the base token (in lots) placed on the book.
+    pub base_lots_placed: i64,
+    /// Base58 encoded signature of the transaction that emitted the event.
+    pub signature: String,
+    /// The slot the transaction landed in.
+    pub slot: i64,
+    /// The market's sequence number of the instruction that emitted the event.
+    pub sequence_number: i64,
+    /// Index of the event among the events of its instruction.
+    pub event_index: i64,
+    /// Extracted below the finalized commitment, the transaction may still be rolled back.
+    pub provisional: bool,
+}
+
+/// Represents an order reduced or cancelled by its owner as stored in the database.
+#[derive(Debug, Queryable, Selectable, Eq, PartialEq, Serialize, Clone)]
+#[diesel(table_name = crate::schema::order_reduces)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct OrderReduce {
+    /// Primary key of the record.
+    pub id: i32,
+    /// Base58 encoded public key of the market the order is on.
+    pub market_pubkey: String,
+    /// The Unix timestamp (in seconds) when the event occurred.
+    pub event_timestamp: i64,
+    /// Sequence number identifying the order on the book.
+    pub order_sequence_number: i64,
+    /// Base58 encoded public key of the order owner.
+    pub maker: String,
+    /// The price of the order expressed in ticks.
+    pub price_in_ticks: i64,
+    /// The volume of the base token (in lots) removed from the order.
+    pub base_lots_removed: i64,
+    /// The volume of the base token (in lots) left on the book.
+    pub base_lots_remaining: i64,
+    /// Whether the whole order was cancelled.
+    pub is_full_cancel: bool,
+    /// Base58 encoded signature of the transaction that emitted the event.
+    pub signature: String,
+    /// The slot the transaction landed in.
+    pub slot: i64,
+    /// The market's sequence number of the instruction that emitted the event.
+    pub sequence_number: i64,
+    /// Index of the event among the events of its instruction.
+    pub event_index: i64,
+    /// Extracted below the finalized commitment, the transaction may still be rolled back.
+    pub provisional: bool,
+}
+
+/// Represents a new reduced order to be inserted into the database.
+#[derive(Debug, Insertable, Queryable, Selectable, Serialize, Clone)]
+#[diesel(table_name = crate::schema::order_reduces)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewOrderReduce {
+    /// Base58 encoded public key of the market the order is on.
+    pub market_pubkey: String,
+    /// The Unix timestamp (in seconds) when the event occurred.
+    pub event_timestamp: i64,
+    /// Sequence number identifying the order on the book.
+    pub order_sequence_number: i64,
+    /// Base58 encoded public key of the order owner.
+    pub maker: String,
+    /// The price of the order expressed in ticks.
+    pub price_in_ticks: i64,
+    /// The volume of the base token (in lots) removed from the order.
+    pub base_lots_removed: i64,
+    /// The volume of the base token (in lots) left on the book.
+    pub base_lots_remaining: i64,
+    /// Whether the whole order was cancelled.
+    pub is_full_cancel: bool,
+    /// Base58 encoded signature of the transaction that emitted the event.
+    pub signature: String,
+    /// The slot the transaction landed in.
+    pub slot: i64,
+    /// The market's sequence number of the instruction that emitted the event.
+    pub sequence_number: i64,
+    /// Index of the event among the events of its instruction.
+    pub event_index: i64,
+    /// Extracted below the finalized commitment, the transaction may still be rolled back.
+    pub provisional: bool,
+}
+
+/// Represents an order evicted from a full book as stored in the database.
+#[derive(Debug, Queryable, Selectable, Eq, PartialEq, Serialize, Clone)]
+#[diesel(table_name = crate::schema::order_evictions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct OrderEviction {
+    /// Primary key of the record.
+    pub id: i32,
+    /// Base58 encoded public key of the market the order was on.
+    pub market_pubkey: String,
+    /// The Unix timestamp (in seconds) when the event occurred.
+    pub event_timestamp: i64,
+    /// Sequence number identifying the order on the book.
+    pub order_sequence_number: i64,
+    /// Base58 encoded public key of the order owner.
+    pub maker: String,
+    /// The price of the order expressed in ticks.
+    pub price_in_ticks: i64,
+    /// The volume of the base token (in lots) evicted from the book.
+    pub base_lots_evicted: i64,
+    /// Base58 encoded signature of the transaction that emitted the event.
+    pub signature: String,
+    /// The slot the transaction landed in.
+    pub slot: i64,
+    /// The market's sequence number of the instruction that emitted the event.
+    pub sequence_number: i64,
+    /// Index of the event among the events of its instruction.
+    pub event_index: i64,
+    /// Extracted below the finalized commitment, the transaction may still be rolled back.
+    pub provisional: bool,
+}
+
+/// Represents a new evicted order to be inserted into the database.
+#[derive(Debug, Insertable, Queryable, Selectable, Serialize, Clone)]
+#[diesel(table_name = crate::schema::order_evictions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewOrderEviction {
+    /// Base58 encoded public key of the market the order was on.
+    pub market_pubkey: String,
+    /// The Unix timestamp (in seconds) when the event occurred.
+    pub event_timestamp: i64,
+    /// Sequence number identifying the order on the book.
+    pub order_sequence_number: i64,
+    /// Base58 encoded public key of the order owner.
+    pub maker: String,
+    /// The price of the order expressed in ticks.
+    pub price_in_ticks: i64,
+    /// The volume of the base token (in lots) evicted from the book.
+    pub base_lots_evicted: i64,
+    /// Base58 encoded signature of the transaction that emitted the event.
+    pub signature: String,
+    /// The slot the transaction landed in.
+    pub slot: i64,
+    /// The market's sequence number of the instruction that emitted the event.
+    pub sequence_number: i64,
+    /// Index of the event among the events of its instruction.
+    pub event_index: i64,
+    /// Extracted below the finalized commitment, the transaction may still be rolled back.
+    pub provisional: bool,
+}
+
+/// Represents the totals of a taker order's fills as stored in the database.
+#[derive(Debug, Queryable, Selectable, Eq, PartialEq, Serialize, Clone)]
+#[diesel(table_name = crate::schema::fill_summaries)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct FillSummary {
+    /// Primary key of the record.
+    pub id: i32,
+    /// Base58 encoded public key of the market the fills happened on.
+    pub market_pubkey: String,
+    /// The Unix timestamp (in seconds) when the event occurred.
+    pub event_timestamp: i64,
+    /// Client chosen u128 order id of the taker order, in decimal.
+    pub client_order_id: String,
+    /// The volume of the base token (in lots) filled in total.
+    pub total_base_lots_filled: i64,
+    /// The volume of the quote token (in lots) filled in total, fees included.
+    pub total_quote_lots_filled_including_fees: i64,
+    /// The fees (in quote lots) paid by the taker.
+    pub total_quote_lots_fees: i64,
+    /// Base58 encoded signature of the transaction that emitted the event.
+    pub signature: String,
+    /// The slot the transaction landed in.
+    pub slot: i64,
+    /// The market's sequence number of the instruction that emitted the event.
+    pub sequence_number: i64,
+    /// Index of the event among the events of its instruction.
+    pub event_index: i64,
+    /// Extracted below the finalized commitment, the transaction may still be rolled back.
+    pub provisional: bool,
+}
+
+/// Represents a new fill summary to be inserted into the database.
+#[derive(Debug, Insertable, Serialize, Clone)]
+#[diesel(table_name = crate::schema::fill_summaries)]
+pub struct NewFillSummary {
+    /// Base58 encoded public key of the market the fills happened on.
+    pub market_pubkey: String,
+    /// The Unix timestamp (in seconds) when the event occurred.
+    pub event_timestamp: i64,
+    /// Client chosen u128 order id of the taker order, in decimal.
+    pub client_order_id: String,
+    /// The volume of the base token (in lots) filled in total.
+    pub total_base_lots_filled: i64,
+    /// The volume of the quote token (in lots) filled in total, fees included.
+    pub total_quote_lots_filled_including_fees: i64,
+    /// The fees (in quote lots) paid by the taker.
+    pub total_quote_lots_fees: i64,
+    /// Base58 encoded signature of the transaction that emitted the event.
+    pub signature: String,
+    /// The slot the transaction landed in.
+    pub slot: i64,
+    /// The market's sequence number of the instruction that emitted the event.
+    pub sequence_number: i64,
+    /// Index of the event among the events of its instruction.
+    pub event_index: i64,
+    /// Extracted below the finalized commitment, the transaction may still be rolled back.
+    pub provisional: bool,
+}
+
+/// Represents fees collected by the market as stored in the database.
+#[derive(Debug, Queryable, Selectable, Eq, PartialEq, Serialize, Clone)]
+#[diesel(table_name = crate::schema::fee_events)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct FeeEvent {
+    /// Primary key of the record.
+    pub id: i32,
+    /// Base58 encoded public key of the market that collected the fees.
+    pub market_pubkey: String,
+    /// The Unix timestamp (in seconds) when the event occurred.
+    pub event_timestamp: i64,
+    /// The fees collected, in quote lots.
+    pub fees_collected_in_quote_lots: i64,
+    /// Base58 encoded signature of the transaction that emitted the event.
+    pub signature: String,
+    /// The slot the transaction landed in.
+    pub slot: i64,
+    /// The market's sequence number of the instruction that emitted the event.
+    pub sequence_number: i64,
+    /// Index of the event among the events of its instruction.
+    pub event_index: i64,
+    /// Extracted below the finalized commitment, the transaction may still be rolled back.
+    pub provisional: bool,
+}
+
+/// Represents new collected fees to be inserted into the database.
+#[derive(Debug, Insertable, Serialize, Clone)]
+#[diesel(table_name = crate::schema::fee_events)]
+pub struct NewFeeEvent {
+    /// Base58 encoded public key of the market that collected the fees.
+    pub market_pubkey: String,
+    /// The Unix timestamp (in seconds) when the event occurred.
+    pub event_timestamp: i64,
+    /// The fees collected, in quote lots.
+    pub fees_collected_in_quote_lots: i64,
+    /// Base58 encoded signature of the transaction that emitted the event.
+    pub signature: String,
+    /// The slot the transaction landed in.
+    pub slot: i64,
+    /// The market's sequence number of the instruction that emitted the event.
+    pub sequence_number: i64,
+    /// Index of the event among the events of its instruction.
+    pub event_index: i64,
+    /// Extracted below the finalized commitment, the transaction may still be rolled back.
+    pub provisional: bool,
+}
+
+/// Represents the expiry of an order as stored in the database.
+#[derive(Debug, Queryable, Selectable, Eq, PartialEq, Serialize, Clone)]
+#[diesel(table_name = crate::schema::time_in_force_events)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct TimeInForceEvent {
+    /// Primary key of the record.
+    pub id: i32,
+    /// Base58 encoded public key of the market the order is on.
+    pub market_pubkey: String,
+    /// The Unix timestamp (in seconds) when the event occurred.
+    pub event_timestamp: i64,
+    /// Sequence number identifying the order on the book.
+    pub order_sequence_number: i64,
+    /// Last slot the order is valid in, 0 when it has no slot expiry.
+    pub last_valid_slot: i64,
+    /// Last Unix timestamp (in seconds) the order is valid at, 0 when it has no time expiry.
+    pub last_valid_unix_timestamp_in_seconds: i64,
+    /// Base58 encoded signature of the transaction that emitted the event.
+    pub signature: String,
+    /// The slot the transaction landed in.
+    pub slot: i64,
+    /// The market's sequence number of the instruction that emitted the event.
+    pub sequence_number: i64,
+    /// Index of the event among the events of its instruction.
+    pub event_index: i64,
+    /// Extracted below the finalized commitment, the transaction may still be rolled back.
+    pub provisional: bool,
+}
+
+/// Represents a new order expiry to be inserted into the database.
+#[derive(Debug, Insertable, Serialize, Clone)]
+#[diesel(table_name = crate::schema::time_in_force_events)]
+pub struct NewTimeInForceEvent {
+    /// Base58 encoded public key of the market the order is on.
+    pub market_pubkey: String,
+    /// The Unix timestamp (in seconds) when the event occurred.
+    pub event_timestamp: i64,
+    /// Sequence number identifying the order on the book.
+    pub order_sequence_number: i64,
+    /// Last slot the order is valid in, 0 when it has no slot expiry.
+    pub last_valid_slot: i64,
+    /// Last Unix timestamp (in seconds) the order is valid at, 0 when it has no time expiry.
+    pub last_valid_unix_timestamp_in_seconds: i64,
+    /// Base58 encoded signature of the transaction that emitted the event.
+    pub signature: String,
+    /// The slot the transaction landed in.
+    pub slot: i64,
+    /// The market's sequence number of the instruction that emitted the event.
+    pub sequence_number: i64,
+    /// Index of the event among the events of its instruction.
+    pub event_index: i64,
+    /// Extracted below the finalized commitment, the transaction may still be rolled back.
+    pub provisional: bool,
+}
+
+/// The kinds of Phoenix market events, one per `MarketEventDetails` variant.
+/// Used to choose which events are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MarketEventKind {
+    /// A taker order filled a resting order, stored in `trade_fills`
+    Fill,
+    /// An order was placed on the book, stored in `order_places`
+    Place,
+    /// An order was reduced or cancelled, stored in `order_reduces`
+    Reduce,
+    /// An order was evicted from a full book, stored in `order_evictions`
+    Evict,
+    /// The totals of a taker order's fills, stored in `fill_summaries`
+    FillSummary,
+    /// Fees collected by the market, stored in `fee_events`
+    Fee,
+    /// The expiry of an order, stored in `time_in_force_events`
+    TimeInForce,
+}
+
+impl MarketEventKind {
+    /// Every kind of market event
+    pub const ALL: [Self; 7] = [
+        Self::Fill,
+        Self::Place,
+        Self::Reduce,
+        Self::Evict,
+        Self::FillSummary,
+        Self::Fee,
+        Self::TimeInForce,
+    ];
+
+    /// The kind of a decoded market event
+    pub fn of(details: &MarketEventDetails) -> Self {
+        match *details {
+            MarketEventDetails::Fill(_) => Self::Fill,
+            MarketEventDetails::Place(_) => Self::Place,
+            MarketEventDetails::Reduce(_) => Self::Reduce,
+            MarketEventDetails::Evict(_) => Self::Evict,
+            MarketEventDetails::FillSummary(_) => Self::FillSummary,
+            MarketEventDetails::Fee(_) => Self::Fee,
+            MarketEventDetails::TimeInForce(_) => Self::TimeInForce,
+        }
+    }
+
+    /// Name used on the command line, e.g. `fill_summary`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Fill => "fill",
+            Self::Place => "place",
+            Self::Reduce => "reduce",
+            Self::Evict => "evict",
+            Self::FillSummary => "fill_summary",
+            Self::Fee => "fee",
+            Self::TimeInForce => "time_in_force",
+        }
+    }
+}
+
+impl FromStr for MarketEventKind {
+    type Err = VybeDatabaseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| VybeDatabaseError::InvalidEventKind(s.to_owned()))
+    }
+}
+
+impl fmt::Display for MarketEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Any market event to be inserted into the database, converted from a `PhoenixEvent`
+#[derive(Debug)]
+pub enum NewMarketEvent {
+    /// A new trade fill
+    Fill(NewTradeFill),
+    /// A new placed order
+    Place(NewOrderPlace),
+    /// A new reduced order
+    Reduce(NewOrderReduce),
+    /// A new evicted order
+    Evict(NewOrderEviction),
+    /// A new fill summary
+    FillSummary(NewFillSummary),
+    /// New collected fees
+    Fee(NewFeeEvent),
+    /// A new order expiry
+    TimeInForce(NewTimeInForceEvent),
+}
+
+/// Market events to be inserted into the database, grouped by table so each table
+/// is written with a single batch insert.
+#[derive(Debug, Default)]
+pub struct NewMarketEvents {
+    /// New trade fills
+    pub fills: Vec<NewTradeFill>,
+    /// New placed orders
+    pub places: Vec<NewOrderPlace>,
+    /// New reduced orders
+    pub reduces: Vec<NewOrderReduce>,
+    /// New evicted orders
+    pub evictions: Vec<NewOrderEviction>,
+    /// New fill summaries
+    pub fill_summaries: Vec<NewFillSummary>,
+    /// New collected fees
+    pub fees: Vec<NewFeeEvent>,
+    /// New order expiries
+    pub time_in_force: Vec<NewTimeInForceEvent>,
+}
+
+impl NewMarketEvents {
+    /// Adds an event to the batch of its table
+    pub fn push(&mut self, event: NewMarketEvent) {
+        match event {
+            NewMarketEvent::Fill(fill) => self.fills.push(fill),
+            NewMarketEvent::Place(place) => self.places.push(place),
+            NewMarketEvent::Reduce(reduce) => self.reduces.push(reduce),
+            NewMarketEvent::Evict(eviction) => self.evictions.push(eviction),
+            NewMarketEvent::FillSummary(summary) => self.fill_summaries.push(summary),
+            NewMarketEvent::Fee(fee) => self.fees.push(fee),
+            NewMarketEvent::TimeInForce(time_in_force) => self.time_in_force.push(time_in_force),
+        }
+    }
+
+    /// Number of events of every kind
+    pub fn len(&self) -> usize {
+        self.fills.len()
+            + self.places.len()
+            + self.reduces.len()
+            + self.evictions.len()
+            + self.fill_summaries.len()
+            + self.fees.len()
+            + self.time_in_force.len()
+    }
+
+    /// Whether there are no events at all
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Highest sequence number of the instructions the events were emitted by
+    pub fn last_sequence_number(&self) -> Option<i64> {
+        self.fills
+            .iter()
+            .map(|fill| fill.sequence_number)
+            .chain(self.places.iter().map(|place| place.sequence_number))
+            .chain(self.reduces.iter().map(|reduce| reduce.sequence_number))
+            .chain(
+                self.evictions
+                    .iter()
+                    .map(|eviction| eviction.sequence_number),
+            )
+            .chain(
+                self.fill_summaries
+                    .iter()
+                    .map(|summary| summary.sequence_number),
+            )
+            .chain(self.fees.iter().map(|fee| fee.sequence_number))
+            .chain(
+                self.time_in_force
+                    .iter()
+                    .map(|time_in_force| time_in_force.sequence_number),
+            )
+            .max()
+    }
+
+    /// The events emitted by instructions after `sequence_number`
+    pub fn newer_than(&self, sequence_number: i64) -> Self {
+        /// Clones the rows with a sequence number above `sequence_number`
+        fn newer<T: Clone>(rows: &[T], sequence_number: i64, of: impl Fn(&T) -> i64) -> Vec<T> {
+            rows.iter()
+                .filter(|row| of(row) > sequence_number)
+                .cloned()
+                .collect()
+        }
+
+        Self {
+            fills: newer(&self.fills, sequence_number, |fill| fill.sequence_number),
+            places: newer(&self.places, sequence_number, |place| place.sequence_number),
+            reduces: newer(&self.reduces, sequence_number, |reduce| {
+                reduce.sequence_number
+            }),
+            evictions: newer(&self.evictions, sequence_number, |eviction| {
+                eviction.sequence_number
+            }),
+            fill_summaries: newer(&self.fill_summaries, sequence_number, |summary| {
+                summary.sequence_number
+            }),
+            fees: newer(&self.fees, sequence_number, |fee| fee.sequence_number),
+            time_in_force: newer(&self.time_in_force, sequence_number, |time_in_force| {
+                time_in_force.sequence_number
+            }),
+        }
+    }
+
+    /// Marks every event as provisional, for events extracted below the finalized commitment
+    pub fn mark_provisional(&mut self) {
+        for fill in &mut self.fills {
+            fill.provisional = true;
+        }
+        for place in &mut self.places {
+            place.provisional = true;
+        }
+        for reduce in &mut self.reduces {
+            reduce.provisional = true;
+        }
+        for eviction in &mut self.evictions {
+            eviction.provisional = true;
+        }
+        for summary in &mut self.fill_summaries {
+            summary.provisional = true;
+        }
+        for fee in &mut self.fees {
+            fee.provisional = true;
+        }
+        for time_in_force in &mut self.time_in_force {
+            time_in_force.provisional = true;
+        }
+    }
+
+    /// Sets the quote lots of every fill from its price and size, `units` are those of the
+    /// market the events were extracted from
+    pub fn derive_quote_lots(&mut self, units: &MarketUnits) {
+        for fill in &mut self.fills {
+            fill.quote_lots_filled = units.quote_lots(fill.price_in_ticks, fill.base_lots_filled);
+        }
+    }
+}
+
+impl FromIterator<NewMarketEvent> for NewMarketEvents {
+    fn from_iter<I: IntoIterator<Item = NewMarketEvent>>(iter: I) -> Self {
+        let mut events = Self::default();
+        for event in iter {
+            events.push(event);
+        }
+        events
+    }
+}
+
+/// Represents the on-chain metadata of a Phoenix market as stored in the database,
+/// read from the market account's header when the daemon starts.
+#[derive(Debug, Queryable, Selectable, Eq, PartialEq, Serialize, Clone)]
+#[diesel(table_name = crate::schema::markets)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Market {
+    /// Base58 encoded public key of the market.
+    pub market_pubkey: String,
+    /// Base58 encoded mint of the base token.
+    pub base_mint: String,
+    /// Base58 encoded mint of the quote token.
+    pub quote_mint: String,
+    /// Decimals of the base mint.
+    pub base_decimals: i32,
+    /// Decimals of the quote mint.
+    pub quote_decimals: i32,
+    /// Number of base atoms in one base lot.
+    pub base_lot_size: i64,
+    /// Number of quote atoms in one quote lot.
+    pub quote_lot_size: i64,
+    /// Price increment of one tick, in quote atoms per base unit.
+    pub tick_size_in_quote_atoms_per_base_unit: i64,
+    /// Number of raw base units (whole base tokens) in one base unit,
+    /// greater than 1 for markets of low priced tokens.
+    pub raw_base_units_per_base_unit: i32,
+    /// Taker fee in basis points.
+    pub taker_fee_bps: i32,
+}
+
+impl Market {
+    /// Converts the market's prices and sizes to UI units
+    pub fn units(&self) -> MarketUnits {
+        MarketUnits::from(self)
+    }
+}
+
+/// Represents the metadata of a market to be inserted or updated in the database.
+#[derive(Debug, Insertable, AsChangeset, Eq, PartialEq, Clone)]
+#[diesel(table_name = crate::schema::markets)]
+pub struct NewMarket {
+    /// Base58 encoded public key of the market.
+    pub market_pubkey: String,
+    /// Base58 encoded mint of the base token.
+    pub base_mint: String,
+    /// Base58 encoded mint of the quote token.
+    pub quote_mint: String,
+    /// Decimals of the base mint.
+    pub base_decimals: i32,
+    /// Decimals of the quote mint.
+    pub quote_decimals: i32,
+    /// Number of base atoms in one base lot.
+    pub base_lot_size: i64,
+    /// Number of quote atoms in one quote lot.
+    pub quote_lot_size: i64,
+    /// Price increment of one tick, in quote atoms per base unit.
+    pub tick_size_in_quote_atoms_per_base_unit: i64,
+    /// Number of raw base units (whole base tokens) in one base unit.
+    pub raw_base_units_per_base_unit: i32,
+    /// Taker fee in basis points.
+    pub taker_fee_bps: i32,
+}
+
+/// Represents the signature cursor of a market as stored in the database.
+/// Used to resume extraction exactly where the daemon previously stopped.
+#[derive(Debug, Queryable, Selectable, Eq, PartialEq, Serialize, Clone)]
+#[diesel(table_name = crate::schema::sync_checkpoints)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct SyncCheckpoint {
+    /// Base58 encoded public key of the market this cursor belongs to.
+    pub market_pubkey: String,
+    /// The newest transaction signature that has been fully processed.
+    pub last_signature: String,
+    /// The slot the newest processed transaction landed in.
+    pub last_slot: i64,
+}
+
+/// Represents a signature cursor to be inserted or updated in the database.
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = crate::schema::sync_checkpoints)]
+pub struct NewSyncCheckpoint {
+    /// Base58 encoded public key of the market this cursor belongs to.
+    pub market_pubkey: String,
+    /// The newest transaction signature that has been fully processed.
+    pub last_signature: String,
+    /// The slot the newest processed transaction landed in.
+    pub last_slot: i64,
+}
+
+/// Represents the progress of a historical backfill as stored in the database.
+/// A backfill is identified by its market and the range it was started with.
+#[derive(Debug, Queryable, Selectable, Eq, PartialEq, Serialize, Clone)]
+#[diesel(table_name = crate::schema::backfill_progress)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct BackfillProgress {
+    /// Base58 encoded public key of the market being backfilled.
+    pub market_pubkey: String,
+    /// Oldest bound of the range, e.g. `slot:1000` or `time:1740956436`.
+    pub range_from: String,
+    /// Newest bound of the range, e.g. `slot:2000` or `time:1740999999`.
+    pub range_to: String,
+    /// Oldest signature processed so far, the backfill resumes just before it.
+    pub before_signature: Option<String>,
+    /// Number of signatures inside the range that have been decoded.
+    pub signatures_processed: i64,
+    /// Number of trade fills the backfill has inserted.
+    pub fills_inserted: i64,
+    /// Whether the backfill reached the oldest bound of its range.
+    pub completed: bool,
+}
+
+/// Represents backfill progress to be inserted or updated in the database.
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = crate::schema::backfill_progress)]
+pub struct NewBackfillProgress {
+    /// Base58 encoded public key of the market being backfilled.
+    pub market_pubkey: String,
+    /// Oldest bound of the range, e.g. `slot:1000` or `time:1740956436`.
+    pub range_from: String,
+    /// Newest bound of the range, e.g. `slot:2000` or `time:1740999999`.
+    pub range_to: String,
+    /// Oldest signature processed so far, the backfill resumes just before it.
+    pub before_signature: Option<String>,
+    /// Number of signatures inside the range that have been decoded.
+    pub signatures_processed: i64,
+    /// Number of trade fills the backfill has inserted.
+    pub fills_inserted: i64,
+    /// Whether the backfill reached the oldest bound of its range.
+    pub completed: bool,
+}
+
+/// A transaction whose events are still provisional, and the slot it was extracted from.
+/// Used by the reconciliation pass to confirm or roll back the transaction's events.
+#[derive(Debug, Queryable, Eq, PartialEq, Hash, Clone)]
+pub struct ProvisionalTransaction {
+    /// Base58 encoded signature of the transaction.
+    pub signature: String,
+    /// The slot the transaction landed in when it was extracted.
+    pub slot: i64,
+}
+
+/// The stage at which a transaction failed, stored in `dead_letters.error_kind`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeadLetterKind {
+    /// The transaction couldn't be fetched for a reason retrying right away won't fix
+    Fetch,
+    /// The transaction was fetched but its Phoenix events couldn't be decoded
+    Decode,
+    /// The transaction's events were decoded but couldn't be inserted
+    Insert,
+}
+
+impl DeadLetterKind {
+    /// Every stage a transaction can fail at
+    pub const ALL: [Self; 3] = [Self::Fetch, Self::Decode, Self::Insert];
+
+    /// Name stored in the database, e.g. `decode`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Fetch => "fetch",
+            Self::Decode => "decode",
+            Self::Insert => "insert",
+        }
+    }
+}
+
+impl FromStr for DeadLetterKind {
+    type Err = VybeDatabaseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| VybeDatabaseError::InvalidDeadLetterKind(s.to_owned()))
+    }
+}
+
+impl fmt::Display for DeadLetterKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Represents a transaction that failed to be extracted as stored in the database.
+/// It stays until a retry stores its events or it is discarded.
+#[derive(Debug, Queryable, Selectable, Eq, PartialEq, Serialize, Clone)]
+#[diesel(table_name = crate::schema::dead_letters)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct DeadLetter {
+    /// Primary key of the dead letter.
+    pub id: i32,
+    /// Base58 encoded public key of the market the transaction was extracted for.
+    pub market_pubkey: String,
+    /// Base58 encoded signature of the transaction.
+    pub signature: String,
+    /// Stage that failed, see `DeadLetterKind`.
+    pub error_kind: String,
+    /// Error of the latest attempt.
+    pub error_message: String,
+    /// Failed attempts since the transaction was recorded or requeued.
+    pub attempts: i32,
+}
+
+/// Represents a failed transaction to be recorded, recording it again bumps its attempts.
+#[derive(Debug, Insertable, Clone, PartialEq, Eq)]
+#[diesel(table_name = crate::schema::dead_letters)]
+pub struct NewDeadLetter {
+    /// Base58 encoded public key of the market the transaction was extracted for.
+    pub market_pubkey: String,
+    /// Base58 encoded signature of the transaction.
+    pub signature: String,
+    /// Stage that failed, see `DeadLetterKind`.
+    pub error_kind: String,
+    /// Why it failed.
+    pub error_message: String,
+}
+
+impl NewDeadLetter {
+    /// Creates a dead letter for a transaction of `market_pubkey` that failed at `kind`
+    pub fn new(
+        market_pubkey: &str,
+        signature: &str,
+        kind: DeadLetterKind,
+        error_message: impl Into<String>,
+    ) -> Self {
+        Self {
+            market_pubkey: market_pubkey.to_owned(),
+            signature: signature.to_owned(),
+            error_kind: kind.as_str().to_owned(),
+            error_message: error_message.into(),
+        }
+    }
+}
+
+/// Where a gap in a market's sequence numbers stands, stored in `sequence_gaps.status`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SequenceGapStatus {
+    /// Detected, waiting for its targeted backfill
+    Open,
+    /// The backfill found every missing sequence number
+    Closed,
+    /// The backfill ran but sequence numbers are still missing
+    Unresolved,
+}
+
+impl SequenceGapStatus {
+    /// Every status a gap can have
+    pub const ALL: [Self; 3] = [Self::Open, Self::Closed, Self::Unresolved];
+
+    /// Name stored in the database, e.g. `unresolved`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Open => "open",
+            Self::Closed => "closed",
+            Self::Unresolved => "unresolved",
+        }
+    }
+}
+
+impl FromStr for SequenceGapStatus {
+    type Err = VybeDatabaseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|status| status.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| VybeDatabaseError::InvalidSequenceGapStatus(s.to_owned()))
+    }
+}
+
+impl fmt::Display for SequenceGapStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Represents a range of sequence numbers missing from a market's events as stored in the database.
+#[derive(Debug, Queryable, Selectable, Eq, PartialEq, Serialize, Clone)]
+#[diesel(table_name = crate::schema::sequence_gaps)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct SequenceGap {
+    /// Primary key of the gap.
+    pub id: i32,
+    /// Base58 encoded public key of the market.
+    pub market_pubkey: String,
+    /// First missing sequence number.
+    pub first_missing: i64,
+    /// Last missing sequence number.
+    pub last_missing: i64,
+    /// Slot of the sequence number seen before the gap.
+    pub from_slot: i64,
+    /// Slot of the sequence number seen after the gap.
+    pub to_slot: i64,
+    /// See `SequenceGapStatus`.
+    pub status: String,
+}
+
+/// Represents a newly detected gap to be inserted into the database, it starts open.
+#[derive(Debug, Insertable, Clone, PartialEq, Eq)]
+#[diesel(table_name = crate::schema::sequence_gaps)]
+pub struct NewSequenceGap {
+    /// Base58 encoded public key of the market.
+    pub market_pubkey: String,
+    /// First missing sequence number.
+    pub first_missing: i64,
+    /// Last missing sequence number.
+    pub last_missing: i64,
+    /// Slot of the sequence number seen before the gap.
+    pub from_slot: i64,
+    /// Slot of the sequence number seen after the gap.
+    pub to_slot: i64,
+}
+
+/// Represents a snapshot of a market's order book as stored in the database,
+/// its price levels are stored in `orderbook_levels`.
+#[derive(Debug, Queryable, Selectable, Eq, PartialEq, Serialize, Clone)]
+#[diesel(table_name = crate::schema::orderbook_snapshots)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct OrderbookSnapshot {
+    /// Primary key of the snapshot.
+    pub id: i32,
+    /// Base58 encoded public key of the market.
+    pub market_pubkey: String,
+    /// The slot the market account was read at.
+    pub slot: i64,
+    /// The Unix timestamp (in seconds) when the snapshot was taken.
+    pub snapshot_timestamp: i64,
+}
+
+/// Represents a price level of an order book snapshot as stored in the database.
+#[derive(Debug, Queryable, Selectable, Eq, PartialEq, Clone)]
+#[diesel(table_name = crate::schema::orderbook_levels)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct OrderbookLevel {
+    /// The snapshot the level belongs to.
+    pub snapshot_id: i32,
+    /// Side of the book, `bid` or `ask`.
+    pub side: String,
+    /// Distance from the best price in levels, 0 is the best bid or ask.
+    pub level: i32,
+    /// Price of the level in ticks.
+    pub price_in_ticks: i64,
+    /// Resting size at the price in base lots.
+    pub size_in_base_lots: i64,
+}
+
+/// Represents a price level of an order book snapshot to be inserted into the database.
+#[derive(Debug, Insertable)]
+#[diesel(table_name = crate::schema::orderbook_levels)]
+pub struct NewOrderbookLevel {
+    /// The snapshot the level belongs to.
+    pub snapshot_id: i32,
+    /// Side of the book, `bid` or `ask`.
+    pub side: String,
+    /// Distance from the best price in levels, 0 is the best bid or ask.
+    pub level: i32,
+    /// Price of the level in ticks.
+    pub price_in_ticks: i64,
+    /// Resting size at the price in base lots.
+    pub size_in_base_lots: i64,
+}
+
+/// The resting size at one price of an order book.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize)]
+pub struct PriceLevel {
+    /// Price in ticks.
+    pub price_in_ticks: i64,
+    /// Resting size in base lots.
+    pub size_in_base_lots: i64,
+}
+
+/// An order book snapshot with its price levels, best price first.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct Orderbook {
+    /// The stored snapshot.
+    #[serde(flatten)]
+    pub snapshot: OrderbookSnapshot,
+    /// Bids, highest price first.
+    pub bids: Vec<PriceLevel>,
+    /// Asks, lowest price first.
+    pub asks: Vec<PriceLevel>,
+}
+
+/// Represents a new order book snapshot to be inserted into the database with its price levels.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct NewOrderbookSnapshot {
+    /// Base58 encoded public key of the market.
+    pub market_pubkey: String,
+    /// The slot the market account was read at.
+    pub slot: i64,
+    /// The Unix timestamp (in seconds) when the snapshot was taken.
+    pub snapshot_timestamp: i64,
+    /// Bids, highest price first.
+    pub bids: Vec<PriceLevel>,
+    /// Asks, lowest price first.
+    pub asks: Vec<PriceLevel>,
+}
+
+impl NewOrderbookSnapshot {
+    /// The rows of the price levels once the snapshot is stored as `snapshot_id`
+    pub fn levels(&self, snapshot_id: i32) -> Vec<NewOrderbookLevel> {
+        let side = |side: Side, levels: &[PriceLevel]| {
+            levels
+                .iter()
+                .zip(0_i32..)
+                .map(|(level, index)| NewOrderbookLevel {
+                    snapshot_id,
+                    side: side_name(side).to_owned(),
+                    level: index,
+                    price_in_ticks: level.price_in_ticks,
+                    size_in_base_lots: level.size_in_base_lots,
+                })
+                .collect::<Vec<NewOrderbookLevel>>()
+        };
+        let mut levels = side(Side::Bid, &self.bids);
+        levels.extend(side(Side::Ask, &self.asks));
+        levels
+    }
+}
+
+impl Orderbook {
+    /// Puts a snapshot back together with its stored price levels
+    pub fn new(snapshot: OrderbookSnapshot, mut levels: Vec<OrderbookLevel>) -> Self {
+        levels.sort_by_key(|level| level.level);
+        let side = |side: Side| {
+            levels
+                .iter()
+                .filter(|level| level.side == side_name(side))
+                .map(|level| PriceLevel {
+                    price_in_ticks: level.price_in_ticks,
+                    size_in_base_lots: level.size_in_base_lots,
+                })
+                .collect::<Vec<PriceLevel>>()
+        };
+        Self {
+            bids: side(Side::Bid),
+            asks: side(Side::Ask),
+            snapshot,
+        }
+    }
+}
+
+/// Name of an order side as stored in the database, `bid` or `ask`
+fn side_name(side: Side) -> &'static str {
+    match side {
+        Side::Bid => "bid",
+        Side::Ask => "ask",
+    }
 }
 
 impl TryFrom<PhoenixEvent> for NewTradeFill {
@@ -49,8 +1168,177 @@ impl TryFrom<PhoenixEvent> for NewTradeFill {
                 event_timestamp: event.timestamp,
                 price_in_ticks: fill.price_in_ticks as i64,
                 base_lots_filled: fill.base_lots_filled as i64,
+                market_pubkey: event.market.to_string(),
+                signature: event.signature.to_string(),
+                slot: event.slot as i64,
+                sequence_number: event.sequence_number as i64,
+                event_index: event.event_index as i64,
+                provisional: false,
+                order_sequence_number: fill.order_sequence_number as i64,
+                maker: fill.maker.to_string(),
+                taker: fill.taker.to_string(),
+                side_filled: side_name(fill.side_filled).to_owned(),
+                quote_lots_filled: None,
             }),
             _ => Err(VybeDatabaseError::InvalidPhoenixEvent),
         }
     }
 }
+
+impl TryFrom<PhoenixEvent> for NewOrderPlace {
+    type Error = VybeDatabaseError;
+
+    fn try_from(event: PhoenixEvent) -> Result<Self, Self::Error> {
+        match event.details {
+            MarketEventDetails::Place(place) => Ok(NewOrderPlace {
+                market_pubkey: event.market.to_string(),
+                event_timestamp: event.timestamp,
+                order_sequence_number: place.order_sequence_number as i64,
+                client_order_id: place.client_order_id.to_string(),
+                maker: place.maker.to_string(),
+                price_in_ticks: place.price_in_ticks as i64,
+                base_lots_placed: place.base_lots_placed as i64,
+                signature: event.signature.to_string(),
+                slot: event.slot as i64,
+                sequence_number: event.sequence_number as i64,
+                event_index: event.event_index as i64,
+                provisional: false,
+            }),
+            _ => Err(VybeDatabaseError::InvalidPhoenixEvent),
+        }
+    }
+}
+
+impl TryFrom<PhoenixEvent> for NewOrderReduce {
+    type Error = VybeDatabaseError;
+
+    fn try_from(event: PhoenixEvent) -> Result<Self, Self::Error> {
+        match event.details {
+            MarketEventDetails::Reduce(reduce) => Ok(NewOrderReduce {
+                market_pubkey: event.market.to_string(),
+                event_timestamp: event.timestamp,
+                order_sequence_number: reduce.order_sequence_number as i64,
+                maker: reduce.maker.to_string(),
+                price_in_ticks: reduce.price_in_ticks as i64,
+                base_lots_removed: reduce.base_lots_removed as i64,
+                base_lots_remaining: reduce.base_lots_remaining as i64,
+                is_full_cancel: reduce.is_full_cancel,
+                signature: event.signature.to_string(),
+                slot: event.slot as i64,
+                sequence_number: event.sequence_number as i64,
+                event_index: event.event_index as i64,
+                provisional: false,
+            }),
+            _ => Err(VybeDatabaseError::InvalidPhoenixEvent),
+        }
+    }
+}
+
+impl TryFrom<PhoenixEvent> for NewOrderEviction {
+    type Error = VybeDatabaseError;
+
+    fn try_from(event: PhoenixEvent) -> Result<Self, Self::Error> {
+        match event.details {
+            MarketEventDetails::Evict(evict) => Ok(NewOrderEviction {
+                market_pubkey: event.market.to_string(),
+                event_timestamp: event.timestamp,
+                order_sequence_number: evict.order_sequence_number as i64,
+                maker: evict.maker.to_string(),
+                price_in_ticks: evict.price_in_ticks as i64,
+                base_lots_evicted: evict.base_lots_evicted as i64,
+                signature: event.signature.to_string(),
+                slot: event.slot as i64,
+                sequence_number: event.sequence_number as i64,
+                event_index: event.event_index as i64,
+                provisional: false,
+            }),
+            _ => Err(VybeDatabaseError::InvalidPhoenixEvent),
+        }
+    }
+}
+
+impl TryFrom<PhoenixEvent> for NewFillSummary {
+    type Error = VybeDatabaseError;
+
+    fn try_from(event: PhoenixEvent) -> Result<Self, Self::Error> {
+        match event.details {
+            MarketEventDetails::FillSummary(summary) => Ok(NewFillSummary {
+                market_pubkey: event.market.to_string(),
+                event_timestamp: event.timestamp,
+                client_order_id: summary.client_order_id.to_string(),
+                total_base_lots_filled: summary.total_base_filled as i64,
+                total_quote_lots_filled_including_fees: summary.total_quote_filled_including_fees
+                    as i64,
+                total_quote_lots_fees: summary.total_quote_fees as i64,
+                signature: event.signature.to_string(),
+                slot: event.slot as i64,
+                sequence_number: event.sequence_number as i64,
+                event_index: event.event_index as i64,
+                provisional: false,
+            }),
+            _ => Err(VybeDatabaseError::InvalidPhoenixEvent),
+        }
+    }
+}
+
+impl TryFrom<PhoenixEvent> for NewFeeEvent {
+    type Error = VybeDatabaseError;
+
+    fn try_from(event: PhoenixEvent) -> Result<Self, Self::Error> {
+        match event.details {
+            MarketEventDetails::Fee(fee) => Ok(NewFeeEvent {
+                market_pubkey: event.market.to_string(),
+                event_timestamp: event.timestamp,
+                fees_collected_in_quote_lots: fee.fees_collected_in_quote_lots as i64,
+                signature: event.signature.to_string(),
+                slot: event.slot as i64,
+                sequence_number: event.sequence_number as i64,
+                event_index: event.event_index as i64,
+                provisional: false,
+            }),
+            _ => Err(VybeDatabaseError::InvalidPhoenixEvent),
+        }
+    }
+}
+
+impl TryFrom<PhoenixEvent> for NewTimeInForceEvent {
+    type Error = VybeDatabaseError;
+
+    fn try_from(event: PhoenixEvent) -> Result<Self, Self::Error> {
+        match event.details {
+            MarketEventDetails::TimeInForce(time_in_force) => Ok(NewTimeInForceEvent {
+                market_pubkey: event.market.to_string(),
+                event_timestamp: event.timestamp,
+                order_sequence_number: time_in_force.order_sequence_number as i64,
+                last_valid_slot: time_in_force.last_valid_slot as i64,
+                last_valid_unix_timestamp_in_seconds: time_in_force
+                    .last_valid_unix_timestamp_in_seconds
+                    as i64,
+                signature: event.signature.to_string(),
+                slot: event.slot as i64,
+                sequence_number: event.sequence_number as i64,
+                event_index: event.event_index as i64,
+                provisional: false,
+            }),
+            _ => Err(VybeDatabaseError::InvalidPhoenixEvent),
+        }
+    }
+}
+
+impl TryFrom<PhoenixEvent> for NewMarketEvent {
+    type Error = VybeDatabaseError;
+
+    fn try_from(event: PhoenixEvent) -> Result<Self, Self::Error> {
+        match MarketEventKind::of(&event.details) {
+            MarketEventKind::Fill => NewTradeFill::try_from(event).map(Self::Fill),
+            MarketEventKind::Place => NewOrderPlace::try_from(event).map(Self::Place),
+            MarketEventKind::Reduce => NewOrderReduce::try_from(event).map(Self::Reduce),
+            MarketEventKind::Evict => NewOrderEviction::try_from(event).map(Self::Evict),
+            MarketEventKind::FillSummary => NewFillSummary::try_from(event).map(Self::FillSummary),
+            MarketEventKind::Fee => NewFeeEvent::try_from(event).map(Self::Fee),
+            MarketEventKind::TimeInForce => {
+                NewTimeInForceEvent::try_from(event).map(Self::TimeInForce)
+            }
+        }
+    }
+}
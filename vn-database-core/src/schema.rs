@@ -1,10 +1,228 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    backfill_progress (market_pubkey, range_from, range_to) {
+        market_pubkey -> Text,
+        range_from -> Text,
+        range_to -> Text,
+        before_signature -> Nullable<Text>,
+        signatures_processed -> Int8,
+        fills_inserted -> Int8,
+        completed -> Bool,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    dead_letters (id) {
+        id -> Int4,
+        market_pubkey -> Text,
+        signature -> Text,
+        error_kind -> Text,
+        error_message -> Text,
+        attempts -> Int4,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    fee_events (id) {
+        id -> Int4,
+        market_pubkey -> Text,
+        event_timestamp -> Int8,
+        fees_collected_in_quote_lots -> Int8,
+        signature -> Text,
+        slot -> Int8,
+        sequence_number -> Int8,
+        event_index -> Int8,
+        provisional -> Bool,
+    }
+}
+
+diesel::table! {
+    fill_summaries (id) {
+        id -> Int4,
+        market_pubkey -> Text,
+        event_timestamp -> Int8,
+        client_order_id -> Text,
+        total_base_lots_filled -> Int8,
+        total_quote_lots_filled_including_fees -> Int8,
+        total_quote_lots_fees -> Int8,
+        signature -> Text,
+        slot -> Int8,
+        sequence_number -> Int8,
+        event_index -> Int8,
+        provisional -> Bool,
+    }
+}
+
+diesel::table! {
+    markets (market_pubkey) {
+        market_pubkey -> Text,
+        base_mint -> Text,
+        quote_mint -> Text,
+        base_decimals -> Int4,
+        quote_decimals -> Int4,
+        base_lot_size -> Int8,
+        quote_lot_size -> Int8,
+        tick_size_in_quote_atoms_per_base_unit -> Int8,
+        raw_base_units_per_base_unit -> Int4,
+        taker_fee_bps -> Int4,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    order_evictions (id) {
+        id -> Int4,
+        market_pubkey -> Text,
+        event_timestamp -> Int8,
+        order_sequence_number -> Int8,
+        maker -> Text,
+        price_in_ticks -> Int8,
+        base_lots_evicted -> Int8,
+        signature -> Text,
+        slot -> Int8,
+        sequence_number -> Int8,
+        event_index -> Int8,
+        provisional -> Bool,
+    }
+}
+
+diesel::table! {
+    order_places (id) {
+        id -> Int4,
+        market_pubkey -> Text,
+        event_timestamp -> Int8,
+        order_sequence_number -> Int8,
+        client_order_id -> Text,
+        maker -> Text,
+        price_in_ticks -> Int8,
+        base_lots_placed -> Int8,
+        signature -> Text,
+        slot -> Int8,
+        sequence_number -> Int8,
+        event_index -> Int8,
+        provisional -> Bool,
+    }
+}
+
+diesel::table! {
+    order_reduces (id) {
+        id -> Int4,
+        market_pubkey -> Text,
+        event_timestamp -> Int8,
+        order_sequence_number -> Int8,
+        maker -> Text,
+        price_in_ticks -> Int8,
+        base_lots_removed -> Int8,
+        base_lots_remaining -> Int8,
+        is_full_cancel -> Bool,
+        signature -> Text,
+        slot -> Int8,
+        sequence_number -> Int8,
+        event_index -> Int8,
+        provisional -> Bool,
+    }
+}
+
+diesel::table! {
+    orderbook_levels (snapshot_id, side, level) {
+        snapshot_id -> Int4,
+        side -> Text,
+        level -> Int4,
+        price_in_ticks -> Int8,
+        size_in_base_lots -> Int8,
+    }
+}
+
+diesel::table! {
+    orderbook_snapshots (id) {
+        id -> Int4,
+        market_pubkey -> Text,
+        slot -> Int8,
+        snapshot_timestamp -> Int8,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    sequence_gaps (id) {
+        id -> Int4,
+        market_pubkey -> Text,
+        first_missing -> Int8,
+        last_missing -> Int8,
+        from_slot -> Int8,
+        to_slot -> Int8,
+        status -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    sync_checkpoints (market_pubkey) {
+        market_pubkey -> Text,
+        last_signature -> Text,
+        last_slot -> Int8,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    time_in_force_events (id) {
+        id -> Int4,
+        market_pubkey -> Text,
+        event_timestamp -> Int8,
+        order_sequence_number -> Int8,
+        last_valid_slot -> Int8,
+        last_valid_unix_timestamp_in_seconds -> Int8,
+        signature -> Text,
+        slot -> Int8,
+        sequence_number -> Int8,
+        event_index -> Int8,
+        provisional -> Bool,
+    }
+}
+
 diesel::table! {
     trade_fills (id) {
         id -> Int4,
         event_timestamp -> Int8,
         price_in_ticks -> Int8,
         base_lots_filled -> Int8,
+        market_pubkey -> Text,
+        signature -> Text,
+        slot -> Int8,
+        sequence_number -> Int8,
+        event_index -> Int8,
+        provisional -> Bool,
+        order_sequence_number -> Nullable<Int8>,
+        maker -> Nullable<Text>,
+        taker -> Nullable<Text>,
+        side_filled -> Nullable<Text>,
+        quote_lots_filled -> Nullable<Int8>,
     }
 }
+
+diesel::joinable!(orderbook_levels -> orderbook_snapshots (snapshot_id));
+diesel::joinable!(orderbook_snapshots -> markets (market_pubkey));
+diesel::joinable!(trade_fills -> markets (market_pubkey));
+
+diesel::allow_tables_to_appear_in_same_query!(
+    backfill_progress,
+    dead_letters,
+    fee_events,
+    fill_summaries,
+    markets,
+    order_evictions,
+    order_places,
+    order_reduces,
+    orderbook_levels,
+    orderbook_snapshots,
+    sequence_gaps,
+    sync_checkpoints,
+    time_in_force_events,
+    trade_fills,
+);
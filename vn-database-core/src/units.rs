@@ -0,0 +1,184 @@
+//! Exact conversion of Phoenix prices and sizes to UI units. Events store prices in ticks and
+//! sizes in base lots, the market's tick size, lot size and token decimals turn them into
+//! decimal prices (quote tokens per base token), base quantities (base tokens) and quote
+//! notionals (quote tokens). Every conversion is integer arithmetic followed by a decimal
+//! scale, so no float rounding is involved.
+
+use {
+    crate::models::{Market, NewMarket},
+    rust_decimal::Decimal,
+};
+
+/// Converts the raw prices and sizes of one market to UI units
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarketUnits {
+    /// Price increment of one tick, in quote atoms per base unit
+    tick_size_in_quote_atoms_per_base_unit: i64,
+    /// Number of base atoms in one base lot
+    base_lot_size: i64,
+    /// Number of quote atoms in one quote lot
+    quote_lot_size: i64,
+    /// Decimals of the base mint
+    base_decimals: u32,
+    /// Decimals of the quote mint
+    quote_decimals: u32,
+    /// Number of raw base units (whole base tokens) in one base unit
+    raw_base_units_per_base_unit: i64,
+}
+
+impl MarketUnits {
+    /// Creates a converter from the market's on-chain metadata
+    pub fn new(
+        tick_size_in_quote_atoms_per_base_unit: i64,
+        base_lot_size: i64,
+        quote_lot_size: i64,
+        base_decimals: i32,
+        quote_decimals: i32,
+        raw_base_units_per_base_unit: i32,
+    ) -> Self {
+        Self {
+            tick_size_in_quote_atoms_per_base_unit,
+            base_lot_size,
+            quote_lot_size,
+            base_decimals: u32::try_from(base_decimals).unwrap_or_default(),
+            quote_decimals: u32::try_from(quote_decimals).unwrap_or_default(),
+            raw_base_units_per_base_unit: i64::from(raw_base_units_per_base_unit.max(1)),
+        }
+    }
+
+    /// `value / 10^scale / raw_base_units_per_base_unit`, `None` if it doesn't fit a `Decimal`
+    fn scale(&self, value: i128, scale: u32) -> Option<Decimal> {
+        Decimal::try_from_i128_with_scale(value, scale)
+            .ok()?
+            .checked_div(Decimal::from(self.raw_base_units_per_base_unit))
+            .map(|value| value.normalize())
+    }
+
+    /// The price of one tick in quote tokens per base token
+    pub fn tick_size(&self) -> Option<Decimal> {
+        self.price(1)
+    }
+
+    /// Converts a price in ticks to quote tokens per base token,
+    /// `None` if it doesn't fit a `Decimal`
+    pub fn price(&self, price_in_ticks: i64) -> Option<Decimal> {
+        let quote_atoms = i128::from(price_in_ticks)
+            .checked_mul(i128::from(self.tick_size_in_quote_atoms_per_base_unit))?;
+        self.scale(quote_atoms, self.quote_decimals)
+    }
+
+    /// Converts a size in base lots to base tokens, `None` if it doesn't fit a `Decimal`
+    pub fn base_quantity(&self, base_lots: i64) -> Option<Decimal> {
+        let base_atoms = i128::from(base_lots).checked_mul(i128::from(self.base_lot_size))?;
+        Decimal::try_from_i128_with_scale(base_atoms, self.base_decimals)
+            .ok()
+            .map(|quantity| quantity.normalize())
+    }
+
+    /// The value in quote tokens of `base_lots` traded at `price_in_ticks`,
+    /// `None` if it doesn't fit a `Decimal`
+    pub fn quote_notional(&self, price_in_ticks: i64, base_lots: i64) -> Option<Decimal> {
+        // quote atoms * base atoms, scaled by both decimals
+        let product = i128::from(price_in_ticks)
+            .checked_mul(i128::from(self.tick_size_in_quote_atoms_per_base_unit))?
+            .checked_mul(i128::from(base_lots))?
+            .checked_mul(i128::from(self.base_lot_size))?;
+        self.scale(
+            product,
+            self.quote_decimals.checked_add(self.base_decimals)?,
+        )
+    }
+
+    /// The quote lots exchanged for `base_lots` traded at `price_in_ticks`, rounded down like
+    /// the Phoenix program does: `price_in_ticks × base_lots × tick size / base lot size`,
+    /// with the tick size in quote lots per base unit and the lot size in base lots per base
+    /// unit. `None` if it doesn't fit an `i64` or the market has an empty lot size.
+    pub fn quote_lots(&self, price_in_ticks: i64, base_lots: i64) -> Option<i64> {
+        // quote atoms * base atoms over quote atoms per quote lot * base atoms per base unit
+        let product = i128::from(price_in_ticks)
+            .checked_mul(i128::from(self.tick_size_in_quote_atoms_per_base_unit))?
+            .checked_mul(i128::from(base_lots))?
+            .checked_mul(i128::from(self.base_lot_size))?;
+        let base_atoms_per_base_unit = 10_i128
+            .checked_pow(self.base_decimals)?
+            .checked_mul(i128::from(self.raw_base_units_per_base_unit))?;
+        let divisor = i128::from(self.quote_lot_size).checked_mul(base_atoms_per_base_unit)?;
+        if divisor <= 0 {
+            return None;
+        }
+        i64::try_from(product.div_euclid(divisor)).ok()
+    }
+}
+
+impl From<&Market> for MarketUnits {
+    fn from(market: &Market) -> Self {
+        Self::new(
+            market.tick_size_in_quote_atoms_per_base_unit,
+            market.base_lot_size,
+            market.quote_lot_size,
+            market.base_decimals,
+            market.quote_decimals,
+            market.raw_base_units_per_base_unit,
+        )
+    }
+}
+
+impl From<&NewMarket> for MarketUnits {
+    fn from(market: &NewMarket) -> Self {
+        Self::new(
+            market.tick_size_in_quote_atoms_per_base_unit,
+            market.base_lot_size,
+            market.quote_lot_size,
+            market.base_decimals,
+            market.quote_decimals,
+            market.raw_base_units_per_base_unit,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::MarketUnits, rust_decimal::Decimal, std::str::FromStr};
+
+    /// Parses an expected decimal
+    fn decimal(value: &str) -> Option<Decimal> {
+        Decimal::from_str(value).ok()
+    }
+
+    #[test]
+    fn convert_sol_usdc() {
+        // 0.001 USDC ticks, 0.001 SOL lots
+        let units = MarketUnits::new(1000, 1_000_000, 1, 9, 6, 1);
+
+        assert_eq!(units.tick_size(), decimal("0.001"));
+        assert_eq!(units.price(177_096), decimal("177.096"));
+        assert_eq!(units.base_quantity(16_782), decimal("16.782"));
+        assert_eq!(
+            units.quote_notional(177_096, 16_782),
+            decimal("2972.025072")
+        );
+        // One quote lot is one USDC atom
+        assert_eq!(units.quote_lots(177_096, 16_782), Some(2_972_025_072));
+        // No float rounding, 0.1 + 0.2 is exactly 0.3
+        assert_eq!(
+            units.price(100).zip(units.price(200)).map(|(a, b)| a + b),
+            units.price(300)
+        );
+    }
+
+    #[test]
+    fn convert_scaled_base_units() {
+        // One base unit is 1000 whole tokens of a 5 decimal token priced in 6 decimal USDC
+        let units = MarketUnits::new(10, 100_000, 1, 5, 6, 1000);
+
+        assert_eq!(units.tick_size(), decimal("0.00000001"));
+        assert_eq!(units.price(2_500), decimal("0.000025"));
+        assert_eq!(units.base_quantity(3), decimal("3"));
+        assert_eq!(units.quote_notional(2_500, 4_000), decimal("0.1"));
+        assert_eq!(units.quote_notional(i64::MAX, i64::MAX), None);
+        assert_eq!(units.quote_lots(2_500, 4_000), Some(100_000));
+        // Rounded down to whole quote lots
+        assert_eq!(units.quote_lots(1, 1), Some(0));
+        assert_eq!(units.quote_lots(i64::MAX, i64::MAX), None);
+    }
+}
@@ -4,12 +4,35 @@
 //! These tests are ran with `just itest` the database is migrated and cleared afterwards
 #![allow(clippy::unwrap_used)]
 
+#[cfg(feature = "integration_tests")]
+use vn_config::DatabaseConfig;
 #[cfg(feature = "integration_tests")]
 use vn_database_core::{
-    models::{NewTradeFill, TradeFill},
+    models::{
+        DeadLetterKind, NewDeadLetter, NewFeeEvent, NewMarket, NewMarketEvent, NewMarketEvents,
+        NewOrderPlace, NewOrderReduce, NewOrderbookSnapshot, NewSequenceGap, NewSyncCheckpoint,
+        NewTradeFill, PriceLevel, ProvisionalTransaction, SequenceGapStatus, TradeFill,
+    },
     VybeDatabase, VybeDatabaseError,
 };
 
+/// Metadata of the SOL/USDC market, fills reference their market's row
+#[cfg(feature = "integration_tests")]
+fn sol_usdc_market() -> NewMarket {
+    NewMarket {
+        market_pubkey: "4DoNfFBfF7UokCC2FQzriy7yHK6DY6NVdYpuekQ5pRgg".to_owned(),
+        base_mint: "So11111111111111111111111111111111111111112".to_owned(),
+        quote_mint: "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".to_owned(),
+        base_decimals: 9,
+        quote_decimals: 6,
+        base_lot_size: 1000000,
+        quote_lot_size: 1,
+        tick_size_in_quote_atoms_per_base_unit: 1000,
+        raw_base_units_per_base_unit: 1,
+        taker_fee_bps: 2,
+    }
+}
+
 #[cfg(feature = "integration_tests")]
 #[test]
 fn database_connection_test() {
@@ -17,19 +40,53 @@ fn database_connection_test() {
     assert_eq!(dbconn_res.is_ok(), true);
 }
 
+#[cfg(feature = "integration_tests")]
+#[test]
+fn database_pool_test() -> Result<(), VybeDatabaseError> {
+    let config = DatabaseConfig {
+        pool_size: 1,
+        ..DatabaseConfig::from_env()
+    };
+    let pool = VybeDatabase::pool(&config)?;
+    let first = &mut VybeDatabase::from_pool(&pool);
+    let second = &mut VybeDatabase::from_pool(&pool);
+
+    // A single connection serves both, every operation returns it to the pool
+    first.upsert_market(&sol_usdc_market())?;
+    assert!(second
+        .get_market("4DoNfFBfF7UokCC2FQzriy7yHK6DY6NVdYpuekQ5pRgg")?
+        .is_some());
+    assert!(first
+        .get_market("4DoNfFBfF7UokCC2FQzriy7yHK6DY6NVdYpuekQ5pRgg")?
+        .is_some());
+    Ok(())
+}
+
 #[cfg(feature = "integration_tests")]
 #[test]
 fn database_read_write_test() -> Result<(), VybeDatabaseError> {
     let db = &mut VybeDatabase::new()?;
+    db.upsert_market(&sol_usdc_market())?;
     // Write Test
 
     let new_trade_fill = NewTradeFill {
         event_timestamp: 1740956436,
         price_in_ticks: 177096,
         base_lots_filled: 16782,
+        market_pubkey: "4DoNfFBfF7UokCC2FQzriy7yHK6DY6NVdYpuekQ5pRgg".to_owned(),
+        signature: "read-write-signature".to_owned(),
+        slot: 325000000,
+        sequence_number: 1234,
+        event_index: 0,
+        provisional: false,
+        order_sequence_number: 9876,
+        maker: "ReadWriteMaker11111111111111111111111111111".to_owned(),
+        taker: "ReadWriteTaker11111111111111111111111111111".to_owned(),
+        side_filled: "ask".to_owned(),
+        quote_lots_filled: Some(2972025072),
     };
 
-    let returned_trade_fill: TradeFill = db.create_trade_fill(&new_trade_fill)?;
+    let returned_trade_fill: TradeFill = db.create_trade_fill(&new_trade_fill)?.unwrap();
     assert_eq!(
         returned_trade_fill.event_timestamp,
         new_trade_fill.event_timestamp
@@ -42,6 +99,14 @@ fn database_read_write_test() -> Result<(), VybeDatabaseError> {
         returned_trade_fill.base_lots_filled,
         new_trade_fill.base_lots_filled
     );
+    assert_eq!(
+        returned_trade_fill.market_pubkey,
+        new_trade_fill.market_pubkey
+    );
+    assert_eq!(returned_trade_fill.signature, new_trade_fill.signature);
+
+    // Storing the same fill again is a no-op
+    assert!(db.create_trade_fill(&new_trade_fill)?.is_none());
 
     // Read Test
     let trade_fills = db.get_trade_fill_by_id(1)?;
@@ -50,5 +115,489 @@ fn database_read_write_test() -> Result<(), VybeDatabaseError> {
     let trade_fill = trade_fill_opt.unwrap();
     assert_eq!(trade_fill, &returned_trade_fill);
 
+    let market_trade_fills = db.get_trade_fills_by_market(&new_trade_fill.market_pubkey)?;
+    assert!(market_trade_fills.contains(&returned_trade_fill));
+
+    // The fill shows up in both traders' history
+    assert_eq!(returned_trade_fill.side_filled.as_deref(), Some("ask"));
+    assert_eq!(returned_trade_fill.order_sequence_number, Some(9876));
+    assert_eq!(returned_trade_fill.quote_lots_filled, Some(2972025072));
+    assert!(db
+        .get_trade_fills_by_trader(&new_trade_fill.maker)?
+        .contains(&returned_trade_fill));
+    assert!(db
+        .get_trade_fills_by_trader(&new_trade_fill.taker)?
+        .contains(&returned_trade_fill));
+
+    Ok(())
+}
+
+#[cfg(feature = "integration_tests")]
+#[test]
+fn database_market_test() -> Result<(), VybeDatabaseError> {
+    let db = &mut VybeDatabase::new()?;
+    let mut new_market = sol_usdc_market();
+    new_market.market_pubkey = "MarketTestMarket111111111111111111111111111111".to_owned();
+    assert!(db.get_market(&new_market.market_pubkey)?.is_none());
+
+    assert_eq!(db.upsert_market(&new_market)?, 1);
+    let market = db.get_market(&new_market.market_pubkey)?.unwrap();
+    assert_eq!(market.quote_mint, new_market.quote_mint);
+    assert_eq!(
+        market.units().tick_size().map(|tick| tick.to_string()),
+        Some("0.001".to_owned())
+    );
+
+    // Reading the market again replaces its metadata
+    new_market.taker_fee_bps = 5;
+    assert_eq!(db.upsert_market(&new_market)?, 1);
+    let market = db.get_market(&new_market.market_pubkey)?.unwrap();
+    assert_eq!(market.taker_fee_bps, 5);
+    assert!(db.get_markets()?.contains(&market));
+
+    // A fill can't reference a market that was never read
+    let orphan = NewTradeFill {
+        event_timestamp: 1740956436,
+        price_in_ticks: 177096,
+        base_lots_filled: 1,
+        market_pubkey: "UnknownMarket11111111111111111111111111111111".to_owned(),
+        signature: "orphan-signature".to_owned(),
+        slot: 325000000,
+        sequence_number: 1,
+        event_index: 0,
+        provisional: false,
+        order_sequence_number: 1,
+        maker: "OrphanMaker111111111111111111111111111111111".to_owned(),
+        taker: "OrphanTaker111111111111111111111111111111111".to_owned(),
+        side_filled: "bid".to_owned(),
+        quote_lots_filled: None,
+    };
+    assert!(matches!(
+        db.create_trade_fill(&orphan),
+        Err(VybeDatabaseError::Diesel(_))
+    ));
+
+    Ok(())
+}
+
+#[cfg(feature = "integration_tests")]
+#[test]
+fn database_checkpoint_test() -> Result<(), VybeDatabaseError> {
+    let db = &mut VybeDatabase::new()?;
+    let market = "CheckpointTestMarket1111111111111111111111111";
+    assert!(db.get_checkpoint(market)?.is_none());
+
+    let first = NewSyncCheckpoint {
+        market_pubkey: market.to_owned(),
+        last_signature: "first-signature".to_owned(),
+        last_slot: 100,
+    };
+    // No fills are written here, `database_read_write_test` expects to own trade fill id 1
+    assert_eq!(
+        db.commit_market_events(&NewMarketEvents::default(), &first)?
+            .total,
+        0
+    );
+    assert_eq!(
+        db.get_checkpoint(market)?.unwrap().last_signature,
+        first.last_signature
+    );
+
+    // Moving the cursor forward updates the existing row
+    let second = NewSyncCheckpoint {
+        market_pubkey: market.to_owned(),
+        last_signature: "second-signature".to_owned(),
+        last_slot: 200,
+    };
+    assert_eq!(
+        db.commit_market_events(&NewMarketEvents::default(), &second)?
+            .total,
+        0
+    );
+
+    let checkpoint = db.get_checkpoint(market)?.unwrap();
+    assert_eq!(checkpoint.last_signature, second.last_signature);
+    assert_eq!(checkpoint.last_slot, second.last_slot);
+
+    Ok(())
+}
+
+#[cfg(feature = "integration_tests")]
+#[test]
+fn database_market_events_test() -> Result<(), VybeDatabaseError> {
+    let db = &mut VybeDatabase::new()?;
+    let market = "MarketEventsTestMarket11111111111111111111111";
+
+    // No fills are written here, `database_read_write_test` expects to own trade fill id 1
+    let events = [
+        NewMarketEvent::Place(NewOrderPlace {
+            market_pubkey: market.to_owned(),
+            event_timestamp: 1740956436,
+            order_sequence_number: 42,
+            client_order_id: "7".to_owned(),
+            maker: "MakerTestPubkey1111111111111111111111111111".to_owned(),
+            price_in_ticks: 177096,
+            base_lots_placed: 100,
+            signature: "events-signature".to_owned(),
+            slot: 300,
+            sequence_number: 42,
+            event_index: 0,
+            provisional: false,
+        }),
+        NewMarketEvent::Fee(NewFeeEvent {
+            market_pubkey: market.to_owned(),
+            event_timestamp: 1740956436,
+            fees_collected_in_quote_lots: 12,
+            signature: "events-signature".to_owned(),
+            slot: 300,
+            sequence_number: 42,
+            event_index: 1,
+            provisional: false,
+        }),
+    ]
+    .into_iter()
+    .collect::<NewMarketEvents>();
+    assert_eq!(events.len(), 2);
+
+    let checkpoint = NewSyncCheckpoint {
+        market_pubkey: market.to_owned(),
+        last_signature: "events-signature".to_owned(),
+        last_slot: 300,
+    };
+    let inserted = db.commit_market_events(&events, &checkpoint)?;
+    assert_eq!(inserted.total, 2);
+    assert_eq!(inserted.fills, 0);
+    assert_eq!(db.get_checkpoint(market)?.unwrap().last_slot, 300);
+
+    // Replaying the same transaction inserts nothing
+    assert_eq!(db.commit_market_events(&events, &checkpoint)?.total, 0);
+
+    // A gap before sequence number 42 is backfilled from its transaction
+    assert_eq!(
+        db.get_next_sequence_signature(market, 40)?,
+        Some((42, "events-signature".to_owned()))
+    );
+    assert!(db.get_next_sequence_signature(market, 42)?.is_none());
+
+    Ok(())
+}
+
+#[cfg(feature = "integration_tests")]
+#[test]
+fn database_chunked_market_events_test() -> Result<(), VybeDatabaseError> {
+    let db = &mut VybeDatabase::new()?;
+    let market = "ChunkedEventsTestMarket111111111111111111111";
+
+    // More places than fit in one statement's 65535 bind parameters
+    let events = (0..6000_i64)
+        .map(|sequence_number| {
+            NewMarketEvent::Place(NewOrderPlace {
+                market_pubkey: market.to_owned(),
+                event_timestamp: 1740956436,
+                order_sequence_number: sequence_number,
+                client_order_id: sequence_number.to_string(),
+                maker: "MakerTestPubkey1111111111111111111111111111".to_owned(),
+                price_in_ticks: 177096,
+                base_lots_placed: 1,
+                signature: "chunked-signature".to_owned(),
+                slot: 500,
+                sequence_number,
+                event_index: 0,
+                provisional: false,
+            })
+        })
+        .collect::<NewMarketEvents>();
+
+    let checkpoint = NewSyncCheckpoint {
+        market_pubkey: market.to_owned(),
+        last_signature: "chunked-signature".to_owned(),
+        last_slot: 500,
+    };
+    assert_eq!(db.commit_market_events(&events, &checkpoint)?.total, 6000);
+    assert_eq!(db.commit_market_events(&events, &checkpoint)?.total, 0);
+
+    Ok(())
+}
+
+#[cfg(feature = "integration_tests")]
+#[test]
+fn database_provisional_events_test() -> Result<(), VybeDatabaseError> {
+    let db = &mut VybeDatabase::new()?;
+    let market = "ProvisionalTestMarket111111111111111111111111";
+    let fee = |signature: &str, slot: i64| {
+        NewMarketEvent::Fee(NewFeeEvent {
+            market_pubkey: market.to_owned(),
+            event_timestamp: 1740956436,
+            fees_collected_in_quote_lots: 5,
+            signature: signature.to_owned(),
+            slot,
+            sequence_number: slot,
+            event_index: 0,
+            provisional: false,
+        })
+    };
+
+    let mut events = [
+        fee("finalized-signature", 400),
+        fee("rolled-back-signature", 401),
+    ]
+    .into_iter()
+    .collect::<NewMarketEvents>();
+    events.mark_provisional();
+    let checkpoint = NewSyncCheckpoint {
+        market_pubkey: market.to_owned(),
+        last_signature: "rolled-back-signature".to_owned(),
+        last_slot: 401,
+    };
+    assert_eq!(db.commit_market_events(&events, &checkpoint)?.total, 2);
+
+    let provisional = db.get_provisional_transactions(10)?;
+    assert_eq!(
+        provisional,
+        vec![
+            ProvisionalTransaction {
+                signature: "finalized-signature".to_owned(),
+                slot: 400,
+            },
+            ProvisionalTransaction {
+                signature: "rolled-back-signature".to_owned(),
+                slot: 401,
+            },
+        ]
+    );
+
+    // The finalized transaction landed again in a later slot
+    let finalized = ProvisionalTransaction {
+        signature: "finalized-signature".to_owned(),
+        slot: 402,
+    };
+    assert_eq!(db.finalize_transactions(&[finalized])?, 1);
+    assert_eq!(
+        db.delete_provisional_transactions(&["rolled-back-signature".to_owned()])?,
+        1
+    );
+    assert!(db.get_provisional_transactions(10)?.is_empty());
+
+    // Final events are never deleted
+    assert_eq!(
+        db.delete_provisional_transactions(&["finalized-signature".to_owned()])?,
+        0
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "integration_tests")]
+#[test]
+fn database_dead_letters_test() -> Result<(), VybeDatabaseError> {
+    let db = &mut VybeDatabase::new()?;
+    let market = "DeadLetterTestMarket1111111111111111111111111";
+    let checkpoint = NewSyncCheckpoint {
+        market_pubkey: market.to_owned(),
+        last_signature: "insert-failed-signature".to_owned(),
+        last_slot: 500,
+    };
+    let letters = [
+        NewDeadLetter::new(
+            market,
+            "decode-failed-signature",
+            DeadLetterKind::Decode,
+            "no phoenix events",
+        ),
+        NewDeadLetter::new(
+            market,
+            "insert-failed-signature",
+            DeadLetterKind::Insert,
+            "connection reset",
+        ),
+    ];
+    assert_eq!(db.commit_dead_letters(&letters, &checkpoint)?, 2);
+    assert_eq!(
+        db.get_checkpoint(market)?.unwrap().last_signature,
+        "insert-failed-signature"
+    );
+
+    // Failing again bumps the attempts and keeps the latest error
+    let retried = NewDeadLetter::new(
+        market,
+        "decode-failed-signature",
+        DeadLetterKind::Fetch,
+        "timed out",
+    );
+    assert_eq!(db.record_dead_letters(&[retried])?, 1);
+    let stored = db.get_dead_letters(Some(market), 10)?;
+    assert_eq!(stored.len(), 2);
+    assert_eq!(stored[0].attempts, 2);
+    assert_eq!(stored[0].error_kind, "fetch");
+    assert_eq!(stored[0].error_message, "timed out");
+    assert_eq!(db.get_retryable_dead_letters(2, 10)?.len(), 1);
+
+    assert_eq!(db.requeue_dead_letters(&[stored[0].id])?, 1);
+    assert_eq!(
+        db.resolve_dead_letter(stored[0].id, &NewMarketEvents::default())?,
+        0
+    );
+    assert_eq!(db.delete_dead_letters(&[stored[1].id])?, 1);
+    assert!(db.get_dead_letters(Some(market), 10)?.is_empty());
+
+    Ok(())
+}
+
+#[cfg(feature = "integration_tests")]
+#[test]
+fn database_sequence_gaps_test() -> Result<(), VybeDatabaseError> {
+    let db = &mut VybeDatabase::new()?;
+    let market = "SequenceGapTestMarket111111111111111111111111";
+    let fee = |sequence_number: i64, slot: i64| {
+        NewMarketEvent::Fee(NewFeeEvent {
+            market_pubkey: market.to_owned(),
+            event_timestamp: 1740956436,
+            fees_collected_in_quote_lots: 5,
+            signature: format!("sequence-gap-signature-{sequence_number}"),
+            slot,
+            sequence_number,
+            event_index: 0,
+            provisional: false,
+        })
+    };
+    assert_eq!(db.get_last_sequence_number(market)?, None);
+
+    let events = [fee(10, 600), fee(14, 604)]
+        .into_iter()
+        .collect::<NewMarketEvents>();
+    let checkpoint = NewSyncCheckpoint {
+        market_pubkey: market.to_owned(),
+        last_signature: "sequence-gap-signature-14".to_owned(),
+        last_slot: 604,
+    };
+    db.commit_market_events(&events, &checkpoint)?;
+    assert_eq!(db.get_last_sequence_number(market)?, Some((14, 604)));
+
+    let gap = NewSequenceGap {
+        market_pubkey: market.to_owned(),
+        first_missing: 11,
+        last_missing: 13,
+        from_slot: 600,
+        to_slot: 604,
+    };
+    assert_eq!(db.record_sequence_gaps(&[gap.clone()])?, 1);
+    // A gap is recorded once
+    assert_eq!(db.record_sequence_gaps(&[gap])?, 0);
+
+    let open = db.get_sequence_gaps(Some(market), Some(SequenceGapStatus::Open), 10)?;
+    assert_eq!(open.len(), 1);
+    db.set_sequence_gap_status(open[0].id, SequenceGapStatus::Unresolved)?;
+    assert!(db
+        .get_sequence_gaps(Some(market), Some(SequenceGapStatus::Open), 10)?
+        .is_empty());
+    assert_eq!(
+        db.get_sequence_gaps(Some(market), None, 10)?[0].status,
+        "unresolved"
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "integration_tests")]
+#[test]
+fn database_orderbook_snapshots_test() -> Result<(), VybeDatabaseError> {
+    let db = &mut VybeDatabase::new()?;
+    let market = sol_usdc_market();
+    db.upsert_market(&market)?;
+    let level = |price_in_ticks: i64, size_in_base_lots: i64| PriceLevel {
+        price_in_ticks,
+        size_in_base_lots,
+    };
+    let snapshot = |slot: i64, snapshot_timestamp: i64| NewOrderbookSnapshot {
+        market_pubkey: market.market_pubkey.clone(),
+        slot,
+        snapshot_timestamp,
+        bids: vec![level(140_000, 25), level(139_990, 40)],
+        asks: vec![level(140_010, 10)],
+    };
+
+    let first = db.record_orderbook_snapshot(&snapshot(900, 1740956400))?;
+    assert!(first.is_some());
+    // A slot is snapshot once
+    assert_eq!(
+        db.record_orderbook_snapshot(&snapshot(900, 1740956401))?,
+        None
+    );
+    db.record_orderbook_snapshot(&snapshot(910, 1740956460))?;
+
+    let latest = db
+        .get_latest_orderbook(&market.market_pubkey)?
+        .expect("a stored snapshot");
+    assert_eq!(latest.snapshot.slot, 910);
+    assert_eq!(latest.bids, [level(140_000, 25), level(139_990, 40)]);
+    assert_eq!(latest.asks, [level(140_010, 10)]);
+
+    let history = db.get_orderbook_history(&market.market_pubkey, None, Some(1740956400), 10)?;
+    assert_eq!(history.len(), 1);
+    assert_eq!(Some(history[0].snapshot.id), first);
+    assert_eq!(history[0].bids.len(), 2);
+
+    Ok(())
+}
+
+#[cfg(feature = "integration_tests")]
+#[test]
+fn database_order_book_events_test() -> Result<(), VybeDatabaseError> {
+    let db = &mut VybeDatabase::new()?;
+    let market = "OrderBookEventsTestMarket111111111111111111";
+    let place = |sequence_number: i64| {
+        NewMarketEvent::Place(NewOrderPlace {
+            market_pubkey: market.to_owned(),
+            event_timestamp: 1740956400 + sequence_number,
+            order_sequence_number: sequence_number,
+            client_order_id: "0".to_owned(),
+            maker: "MakerTestPubkey1111111111111111111111111111".to_owned(),
+            price_in_ticks: 177096,
+            base_lots_placed: 100,
+            signature: format!("book-signature-{sequence_number}"),
+            slot: 400 + sequence_number,
+            sequence_number,
+            event_index: 0,
+            provisional: false,
+        })
+    };
+    let events = [
+        place(1),
+        place(2),
+        NewMarketEvent::Reduce(NewOrderReduce {
+            market_pubkey: market.to_owned(),
+            event_timestamp: 1740956403,
+            order_sequence_number: 1,
+            maker: "MakerTestPubkey1111111111111111111111111111".to_owned(),
+            price_in_ticks: 177096,
+            base_lots_removed: 40,
+            base_lots_remaining: 60,
+            is_full_cancel: false,
+            signature: "book-signature-3".to_owned(),
+            slot: 403,
+            sequence_number: 3,
+            event_index: 0,
+            provisional: false,
+        }),
+    ]
+    .into_iter()
+    .collect::<NewMarketEvents>();
+    let checkpoint = NewSyncCheckpoint {
+        market_pubkey: market.to_owned(),
+        last_signature: "book-signature-3".to_owned(),
+        last_slot: 403,
+    };
+    assert_eq!(db.commit_market_events(&events, &checkpoint)?.total, 3);
+
+    let all = db.get_order_book_events(market, 0, i64::MAX)?;
+    assert_eq!(all.places.len(), 2);
+    assert_eq!(all.reduces.len(), 1);
+    assert_eq!(all.places[0].sequence_number, 1);
+
+    let later = db.get_order_book_events(market, 2, 1740956402)?;
+    assert_eq!(later.places.len(), 1);
+    assert_eq!(later.places[0].sequence_number, 2);
+    assert!(later.reduces.is_empty());
+
     Ok(())
 }
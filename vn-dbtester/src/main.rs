@@ -2,7 +2,7 @@
 
 use {
     anyhow::Result,
-    clap::Parser,
+    clap::{Parser, Subcommand},
     tracing::{error, info, warn, Level},
     tracing_subscriber::EnvFilter,
     vn_database_core::VybeDatabase,
@@ -15,6 +15,95 @@ struct Args {
     /// Log level (e.g., error, warn, info, debug, trace)
     #[arg(short, long, default_value = "info")]
     log_level: String,
+
+    /// Lists the trade fills when omitted
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// What to do with the database
+#[derive(Subcommand)]
+enum Command {
+    /// Inspect the transactions the daemon failed to fetch, decode or insert
+    #[command(subcommand)]
+    DeadLetters(DeadLetterCommand),
+}
+
+/// What to do with the dead letters
+#[derive(Subcommand)]
+enum DeadLetterCommand {
+    /// List the dead letters, oldest first
+    List {
+        /// Only list the dead letters of this market
+        #[arg(short, long)]
+        market: Option<String>,
+        /// Maximum number of dead letters listed
+        #[arg(long, default_value_t = 100)]
+        limit: i64,
+    },
+    /// Reset the attempts of dead letters so the running daemon retries them
+    Retry {
+        /// Ids of the dead letters, as listed
+        #[arg(required = true)]
+        ids: Vec<i32>,
+    },
+    /// Delete dead letters, their events are given up on
+    Discard {
+        /// Ids of the dead letters, as listed
+        #[arg(required = true)]
+        ids: Vec<i32>,
+    },
+}
+
+/// Lists every trade fill
+fn list_trade_fills(db: &mut VybeDatabase) {
+    match db.get_all_trade_fills() {
+        Ok(trades) => {
+            if !trades.is_empty() {
+                info!("Number of trade entries: {}", trades.len());
+                for trade in trades {
+                    println!("{trade:#?}");
+                }
+            } else {
+                warn!("No trade fills available");
+            }
+        }
+        Err(e) => {
+            error!("{e}");
+        }
+    }
+}
+
+/// Lists, requeues or discards dead letters
+fn manage_dead_letters(db: &mut VybeDatabase, command: &DeadLetterCommand) -> Result<()> {
+    match command {
+        DeadLetterCommand::List { market, limit } => {
+            let letters = db.get_dead_letters(market.as_deref(), *limit)?;
+            if letters.is_empty() {
+                info!("No dead letters");
+            }
+            for letter in letters {
+                println!(
+                    "{:>6}  {}  {}  {:<6}  attempts: {}  {}",
+                    letter.id,
+                    letter.market_pubkey,
+                    letter.signature,
+                    letter.error_kind,
+                    letter.attempts,
+                    letter.error_message
+                );
+            }
+        }
+        DeadLetterCommand::Retry { ids } => {
+            let requeued = db.requeue_dead_letters(ids)?;
+            info!("Requeued {requeued} dead letter(s), the daemon retries them at its next pass");
+        }
+        DeadLetterCommand::Discard { ids } => {
+            let discarded = db.delete_dead_letters(ids)?;
+            info!("Discarded {discarded} dead letter(s)");
+        }
+    }
+    Ok(())
 }
 
 /// Converts cli argument string log level to tracing `Level`
@@ -46,20 +135,9 @@ fn main() -> Result<()> {
     info!("Starting Vybe database test tool");
 
     match &mut VybeDatabase::new() {
-        Ok(db) => match db.get_all_trade_fills() {
-            Ok(trades) => {
-                if !trades.is_empty() {
-                    info!("Number of trade entries: {}", trades.len());
-                    for trade in trades {
-                        println!("{trade:#?}");
-                    }
-                } else {
-                    warn!("No trade fills available");
-                }
-            }
-            Err(e) => {
-                error!("{e}");
-            }
+        Ok(db) => match &args.command {
+            Some(Command::DeadLetters(command)) => manage_dead_letters(db, command)?,
+            None => list_trade_fills(db),
         },
         Err(e) => {
             error!("{e}");
@@ -0,0 +1,307 @@
+//! Historical backfill, walks a market's signature history backwards between two bounds
+//! and stores every market event found in between.
+
+use {
+    crate::{
+        error::VybeDaemonError,
+        extractor::{
+            DecodedEvents, SequenceMark, SignatureInfo, VybeResult, VybeTradeFillExtractor,
+        },
+    },
+    chrono::DateTime,
+    solana_sdk::signature::Signature,
+    std::{fmt, str::FromStr},
+    tracing::info,
+    vn_database_core::{
+        models::{Market, NewBackfillProgress, NewMarketEvent, NewMarketEvents},
+        VybeDatabase,
+    },
+};
+
+/// One end of a backfill range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryBound {
+    /// A slot number
+    Slot(u64),
+    /// A UNIX timestamp (in seconds)
+    Time(i64),
+}
+
+impl HistoryBound {
+    /// Whether the transaction landed strictly before this bound.
+    /// Transactions without a block time are never considered older than a time bound.
+    fn is_after(self, info: &SignatureInfo) -> bool {
+        match self {
+            Self::Slot(slot) => info.slot < slot,
+            Self::Time(time) => info.block_time.is_some_and(|block_time| block_time < time),
+        }
+    }
+
+    /// Whether the transaction landed strictly after this bound.
+    /// Transactions without a block time are never considered newer than a time bound.
+    fn is_before(self, info: &SignatureInfo) -> bool {
+        match self {
+            Self::Slot(slot) => info.slot > slot,
+            Self::Time(time) => info.block_time.is_some_and(|block_time| block_time > time),
+        }
+    }
+}
+
+impl FromStr for HistoryBound {
+    type Err = VybeDaemonError;
+
+    /// Parses a plain number as a slot and an RFC 3339 date as a time,
+    /// the `slot:<n>` and `time:<unix seconds>` forms produced by `Display` are accepted too.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(slot) = s.strip_prefix("slot:") {
+            return slot
+                .parse()
+                .map(Self::Slot)
+                .map_err(|_| VybeDaemonError::InvalidHistoryBound(s.to_owned()));
+        }
+        if let Some(time) = s.strip_prefix("time:") {
+            return time
+                .parse()
+                .map(Self::Time)
+                .map_err(|_| VybeDaemonError::InvalidHistoryBound(s.to_owned()));
+        }
+        if let Ok(slot) = s.parse() {
+            return Ok(Self::Slot(slot));
+        }
+        DateTime::parse_from_rfc3339(s)
+            .map(|time| Self::Time(time.timestamp()))
+            .map_err(|_| VybeDaemonError::InvalidHistoryBound(s.to_owned()))
+    }
+}
+
+impl fmt::Display for HistoryBound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Slot(slot) => write!(f, "slot:{slot}"),
+            Self::Time(time) => write!(f, "time:{time}"),
+        }
+    }
+}
+
+/// An inclusive range of history to backfill, from the oldest bound to the newest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackfillRange {
+    /// Oldest bound, the backfill stops once it walks past it
+    from: HistoryBound,
+    /// Newest bound, signatures newer than this are skipped
+    to: HistoryBound,
+}
+
+impl BackfillRange {
+    /// Creates a new range, bounds of a different kind (slot and time) can be mixed
+    ///
+    /// # Errors
+    ///
+    /// `VybeDaemonError::InvalidBackfillRange` if both bounds are the same kind and `from` is after `to`
+    pub fn new(from: HistoryBound, to: HistoryBound) -> VybeResult<Self> {
+        let reversed = match (from, to) {
+            (HistoryBound::Slot(from), HistoryBound::Slot(to)) => from > to,
+            (HistoryBound::Time(from), HistoryBound::Time(to)) => from > to,
+            _ => false,
+        };
+        if reversed {
+            return Err(VybeDaemonError::InvalidBackfillRange(from, to));
+        }
+        Ok(Self { from, to })
+    }
+
+    /// Whether the transaction landed inside the range
+    fn contains(&self, info: &SignatureInfo) -> bool {
+        !self.from.is_after(info) && !self.to.is_before(info)
+    }
+}
+
+/// Totals of a backfill, including work done by previous runs of the same range
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackfillReport {
+    /// Base58 encoded public key of the backfilled market
+    pub market_pubkey: String,
+    /// Number of signatures inside the range that have been decoded
+    pub signatures_processed: i64,
+    /// Number of trade fills inserted
+    pub fills_inserted: i64,
+    /// Whether the oldest bound of the range was reached
+    pub completed: bool,
+}
+
+/// Walks the market's signature history backwards from the chain tip (or from where a previous
+/// run of the same range stopped), decodes every transaction inside the range and commits each
+/// page of fills together with the progress, so the backfill can be interrupted at any point.
+pub(crate) async fn backfill(
+    extractor: &VybeTradeFillExtractor,
+    db: &mut VybeDatabase,
+    range: BackfillRange,
+) -> VybeResult<BackfillReport> {
+    backfill_observed(extractor, db, range, range.from.to_string(), None, |_| {}).await
+}
+
+/// Same as `backfill`, except that the progress is stored under `progress_from` in place of the
+/// range's oldest bound, so it is never shared with another backfill of the same slots, and the
+/// walk starts at the signatures older than `newest` instead of the chain tip.
+/// `observe` is given the sequence numbers of every page once it is committed.
+pub(crate) async fn backfill_observed(
+    extractor: &VybeTradeFillExtractor,
+    db: &mut VybeDatabase,
+    range: BackfillRange,
+    progress_from: String,
+    newest: Option<Signature>,
+    mut observe: impl FnMut(&[SequenceMark]),
+) -> VybeResult<BackfillReport> {
+    let market = extractor.market_pubkey().to_string();
+    let range_from = progress_from;
+    let range_to = range.to.to_string();
+    // The worker stored the market's header before anything is extracted
+    let units = db.get_market(&market)?.as_ref().map(Market::units);
+
+    let mut progress = match db.get_backfill_progress(&market, &range_from, &range_to)? {
+        Some(stored) => {
+            info!(
+                "Resuming backfill of {market} [{range_from} -> {range_to}], {} signature(s) already processed",
+                stored.signatures_processed
+            );
+            NewBackfillProgress {
+                market_pubkey: stored.market_pubkey,
+                range_from: stored.range_from,
+                range_to: stored.range_to,
+                before_signature: stored.before_signature,
+                signatures_processed: stored.signatures_processed,
+                fills_inserted: stored.fills_inserted,
+                completed: stored.completed,
+            }
+        }
+        None => NewBackfillProgress {
+            market_pubkey: market.clone(),
+            range_from,
+            range_to,
+            before_signature: newest.map(|signature| signature.to_string()),
+            signatures_processed: 0,
+            fills_inserted: 0,
+            completed: false,
+        },
+    };
+    let mut before = progress
+        .before_signature
+        .as_deref()
+        .map(Signature::from_str)
+        .transpose()?;
+
+    while !progress.completed {
+        let page = extractor.get_signature_page(before, None).await?;
+        let Some(oldest) = page.signatures.last().copied() else {
+            progress.completed = true;
+            db.commit_backfill_page(&NewMarketEvents::default(), &mut progress)?;
+            break;
+        };
+
+        let in_range = page
+            .signatures
+            .iter()
+            .filter(|info| range.contains(info))
+            .map(|info| info.signature)
+            .collect::<Vec<Signature>>();
+        let decoded = if in_range.is_empty() {
+            DecodedEvents::default()
+        } else {
+            extractor.extract_events(in_range.iter().copied()).await?
+        };
+        let mut new_events = decoded
+            .events
+            .into_iter()
+            .map(NewMarketEvent::try_from)
+            .collect::<Result<NewMarketEvents, _>>()?;
+        // Recent history may not be finalized yet, the reconciliation pass settles it
+        if !extractor.rpc().commitment().is_final() {
+            new_events.mark_provisional();
+        }
+        if let Some(units) = &units {
+            new_events.derive_quote_lots(units);
+        }
+
+        progress.before_signature = Some(oldest.signature.to_string());
+        progress.signatures_processed += in_range.len() as i64;
+        progress.completed = page.exhausted || range.from.is_after(&oldest);
+        // Recorded before the page, a failed transaction is never skipped without a trace
+        if !decoded.failed.is_empty() {
+            let letters = decoded
+                .failed
+                .iter()
+                .map(|failed| failed.dead_letter(&market))
+                .collect::<Vec<_>>();
+            db.record_dead_letters(&letters)?;
+        }
+        db.commit_backfill_page(&new_events, &mut progress)?;
+        observe(&decoded.sequences);
+        before = Some(oldest.signature);
+
+        info!(
+            "Backfill of {market} reached slot {}: {} signature(s) processed, {} fill(s) inserted",
+            oldest.slot, progress.signatures_processed, progress.fills_inserted
+        );
+    }
+
+    Ok(BackfillReport {
+        market_pubkey: market,
+        signatures_processed: progress.signatures_processed,
+        fills_inserted: progress.fills_inserted,
+        completed: progress.completed,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{BackfillRange, HistoryBound, SignatureInfo, VybeDaemonError},
+        solana_sdk::signature::Signature,
+        std::str::FromStr,
+    };
+
+    /// Builds a signature that landed in `slot` at `block_time`
+    fn info(slot: u64, block_time: Option<i64>) -> SignatureInfo {
+        SignatureInfo {
+            signature: Signature::default(),
+            slot,
+            block_time,
+        }
+    }
+
+    #[test]
+    fn parse_history_bounds() {
+        assert_eq!(
+            HistoryBound::from_str("325000000").ok(),
+            Some(HistoryBound::Slot(325_000_000))
+        );
+        assert_eq!(
+            HistoryBound::from_str("2025-03-02T00:00:00Z").ok(),
+            Some(HistoryBound::Time(1_740_873_600))
+        );
+        assert_eq!(
+            HistoryBound::from_str(&HistoryBound::Time(1_740_873_600).to_string()).ok(),
+            Some(HistoryBound::Time(1_740_873_600))
+        );
+        assert!(matches!(
+            HistoryBound::from_str("yesterday"),
+            Err(VybeDaemonError::InvalidHistoryBound(_))
+        ));
+    }
+
+    #[test]
+    fn backfill_range_bounds() {
+        let range = BackfillRange::new(HistoryBound::Slot(100), HistoryBound::Time(2_000))
+            .expect("slot and time bounds are always valid");
+        assert!(range.contains(&info(100, Some(2_000))));
+        assert!(range.contains(&info(150, None)));
+        assert!(!range.contains(&info(99, Some(1_000))));
+        assert!(!range.contains(&info(150, Some(2_001))));
+
+        assert!(matches!(
+            BackfillRange::new(HistoryBound::Slot(200), HistoryBound::Slot(100)),
+            Err(VybeDaemonError::InvalidBackfillRange(..))
+        ));
+    }
+}
@@ -0,0 +1,820 @@
+//! L3 order book reconstruction. Every resting order of a market is kept by its order sequence
+//! number and maker, driven by the place, reduce, evict and fill events the extractor decodes.
+//! Events are applied one instruction (market sequence number) at a time in sequence number
+//! order, an instruction the book already reflects is skipped, so applying the same events
+//! twice or in overlapping batches leaves the same book.
+//!
+//! A Phoenix market account holds its book too, the live book of a market is seeded from it and
+//! checked against it periodically. Orders that expire leave the book without an event, and
+//! transactions that failed to decode are missing, so when the two disagree the book is re-seeded
+//! from the account.
+
+use {
+    crate::{extractor::VybeResult, market::Ladder},
+    phoenix::state::Side,
+    std::{
+        collections::{BTreeMap, BTreeSet},
+        sync::{Arc, PoisonError, RwLock},
+        time::Duration,
+    },
+    tokio::time::Instant,
+    tracing::{debug, info, warn},
+    vn_database_core::{
+        models::{
+            NewMarketEvents, NewOrderEviction, NewOrderPlace, NewOrderReduce, NewTradeFill,
+            PriceLevel,
+        },
+        VybeDatabase,
+    },
+};
+
+/// The live order book of a market shared with its worker, `None` until it is first seeded
+/// from the market account
+pub type SharedOrderBook = Arc<RwLock<Option<OrderBook>>>;
+
+/// An order resting on the book
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookOrder {
+    /// Sequence number identifying the order on the book, bids have the highest bit set
+    /// so they are negative
+    pub order_sequence_number: i64,
+    /// Base58 encoded public key of the order owner
+    pub maker: String,
+    /// Side of the book the order rests on
+    pub side: Side,
+    /// The price of the order expressed in ticks
+    pub price_in_ticks: i64,
+    /// The volume of the base token (in lots) still resting
+    pub base_lots: i64,
+    /// The Unix timestamp (in seconds) the order was placed at, `None` when it was already
+    /// resting on the market account the book was seeded from
+    pub placed_at: Option<i64>,
+}
+
+/// Where an order is in the queue of its price level, orders at the same price are matched
+/// first in first out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueuePosition {
+    /// Number of orders at the same price matched before it
+    pub orders_ahead: usize,
+    /// Base lots at the same price matched before it
+    pub base_lots_ahead: i64,
+}
+
+/// An event that changes the book, borrowed from extracted or stored market events
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum BookEvent<'a> {
+    /// An order was placed
+    Place(&'a NewOrderPlace),
+    /// An order was reduced or cancelled by its owner
+    Reduce(&'a NewOrderReduce),
+    /// An order was evicted from a full book
+    Evict(&'a NewOrderEviction),
+    /// An order was (partially) filled by a taker
+    Fill(&'a NewTradeFill),
+}
+
+impl BookEvent<'_> {
+    /// The market sequence number of the instruction that emitted the event
+    fn sequence_number(&self) -> i64 {
+        match self {
+            Self::Place(place) => place.sequence_number,
+            Self::Reduce(reduce) => reduce.sequence_number,
+            Self::Evict(eviction) => eviction.sequence_number,
+            Self::Fill(fill) => fill.sequence_number,
+        }
+    }
+
+    /// Index of the event among the events of its instruction
+    fn event_index(&self) -> i64 {
+        match self {
+            Self::Place(place) => place.event_index,
+            Self::Reduce(reduce) => reduce.event_index,
+            Self::Evict(eviction) => eviction.event_index,
+            Self::Fill(fill) => fill.event_index,
+        }
+    }
+
+    /// The Unix timestamp (in seconds) of the event
+    fn event_timestamp(&self) -> i64 {
+        match self {
+            Self::Place(place) => place.event_timestamp,
+            Self::Reduce(reduce) => reduce.event_timestamp,
+            Self::Evict(eviction) => eviction.event_timestamp,
+            Self::Fill(fill) => fill.event_timestamp,
+        }
+    }
+
+    /// The events that change the book, in the order they happened
+    pub(crate) fn all(events: &NewMarketEvents) -> Vec<BookEvent<'_>> {
+        let mut all = events
+            .places
+            .iter()
+            .map(BookEvent::Place)
+            .chain(events.reduces.iter().map(BookEvent::Reduce))
+            .chain(events.evictions.iter().map(BookEvent::Evict))
+            .chain(events.fills.iter().map(BookEvent::Fill))
+            .collect::<Vec<BookEvent<'_>>>();
+        all.sort_by_key(|event| (event.sequence_number(), event.event_index()));
+        all
+    }
+}
+
+/// Phoenix stores bids with the bits of their order sequence number inverted, so only bids have
+/// the highest bit set
+fn side_of(order_sequence_number: i64) -> Side {
+    if order_sequence_number < 0 {
+        Side::Bid
+    } else {
+        Side::Ask
+    }
+}
+
+/// Every resting order of a market, see the module documentation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderBook {
+    /// Base58 encoded public key of the market
+    market_pubkey: String,
+    /// Resting orders by order sequence number
+    orders: BTreeMap<i64, BookOrder>,
+    /// Order sequence numbers of the resting orders of every maker
+    makers: BTreeMap<String, BTreeSet<i64>>,
+    /// Market sequence number of the first instruction the book doesn't reflect yet
+    next_sequence_number: i64,
+    /// Events since the book was seeded that referenced an order it doesn't hold
+    unmatched_events: usize,
+}
+
+impl OrderBook {
+    /// An empty book of a market no instruction has run on yet, replaying the market's events
+    /// from its creation onwards rebuilds its book
+    pub fn new(market_pubkey: &str) -> Self {
+        Self::with_orders(market_pubkey, 0, [])
+    }
+
+    /// A book holding `orders`, reflecting every instruction before `next_sequence_number`
+    pub fn with_orders(
+        market_pubkey: &str,
+        next_sequence_number: i64,
+        orders: impl IntoIterator<Item = BookOrder>,
+    ) -> Self {
+        let mut book = Self {
+            market_pubkey: market_pubkey.to_owned(),
+            orders: BTreeMap::new(),
+            makers: BTreeMap::new(),
+            next_sequence_number,
+            unmatched_events: 0,
+        };
+        for order in orders {
+            book.insert(order);
+        }
+        book
+    }
+
+    /// Base58 encoded public key of the market
+    pub fn market_pubkey(&self) -> &str {
+        &self.market_pubkey
+    }
+
+    /// Market sequence number of the first instruction the book doesn't reflect yet
+    pub fn next_sequence_number(&self) -> i64 {
+        self.next_sequence_number
+    }
+
+    /// Number of events since the book was seeded that referenced an order it doesn't hold,
+    /// anything but 0 means the book missed events
+    pub fn unmatched_events(&self) -> usize {
+        self.unmatched_events
+    }
+
+    /// Number of resting orders
+    pub fn len(&self) -> usize {
+        self.orders.len()
+    }
+
+    /// Whether no order is resting
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+
+    /// A resting order by its order sequence number
+    pub fn order(&self, order_sequence_number: i64) -> Option<&BookOrder> {
+        self.orders.get(&order_sequence_number)
+    }
+
+    /// The resting orders of a maker, by order sequence number
+    pub fn orders_of(&self, maker: &str) -> Vec<&BookOrder> {
+        self.makers
+            .get(maker)
+            .into_iter()
+            .flatten()
+            .filter_map(|order_sequence_number| self.orders.get(order_sequence_number))
+            .collect()
+    }
+
+    /// Where a resting order is in the queue of its price level, `None` when it isn't resting
+    pub fn queue_position(&self, order_sequence_number: i64) -> Option<QueuePosition> {
+        let order = self.orders.get(&order_sequence_number)?;
+        // Bids carry inverted order sequence numbers, the older of two bids has the higher one
+        let ahead = self
+            .orders
+            .values()
+            .filter(|other| {
+                other.side == order.side && other.price_in_ticks == order.price_in_ticks
+            })
+            .filter(|other| match order.side {
+                Side::Bid => other.order_sequence_number > order.order_sequence_number,
+                Side::Ask => other.order_sequence_number < order.order_sequence_number,
+            })
+            .collect::<Vec<&BookOrder>>();
+        Some(QueuePosition {
+            orders_ahead: ahead.len(),
+            base_lots_ahead: ahead.iter().map(|other| other.base_lots).sum(),
+        })
+    }
+
+    /// The resting size of every price of one side, best price first
+    fn levels(&self, side: Side) -> Vec<PriceLevel> {
+        let mut levels = BTreeMap::<i64, i64>::new();
+        for order in self.orders.values().filter(|order| order.side == side) {
+            *levels.entry(order.price_in_ticks).or_default() += order.base_lots;
+        }
+        let levels = levels
+            .into_iter()
+            .map(|(price_in_ticks, size_in_base_lots)| PriceLevel {
+                price_in_ticks,
+                size_in_base_lots,
+            });
+        match side {
+            Side::Bid => levels.rev().collect(),
+            Side::Ask => levels.collect(),
+        }
+    }
+
+    /// The top `depth` price levels of each side, the same view an L2 snapshot stores
+    pub fn ladder(&self, depth: usize) -> Ladder {
+        let mut bids = self.levels(Side::Bid);
+        let mut asks = self.levels(Side::Ask);
+        bids.truncate(depth);
+        asks.truncate(depth);
+        Ladder { bids, asks }
+    }
+
+    /// Base lots resting on one side within `ticks` of its best price, 0 when the side is empty
+    pub fn depth_within(&self, side: Side, ticks: i64) -> i64 {
+        let levels = self.levels(side);
+        let Some(best) = levels.first().map(|level| level.price_in_ticks) else {
+            return 0;
+        };
+        levels
+            .iter()
+            .take_while(|level| level.price_in_ticks.abs_diff(best) <= ticks.unsigned_abs())
+            .map(|level| level.size_in_base_lots)
+            .sum()
+    }
+
+    /// Base lots every maker has resting on one side, largest first
+    pub fn liquidity_by_maker(&self, side: Side) -> Vec<(String, i64)> {
+        let mut liquidity = BTreeMap::<&str, i64>::new();
+        for order in self.orders.values().filter(|order| order.side == side) {
+            *liquidity.entry(order.maker.as_str()).or_default() += order.base_lots;
+        }
+        let mut liquidity = liquidity
+            .into_iter()
+            .map(|(maker, base_lots)| (maker.to_owned(), base_lots))
+            .collect::<Vec<(String, i64)>>();
+        liquidity.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        liquidity
+    }
+
+    /// Whether both books hold the same resting orders, when and where the orders were placed
+    /// isn't compared since a book seeded from the market account doesn't know it
+    pub fn agrees_with(&self, other: &OrderBook) -> bool {
+        let resting = |book: &OrderBook| {
+            book.orders
+                .values()
+                .map(|order| {
+                    (
+                        order.order_sequence_number,
+                        order.maker.clone(),
+                        order.price_in_ticks,
+                        order.base_lots,
+                    )
+                })
+                .collect::<Vec<(i64, String, i64, i64)>>()
+        };
+        resting(self) == resting(other)
+    }
+
+    /// Applies every event that changes the book, see `OrderBook::replay`
+    ///
+    /// # Returns
+    ///
+    /// The number of applied events
+    pub fn apply(&mut self, events: &NewMarketEvents) -> usize {
+        self.replay(events, i64::MAX)
+    }
+
+    /// Applies the events that changed the book until `until_timestamp` (included), one
+    /// instruction at a time, the instructions the book already reflects are skipped.
+    /// Replaying the events of a market up to a time from a book seeded before it rebuilds the
+    /// book at that time.
+    ///
+    /// # Returns
+    ///
+    /// The number of applied events
+    pub fn replay(&mut self, events: &NewMarketEvents, until_timestamp: i64) -> usize {
+        let events = BookEvent::all(events);
+        let mut applied = 0_usize;
+        for instruction in events.chunk_by(|a, b| a.sequence_number() == b.sequence_number()) {
+            if instruction
+                .iter()
+                .any(|event| event.event_timestamp() > until_timestamp)
+            {
+                break;
+            }
+            applied += self.apply_instruction(instruction);
+        }
+        applied
+    }
+
+    /// Loads the stored events that changed the book from its next sequence number until
+    /// `until_timestamp` (included) and replays them, see `OrderBook::replay`
+    ///
+    /// # Errors
+    ///
+    /// `VybeDaemonError::Database` if the events can't be read
+    ///
+    /// # Returns
+    ///
+    /// The number of applied events
+    pub fn replay_stored(
+        &mut self,
+        db: &mut VybeDatabase,
+        until_timestamp: i64,
+    ) -> VybeResult<usize> {
+        let events = db.get_order_book_events(
+            &self.market_pubkey,
+            self.next_sequence_number,
+            until_timestamp,
+        )?;
+        Ok(self.replay(&events, until_timestamp))
+    }
+
+    /// Applies the events of a single instruction, skipped when the book already reflects it
+    pub(crate) fn apply_instruction(&mut self, instruction: &[BookEvent<'_>]) -> usize {
+        let Some(sequence_number) = instruction.first().map(BookEvent::sequence_number) else {
+            return 0;
+        };
+        if sequence_number < self.next_sequence_number {
+            return 0;
+        }
+        for event in instruction {
+            self.apply_event(event);
+        }
+        self.next_sequence_number = sequence_number.saturating_add(1);
+        instruction.len()
+    }
+
+    /// Applies a single event, an event referencing an order the book doesn't hold is counted
+    /// as unmatched
+    fn apply_event(&mut self, event: &BookEvent<'_>) {
+        let matched = match *event {
+            BookEvent::Place(place) => {
+                let fresh = !self.orders.contains_key(&place.order_sequence_number);
+                if fresh {
+                    self.insert(BookOrder {
+                        order_sequence_number: place.order_sequence_number,
+                        maker: place.maker.clone(),
+                        side: side_of(place.order_sequence_number),
+                        price_in_ticks: place.price_in_ticks,
+                        base_lots: place.base_lots_placed,
+                        placed_at: Some(place.event_timestamp),
+                    });
+                }
+                fresh
+            }
+            BookEvent::Reduce(reduce) => {
+                self.resize(reduce.order_sequence_number, &reduce.maker, |_| {
+                    Some(reduce.base_lots_remaining)
+                })
+            }
+            BookEvent::Evict(eviction) => {
+                self.resize(eviction.order_sequence_number, &eviction.maker, |_| Some(0))
+            }
+            BookEvent::Fill(fill) => self.resize(fill.order_sequence_number, &fill.maker, |lots| {
+                lots.checked_sub(fill.base_lots_filled)
+                    .filter(|remaining| *remaining >= 0)
+            }),
+        };
+        if !matched {
+            self.unmatched_events += 1;
+        }
+    }
+
+    /// Sets the resting size of a maker's order to what `remaining` returns for its current
+    /// size, the order leaves the book at 0. Returns `false` when the maker has no such order,
+    /// or `remaining` returns `None` because more was taken than it held, which removes it.
+    fn resize(
+        &mut self,
+        order_sequence_number: i64,
+        maker: &str,
+        remaining: impl FnOnce(i64) -> Option<i64>,
+    ) -> bool {
+        let Some(order) = self
+            .orders
+            .get_mut(&order_sequence_number)
+            .filter(|order| order.maker == maker)
+        else {
+            return false;
+        };
+        match remaining(order.base_lots) {
+            Some(base_lots) if base_lots > 0 => {
+                order.base_lots = base_lots;
+                true
+            }
+            Some(_) => {
+                self.remove(order_sequence_number);
+                true
+            }
+            None => {
+                self.remove(order_sequence_number);
+                false
+            }
+        }
+    }
+
+    /// Adds a resting order
+    fn insert(&mut self, order: BookOrder) {
+        self.makers
+            .entry(order.maker.clone())
+            .or_default()
+            .insert(order.order_sequence_number);
+        self.orders.insert(order.order_sequence_number, order);
+    }
+
+    /// Removes a resting order
+    fn remove(&mut self, order_sequence_number: i64) {
+        let Some(order) = self.orders.remove(&order_sequence_number) else {
+            return;
+        };
+        if let Some(orders) = self.makers.get_mut(&order.maker) {
+            orders.remove(&order_sequence_number);
+            if orders.is_empty() {
+                self.makers.remove(&order.maker);
+            }
+        }
+    }
+}
+
+/// The book of the market account, read at a slot
+pub(crate) struct ChainBook {
+    /// The slot the market account was read at
+    pub(crate) slot: u64,
+    /// The resting orders of the account
+    pub(crate) book: OrderBook,
+}
+
+/// Keeps a market's live book, the market worker applies the events it extracts and
+/// periodically reads the book of the market account to seed and check it
+pub(crate) struct BookTracker {
+    /// The live book, shared with the daemon
+    book: SharedOrderBook,
+    /// The book of the market account the live book is checked against once it reaches the
+    /// account's sequence number
+    pending: Option<ChainBook>,
+    /// How often the market account is read
+    check_interval: Duration,
+    /// When the market account is read next
+    next_check: Instant,
+}
+
+impl BookTracker {
+    /// Tracks a book that is seeded from the market account at the first check
+    pub(crate) fn new(check_interval: Duration) -> Self {
+        Self {
+            book: Arc::new(RwLock::new(None)),
+            pending: None,
+            check_interval,
+            next_check: Instant::now(),
+        }
+    }
+
+    /// The live book, shared with the daemon
+    pub(crate) fn shared(&self) -> SharedOrderBook {
+        Arc::clone(&self.book)
+    }
+
+    /// Whether the market account is due to be read
+    pub(crate) fn is_due(&self) -> bool {
+        Instant::now() >= self.next_check
+    }
+
+    /// Puts off reading the market account until the next interval
+    pub(crate) fn postpone(&mut self) {
+        self.next_check = Instant::now() + self.check_interval;
+    }
+
+    /// Drops the live book after events were left out of it, it is seeded again from the
+    /// market account at the next pass
+    pub(crate) fn reset(&mut self) {
+        *self.book.write().unwrap_or_else(PoisonError::into_inner) = None;
+        self.pending = None;
+        self.next_check = Instant::now();
+    }
+
+    /// Takes in a freshly read book of the market account. An unseeded book, or a book that
+    /// missed events, is re-seeded from it right away unless the account was read from a node
+    /// lagging behind the book. Otherwise the book is checked against it once the book reaches
+    /// the account's sequence number.
+    pub(crate) fn observe(&mut self, chain: ChainBook) {
+        self.postpone();
+        let mut guard = self.book.write().unwrap_or_else(PoisonError::into_inner);
+        let Some(book) = guard.as_mut() else {
+            info!(
+                "{}: seeded the order book with {} resting order(s) at slot {}",
+                chain.book.market_pubkey(),
+                chain.book.len(),
+                chain.slot
+            );
+            *guard = Some(chain.book);
+            return;
+        };
+        if chain.book.next_sequence_number() < book.next_sequence_number() {
+            debug!(
+                "{}: the market account at slot {} is behind the order book, checking it later",
+                book.market_pubkey(),
+                chain.slot
+            );
+        } else if book.unmatched_events() > 0 {
+            warn!(
+                "{}: the order book missed events, re-seeding it from the market account at slot {}",
+                book.market_pubkey(),
+                chain.slot
+            );
+            *book = chain.book;
+        } else {
+            self.pending = Some(chain);
+        }
+    }
+
+    /// Applies newly extracted events, the book is checked against the pending market account
+    /// at the instruction it was read before, or once the extraction moved past its slot when no
+    /// later instruction changed the book
+    pub(crate) fn apply(&mut self, events: &NewMarketEvents, cursor_slot: u64) {
+        let mut guard = self.book.write().unwrap_or_else(PoisonError::into_inner);
+        let Some(book) = guard.as_mut() else {
+            return;
+        };
+        let unmatched = book.unmatched_events();
+        let events = BookEvent::all(events);
+        for instruction in events.chunk_by(|a, b| a.sequence_number() == b.sequence_number()) {
+            let sequence_number = instruction
+                .first()
+                .map_or(i64::MIN, BookEvent::sequence_number);
+            if let Some(chain) = self
+                .pending
+                .take_if(|chain| sequence_number >= chain.book.next_sequence_number())
+            {
+                check(book, chain);
+            }
+            book.apply_instruction(instruction);
+        }
+        if let Some(chain) = self.pending.take_if(|chain| {
+            cursor_slot > chain.slot
+                && book.next_sequence_number() <= chain.book.next_sequence_number()
+        }) {
+            check(book, chain);
+        }
+        if book.unmatched_events() > unmatched {
+            // Read the market account at the next pass instead of waiting for the interval
+            self.next_check = Instant::now();
+        }
+    }
+}
+
+/// Re-seeds the book from the market account when they don't hold the same resting orders
+fn check(book: &mut OrderBook, chain: ChainBook) {
+    if book.agrees_with(&chain.book) {
+        debug!(
+            "{}: the order book agrees with the market account at slot {}",
+            book.market_pubkey(),
+            chain.slot
+        );
+        return;
+    }
+    warn!(
+        "{}: the order book ({} order(s)) disagrees with the market account at slot {} ({} order(s)), re-seeding it",
+        book.market_pubkey(),
+        book.len(),
+        chain.slot,
+        chain.book.len()
+    );
+    *book = chain.book;
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{BookOrder, ChainBook, OrderBook, QueuePosition},
+        phoenix::state::Side,
+        vn_database_core::models::{
+            NewMarketEvent, NewMarketEvents, NewOrderPlace, NewOrderReduce, NewTradeFill,
+            PriceLevel,
+        },
+    };
+
+    /// Market of every event
+    const MARKET: &str = "4DoNfFBfF7UokCC2FQzriy7yHK6DY6NVdYpuekQ5pRgg";
+
+    /// An order placed by `maker` in the instruction `sequence_number`
+    fn place(
+        sequence_number: i64,
+        order_sequence_number: i64,
+        maker: &str,
+        price_in_ticks: i64,
+        base_lots_placed: i64,
+    ) -> NewMarketEvent {
+        NewMarketEvent::Place(NewOrderPlace {
+            market_pubkey: MARKET.to_owned(),
+            event_timestamp: 1_740_956_400 + sequence_number,
+            order_sequence_number,
+            client_order_id: "0".to_owned(),
+            maker: maker.to_owned(),
+            price_in_ticks,
+            base_lots_placed,
+            signature: format!("signature-{sequence_number}"),
+            slot: 100 + sequence_number,
+            sequence_number,
+            event_index: 0,
+            provisional: false,
+        })
+    }
+
+    /// `base_lots_filled` taken from a maker's order in the instruction `sequence_number`
+    fn fill(
+        sequence_number: i64,
+        order_sequence_number: i64,
+        maker: &str,
+        base_lots_filled: i64,
+    ) -> NewMarketEvent {
+        NewMarketEvent::Fill(NewTradeFill {
+            event_timestamp: 1_740_956_400 + sequence_number,
+            price_in_ticks: 0,
+            base_lots_filled,
+            market_pubkey: MARKET.to_owned(),
+            signature: format!("signature-{sequence_number}"),
+            slot: 100 + sequence_number,
+            sequence_number,
+            event_index: 0,
+            provisional: false,
+            order_sequence_number,
+            maker: maker.to_owned(),
+            taker: "taker".to_owned(),
+            side_filled: "ask".to_owned(),
+            quote_lots_filled: Some(0),
+        })
+    }
+
+    /// A maker's order cancelled in the instruction `sequence_number`
+    fn cancel(sequence_number: i64, order_sequence_number: i64, maker: &str) -> NewMarketEvent {
+        NewMarketEvent::Reduce(NewOrderReduce {
+            market_pubkey: MARKET.to_owned(),
+            event_timestamp: 1_740_956_400 + sequence_number,
+            order_sequence_number,
+            maker: maker.to_owned(),
+            price_in_ticks: 0,
+            base_lots_removed: 0,
+            base_lots_remaining: 0,
+            is_full_cancel: true,
+            signature: format!("signature-{sequence_number}"),
+            slot: 100 + sequence_number,
+            sequence_number,
+            event_index: 0,
+            provisional: false,
+        })
+    }
+
+    /// Two bids of `alice` and `bob` at the same price, and two asks of `alice`
+    fn events() -> NewMarketEvents {
+        [
+            place(1, !1, "alice", 140_000, 10),
+            place(2, !2, "bob", 140_000, 20),
+            place(3, 3, "alice", 140_010, 5),
+            place(4, 4, "alice", 140_020, 7),
+            fill(5, 3, "alice", 2),
+            cancel(6, 4, "alice"),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    #[test]
+    fn replay_events() {
+        let events = events();
+        let mut book = OrderBook::new(MARKET);
+        assert_eq!(book.apply(&events), 6);
+        assert_eq!(book.next_sequence_number(), 7);
+        assert_eq!(book.unmatched_events(), 0);
+        assert_eq!(book.len(), 3);
+        assert_eq!(book.order(3).map(|order| order.base_lots), Some(3));
+        assert_eq!(book.order(3).map(|order| order.side), Some(Side::Ask));
+        assert_eq!(book.order(!1).map(|order| order.side), Some(Side::Bid));
+        assert!(book.order(4).is_none());
+        assert_eq!(book.orders_of("alice").len(), 2);
+
+        let ladder = book.ladder(5);
+        assert_eq!(
+            ladder.bids,
+            [PriceLevel {
+                price_in_ticks: 140_000,
+                size_in_base_lots: 30
+            }]
+        );
+        assert_eq!(
+            ladder.asks,
+            [PriceLevel {
+                price_in_ticks: 140_010,
+                size_in_base_lots: 3
+            }]
+        );
+        // The older bid of alice is ahead of bob's
+        assert_eq!(
+            book.queue_position(!2),
+            Some(QueuePosition {
+                orders_ahead: 1,
+                base_lots_ahead: 10
+            })
+        );
+        assert_eq!(book.depth_within(Side::Bid, 0), 30);
+        assert_eq!(
+            book.liquidity_by_maker(Side::Bid),
+            [("bob".to_owned(), 20), ("alice".to_owned(), 10)]
+        );
+
+        // Events the book already reflects are skipped
+        assert_eq!(book.apply(&events), 0);
+        assert_eq!(book.order(3).map(|order| order.base_lots), Some(3));
+
+        // The book at a time only has the events until then
+        let mut earlier = OrderBook::new(MARKET);
+        earlier.replay(&events, 1_740_956_404);
+        assert_eq!(earlier.len(), 4);
+        assert_eq!(earlier.order(4).map(|order| order.base_lots), Some(7));
+        earlier.apply(&events);
+        assert_eq!(earlier, book);
+    }
+
+    #[test]
+    fn reseed_on_disagreement() {
+        let events = events();
+        let mut tracker = super::BookTracker::new(std::time::Duration::from_secs(60));
+        // Nothing is applied before the book is seeded
+        tracker.apply(&events, 110);
+
+        let seeded = OrderBook::with_orders(
+            MARKET,
+            5,
+            [BookOrder {
+                order_sequence_number: 3,
+                maker: "alice".to_owned(),
+                side: Side::Ask,
+                price_in_ticks: 140_010,
+                base_lots: 5,
+                placed_at: None,
+            }],
+        );
+        tracker.observe(ChainBook {
+            slot: 104,
+            book: seeded,
+        });
+        // The fill matches the seeded order, the cancel of an order it doesn't hold doesn't
+        tracker.apply(&events, 106);
+        let shared = tracker.shared();
+        let book = shared.read().expect("lock").clone().expect("seeded");
+        assert_eq!(book.order(3).map(|order| order.base_lots), Some(3));
+        assert_eq!(book.unmatched_events(), 1);
+        assert!(tracker.is_due());
+
+        // The account shows an order the book never saw, it is re-seeded from it
+        let chain = OrderBook::with_orders(
+            MARKET,
+            7,
+            [BookOrder {
+                order_sequence_number: 9,
+                maker: "carol".to_owned(),
+                side: Side::Ask,
+                price_in_ticks: 140_030,
+                base_lots: 1,
+                placed_at: None,
+            }],
+        );
+        tracker.observe(ChainBook {
+            slot: 107,
+            book: chain.clone(),
+        });
+        let book = shared.read().expect("lock").clone().expect("seeded");
+        assert!(book.agrees_with(&chain));
+        assert!(!tracker.is_due());
+    }
+}
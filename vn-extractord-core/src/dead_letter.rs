@@ -0,0 +1,153 @@
+//! Retries of dead letters. Transactions that failed to be fetched, decoded or inserted are
+//! recorded in the `dead_letters` table instead of being forgotten, they are extracted again
+//! periodically until their events are stored or they run out of attempts.
+
+use {
+    crate::{
+        extractor::{VybeResult, VybeTradeFillExtractor},
+        metrics,
+        rpc::RpcPool,
+        shutdown::Shutdown,
+    },
+    solana_sdk::signature::Signature,
+    std::{str::FromStr, sync::Arc, time::Duration},
+    tracing::{info, warn},
+    vn_database_core::{
+        models::{
+            DeadLetter, DeadLetterKind, Market, MarketEventKind, NewDeadLetter, NewMarketEvent,
+            NewMarketEvents,
+        },
+        VybeDatabase, VybePool,
+    },
+};
+
+/// How often dead letters are retried
+const RETRY_INTERVAL_SECS: u64 = 300;
+
+/// Dead letters that failed this many times are left for an operator, see `vn-dbtester`
+const MAX_RETRY_ATTEMPTS: i32 = 5;
+
+/// Maximum number of dead letters retried per pass
+const RETRY_BATCH: i64 = 100;
+
+/// Periodically extracts dead letters again, shares the database pool with the market workers
+/// so it can run on a separate task next to them.
+pub(crate) struct DeadLetterRetrier {
+    /// RPC endpoints shared with the market workers
+    rpc: Arc<RpcPool>,
+    /// PG database connection and interface
+    db: VybeDatabase,
+    /// Stops the retry loop once the daemon shuts down
+    shutdown: Shutdown,
+    /// Kinds of market events the market workers store
+    event_kinds: Vec<MarketEventKind>,
+}
+
+impl DeadLetterRetrier {
+    /// Creates a new retrier checking connections out of the daemon's pool
+    pub(crate) fn new(rpc: Arc<RpcPool>, database: &VybePool, shutdown: Shutdown) -> Self {
+        Self {
+            rpc,
+            db: VybeDatabase::from_pool(database),
+            shutdown,
+            event_kinds: MarketEventKind::ALL.to_vec(),
+        }
+    }
+
+    /// Chooses which kinds of market events are stored, the same as the market workers
+    pub(crate) fn set_event_kinds(&mut self, event_kinds: &[MarketEventKind]) {
+        self.event_kinds = event_kinds.to_vec();
+    }
+
+    /// Retry inside a loop until shutdown, transient RPC failures skip a pass.
+    pub(crate) async fn run(mut self) -> VybeResult<()> {
+        info!("Retrying dead letters..");
+        let mut interval = tokio::time::interval(Duration::from_secs(RETRY_INTERVAL_SECS));
+
+        let shutdown = self.shutdown.clone();
+        while shutdown.or_requested(interval.tick()).await.is_some() {
+            match shutdown.drain(self.retry()).await {
+                Some(Ok(())) | None => {}
+                Some(Err(e)) if e.is_transient() => {
+                    warn!("{e}, retrying dead letters again later..");
+                }
+                Some(Err(e)) => return Err(e),
+            }
+        }
+        info!("Stopped retrying dead letters");
+        Ok(())
+    }
+
+    /// Runs a single pass over the oldest dead letters that still have attempts left
+    async fn retry(&mut self) -> VybeResult<()> {
+        let letters = self
+            .db
+            .get_retryable_dead_letters(MAX_RETRY_ATTEMPTS, RETRY_BATCH)?;
+        let mut resolved = 0_usize;
+        for letter in &letters {
+            if self.retry_letter(letter).await? {
+                resolved += 1;
+            }
+        }
+        if !letters.is_empty() {
+            info!("Resolved {resolved} of {} dead letter(s)", letters.len());
+        }
+        Ok(())
+    }
+
+    /// Extracts a dead letter's transaction again. Its events are stored and the dead letter
+    /// deleted in one transaction, otherwise the failure is recorded as one more attempt.
+    /// Returns whether the dead letter was resolved.
+    async fn retry_letter(&mut self, letter: &DeadLetter) -> VybeResult<bool> {
+        let mut extractor =
+            VybeTradeFillExtractor::with_rpc(Arc::clone(&self.rpc), &letter.market_pubkey)?;
+        extractor.set_event_kinds(&self.event_kinds);
+        let signature = Signature::from_str(&letter.signature)?;
+        let decoded = extractor.extract_events([signature]).await?;
+
+        if let Some(failed) = decoded.failed.first() {
+            self.record_attempt(&failed.dead_letter(&letter.market_pubkey))?;
+            return Ok(false);
+        }
+        let mut new_events = decoded
+            .events
+            .into_iter()
+            .map(NewMarketEvent::try_from)
+            .collect::<Result<NewMarketEvents, _>>()?;
+        if !self.rpc.commitment().is_final() {
+            new_events.mark_provisional();
+        }
+        if let Some(market) = self.db.get_market(&letter.market_pubkey)? {
+            new_events.derive_quote_lots(&market.units());
+        }
+        match self.db.resolve_dead_letter(letter.id, &new_events) {
+            Ok(inserted) => {
+                info!(
+                    "{}: stored {inserted} market event(s) of dead letter {}",
+                    letter.market_pubkey, letter.signature
+                );
+                Ok(true)
+            }
+            Err(e) => {
+                self.record_attempt(&NewDeadLetter::new(
+                    &letter.market_pubkey,
+                    &letter.signature,
+                    DeadLetterKind::Insert,
+                    e.to_string(),
+                ))?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Records one more failed attempt of a dead letter
+    fn record_attempt(&mut self, letter: &NewDeadLetter) -> VybeResult<()> {
+        warn!(
+            "{}: retry of dead letter {} failed: {}",
+            letter.market_pubkey, letter.signature, letter.error_message
+        );
+        self.db.record_dead_letters(std::slice::from_ref(letter))?;
+        metrics::record_dead_letters(&letter.market_pubkey, 1);
+        Ok(())
+    }
+}
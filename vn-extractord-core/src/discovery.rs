@@ -0,0 +1,148 @@
+//! Discovery of every market of the Phoenix program. The program owns market and seat
+//! accounts, only the start of each account is downloaded and the ones holding a market
+//! header are decoded, so new listings are found without copying addresses by hand.
+
+use {
+    crate::{
+        extractor::VybeResult,
+        market::{decode_market, MARKET_METADATA_SIZE, PHOENIX_PROGRAM_ID},
+        rpc::RpcPool,
+    },
+    serde_json::json,
+    solana_account_decoder::{UiAccountEncoding, UiDataSliceConfig},
+    solana_client::{
+        rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+        rpc_request::RpcRequest,
+        rpc_response::RpcKeyedAccount,
+    },
+    solana_sdk::{account::Account, pubkey::Pubkey},
+    std::{collections::HashSet, str::FromStr},
+    tracing::debug,
+    vn_database_core::models::NewMarket,
+};
+
+/// Which discovered markets are extracted, every discovered market is stored either way
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum MarketFilter {
+    /// Extract every market of the program
+    #[default]
+    All,
+    /// Only extract markets whose base or quote mint is one of these (base58 addresses)
+    Mints(HashSet<String>),
+}
+
+impl MarketFilter {
+    /// A filter keeping markets that trade one of `mints`, every market when `mints` is empty
+    ///
+    /// # Errors
+    ///
+    /// `VybeDaemonError::ParsePubkey` if a mint isn't a valid address
+    pub fn mints(mints: &[String]) -> VybeResult<Self> {
+        if mints.is_empty() {
+            return Ok(Self::All);
+        }
+        mints
+            .iter()
+            .map(|mint| Ok(Pubkey::from_str(mint)?.to_string()))
+            .collect::<VybeResult<HashSet<String>>>()
+            .map(Self::Mints)
+    }
+
+    /// Whether a discovered market is extracted
+    pub fn matches(&self, market: &NewMarket) -> bool {
+        match self {
+            Self::All => true,
+            Self::Mints(mints) => {
+                mints.contains(&market.base_mint) || mints.contains(&market.quote_mint)
+            }
+        }
+    }
+}
+
+/// Reads the header of every account owned by the Phoenix program and decodes the markets
+/// among them, seats and other accounts are skipped.
+///
+/// # Errors
+///
+/// `VybeDaemonError::SolanaClient` if the provider kept failing
+/// `VybeDaemonError::CircuitOpen` while the provider is given time to recover
+pub(crate) async fn discover_markets(rpc: &RpcPool) -> VybeResult<Vec<NewMarket>> {
+    let config = RpcProgramAccountsConfig {
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            // Markets hold their whole order book, only the metadata is needed
+            data_slice: Some(UiDataSliceConfig {
+                offset: 0,
+                length: MARKET_METADATA_SIZE,
+            }),
+            commitment: Some(rpc.commitment().config()),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+    let accounts = rpc
+        .call(|sdk| {
+            let params = json!([PHOENIX_PROGRAM_ID.to_string(), config.clone()]);
+            async move {
+                sdk.client
+                    .send::<Vec<RpcKeyedAccount>>(RpcRequest::GetProgramAccounts, params)
+                    .await
+            }
+        })
+        .await?;
+
+    let mut markets = accounts
+        .into_iter()
+        .filter_map(|keyed| {
+            let pubkey = Pubkey::from_str(&keyed.pubkey).ok()?;
+            let account = keyed.account.decode::<Account>()?;
+            decode_market(&pubkey, &account.owner, &account.data)
+                .inspect_err(|e| debug!("Skipping {pubkey}: {e}"))
+                .ok()
+        })
+        .collect::<Vec<NewMarket>>();
+    markets.sort_by(|a, b| a.market_pubkey.cmp(&b.market_pubkey));
+    Ok(markets)
+}
+
+#[cfg(test)]
+mod test {
+    use {super::MarketFilter, crate::error::VybeDaemonError, vn_database_core::models::NewMarket};
+
+    /// A SOL/USDC market
+    fn sol_usdc() -> NewMarket {
+        NewMarket {
+            market_pubkey: "4DoNfFBfF7UokCC2FQzriy7yHK6DY6NVdYpuekQ5pRgg".to_owned(),
+            base_mint: "So11111111111111111111111111111111111111112".to_owned(),
+            quote_mint: "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".to_owned(),
+            base_decimals: 9,
+            quote_decimals: 6,
+            base_lot_size: 1_000_000,
+            quote_lot_size: 1,
+            tick_size_in_quote_atoms_per_base_unit: 1000,
+            raw_base_units_per_base_unit: 1,
+            taker_fee_bps: 2,
+        }
+    }
+
+    #[test]
+    fn filter_markets_by_mint() {
+        let market = sol_usdc();
+        assert!(MarketFilter::All.matches(&market));
+        assert_eq!(MarketFilter::mints(&[]).ok(), Some(MarketFilter::All));
+
+        let usdc = MarketFilter::mints(&[market.quote_mint.clone()]).expect("valid mint");
+        assert!(usdc.matches(&market));
+        let sol = MarketFilter::mints(&[market.base_mint.clone()]).expect("valid mint");
+        assert!(sol.matches(&market));
+        let bonk =
+            MarketFilter::mints(&["DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263".to_owned()])
+                .expect("valid mint");
+        assert!(!bonk.matches(&market));
+
+        assert!(matches!(
+            MarketFilter::mints(&["not a mint".to_owned()]),
+            Err(VybeDaemonError::ParsePubkey(_))
+        ));
+    }
+}
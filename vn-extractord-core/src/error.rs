@@ -1,8 +1,13 @@
 //! Custom error handling
 
 use {
-    ellipsis_client::EllipsisClientError, solana_client::client_error::ClientError,
-    solana_sdk::pubkey::ParsePubkeyError, thiserror::Error, tokio::task::JoinError,
+    crate::{backfill::HistoryBound, rpc::is_endpoint_failure},
+    ellipsis_client::EllipsisClientError,
+    solana_client::{client_error::ClientError, pubsub_client::PubsubClientError},
+    solana_sdk::{pubkey::ParsePubkeyError, signature::ParseSignatureError},
+    std::time::Duration,
+    thiserror::Error,
+    tokio::task::JoinError,
     vn_database_core::VybeDatabaseError,
 };
 
@@ -12,6 +17,9 @@ pub enum VybeDaemonError {
     /// Address string is wrong size or invalid
     #[error(transparent)]
     ParsePubkey(#[from] ParsePubkeyError),
+    /// Signature string stored in a checkpoint is invalid
+    #[error(transparent)]
+    ParseSignature(#[from] ParseSignatureError),
     /// Wrapper error for phoenix-sdk client errors
     #[error("{0}")]
     PhoenixClient(String),
@@ -21,10 +29,58 @@ pub enum VybeDaemonError {
     /// Encapsulate Solana Client Errors
     #[error(transparent)]
     SolanaClient(#[from] ClientError),
+    /// No RPC endpoint is configured, or every one failed without returning an error
+    #[error("No RPC endpoint configured")]
+    NoRpcEndpoint,
+    /// Too many RPC requests failed in a row, the provider is given time to recover
+    #[error("RPC provider is unavailable, retrying in {0:?}")]
+    CircuitOpen(Duration),
+    /// Encapsulate Solana WebSocket (pubsub) client errors
+    #[error(transparent)]
+    PubsubClient(#[from] PubsubClientError),
+    /// The WebSocket subscription task stopped before confirming the subscription
+    #[error("WebSocket subscription stopped before it was confirmed")]
+    SubscriptionClosed,
+    /// The metrics listener couldn't be started, or a file sink couldn't be written
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// A parquet file couldn't be written
+    #[error(transparent)]
+    Parquet(#[from] parquet::errors::ParquetError),
     /// Encapsulate tokio join errors
     #[error(transparent)]
     TokioJoin(#[from] JoinError),
     /// Database connection errors
     #[error(transparent)]
     Database(#[from] VybeDatabaseError),
+    /// A backfill bound is neither a slot number nor an RFC 3339 time
+    #[error("Invalid backfill bound '{0}', expected a slot number or an RFC 3339 time")]
+    InvalidHistoryBound(String),
+    /// A commitment level other than processed, confirmed or finalized
+    #[error("Invalid commitment '{0}', expected processed, confirmed or finalized")]
+    InvalidCommitment(String),
+    /// A market address doesn't hold a Phoenix market account
+    #[error("Invalid market account, {0}")]
+    InvalidMarketAccount(String),
+    /// A sink other than postgres, jsonl, parquet or stdout
+    #[error("Invalid sink '{0}', expected postgres, jsonl, parquet or stdout")]
+    InvalidSink(String),
+    /// An operation that reads or writes the daemon's state without the postgres sink
+    #[error("{0} needs the postgres sink")]
+    DatabaseRequired(&'static str),
+    /// A backfill range ends before it starts
+    #[error("Invalid backfill range, {0} is after {1}")]
+    InvalidBackfillRange(HistoryBound, HistoryBound),
+}
+
+impl VybeDaemonError {
+    /// Whether the error is caused by the RPC provider being unavailable, rate limiting or
+    /// overloaded. Such errors go away by themselves, extraction pauses instead of stopping.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::SolanaClient(e) => is_endpoint_failure(e),
+            Self::CircuitOpen(_) => true,
+            _ => false,
+        }
+    }
 }
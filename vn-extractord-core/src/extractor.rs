@@ -1,39 +1,159 @@
-//! crate to extract trade fill events from solana using the phoenix dex software development kit
+//! crate to extract market events from solana using the phoenix dex software development kit
 
 use {
-    crate::error::VybeDaemonError,
+    crate::{
+        book::OrderBook,
+        error::VybeDaemonError,
+        market::{decode_book, decode_ladder, decode_market},
+        pipeline::{Pipeline, Source},
+        rpc::{RpcConfig, RpcPool},
+    },
     derive_getters::Getters,
-    futures::StreamExt,
-    phoenix_sdk::sdk_client::{MarketEventDetails, PhoenixEvent, SDKClient},
-    solana_sdk::{pubkey::Pubkey, signature::Signature, signer::keypair::Keypair},
-    std::{convert::TryFrom, str::FromStr, sync::Arc},
-    tokio::task::JoinHandle,
-    tracing::{debug, error, info, warn},
+    phoenix_sdk::sdk_client::PhoenixEvent,
+    serde_json::json,
+    solana_account_decoder::{UiAccount, UiAccountEncoding},
+    solana_client::{
+        rpc_client::GetConfirmedSignaturesForAddress2Config, rpc_config::RpcAccountInfoConfig,
+        rpc_request::RpcRequest, rpc_response::Response,
+    },
+    solana_sdk::{account::Account, pubkey::Pubkey, signature::Signature},
+    std::{
+        convert::TryFrom,
+        str::FromStr,
+        sync::Arc,
+        time::{SystemTime, UNIX_EPOCH},
+    },
+    tracing::{debug, error, info},
+    vn_config::{DEFAULT_PARSE_CONCURRENCY, MAX_SIGNATURE_PAGE_SIZE},
+    vn_database_core::models::{
+        DeadLetterKind, MarketEventKind, NewDeadLetter, NewMarket, NewOrderbookSnapshot,
+    },
 };
 
-/// Remote Procedure Call endpoint for Solana
-const HELIUS_RPC_ENDPOINT: &str = "https://mainnet.helius-rpc.com/?api-key=";
+/// Custom result type
+pub type VybeResult<T> = Result<T, VybeDaemonError>;
 
-/// The `get_signatures_for_address` endpoint should return 1000 signatures
-const NUM_EXPECTED_TRANSACTIONS: usize = 1000;
+/// The newest transaction signature (and the slot it landed in) that has been processed
+/// for a market. Signatures are fetched backwards from the chain tip until this one is reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignatureCursor {
+    /// Newest processed transaction signature
+    pub signature: Signature,
+    /// Slot of the newest processed transaction
+    pub slot: u64,
+}
 
-/// Number of task threads we want to use when parsing transactions concurrently
-const NUM_TASK_THREADS: usize = 100;
+/// The market sequence number of a Phoenix event and the slot its transaction landed in.
+/// Every instruction on a market takes the next sequence number, its events share it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SequenceMark {
+    /// Market sequence number of the event's instruction
+    pub sequence_number: u64,
+    /// Slot the event's transaction landed in
+    pub slot: u64,
+}
 
-/// Custom result type
-pub type VybeResult<T> = Result<T, VybeDaemonError>;
+impl SequenceMark {
+    /// Sequence number and slot of `event`
+    pub fn of(event: &PhoenixEvent) -> Self {
+        Self {
+            sequence_number: event.sequence_number,
+            slot: event.slot,
+        }
+    }
+}
+
+/// A transaction signature that touched the market, with where and when it landed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignatureInfo {
+    /// Transaction signature
+    pub signature: Signature,
+    /// Slot the transaction landed in
+    pub slot: u64,
+    /// Estimated production time of the block, when the RPC node knows it
+    pub block_time: Option<i64>,
+}
+
+/// One page of signatures returned by `get_signatures_for_address`, newest first
+#[derive(Debug)]
+pub struct SignaturePage {
+    /// Signatures in the page, newest first
+    pub signatures: Vec<SignatureInfo>,
+    /// The page was shorter than the limit, there is nothing older to fetch
+    pub exhausted: bool,
+}
 
-/// Wraps the Helium blochchain RPC service, and the Phoenix SDK onchain orderbook.
-#[derive(Getters)]
+/// A transaction that could not be fetched or decoded for a reason retrying right away won't fix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailedTransaction {
+    /// Signature of the transaction
+    pub signature: Signature,
+    /// Stage that failed
+    pub kind: DeadLetterKind,
+    /// Why it failed
+    pub error: String,
+}
+
+impl FailedTransaction {
+    /// Dead letter recording this failure for `market_pubkey`
+    pub fn dead_letter(&self, market_pubkey: &str) -> NewDeadLetter {
+        NewDeadLetter::new(
+            market_pubkey,
+            &self.signature.to_string(),
+            self.kind,
+            self.error.clone(),
+        )
+    }
+}
+
+/// Market events decoded from a batch of transactions, and the transactions that failed
+#[derive(Debug, Default)]
+pub struct DecodedEvents {
+    /// Market events of the stored kinds, sorted by `sequence_number`
+    pub events: Vec<PhoenixEvent>,
+    /// Transactions whose events are missing from `events`
+    pub failed: Vec<FailedTransaction>,
+    /// Sequence numbers of every Phoenix event of the decoded transactions, whatever its kind
+    pub sequences: Vec<SequenceMark>,
+}
+
+/// Market events extracted in one pass, and the cursor they were extracted up to.
+/// The cursor should only be applied with `VybeTradeFillExtractor::set_cursor`
+/// once the events have been stored.
+#[derive(Debug, Default)]
+pub struct Extraction {
+    /// Market events of the stored kinds, sorted by `sequence_number`
+    pub events: Vec<PhoenixEvent>,
+    /// Transactions that failed for good, their events are missing from `events`
+    pub failed: Vec<FailedTransaction>,
+    /// Sequence numbers of every Phoenix event of the decoded transactions, whatever its kind
+    pub sequences: Vec<SequenceMark>,
+    /// Signatures of the transactions that were decoded, oldest first
+    pub signatures: Vec<Signature>,
+    /// Newest signature seen during this pass, `None` when no new signatures were found
+    pub cursor: Option<SignatureCursor>,
+}
+
+/// Wraps the blockchain RPC service (Helius or any other provider), and the Phoenix SDK onchain orderbook.
+#[derive(Getters, Clone)]
 pub struct VybeTradeFillExtractor {
     /// Public Key to market
     market_pubkey: Pubkey,
-    /// Atomically ref counted client
-    sdk_client: Arc<SDKClient>,
+    /// Atomically ref counted pool of RPC endpoints, shared with the extractors of other markets
+    rpc: Arc<RpcPool>,
+    /// Newest signature that has been processed, `None` until the first pass completes
+    cursor: Option<SignatureCursor>,
+    /// Kinds of market events that are extracted, every kind by default
+    event_kinds: Vec<MarketEventKind>,
+    /// Number of transactions fetched and parsed concurrently
+    parse_concurrency: usize,
+    /// Number of signatures listed per `get_signatures_for_address` request, at most 1000
+    signature_page_size: usize,
 }
 
 impl VybeTradeFillExtractor {
-    /// Creates a new `VybeTradeFillExtractor` instance by concatenating the Helium API endpoint with the provided API key.
+    /// Creates a new `VybeTradeFillExtractor` instance connected to Helius mainnet with the provided API key,
+    /// use `VybeTradeFillExtractor::with_rpc` for any other provider.
     ///
     /// # Parameters
     ///
@@ -48,149 +168,305 @@ impl VybeTradeFillExtractor {
     ///
     /// Result<Self, `VybeDaemonError::Pubkey`>
     pub async fn new(api_key: &str, phoenix_addr: &str) -> VybeResult<Self> {
-        let url = format!("{HELIUS_RPC_ENDPOINT}{api_key}");
-        let market_pubkey = Pubkey::try_from(phoenix_addr)?;
-        match SDKClient::new(&Keypair::new(), url.as_str()).await {
-            Ok(sdk_client) => {
-                debug!("Connected to solana provider {HELIUS_RPC_ENDPOINT}");
-                Ok(Self {
-                    market_pubkey,
-                    sdk_client: Arc::new(sdk_client),
-                })
-            }
-            Err(e) => Err(VybeDaemonError::PhoenixClient(e.to_string())),
-        }
+        Self::connect(&RpcConfig::helius(api_key), phoenix_addr).await
     }
 
-    /// Extracts and returns Fill events, the fill events are sorted by
-    /// the `sequence_number`, but since Solana is a distributed system
-    /// this makes no gaurentee that fill events will be in order in the database,
-    /// we could wait until we have 10, or 20 fill events, then sort by sequence number
+    /// Creates a new `VybeTradeFillExtractor` connected to its own pool of RPC endpoints,
+    /// `RpcConfig::fixtures` replays recorded transactions without a network.
+    ///
+    /// # Parameters
+    ///
+    /// - `rpc_config`: primary and fallback RPC endpoints of any provider
+    /// - `phoenix_addr`: Phoenix market address
+    ///
+    /// # Errors
+    ///
+    /// `VybeDaemonError::PhoenixClient` if an RPC client can't be created
+    /// `VybeDaemonError::Pubkey` if `phoenix_addr` is incorrect size
+    pub async fn connect(rpc_config: &RpcConfig, phoenix_addr: &str) -> VybeResult<Self> {
+        let rpc = RpcPool::connect(rpc_config).await?;
+        Self::with_rpc(Arc::new(rpc), phoenix_addr)
+    }
+
+    /// Creates a new `VybeTradeFillExtractor` for a market on an existing pool of RPC endpoints.
+    ///
+    /// # Parameters
+    ///
+    /// - `rpc`: endpoints returned by `RpcPool::connect`
+    /// - `phoenix_addr`: Phoenix market address
+    ///
+    /// # Errors
+    ///
+    /// `VybeDaemonError::Pubkey` if `phoenix_addr` is incorrect size
+    pub fn with_rpc(rpc: Arc<RpcPool>, phoenix_addr: &str) -> VybeResult<Self> {
+        Ok(Self {
+            market_pubkey: Pubkey::try_from(phoenix_addr)?,
+            rpc,
+            cursor: None,
+            event_kinds: MarketEventKind::ALL.to_vec(),
+            parse_concurrency: DEFAULT_PARSE_CONCURRENCY,
+            signature_page_size: MAX_SIGNATURE_PAGE_SIZE,
+        })
+    }
+
+    /// Chooses which kinds of market events are extracted, the others are skipped
+    pub fn set_event_kinds(&mut self, event_kinds: &[MarketEventKind]) {
+        self.event_kinds = event_kinds.to_vec();
+    }
+
+    /// Chooses how many transactions are fetched and parsed concurrently, and how many
+    /// signatures are listed per request (the provider caps it at 1000)
+    pub fn set_parse_limits(&mut self, parse_concurrency: usize, signature_page_size: usize) {
+        self.parse_concurrency = parse_concurrency.max(1);
+        self.signature_page_size = signature_page_size.clamp(1, MAX_SIGNATURE_PAGE_SIZE);
+    }
+
+    /// Moves the extractor's cursor, the next call to `extract` only looks at
+    /// transactions newer than this signature.
+    pub fn set_cursor(&mut self, cursor: SignatureCursor) {
+        self.cursor = Some(cursor);
+    }
+
+    /// Extracts and returns market events from every transaction newer than the current cursor,
+    /// the events are sorted by the `sequence_number`, but since Solana is a distributed system
+    /// this makes no gaurentee that events will be in order in the database,
+    /// we could wait until we have 10, or 20 events, then sort by sequence number
     /// then write to the database, but then we get further and further away from real-time/near
     /// real-time.
     ///
+    /// The cursor is not moved by this call, apply `Extraction::cursor` with `set_cursor` once the
+    /// events have been stored.
+    ///
     /// # Errors
     ///
-    /// `VybeDaemonError::PhoenixClient`
-    /// `VybeDaemonError::EllipsisClient`
-    /// `VybeDaemonError::SolanaClient`
+    /// `VybeDaemonError::SolanaClient` if the provider kept failing, see `VybeDaemonError::is_transient`
+    /// `VybeDaemonError::CircuitOpen` while the provider is given time to recover
     /// `VybeDaemonError::TokioJoin`
     ///
     /// # Returns
     ///
-    /// `Result<Vec<PhoenixEvent>, VybeDaemonError>>`
-    pub async fn extract(&self) -> VybeResult<Option<Vec<PhoenixEvent>>> {
-        info!("Extracting new fill events...");
-        let signatures = self.get_signatures().await?;
-        if signatures.len() < NUM_EXPECTED_TRANSACTIONS {
-            warn!("Extracted {} signatures..", signatures.len());
+    /// `Result<Extraction, VybeDaemonError>>`
+    pub async fn extract(&self) -> VybeResult<Extraction> {
+        info!("Extracting new market events...");
+        let mut pipeline = self.pipeline();
+        let mut extraction = Extraction::default();
+        while let Some(batch) = pipeline.next_batch().await? {
+            extraction.events.extend(batch.events);
+            extraction.failed.extend(batch.failed);
+            extraction.sequences.extend(batch.sequences);
+            extraction.signatures.extend(batch.signatures);
+            extraction.cursor = batch.cursor.or(extraction.cursor);
         }
 
-        let handles = self.build_event_handles(signatures);
-        let mut fill_events = Self::extract_fill_events(handles).await?;
-
-        info!("Recieved {} fill event(s)", fill_events.len());
-        if fill_events.is_empty() {
-            Ok(None)
-        } else {
-            if fill_events.len() > 1 {
-                fill_events.sort_by_key(|event| event.sequence_number);
-            }
-            Ok(Some(fill_events))
+        debug!(
+            "Extracted {} new signature(s)..",
+            extraction.signatures.len()
+        );
+        extraction.events.sort_by_key(|event| event.sequence_number);
+        Ok(extraction)
+    }
+
+    /// Starts an extraction pass from the current cursor, see `Pipeline::next_batch`.
+    /// The signatures above the cursor are listed, then fetched and decoded by up to
+    /// `parse_concurrency` tasks and returned in batches oldest first, each batch should be
+    /// stored (and the cursor moved to `ExtractionBatch::cursor`) before asking for the next.
+    pub fn pipeline(&self) -> Pipeline {
+        Pipeline::spawn(self, Source::Cursor)
+    }
+
+    /// Decodes the given transactions and returns their market events of the extracted kinds,
+    /// sorted by `sequence_number`.
+    /// Transactions that fail for a reason retrying won't fix are logged and returned in
+    /// `DecodedEvents::failed`, a transient failure fails the whole batch so that nothing
+    /// is skipped while the provider is down.
+    ///
+    /// # Errors
+    ///
+    /// `VybeDaemonError::SolanaClient` if the provider kept failing
+    /// `VybeDaemonError::CircuitOpen` while the provider is given time to recover
+    /// `VybeDaemonError::TokioJoin`
+    pub async fn extract_events(
+        &self,
+        signatures: impl IntoIterator<Item = Signature>,
+    ) -> VybeResult<DecodedEvents> {
+        let mut pipeline =
+            Pipeline::spawn(self, Source::Signatures(signatures.into_iter().collect()));
+        let mut decoded = DecodedEvents::default();
+        while let Some(batch) = pipeline.next_batch().await? {
+            decoded.events.extend(batch.events);
+            decoded.failed.extend(batch.failed);
+            decoded.sequences.extend(batch.sequences);
         }
+
+        info!("Recieved {} market event(s)", decoded.events.len());
+        decoded.events.sort_by_key(|event| event.sequence_number);
+        Ok(decoded)
+    }
+
+    /// Reads the market's metadata (mints, decimals, lot sizes, tick size and taker fee)
+    /// from the header of its on-chain account.
+    ///
+    /// # Errors
+    ///
+    /// `VybeDaemonError::SolanaClient` if the provider kept failing
+    /// `VybeDaemonError::CircuitOpen` while the provider is given time to recover
+    /// `VybeDaemonError::InvalidMarketAccount` if the account doesn't exist or isn't a Phoenix market
+    pub async fn get_market(&self) -> VybeResult<NewMarket> {
+        let (_, account) = self.get_account().await?;
+        decode_market(&self.market_pubkey, &account.owner, &account.data)
     }
 
-    /// Get signatures
-    async fn get_signatures(&self) -> VybeResult<Vec<Option<Signature>>> {
-        debug!("Getting signatures...");
-        Ok(self
-            .sdk_client
-            .client
-            .get_signatures_for_address(&self.market_pubkey)
-            .await?
+    /// Reads the top `depth` price levels of each side of the market's book from its
+    /// on-chain account, stamped with the slot it was read at and the current time.
+    ///
+    /// # Errors
+    ///
+    /// `VybeDaemonError::SolanaClient` if the provider kept failing
+    /// `VybeDaemonError::CircuitOpen` while the provider is given time to recover
+    /// `VybeDaemonError::InvalidMarketAccount` if the account doesn't exist or isn't a Phoenix market
+    pub async fn get_orderbook_snapshot(&self, depth: u64) -> VybeResult<NewOrderbookSnapshot> {
+        let (slot, account) = self.get_account().await?;
+        let ladder = decode_ladder(&self.market_pubkey, &account.owner, &account.data, depth)?;
+        let snapshot_timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        Ok(NewOrderbookSnapshot {
+            market_pubkey: self.market_pubkey.to_string(),
+            slot: i64::try_from(slot).unwrap_or(i64::MAX),
+            snapshot_timestamp: i64::try_from(snapshot_timestamp).unwrap_or(i64::MAX),
+            bids: ladder.bids,
+            asks: ladder.asks,
+        })
+    }
+
+    /// Reads every resting order of the market's book from its on-chain account
+    ///
+    /// # Errors
+    ///
+    /// `VybeDaemonError::SolanaClient` if the provider kept failing
+    /// `VybeDaemonError::CircuitOpen` while the provider is given time to recover
+    /// `VybeDaemonError::InvalidMarketAccount` if the account doesn't exist or isn't a Phoenix market
+    ///
+    /// # Returns
+    ///
+    /// The slot the account was read at and its book
+    pub async fn get_order_book(&self) -> VybeResult<(u64, OrderBook)> {
+        let (slot, account) = self.get_account().await?;
+        let book = decode_book(&self.market_pubkey, &account.owner, &account.data)?;
+        Ok((slot, book))
+    }
+
+    /// Fetches the market's on-chain account with the slot it was read at
+    ///
+    /// # Errors
+    ///
+    /// `VybeDaemonError::SolanaClient` if the provider kept failing
+    /// `VybeDaemonError::CircuitOpen` while the provider is given time to recover
+    /// `VybeDaemonError::InvalidMarketAccount` if the account doesn't exist
+    async fn get_account(&self) -> VybeResult<(u64, Account)> {
+        let market_pubkey = self.market_pubkey;
+        let config = RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(self.rpc.commitment().config()),
+            ..RpcAccountInfoConfig::default()
+        };
+        // `RpcClient::get_account_with_config` reports transport failures as a missing
+        // account, send the request directly so they are retried
+        let response = self
+            .rpc
+            .call(|sdk| {
+                let params = json!([market_pubkey.to_string(), config.clone()]);
+                async move {
+                    sdk.client
+                        .send::<Response<Option<UiAccount>>>(RpcRequest::GetAccountInfo, params)
+                        .await
+                }
+            })
+            .await?;
+        let account = response
+            .value
+            .and_then(|account| account.decode::<Account>())
+            .ok_or_else(|| {
+                VybeDaemonError::InvalidMarketAccount(format!("{market_pubkey} doesn't exist"))
+            })?;
+        Ok((response.context.slot, account))
+    }
+
+    /// Fetches a single page of signatures that touched the market, newest first.
+    ///
+    /// # Parameters
+    ///
+    /// - `before`: only return signatures older than this one, `None` starts at the chain tip
+    /// - `until`: stop once this signature is reached, it is not included in the page
+    ///
+    /// # Errors
+    ///
+    /// `VybeDaemonError::SolanaClient`
+    pub async fn get_signature_page(
+        &self,
+        before: Option<Signature>,
+        until: Option<Signature>,
+    ) -> VybeResult<SignaturePage> {
+        let commitment = self.rpc.commitment().config();
+        let page = self
+            .rpc
+            .call(|sdk| async move {
+                sdk.client
+                    .get_signatures_for_address_with_config(
+                        &self.market_pubkey,
+                        GetConfirmedSignaturesForAddress2Config {
+                            before,
+                            until,
+                            limit: Some(self.signature_page_size),
+                            commitment: Some(commitment),
+                        },
+                    )
+                    .await
+            })
+            .await?;
+
+        let exhausted = page.len() < self.signature_page_size;
+        let signatures = page
             .into_iter()
-            .map(
+            .filter_map(
                 |rpc_cts| match Signature::from_str(rpc_cts.signature.as_str()) {
-                    Ok(sig) => Some(sig),
+                    Ok(signature) => Some(SignatureInfo {
+                        signature,
+                        slot: rpc_cts.slot,
+                        block_time: rpc_cts.block_time,
+                    }),
                     Err(e) => {
                         error!("Failed to parse a Signature: {e}");
                         None
                     }
                 },
             )
-            .collect::<Vec<Option<Signature>>>())
-    }
+            .collect();
 
-    /// Build a single asynchronous task handle responsible for parsing each transaction signature
-    fn build_event_handles(
-        &self,
-        signatures: impl IntoIterator<Item = Option<Signature>>,
-    ) -> Vec<JoinHandle<Option<Vec<PhoenixEvent>>>> {
-        debug!("Building event task handles...");
-        let mut handles: Vec<JoinHandle<Option<Vec<PhoenixEvent>>>> = vec![];
-        for opt_sig in signatures {
-            let sdk = Arc::<SDKClient>::clone(&self.sdk_client);
-            if let Some(sig) = opt_sig {
-                handles.push(tokio::spawn(async move {
-                    sdk.parse_events_from_transaction(&sig).await
-                }));
-            }
-        }
-        handles
-    }
-
-    /// Extract fill events from each async handle.
-    /// Since 1000 is a decently sized number let's use a stream that buffers around 100
-    /// join handles concurrently to try and be as fast as possible.
-    async fn extract_fill_events(
-        handles: Vec<JoinHandle<Option<Vec<PhoenixEvent>>>>,
-    ) -> VybeResult<Vec<PhoenixEvent>> {
-        let mut fill_events = Vec::new();
-
-        // Create a stream that buffers up to N join handles concurrently.
-        let mut stream = futures::stream::iter(handles).buffered(NUM_TASK_THREADS);
-        while let Some(join_result) = stream.next().await {
-            let opt_events = join_result?;
-            if let Some(events) = opt_events {
-                for event in events {
-                    if Self::is_market_fill_event(&event.details) {
-                        fill_events.push(event);
-                    }
-                }
-            }
-        }
-        Ok(fill_events)
-    }
-
-    /// Identify if a given phoenix event is a fill event
-    fn is_market_fill_event(details: &MarketEventDetails) -> bool {
-        match *details {
-            MarketEventDetails::Fill(_) => true,
-            MarketEventDetails::Place(_)
-            | MarketEventDetails::Evict(_)
-            | MarketEventDetails::Reduce(_)
-            | MarketEventDetails::FillSummary(_)
-            | MarketEventDetails::Fee(_)
-            | MarketEventDetails::TimeInForce(_) => false,
-        }
+        Ok(SignaturePage {
+            signatures,
+            exhausted,
+        })
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::VybeTradeFillExtractor;
+    use {super::VybeTradeFillExtractor, crate::rpc::RpcConfig, std::path::PathBuf};
     const PHOENIX_ADDRESS: &str = "PhoeNiXZ8ByJGLkxNfZRnkUfjvmuYqLR89jjFHGqdXY";
 
     #[tokio::test]
     async fn verify_tx_extractor_construction() {
-        match VybeTradeFillExtractor::new("some-api-key", PHOENIX_ADDRESS).await {
-            Ok(_txe) => {
-                assert!(true);
-            }
-            Err(err) => {
-                println!("{err}");
-                assert!(false);
-            }
-        }
+        // Replays the recorded fixtures, no network is needed
+        let fixtures =
+            RpcConfig::fixtures(&PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures"));
+        let extractor = VybeTradeFillExtractor::connect(&fixtures, PHOENIX_ADDRESS)
+            .await
+            .expect("offline extractor");
+        assert_eq!(extractor.market_pubkey().to_string(), PHOENIX_ADDRESS);
+        assert!(extractor.cursor().is_none());
+        assert!(VybeTradeFillExtractor::connect(&fixtures, "not a market")
+            .await
+            .is_err());
     }
 }
@@ -0,0 +1,291 @@
+//! Offline replay of recorded transactions. Raw `getTransaction` responses are recorded to a
+//! directory, one `<signature>.json` file per transaction, and a fixture directory can be used
+//! as an RPC endpoint (`file://<dir>`) so the extractor decodes the recorded transactions
+//! without a network. Useful for deterministic tests and reproducing production bugs.
+
+use {
+    async_trait::async_trait,
+    serde_json::{json, Value},
+    solana_client::{
+        client_error::Result as ClientResult,
+        rpc_request::{RpcError, RpcRequest, RpcResponseErrorData},
+        rpc_sender::{RpcSender, RpcTransportStats},
+    },
+    std::{
+        cmp::Reverse,
+        fs,
+        path::{Path, PathBuf},
+    },
+    tracing::{debug, warn},
+};
+
+/// Url scheme of fixture directories used as an RPC endpoint
+pub(crate) const FIXTURE_SCHEME: &str = "file://";
+
+/// `getSignaturesForAddress` returns at most this many signatures when no limit is given
+const DEFAULT_SIGNATURE_LIMIT: usize = 1000;
+
+/// JSON-RPC error code of a method the endpoint doesn't know
+const JSON_RPC_METHOD_NOT_FOUND: i64 = -32601;
+
+/// Path of the recorded response of a transaction
+fn transaction_path(dir: &Path, signature: &str) -> PathBuf {
+    dir.join(format!("{signature}.json"))
+}
+
+/// Writes a `getTransaction` response to `<dir>/<signature>.json`, failures are only logged
+/// since recording must never break extraction.
+pub(crate) fn record_transaction(dir: &Path, signature: &str, transaction: &Value) {
+    if transaction.is_null() {
+        return;
+    }
+    let path = transaction_path(dir, signature);
+    let recorded = fs::create_dir_all(dir)
+        .and_then(|()| Ok(serde_json::to_vec_pretty(transaction)?))
+        .and_then(|json| fs::write(&path, json));
+    match recorded {
+        Ok(()) => debug!("Recorded transaction {signature} to {}", path.display()),
+        Err(e) => warn!("Failed to record transaction {signature}: {e}"),
+    }
+}
+
+/// Whether a recorded transaction mentions the address, either as a static account key or
+/// as an address loaded from a lookup table
+fn mentions(transaction: &Value, address: &str) -> bool {
+    let static_keys = transaction["transaction"]["message"]["accountKeys"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|key| key.as_str().or_else(|| key["pubkey"].as_str()));
+    let loaded = ["writable", "readonly"].into_iter().flat_map(|kind| {
+        transaction["meta"]["loadedAddresses"][kind]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(Value::as_str)
+    });
+    static_keys.chain(loaded).any(|key| key == Some(address))
+}
+
+/// RPC transport answering from a directory of recorded transactions.
+///
+/// - `getTransaction` returns `<dir>/<signature>.json`, or `null` like a node that doesn't know it
+/// - `getSignaturesForAddress` lists the recorded transactions mentioning the address,
+///   newest slot first (ties by descending signature), honoring `before`, `until` and `limit`
+/// - any other method returns `<dir>/<method>.json` when it exists, an error otherwise
+pub(crate) struct FixtureSender {
+    /// Directory of the recorded responses
+    dir: PathBuf,
+}
+
+impl FixtureSender {
+    /// Creates a transport for the fixture directory `dir`
+    pub(crate) fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Reads a recorded response, `None` when it doesn't exist
+    fn read(&self, path: &Path) -> ClientResult<Option<Value>> {
+        match fs::read(path) {
+            Ok(json) => Ok(Some(serde_json::from_slice(&json)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Every recorded transaction of the directory
+    fn transactions(&self) -> ClientResult<Vec<Value>> {
+        let mut transactions = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                if let Some(transaction) = self
+                    .read(&path)?
+                    .filter(|transaction| transaction.get("slot").is_some())
+                {
+                    transactions.push(transaction);
+                }
+            }
+        }
+        Ok(transactions)
+    }
+
+    /// Answers `getSignaturesForAddress` from the recorded transactions
+    fn signatures_for_address(&self, params: &Value) -> ClientResult<Value> {
+        let address = params[0].as_str().unwrap_or_default();
+        let config = &params[1];
+        let before = config["before"].as_str();
+        let until = config["until"].as_str();
+        let limit = config["limit"]
+            .as_u64()
+            .and_then(|limit| usize::try_from(limit).ok())
+            .unwrap_or(DEFAULT_SIGNATURE_LIMIT);
+
+        let mut statuses = self
+            .transactions()?
+            .into_iter()
+            .filter(|transaction| mentions(transaction, address))
+            .filter_map(|transaction| {
+                let signature = transaction["transaction"]["signatures"][0]
+                    .as_str()?
+                    .to_owned();
+                Some(json!({
+                    "signature": signature,
+                    "slot": transaction["slot"],
+                    "err": transaction["meta"]["err"],
+                    "memo": null,
+                    "blockTime": transaction["blockTime"],
+                    "confirmationStatus": "finalized",
+                }))
+            })
+            .collect::<Vec<Value>>();
+        statuses.sort_by_key(|status| {
+            (
+                Reverse(status["slot"].as_u64()),
+                Reverse(status["signature"].as_str().map(str::to_owned)),
+            )
+        });
+
+        let start = match before {
+            Some(before) => statuses
+                .iter()
+                .position(|status| status["signature"] == before)
+                .map_or(statuses.len(), |index| index.saturating_add(1)),
+            None => 0,
+        };
+        let page = statuses
+            .into_iter()
+            .skip(start)
+            .take_while(|status| status["signature"].as_str() != until)
+            .take(limit)
+            .collect();
+        Ok(Value::Array(page))
+    }
+}
+
+#[async_trait]
+impl RpcSender for FixtureSender {
+    async fn send(&self, request: RpcRequest, params: Value) -> ClientResult<Value> {
+        match request {
+            RpcRequest::GetTransaction => {
+                let signature = params[0].as_str().unwrap_or_default();
+                Ok(self
+                    .read(&transaction_path(&self.dir, signature))?
+                    .unwrap_or_default())
+            }
+            RpcRequest::GetSignaturesForAddress => self.signatures_for_address(&params),
+            _ => self
+                .read(&self.dir.join(format!("{request}.json")))?
+                .ok_or_else(|| {
+                    RpcError::RpcResponseError {
+                        code: JSON_RPC_METHOD_NOT_FOUND,
+                        message: format!("{request} is not recorded in {}", self.dir.display()),
+                        data: RpcResponseErrorData::Empty,
+                    }
+                    .into()
+                }),
+        }
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        RpcTransportStats::default()
+    }
+
+    fn url(&self) -> String {
+        format!("{FIXTURE_SCHEME}{}", self.dir.display())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{mentions, record_transaction, FixtureSender},
+        serde_json::{json, Value},
+        solana_client::{rpc_request::RpcRequest, rpc_sender::RpcSender},
+        std::path::PathBuf,
+    };
+
+    /// A recorded transaction touching `accounts`
+    fn transaction(signature: &str, slot: u64, accounts: &[&str]) -> Value {
+        json!({
+            "slot": slot,
+            "blockTime": 1_740_956_436_i64,
+            "transaction": {
+                "signatures": [signature],
+                "message": { "accountKeys": accounts }
+            },
+            "meta": {
+                "err": null,
+                "loadedAddresses": { "writable": [], "readonly": ["LookedUp"] }
+            }
+        })
+    }
+
+    /// A fresh fixture directory under the system temporary directory
+    fn fixture_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("vn-fixture-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn match_mentioned_accounts() {
+        let transaction = transaction("sig", 1, &["Signer", "Market"]);
+        assert!(mentions(&transaction, "Market"));
+        assert!(mentions(&transaction, "LookedUp"));
+        assert!(!mentions(&transaction, "Other"));
+    }
+
+    #[tokio::test]
+    async fn replay_recorded_transactions() {
+        let dir = fixture_dir("replay");
+        for (signature, slot) in [("a", 10), ("b", 11), ("c", 12)] {
+            record_transaction(&dir, signature, &transaction(signature, slot, &["Market"]));
+        }
+        record_transaction(&dir, "other", &transaction("other", 13, &["Other"]));
+        let sender = FixtureSender::new(dir.clone());
+
+        let page = sender
+            .send(
+                RpcRequest::GetSignaturesForAddress,
+                json!(["Market", { "limit": 2 }]),
+            )
+            .await
+            .unwrap_or_default();
+        assert_eq!(page[0]["signature"], "c");
+        assert_eq!(page[1]["signature"], "b");
+        assert_eq!(page.as_array().map(Vec::len), Some(2));
+
+        let page = sender
+            .send(
+                RpcRequest::GetSignaturesForAddress,
+                json!(["Market", { "before": "c", "until": "a" }]),
+            )
+            .await
+            .unwrap_or_default();
+        assert_eq!(page.as_array().map(Vec::len), Some(1));
+        assert_eq!(page[0]["signature"], "b");
+
+        let recorded = sender
+            .send(
+                RpcRequest::GetTransaction,
+                json!(["b", { "encoding": "json" }]),
+            )
+            .await
+            .unwrap_or_default();
+        assert_eq!(recorded["slot"], 11_u64);
+        let missing = sender
+            .send(RpcRequest::GetTransaction, json!(["missing"]))
+            .await;
+        assert!(missing.is_ok_and(|missing| missing.is_null()));
+        sender
+            .send(RpcRequest::GetAccountInfo, json!(["Market"]))
+            .await
+            .expect_err("getAccountInfo is not recorded");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,220 @@
+//! Sequence number gap detection. Every instruction on a Phoenix market takes the market's next
+//! sequence number, a sequence number that never shows up means events were missed, because a
+//! signature was dropped or a transaction failed to decode. Gaps are recorded as open in the
+//! `sequence_gaps` table, a targeted backfill of their slots then closes them or leaves them
+//! unresolved as a data quality signal.
+
+use {
+    crate::{
+        backfill::{self, BackfillRange, HistoryBound},
+        extractor::{SequenceMark, VybeResult, VybeTradeFillExtractor},
+        rpc::RpcPool,
+        shutdown::Shutdown,
+    },
+    solana_sdk::signature::Signature,
+    std::{collections::BTreeSet, str::FromStr, sync::Arc, time::Duration},
+    tracing::{info, warn},
+    vn_database_core::{
+        models::{MarketEventKind, NewSequenceGap, SequenceGap, SequenceGapStatus},
+        VybeDatabase, VybePool,
+    },
+};
+
+/// How often open gaps are backfilled, missing transactions get time to become visible
+const GAP_FILL_INTERVAL_SECS: u64 = 60;
+
+/// Maximum number of gaps backfilled per pass
+const GAP_FILL_BATCH: i64 = 20;
+
+/// Follows a market's sequence numbers as its events are stored and reports the ones skipped
+#[derive(Debug, Default)]
+pub(crate) struct SequenceTracker {
+    /// Highest sequence number stored so far
+    last: Option<SequenceMark>,
+}
+
+impl SequenceTracker {
+    /// Creates a tracker continuing from the highest stored sequence number
+    pub(crate) fn new(last: Option<SequenceMark>) -> Self {
+        Self { last }
+    }
+
+    /// Moves past the sequence numbers of newly stored events and returns the gaps in front of
+    /// them, as new open gaps of `market_pubkey`. Sequence numbers below the highest one seen
+    /// arrived late, they are ignored and the backfill of their gap finds them.
+    pub(crate) fn observe(
+        &mut self,
+        market_pubkey: &str,
+        sequences: &[SequenceMark],
+    ) -> Vec<NewSequenceGap> {
+        let mut sequences = sequences.to_vec();
+        sequences.sort_unstable();
+        sequences.dedup_by_key(|mark| mark.sequence_number);
+
+        let mut gaps = vec![];
+        for mark in sequences {
+            match self.last {
+                Some(last) if mark.sequence_number <= last.sequence_number => continue,
+                Some(last) if mark.sequence_number > last.sequence_number.saturating_add(1) => {
+                    gaps.push(NewSequenceGap {
+                        market_pubkey: market_pubkey.to_owned(),
+                        first_missing: last.sequence_number.saturating_add(1) as i64,
+                        last_missing: mark.sequence_number.saturating_sub(1) as i64,
+                        from_slot: last.slot as i64,
+                        to_slot: mark.slot as i64,
+                    });
+                }
+                _ => {}
+            }
+            self.last = Some(mark);
+        }
+        gaps
+    }
+}
+
+/// Periodically backfills the slots of open gaps, shares the database pool with the market
+/// workers so it can run on a separate task next to them.
+pub(crate) struct GapFiller {
+    /// RPC endpoints shared with the market workers
+    rpc: Arc<RpcPool>,
+    /// PG database connection and interface
+    db: VybeDatabase,
+    /// Stops the loop once the daemon shuts down
+    shutdown: Shutdown,
+    /// Kinds of market events the market workers store
+    event_kinds: Vec<MarketEventKind>,
+}
+
+impl GapFiller {
+    /// Creates a new gap filler checking connections out of the daemon's pool
+    pub(crate) fn new(rpc: Arc<RpcPool>, database: &VybePool, shutdown: Shutdown) -> Self {
+        Self {
+            rpc,
+            db: VybeDatabase::from_pool(database),
+            shutdown,
+            event_kinds: MarketEventKind::ALL.to_vec(),
+        }
+    }
+
+    /// Chooses which kinds of market events are stored, the same as the market workers
+    pub(crate) fn set_event_kinds(&mut self, event_kinds: &[MarketEventKind]) {
+        self.event_kinds = event_kinds.to_vec();
+    }
+
+    /// Fill gaps inside a loop until shutdown, transient RPC failures skip a pass.
+    /// A gap left open at shutdown is backfilled after the next start.
+    pub(crate) async fn run(mut self) -> VybeResult<()> {
+        info!("Filling sequence number gaps..");
+        let mut interval = tokio::time::interval(Duration::from_secs(GAP_FILL_INTERVAL_SECS));
+
+        let shutdown = self.shutdown.clone();
+        while shutdown.or_requested(interval.tick()).await.is_some() {
+            match shutdown.drain(self.fill_open_gaps()).await {
+                Some(Ok(())) | None => {}
+                Some(Err(e)) if e.is_transient() => {
+                    warn!("{e}, filling sequence number gaps again later..");
+                }
+                Some(Err(e)) => return Err(e),
+            }
+        }
+        info!("Stopped filling sequence number gaps");
+        Ok(())
+    }
+
+    /// Runs a single pass over the oldest open gaps
+    async fn fill_open_gaps(&mut self) -> VybeResult<()> {
+        let gaps =
+            self.db
+                .get_sequence_gaps(None, Some(SequenceGapStatus::Open), GAP_FILL_BATCH)?;
+        for gap in &gaps {
+            self.fill(gap).await?;
+        }
+        Ok(())
+    }
+
+    /// Backfills the slots around a gap, it is closed when every missing sequence number
+    /// showed up and left unresolved otherwise. The progress is kept per gap, a backfill of
+    /// the same slots never makes the gap look filled. History is walked from the stored
+    /// transaction that came after the gap, only from the chain tip when there is none.
+    async fn fill(&mut self, gap: &SequenceGap) -> VybeResult<()> {
+        let mut extractor =
+            VybeTradeFillExtractor::with_rpc(Arc::clone(&self.rpc), &gap.market_pubkey)?;
+        extractor.set_event_kinds(&self.event_kinds);
+        let range = BackfillRange::new(
+            HistoryBound::Slot(u64::try_from(gap.from_slot).unwrap_or_default()),
+            HistoryBound::Slot(u64::try_from(gap.to_slot).unwrap_or_default()),
+        )?;
+        let newest = self
+            .db
+            .get_next_sequence_signature(&gap.market_pubkey, gap.last_missing)?
+            .map(|(_, signature)| Signature::from_str(&signature))
+            .transpose()?;
+
+        let mut seen = BTreeSet::new();
+        backfill::backfill_observed(
+            &extractor,
+            &mut self.db,
+            range,
+            format!("gap:{}", gap.id),
+            newest,
+            |sequences| {
+                seen.extend(sequences.iter().map(|mark| mark.sequence_number as i64));
+            },
+        )
+        .await?;
+
+        let missing = (gap.first_missing..=gap.last_missing)
+            .filter(|sequence_number| !seen.contains(sequence_number))
+            .count();
+        if missing == 0 {
+            info!(
+                "{}: closed the gap of sequence numbers {}..={}",
+                gap.market_pubkey, gap.first_missing, gap.last_missing
+            );
+            self.db
+                .set_sequence_gap_status(gap.id, SequenceGapStatus::Closed)?;
+        } else {
+            warn!(
+                "{}: {missing} sequence number(s) between {} and {} are still missing after a backfill of slots {}..={}",
+                gap.market_pubkey, gap.first_missing, gap.last_missing, gap.from_slot, gap.to_slot
+            );
+            self.db
+                .set_sequence_gap_status(gap.id, SequenceGapStatus::Unresolved)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SequenceMark, SequenceTracker};
+
+    /// Sequence number `sequence_number` landed in `slot`
+    fn mark(sequence_number: u64, slot: u64) -> SequenceMark {
+        SequenceMark {
+            sequence_number,
+            slot,
+        }
+    }
+
+    #[test]
+    fn detect_sequence_gaps() {
+        let mut tracker = SequenceTracker::new(Some(mark(10, 100)));
+        // Events of one instruction share a sequence number
+        assert!(tracker
+            .observe("market", &[mark(11, 101), mark(11, 101), mark(12, 101)])
+            .is_empty());
+
+        let gaps = tracker.observe("market", &[mark(16, 105), mark(14, 103)]);
+        assert_eq!(gaps.len(), 2);
+        assert_eq!((gaps[0].first_missing, gaps[0].last_missing), (13, 13));
+        assert_eq!((gaps[0].from_slot, gaps[0].to_slot), (101, 103));
+        assert_eq!((gaps[1].first_missing, gaps[1].last_missing), (15, 15));
+
+        // Late sequence numbers are left to the backfill of their gap
+        assert!(tracker.observe("market", &[mark(13, 102)]).is_empty());
+        assert!(SequenceTracker::default()
+            .observe("market", &[mark(7, 1), mark(8, 1)])
+            .is_empty());
+    }
+}
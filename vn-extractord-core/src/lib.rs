@@ -1,72 +1,549 @@
-//! crate to extract trade fill events from solana using the phoenix dex software development kit
+//! crate to extract market events from solana using the phoenix dex software development kit
 
+mod backfill;
+mod book;
+mod dead_letter;
+mod discovery;
 mod error;
 mod extractor;
-pub use error::VybeDaemonError;
+mod fixture;
+mod gaps;
+mod limiter;
+mod market;
+mod metrics;
+mod pipeline;
+mod reconcile;
+mod retry;
+mod rpc;
+mod shutdown;
+mod sink;
+mod snapshot;
+mod stream;
+mod worker;
+pub use {
+    backfill::{BackfillRange, BackfillReport, HistoryBound},
+    book::{BookOrder, OrderBook, QueuePosition, SharedOrderBook},
+    discovery::MarketFilter,
+    error::VybeDaemonError,
+    extractor::{
+        DecodedEvents, Extraction, FailedTransaction, SequenceMark, SignatureCursor, SignatureInfo,
+        SignaturePage, VybeTradeFillExtractor,
+    },
+    limiter::{RateLimit, RateLimiterStats},
+    market::Ladder,
+    metrics::serve_metrics,
+    pipeline::{ExtractionBatch, Pipeline},
+    retry::RetryPolicy,
+    rpc::{Commitment, RpcConfig},
+    sink::{EventSink, JsonLinesSink, ParquetSink, PostgresSink, SinkBatch, SinkKind, Sinks},
+    snapshot::SnapshotSchedule,
+    vn_database_core::models::{DeadLetterKind, MarketEventKind},
+};
 
 use {
-    crate::extractor::{VybeResult, VybeTradeFillExtractor},
-    tracing::{error, info},
-    vn_database_core::VybeDatabase,
+    crate::{
+        dead_letter::DeadLetterRetrier, extractor::VybeResult, gaps::GapFiller,
+        reconcile::Reconciler, rpc::RpcPool, shutdown::Shutdown, snapshot::Snapshotter,
+        worker::MarketWorker,
+    },
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        collections::{HashMap, HashSet},
+        sync::Arc,
+        time::Duration,
+    },
+    tokio::{sync::mpsc, task::JoinSet},
+    tokio_util::sync::CancellationToken,
+    tracing::{error, info, warn},
+    vn_config::{
+        DatabaseConfig, VybeConfig, DEFAULT_PARSE_CONCURRENCY, DEFAULT_POLL_INTERVAL_MS,
+        MAX_SIGNATURE_PAGE_SIZE,
+    },
+    vn_database_core::{units::MarketUnits, VybeDatabase, VybePool},
 };
 
+/// How often the time spent waiting for the RPC rate limiters is logged
+const RATE_LIMITER_REPORT_INTERVAL_SECS: u64 = 60;
+
+/// How the market workers extract new transactions
+#[derive(Debug, Clone, Copy)]
+enum WorkerMode {
+    /// Poll for new signatures, see `VybeDaemon::run`
+    Poll,
+    /// Subscribe to the WebSocket logs of the market, see `VybeDaemon::stream`
+    Stream,
+}
+
 /// Top level interface
 pub struct VybeDaemon {
-    /// Phoenix sdk and Helius interface
-    trade_fill_extractor: VybeTradeFillExtractor,
-    /// PG database connection and interface
-    db: VybeDatabase,
+    /// One extraction worker per market, they all share a single pool of RPC endpoints
+    workers: Vec<MarketWorker>,
+    /// Every market that has a worker, including the ones already moved onto their tasks
+    markets: HashSet<Pubkey>,
+    /// Kinds of market events every worker stores
+    event_kinds: Vec<MarketEventKind>,
+    /// How long every worker waits between extraction passes
+    poll_interval: Duration,
+    /// Number of transactions every worker parses concurrently
+    parse_concurrency: usize,
+    /// Number of signatures every worker lists per request
+    signature_page_size: usize,
+    /// Markets discovered while running are extracted when they match the filter,
+    /// the program is scanned again at the interval
+    rediscovery: Option<(MarketFilter, Duration)>,
+    /// RPC endpoints shared by the workers
+    rpc: Arc<RpcPool>,
+    /// Connections every worker and background task shares, `None` when the database isn't
+    /// one of the sinks
+    database: Option<VybePool>,
+    /// Where every worker writes the events it extracts
+    sinks: Sinks,
+    /// WebSocket url used by `VybeDaemon::stream`
+    ws_url: String,
+    /// Settles provisional events, `None` at the finalized commitment or without the database
+    reconciler: Option<Reconciler>,
+    /// Retries dead letters, `None` once it runs on its task or without the database
+    retrier: Option<DeadLetterRetrier>,
+    /// Backfills sequence number gaps, `None` once it runs on its task or without the database
+    gap_filler: Option<GapFiller>,
+    /// Snapshots order books, `None` once it runs on its task or without the database
+    snapshotter: Option<Snapshotter>,
+    /// Tells the snapshotter about every market that gets a worker
+    snapshot_markets: mpsc::UnboundedSender<Pubkey>,
+    /// How often every live order book is checked against its market account, `None` when
+    /// order books aren't tracked
+    book_check_interval: Option<Duration>,
+    /// The live order book of every market, when they are tracked
+    order_books: HashMap<Pubkey, SharedOrderBook>,
+    /// Shared by every task, requested on SIGINT or SIGTERM
+    shutdown: Shutdown,
 }
 
 impl VybeDaemon {
-    /// Creates a new `VybeDaemon` this automatically makes a database connection, and a connection
-    /// to Helius.
+    /// Creates a new `VybeDaemon` this automatically connects to the configured RPC endpoints,
+    /// which are shared by every market, and opens a pool of connections shared by every market
+    /// to the database of `DATABASE_URL`, see `VybeDaemon::from_config` to read a
+    /// configuration file.
+    /// Events are only written to the database, markets that have a stored checkpoint resume
+    /// from it. The metadata of every market (mints, decimals, lot sizes, tick size and fee) is
+    /// read from its on-chain account and stored in the `markets` table.
+    /// The pool is shared with the tasks retrying dead letters, backfilling sequence number
+    /// gaps and snapshotting order books, and below the finalized commitment the one
+    /// reconciling provisional events. It holds at most `DEFAULT_DATABASE_POOL_SIZE`
+    /// connections, `VybeDaemon::from_config` reads `pool_size` from the `[database]` section.
     ///
     /// # Parameters
     ///
-    /// - `api_key`: user's API key to Helium RPC
-    /// - `phoenix_addr`: Phoenix deployment address
+    /// - `rpc_config`: primary and fallback RPC endpoints of any provider
+    /// - `market_addrs`: Phoenix market addresses to extract, duplicates are ignored
     ///
     /// # Errors
     ///
-    /// `VybeDaemonError::Pubkey` if `phoenix_addr` is incorrect size
+    /// `VybeDaemonError::PhoenixClient` if an RPC client can't be created
+    /// `VybeDaemonError::Pubkey` if a market address is incorrect size
+    /// `VybeDaemonError::Database` if the pool can't connect, a checkpoint can't be read or a
+    /// market can't be stored
+    /// `VybeDaemonError::ParseSignature` if a stored checkpoint is corrupt
+    /// `VybeDaemonError::InvalidMarketAccount` if a market address isn't a Phoenix market
+    /// `VybeDaemonError::SolanaClient` if a market account can't be read
     ///
     /// # Returns
     ///
     /// Result<Self, `VybeDaemonError::Pubkey`>
-    pub async fn new(api_key: &str, phoenix_addr: &str) -> VybeResult<Self> {
+    pub async fn new(rpc_config: &RpcConfig, market_addrs: &[String]) -> VybeResult<Self> {
+        Self::connect(
+            rpc_config,
+            DatabaseConfig::from_env(),
+            market_addrs,
+            Sinks::default(),
+        )
+        .await
+    }
+
+    /// Creates a new `VybeDaemon` from a layered configuration, see `vn_config::VybeConfig`.
+    /// The RPC endpoints, markets, event kinds, sinks, poll interval, parse limits, order book
+    /// snapshots and tracking come from the `[extractor]` section and the database from the
+    /// `[database]` section. Discovery (`discover = true`) scans the Phoenix program before
+    /// returning and again while running.
+    /// Without the postgres sink the database isn't connected to at all, nothing but the
+    /// events is kept and every start extracts from the newest transactions.
+    /// The configuration is expected to be validated, see `ExtractorConfig::validate`.
+    ///
+    /// # Errors
+    ///
+    /// The errors of `VybeDaemon::new` and `VybeDaemon::discover`
+    /// `VybeDaemonError::NoRpcEndpoint` if neither an RPC url nor an API key is configured
+    /// `VybeDaemonError::InvalidCommitment` if the commitment level is unknown
+    /// `VybeDaemonError::Database` wrapping `InvalidEventKind` if an event kind is unknown
+    /// `VybeDaemonError::InvalidSink` if a sink is unknown
+    /// `VybeDaemonError::Io` if a file sink can't be opened
+    /// `VybeDaemonError::ParsePubkey` if a market with its own snapshot interval isn't valid
+    /// `VybeDaemonError::DatabaseRequired` if order books are snapshot without the postgres sink
+    pub async fn from_config(config: &VybeConfig) -> VybeResult<Self> {
+        let extractor = &config.extractor;
+        let rpc_config = RpcConfig::from_config(extractor)?;
+        let event_kinds = extractor
+            .event_kinds
+            .iter()
+            .map(|kind| kind.parse::<MarketEventKind>())
+            .collect::<Result<Vec<MarketEventKind>, _>>()?;
+        let sink_kinds = extractor
+            .sinks
+            .iter()
+            .map(|sink| sink.parse::<SinkKind>())
+            .collect::<Result<Vec<SinkKind>, _>>()?;
+
+        let mut daemon = Self::connect(
+            &rpc_config,
+            config.database.clone(),
+            &extractor.markets,
+            Sinks::new(&sink_kinds, extractor.sink_dir.clone()),
+        )
+        .await?;
+        if !event_kinds.is_empty() {
+            daemon.set_event_kinds(&event_kinds);
+        }
+        daemon.set_poll_interval(Duration::from_millis(extractor.poll_interval_ms));
+        daemon.set_parse_limits(extractor.parse_concurrency, extractor.signature_page_size);
+        if extractor.takes_snapshots() {
+            let mut schedule = SnapshotSchedule::new(
+                extractor.snapshot_interval_secs.map(Duration::from_secs),
+                extractor.snapshot_depth,
+            );
+            for (market_addr, interval) in &extractor.snapshot_intervals {
+                schedule.set_market_interval(market_addr, Duration::from_secs(*interval))?;
+            }
+            daemon.set_snapshot_schedule(schedule)?;
+        }
+        if extractor.track_order_books {
+            daemon.track_order_books(Duration::from_secs(extractor.book_check_interval_secs));
+        }
+        if extractor.discover {
+            let filter = MarketFilter::mints(&extractor.discover_mints)?;
+            daemon.discover(&filter).await?;
+            daemon.set_rediscovery(
+                filter,
+                Duration::from_secs(extractor.discovery_interval_secs),
+            );
+        }
+        Ok(daemon)
+    }
+
+    /// Creates the daemon writing to `sinks`, with a pool of connections to `database` shared by
+    /// every market and background task when it is one of them
+    async fn connect(
+        rpc_config: &RpcConfig,
+        database: DatabaseConfig,
+        market_addrs: &[String],
+        sinks: Sinks,
+    ) -> VybeResult<Self> {
+        let rpc = Arc::new(RpcPool::connect(rpc_config).await?);
+        let shutdown = Shutdown::default();
+        let database = if sinks.uses_database() {
+            Some(VybeDatabase::pool(&database)?)
+        } else {
+            None
+        };
+
+        let (snapshot_markets, snapshot_receiver) = mpsc::unbounded_channel();
+        let mut workers: Vec<MarketWorker> = vec![];
+        let mut markets = HashSet::new();
+        for market_addr in market_addrs {
+            let extractor = VybeTradeFillExtractor::with_rpc(Arc::clone(&rpc), market_addr)?;
+            if !markets.insert(*extractor.market_pubkey()) {
+                continue;
+            }
+            snapshot_markets.send(*extractor.market_pubkey()).ok();
+            let mut worker =
+                MarketWorker::new(extractor, database.as_ref(), &sinks, shutdown.clone())?;
+            worker.store_market().await?;
+            workers.push(worker);
+        }
+        info!(
+            "Configured {} market(s) writing to {}",
+            workers.len(),
+            sinks
+                .kinds()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+
+        let (reconciler, retrier, gap_filler, snapshotter) = if let Some(pool) = &database {
+            let reconciler = (!rpc_config.commitment.is_final())
+                .then(|| Reconciler::new(Arc::clone(&rpc), pool, shutdown.clone()));
+            (
+                reconciler,
+                Some(DeadLetterRetrier::new(
+                    Arc::clone(&rpc),
+                    pool,
+                    shutdown.clone(),
+                )),
+                Some(GapFiller::new(Arc::clone(&rpc), pool, shutdown.clone())),
+                Some(Snapshotter::new(
+                    Arc::clone(&rpc),
+                    pool,
+                    shutdown.clone(),
+                    snapshot_receiver,
+                )),
+            )
+        } else {
+            (None, None, None, None)
+        };
+
         Ok(Self {
-            trade_fill_extractor: VybeTradeFillExtractor::new(api_key, phoenix_addr).await?,
-            db: VybeDatabase::new()?,
+            workers,
+            markets,
+            event_kinds: MarketEventKind::ALL.to_vec(),
+            poll_interval: Duration::from_millis(DEFAULT_POLL_INTERVAL_MS),
+            parse_concurrency: DEFAULT_PARSE_CONCURRENCY,
+            signature_page_size: MAX_SIGNATURE_PAGE_SIZE,
+            rediscovery: None,
+            rpc,
+            database,
+            sinks,
+            ws_url: rpc_config.ws_url(),
+            reconciler,
+            retrier,
+            gap_filler,
+            snapshotter,
+            snapshot_markets,
+            book_check_interval: None,
+            order_books: HashMap::new(),
+            shutdown,
         })
     }
 
-    /// Run the daemon inside a never ending loop. In the real world we should listen
-    /// for sigterm/sigkill events, but I don't want to put operating specific code in here.
+    /// Chooses which kinds of market events every market stores, all kinds by default
+    pub fn set_event_kinds(&mut self, event_kinds: &[MarketEventKind]) {
+        self.event_kinds = event_kinds.to_vec();
+        self.warn_untracked_book_events();
+        for worker in &mut self.workers {
+            worker.set_event_kinds(event_kinds);
+        }
+        if let Some(retrier) = &mut self.retrier {
+            retrier.set_event_kinds(event_kinds);
+        }
+        if let Some(gap_filler) = &mut self.gap_filler {
+            gap_filler.set_event_kinds(event_kinds);
+        }
+    }
+
+    /// Chooses how long every market waits between extraction passes, 200ms by default
+    pub fn set_poll_interval(&mut self, poll_interval: Duration) {
+        self.poll_interval = poll_interval;
+        for worker in &mut self.workers {
+            worker.set_poll_interval(poll_interval);
+        }
+    }
+
+    /// Chooses how many transactions every market parses concurrently (100 by default) and
+    /// how many signatures are listed per request (1000 by default, the provider's maximum)
+    pub fn set_parse_limits(&mut self, parse_concurrency: usize, signature_page_size: usize) {
+        self.parse_concurrency = parse_concurrency;
+        self.signature_page_size = signature_page_size;
+        for worker in &mut self.workers {
+            worker.set_parse_limits(parse_concurrency, signature_page_size);
+        }
+    }
+
+    /// Chooses which markets have their order book snapshot into the `orderbook_snapshots`
+    /// table while running, how often and how many price levels per side. Markets discovered
+    /// while running are snapshot too when the schedule covers them. Nothing is by default.
+    ///
+    /// # Errors
+    ///
+    /// `VybeDaemonError::DatabaseRequired` without the postgres sink, unless `schedule` is empty
+    pub fn set_snapshot_schedule(&mut self, schedule: SnapshotSchedule) -> VybeResult<()> {
+        match &mut self.snapshotter {
+            Some(snapshotter) => snapshotter.set_schedule(schedule),
+            None if schedule.is_empty() => {}
+            None => {
+                return Err(VybeDaemonError::DatabaseRequired(
+                    "Snapshotting order books",
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// Keeps the L3 order book of every market in memory from the place, reduce, evict and
+    /// fill events it extracts, see `OrderBook`. Each book is seeded from its market account
+    /// at the first extraction pass, then checked against it every `check_interval` and
+    /// re-seeded when they disagree. Markets discovered while running are tracked too.
+    /// Nothing is tracked by default.
+    pub fn track_order_books(&mut self, check_interval: Duration) {
+        self.book_check_interval = Some(check_interval);
+        for worker in &mut self.workers {
+            let book = worker.track_order_book(check_interval);
+            self.order_books.insert(*worker.market_pubkey(), book);
+        }
+        self.warn_untracked_book_events();
+    }
+
+    /// The live order book of a market, `None` when order books aren't tracked or the market
+    /// isn't extracted. The handle stays valid once the daemon runs, it holds `None` until the
+    /// book is first seeded.
+    pub fn order_book(&self, market: &Pubkey) -> Option<SharedOrderBook> {
+        self.order_books.get(market).map(Arc::clone)
+    }
+
+    /// Warns when order books are tracked while kinds of events that change them aren't
+    /// extracted, every such event missed makes a book re-seed from its market account
+    fn warn_untracked_book_events(&self) {
+        if self.book_check_interval.is_none() {
+            return;
+        }
+        let missing = [
+            MarketEventKind::Place,
+            MarketEventKind::Reduce,
+            MarketEventKind::Evict,
+            MarketEventKind::Fill,
+        ]
+        .into_iter()
+        .filter(|kind| !self.event_kinds.contains(kind))
+        .map(|kind| kind.to_string())
+        .collect::<Vec<String>>();
+        if !missing.is_empty() {
+            warn!(
+                "Order books are tracked without extracting {} events, they will be re-seeded from their market accounts instead",
+                missing.join(", ")
+            );
+        }
+    }
+
+    /// Scans the Phoenix program for every market and stores the metadata of each one in the
+    /// `markets` table, when the database is one of the sinks. The markets matching `filter`
+    /// that aren't extracted yet get a worker, which resumes from its stored checkpoint like
+    /// the configured markets.
+    ///
+    /// # Errors
+    ///
+    /// `VybeDaemonError::SolanaClient` if the provider kept failing
+    /// `VybeDaemonError::CircuitOpen` while the provider is given time to recover
+    /// `VybeDaemonError::Database` if a market can't be stored
+    /// `VybeDaemonError::ParseSignature` if a stored checkpoint is corrupt
+    /// `VybeDaemonError::Io` if a file sink can't be opened
+    ///
+    /// # Returns
+    ///
+    /// Result<Vec<String>, `VybeDaemonError`> with the addresses of the newly extracted markets
+    pub async fn discover(&mut self, filter: &MarketFilter) -> VybeResult<Vec<String>> {
+        let discovered = discovery::discover_markets(&self.rpc).await?;
+        let mut db = self.database.as_ref().map(VybeDatabase::from_pool);
+        let mut added = vec![];
+        for market in &discovered {
+            if let Some(db) = &mut db {
+                db.upsert_market(market)?;
+            }
+            if !filter.matches(market) {
+                continue;
+            }
+            let extractor =
+                VybeTradeFillExtractor::with_rpc(Arc::clone(&self.rpc), &market.market_pubkey)?;
+            if !self.markets.insert(*extractor.market_pubkey()) {
+                continue;
+            }
+            self.snapshot_markets.send(*extractor.market_pubkey()).ok();
+            let mut worker = MarketWorker::new(
+                extractor,
+                self.database.as_ref(),
+                &self.sinks,
+                self.shutdown.clone(),
+            )?;
+            // The header was just decoded and stored, no need to read it again
+            worker.set_units(MarketUnits::from(market));
+            worker.set_event_kinds(&self.event_kinds);
+            worker.set_poll_interval(self.poll_interval);
+            worker.set_parse_limits(self.parse_concurrency, self.signature_page_size);
+            if let Some(check_interval) = self.book_check_interval {
+                let book = worker.track_order_book(check_interval);
+                self.order_books.insert(*worker.market_pubkey(), book);
+            }
+            self.workers.push(worker);
+            added.push(market.market_pubkey.clone());
+        }
+        info!(
+            "Discovered {} market(s), extracting {} new one(s)",
+            discovered.len(),
+            added.len()
+        );
+        Ok(added)
+    }
+
+    /// Scan the Phoenix program again every `interval` while running, new markets matching
+    /// `filter` are extracted as soon as they are listed
+    pub fn set_rediscovery(&mut self, filter: MarketFilter, interval: Duration) {
+        self.rediscovery = Some((filter, interval));
+    }
+
+    /// The token that shuts the daemon down when cancelled, the same way SIGINT or SIGTERM do.
+    /// Lets an embedding application or a test stop `run` and `stream`.
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.token()
+    }
+
+    /// Backfill every market's history inside `range` into the database, one market after the
+    /// other. The backfill is resumable, running it again with the same range continues where
+    /// the previous run stopped.
     ///
     /// # Errors
     ///
-    /// `VybeDaemonError::Pubkey`
-    /// `VybeDaemonError::PhoenixClient`
-    /// `VybeDaemonError::EllipsisClient`
+    /// `VybeDaemonError::DatabaseRequired` without the postgres sink
     /// `VybeDaemonError::SolanaClient`
     /// `VybeDaemonError::TokioJoin`
+    /// `VybeDaemonError::Database`
+    /// `VybeDaemonError::ParseSignature` if the stored progress is corrupt
     ///
     /// # Return
     ///
-    /// Result<Self, `VybeDaemonError::Pubkey`>
+    /// Result<Vec<`BackfillReport`>, `VybeDaemonError`> with one report per market
+    pub async fn backfill(&mut self, range: BackfillRange) -> VybeResult<Vec<BackfillReport>> {
+        let mut reports = vec![];
+        for worker in &mut self.workers {
+            reports.push(worker.backfill(range).await?);
+        }
+        Ok(reports)
+    }
+
+    /// Run the daemon, every market is extracted by its own loop on a separate task until
+    /// SIGINT or SIGTERM is received, or the `shutdown_token` is cancelled.
+    /// On shutdown no new signatures are taken, passes in flight get a deadline to store their
+    /// events together with the market's cursor, and passes still running at the deadline are
+    /// abandoned without storing anything. A restart resumes from the stored cursors, so
+    /// nothing is lost or stored twice.
+    ///
+    /// The workers are moved onto their tasks, so this can only be called once.
+    /// With `set_rediscovery` newly listed markets get a task of their own while running.
+    /// Transient RPC failures (timeouts, 429, 5xx) are retried with backoff, when the provider
+    /// stays down extraction pauses until it recovers instead of stopping.
+    /// Below the finalized commitment a separate task confirms provisional events once they are
+    /// finalized, or deletes them when their slot never finalized.
+    ///
+    /// # Errors
+    ///
+    /// The first error returned by any market, the other markets are stopped
+    ///
+    /// `VybeDaemonError::SolanaClient` if a request is rejected by the provider
+    /// `VybeDaemonError::Database`
+    /// `VybeDaemonError::TokioJoin`
+    ///
+    /// # Return
+    ///
+    /// Result<(), `VybeDaemonError`>
     ///
     /// # Examples
     ///
     /// ```rust
-    /// use vn_extractord_core::VybeDaemon;
+    /// use vn_extractord_core::{RpcConfig, VybeDaemon};
     ///
     /// #[tokio::main]
     /// async fn main() {
-    ///     let api_key = "your_api_key";
-    ///     let phoenix_addr = "phoenix_address";
+    ///     let rpc_config = RpcConfig::helius("your_api_key");
+    ///     let market_addrs = vec!["phoenix_address".to_owned()];
     ///
-    ///     match &mut VybeDaemon::new(api_key, phoenix_addr).await {
+    ///     match &mut VybeDaemon::new(&rpc_config, &market_addrs).await {
     ///         Ok(vdaemon) => {
     ///             if let Err(e) = vdaemon.run().await {
     ///                 println!("{e}");
@@ -79,25 +556,115 @@ impl VybeDaemon {
     /// }
     /// ```
     pub async fn run(&mut self) -> VybeResult<()> {
-        loop {
-            let fill_events_opt = self.trade_fill_extractor.extract().await?;
+        self.supervise(WorkerMode::Poll).await
+    }
 
-            if let Some(fill_events) = fill_events_opt {
-                for fill_event in fill_events {
-                    match self.db.create_trade_fill(&fill_event.try_into()?) {
-                        Ok(_) => {
-                            info!("Successfully created new trade fill entry..");
-                        }
-                        Err(e) => {
-                            error!("{e}");
+    /// Run the daemon in streaming mode, every market subscribes to the WebSocket logs of its
+    /// transactions on a separate task and decodes them as they arrive. After a disconnect the
+    /// cursor based polling of `VybeDaemon::run` fills the gap before the stream resumes.
+    /// Shuts down like `VybeDaemon::run`.
+    ///
+    /// The workers are moved onto their tasks, so this can only be called once.
+    /// With `set_rediscovery` newly listed markets get a task of their own while running.
+    /// Transient RPC failures (timeouts, 429, 5xx) are retried with backoff, when the provider
+    /// stays down extraction pauses until it recovers instead of stopping.
+    /// Below the finalized commitment a separate task confirms provisional events once they are
+    /// finalized, or deletes them when their slot never finalized.
+    ///
+    /// # Errors
+    ///
+    /// The first error returned by any market, the other markets are stopped
+    ///
+    /// `VybeDaemonError::SolanaClient` if a request is rejected by the provider
+    /// `VybeDaemonError::Database`
+    /// `VybeDaemonError::TokioJoin`
+    pub async fn stream(&mut self) -> VybeResult<()> {
+        self.supervise(WorkerMode::Stream).await
+    }
+
+    /// What the rate limiter of every RPC endpoint has done so far, by redacted url
+    pub fn rate_limiter_stats(&self) -> Vec<(String, RateLimiterStats)> {
+        self.rpc.rate_limiter_stats()
+    }
+
+    /// Retry dead letters, backfill sequence number gaps, snapshot the scheduled order books,
+    /// and below the finalized commitment reconcile provisional events, next to the market tasks
+    fn spawn_maintenance(&mut self, tasks: &mut JoinSet<VybeResult<()>>) {
+        if let Some(retrier) = self.retrier.take() {
+            tasks.spawn(retrier.run());
+        }
+        if let Some(gap_filler) = self.gap_filler.take() {
+            tasks.spawn(gap_filler.run());
+        }
+        if let Some(reconciler) = self.reconciler.take() {
+            tasks.spawn(reconciler.run());
+        }
+        if let Some(snapshotter) = self.snapshotter.take().filter(Snapshotter::is_scheduled) {
+            tasks.spawn(snapshotter.run());
+        }
+    }
+
+    /// Moves the workers that aren't running yet onto their own tasks
+    fn spawn_workers(&mut self, tasks: &mut JoinSet<VybeResult<()>>, mode: WorkerMode) {
+        for worker in self.workers.drain(..) {
+            match mode {
+                WorkerMode::Poll => tasks.spawn(worker.run()),
+                WorkerMode::Stream => tasks.spawn(worker.stream(self.ws_url.clone())),
+            };
+        }
+    }
+
+    /// Spawn the market tasks and wait for them, returning the first error, or `Ok` once every
+    /// task stopped after shutdown was requested.
+    /// Meanwhile the time requests spent waiting for the rate limiters is logged periodically,
+    /// and markets listed since the last scan are discovered when rediscovery is enabled.
+    async fn supervise(&mut self, mode: WorkerMode) -> VybeResult<()> {
+        let mut tasks = JoinSet::new();
+        self.spawn_workers(&mut tasks, mode);
+        self.spawn_maintenance(&mut tasks);
+
+        let report_interval = Duration::from_secs(RATE_LIMITER_REPORT_INTERVAL_SECS);
+        let mut report = tokio::time::interval(report_interval);
+        report.tick().await;
+        let mut rediscover = tokio::time::interval(
+            self.rediscovery
+                .as_ref()
+                .map_or(report_interval, |(_, interval)| *interval),
+        );
+        rediscover.tick().await;
+
+        let shutdown = self.shutdown.clone();
+        let signals = shutdown.listen_for_signals();
+        tokio::pin!(signals);
+        // Dropping the join set aborts the remaining markets
+        loop {
+            tokio::select! {
+                result = tasks.join_next() => match result {
+                    Some(result) => result??,
+                    None => {
+                        info!("Every market stopped, shut down cleanly");
+                        return Ok(());
+                    }
+                },
+                () = &mut signals, if !shutdown.is_requested() => {}
+                _ = rediscover.tick(), if self.rediscovery.is_some() && !shutdown.is_requested() => {
+                    if let Some((filter, _)) = self.rediscovery.clone() {
+                        // A failed scan is tried again at the next interval, the markets keep running
+                        if let Err(e) = self.discover(&filter).await {
+                            error!("Market discovery failed: {e}");
                         }
+                        self.spawn_workers(&mut tasks, mode);
+                    }
+                }
+                _ = report.tick() => {
+                    for (url, stats) in self.rate_limiter_stats() {
+                        info!(
+                            "{url}: {} request(s), {:?} spent waiting for the rate limiter, throttled {} time(s)",
+                            stats.requests, stats.waited, stats.throttled
+                        );
                     }
                 }
             }
-
-            // NOTE: this is a basic polling loop. If there are >1000 signatures in 200ms,
-            // events may get dropped.
-            tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
         }
     }
 }
@@ -0,0 +1,391 @@
+//! Client side rate limiting, every RPC endpoint gets a token bucket that each request waits on,
+//! and an HTTP transport that enforces it and honors `Retry-After` when the provider pushes back.
+
+use {
+    crate::{fixture::record_transaction, metrics, rpc::redact_url},
+    async_trait::async_trait,
+    reqwest::{
+        header::{CONTENT_TYPE, RETRY_AFTER},
+        StatusCode,
+    },
+    serde::Deserialize,
+    serde_json::Value,
+    solana_client::{
+        client_error::Result as ClientResult,
+        rpc_request::{RpcError, RpcRequest, RpcResponseErrorData},
+        rpc_sender::{RpcSender, RpcTransportStats},
+    },
+    std::{
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex,
+        },
+        time::{Duration, Instant},
+    },
+    tracing::debug,
+};
+
+/// How many times a request answered with `429 Too Many Requests` is sent again
+const TOO_MANY_REQUESTS_RETRIES: u32 = 5;
+
+/// Pause after a `429 Too Many Requests` without a usable `Retry-After` header
+const DEFAULT_RETRY_AFTER_MS: u64 = 500;
+
+/// `Retry-After` values above this are ignored in favor of the default pause
+const MAX_RETRY_AFTER_SECS: u64 = 120;
+
+/// Request budget of a single RPC endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Sustained number of requests per second
+    pub requests_per_second: u32,
+    /// Number of requests that can be sent at once after the endpoint was idle
+    pub burst: u32,
+}
+
+impl RateLimit {
+    /// A budget of `requests_per_second`, allowing a burst of one second worth of requests
+    pub fn per_second(requests_per_second: u32) -> Self {
+        Self {
+            requests_per_second,
+            burst: requests_per_second,
+        }
+    }
+}
+
+/// What a rate limiter has done since the daemon started
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimiterStats {
+    /// Number of requests that went through the limiter
+    pub requests: u64,
+    /// Total time requests spent waiting for the limiter
+    pub waited: Duration,
+    /// Number of `429 Too Many Requests` responses received from the endpoint
+    pub throttled: u64,
+}
+
+/// Token bucket state
+#[derive(Debug)]
+struct Bucket {
+    /// Requests that can be sent right away
+    tokens: f64,
+    /// When `tokens` was last topped up
+    refilled_at: Instant,
+    /// No request is sent before this, set when the endpoint answers with `Retry-After`
+    paused_until: Option<Instant>,
+}
+
+/// Token bucket limiting the requests sent to one endpoint
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    /// Budget of the endpoint, `None` only honors `Retry-After`
+    rate_limit: Option<RateLimit>,
+    /// Tokens left, the lock is never held across an await point
+    bucket: Mutex<Bucket>,
+    /// Number of requests that went through the limiter
+    requests: AtomicU64,
+    /// Total time requests spent waiting, in microseconds
+    waited_micros: AtomicU64,
+    /// Number of `429 Too Many Requests` responses
+    throttled: AtomicU64,
+}
+
+impl RateLimiter {
+    /// Creates a limiter with a full bucket
+    pub(crate) fn new(rate_limit: Option<RateLimit>) -> Self {
+        Self {
+            rate_limit,
+            bucket: Mutex::new(Bucket {
+                tokens: rate_limit.map_or(0.0, |rate_limit| f64::from(rate_limit.burst)),
+                refilled_at: Instant::now(),
+                paused_until: None,
+            }),
+            requests: AtomicU64::new(0),
+            waited_micros: AtomicU64::new(0),
+            throttled: AtomicU64::new(0),
+        }
+    }
+
+    /// Waits until a request may be sent, returns how long that took
+    pub(crate) async fn acquire(&self) -> Duration {
+        let started = Instant::now();
+        while let Some(wait) = self.reserve(Instant::now()) {
+            tokio::time::sleep(wait).await;
+        }
+
+        let waited = started.elapsed();
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.waited_micros.fetch_add(
+            u64::try_from(waited.as_micros()).unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+        waited
+    }
+
+    /// Stops every request for `duration`, used when the endpoint answers with `Retry-After`
+    pub(crate) fn pause(&self, duration: Duration) {
+        self.throttled.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut bucket) = self.bucket.lock() {
+            let until = Instant::now() + duration;
+            bucket.paused_until = Some(
+                bucket
+                    .paused_until
+                    .map_or(until, |paused| paused.max(until)),
+            );
+            bucket.tokens = 0.0;
+        }
+    }
+
+    /// What the limiter has done so far
+    pub(crate) fn stats(&self) -> RateLimiterStats {
+        RateLimiterStats {
+            requests: self.requests.load(Ordering::Relaxed),
+            waited: Duration::from_micros(self.waited_micros.load(Ordering::Relaxed)),
+            throttled: self.throttled.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Takes a token, or returns how long to wait before trying again
+    fn reserve(&self, now: Instant) -> Option<Duration> {
+        let mut bucket = self.bucket.lock().ok()?;
+        if let Some(paused_until) = bucket.paused_until {
+            if paused_until > now {
+                return Some(paused_until.saturating_duration_since(now));
+            }
+            bucket.paused_until = None;
+            bucket.refilled_at = now;
+        }
+
+        let rate_limit = self.rate_limit?;
+        let rate = f64::from(rate_limit.requests_per_second.max(1));
+        let elapsed = now
+            .saturating_duration_since(bucket.refilled_at)
+            .as_secs_f64();
+        bucket.tokens = elapsed
+            .mul_add(rate, bucket.tokens)
+            .min(f64::from(rate_limit.burst.max(1)));
+        bucket.refilled_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+}
+
+/// How long to pause after a `429 Too Many Requests`, only the delay-seconds form of
+/// `Retry-After` is understood
+fn retry_after(header: Option<&str>) -> Duration {
+    header
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .filter(|secs| *secs <= MAX_RETRY_AFTER_SECS)
+        .map_or(
+            Duration::from_millis(DEFAULT_RETRY_AFTER_MS),
+            Duration::from_secs,
+        )
+}
+
+/// JSON-RPC error object of a failed response
+#[derive(Deserialize)]
+struct RpcErrorObject {
+    /// JSON-RPC error code
+    code: i64,
+    /// Human readable error message
+    message: String,
+}
+
+/// HTTP transport of an `RpcClient` that waits on the endpoint's rate limiter before every
+/// request, so signature, transaction and market state fetches all share one budget.
+pub(crate) struct RateLimitedSender {
+    /// HTTP client, with the request timeout
+    client: reqwest::Client,
+    /// JSON-RPC url of the endpoint
+    url: String,
+    /// Budget of the endpoint
+    limiter: Arc<RateLimiter>,
+    /// Id of the next JSON-RPC request
+    request_id: AtomicU64,
+    /// Transport stats reported to the `RpcClient`
+    stats: Mutex<RpcTransportStats>,
+    /// Where fetched transactions are recorded, `None` doesn't record
+    record_dir: Option<PathBuf>,
+}
+
+impl RateLimitedSender {
+    /// Creates a transport for `url`, recording fetched transactions to `record_dir` when set
+    ///
+    /// # Errors
+    ///
+    /// `ClientError` if the HTTP client can't be built
+    pub(crate) fn new(
+        url: String,
+        timeout: Duration,
+        limiter: Arc<RateLimiter>,
+        record_dir: Option<PathBuf>,
+    ) -> ClientResult<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .pool_idle_timeout(timeout)
+            .build()?;
+        Ok(Self {
+            client,
+            url,
+            limiter,
+            request_id: AtomicU64::new(0),
+            stats: Mutex::new(RpcTransportStats::default()),
+            record_dir,
+        })
+    }
+
+    /// Turns an HTTP response into the JSON-RPC result, or the JSON-RPC error
+    async fn into_result(response: reqwest::Response) -> ClientResult<Value> {
+        let mut json = response.error_for_status()?.json::<Value>().await?;
+        if let Some(error) = json.get_mut("error").filter(|error| error.is_object()) {
+            return Err(
+                match serde_json::from_value::<RpcErrorObject>(error.take()) {
+                    Ok(error) => RpcError::RpcResponseError {
+                        code: error.code,
+                        message: error.message,
+                        data: RpcResponseErrorData::Empty,
+                    },
+                    Err(e) => RpcError::RpcRequestError(format!(
+                        "Failed to deserialize RPC error response: {e}"
+                    )),
+                }
+                .into(),
+            );
+        }
+        Ok(json.get_mut("result").map(Value::take).unwrap_or_default())
+    }
+}
+
+#[async_trait]
+impl RpcSender for RateLimitedSender {
+    async fn send(&self, request: RpcRequest, params: Value) -> ClientResult<Value> {
+        let started = Instant::now();
+        let request_id = self.request_id.fetch_add(1, Ordering::Relaxed);
+        let recorded_signature = match (self.record_dir.as_ref(), request) {
+            (Some(_), RpcRequest::GetTransaction) => {
+                params.get(0).and_then(Value::as_str).map(str::to_owned)
+            }
+            _ => None,
+        };
+        let body = request.build_request_json(request_id, params).to_string();
+
+        let mut rate_limited = Duration::ZERO;
+        let mut retries = TOO_MANY_REQUESTS_RETRIES;
+        let result = loop {
+            let waited = self.limiter.acquire().await;
+            metrics::observe_rate_limiter_wait(redact_url(&self.url), waited);
+            rate_limited += waited;
+            let response = match self
+                .client
+                .post(&self.url)
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.clone())
+                .send()
+                .await
+            {
+                Ok(response) => response,
+                Err(e) => break Err(e.into()),
+            };
+
+            if response.status() == StatusCode::TOO_MANY_REQUESTS && retries > 0 {
+                let pause = retry_after(
+                    response
+                        .headers()
+                        .get(RETRY_AFTER)
+                        .and_then(|value| value.to_str().ok()),
+                );
+                debug!("{request} was rate limited, pausing the endpoint for {pause:?}");
+                self.limiter.pause(pause);
+                retries -= 1;
+                continue;
+            }
+            break Self::into_result(response).await;
+        };
+
+        metrics::observe_rpc_latency(&request.to_string(), started.elapsed());
+        if let Ok(mut stats) = self.stats.lock() {
+            stats.request_count += 1;
+            stats.elapsed_time += started.elapsed();
+            stats.rate_limited_time += rate_limited;
+        }
+        if let (Some(dir), Some(signature), Ok(transaction)) = (
+            self.record_dir.as_deref(),
+            recorded_signature.as_deref(),
+            result.as_ref(),
+        ) {
+            record_transaction(dir, signature, transaction);
+        }
+        result
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.stats
+            .lock()
+            .map(|stats| stats.clone())
+            .unwrap_or_default()
+    }
+
+    fn url(&self) -> String {
+        self.url.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{retry_after, RateLimit, RateLimiter},
+        std::time::{Duration, Instant},
+    };
+
+    #[test]
+    fn bucket_allows_burst_then_waits() {
+        let limiter = RateLimiter::new(Some(RateLimit {
+            requests_per_second: 10,
+            burst: 2,
+        }));
+        let now = Instant::now();
+
+        assert_eq!(limiter.reserve(now), None);
+        assert_eq!(limiter.reserve(now), None);
+        let wait = limiter.reserve(now);
+        assert!(
+            wait.is_some_and(|wait| wait > Duration::ZERO && wait <= Duration::from_millis(100))
+        );
+
+        // A tenth of a second later one more token has been refilled
+        assert_eq!(limiter.reserve(now + Duration::from_millis(100)), None);
+    }
+
+    #[test]
+    fn pause_blocks_unlimited_endpoints() {
+        let limiter = RateLimiter::new(None);
+        assert_eq!(limiter.reserve(Instant::now()), None);
+
+        limiter.pause(Duration::from_secs(2));
+        assert!(limiter
+            .reserve(Instant::now())
+            .is_some_and(|wait| wait > Duration::from_secs(1)));
+        assert_eq!(
+            limiter.reserve(Instant::now() + Duration::from_secs(3)),
+            None
+        );
+        assert_eq!(limiter.stats().throttled, 1);
+    }
+
+    #[test]
+    fn parse_retry_after() {
+        assert_eq!(retry_after(Some("3")), Duration::from_secs(3));
+        assert_eq!(retry_after(None), Duration::from_millis(500));
+        assert_eq!(
+            retry_after(Some("Wed, 21 Oct 2015 07:28:00 GMT")),
+            Duration::from_millis(500)
+        );
+        assert_eq!(retry_after(Some("3600")), Duration::from_millis(500));
+    }
+}
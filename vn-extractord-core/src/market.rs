@@ -0,0 +1,308 @@
+//! Market metadata read from a Phoenix market account. The account starts with the
+//! `MarketHeader` (mints, decimals, lot sizes and tick size) followed by the `FIFOMarket`
+//! holding the taker fee, both `#[repr(C)]` so their fields sit at fixed offsets.
+//! The resting orders of the `FIFOMarket` are read through the Phoenix program's own types.
+
+use {
+    crate::{
+        book::{BookOrder, OrderBook},
+        error::VybeDaemonError,
+    },
+    phoenix::{
+        program::{load_with_dispatch, MarketHeader},
+        state::Side,
+    },
+    solana_sdk::pubkey::Pubkey,
+    vn_database_core::models::{NewMarket, PriceLevel},
+};
+
+/// Address of the Phoenix program, the owner of every market account
+pub(crate) const PHOENIX_PROGRAM_ID: Pubkey =
+    solana_sdk::pubkey!("PhoeNiXZ8ByJGLkxNfZRnkUfjvmuYqLR89jjFHGqdXY");
+
+/// `MarketHeader.base_params.decimals`
+const BASE_DECIMALS_OFFSET: usize = 40;
+/// `MarketHeader.base_params.mint_key`
+const BASE_MINT_OFFSET: usize = 48;
+/// `MarketHeader.base_lot_size`
+const BASE_LOT_SIZE_OFFSET: usize = 112;
+/// `MarketHeader.quote_params.decimals`
+const QUOTE_DECIMALS_OFFSET: usize = 120;
+/// `MarketHeader.quote_params.mint_key`
+const QUOTE_MINT_OFFSET: usize = 128;
+/// `MarketHeader.quote_lot_size`
+const QUOTE_LOT_SIZE_OFFSET: usize = 192;
+/// `MarketHeader.tick_size_in_quote_atoms_per_base_unit`
+const TICK_SIZE_OFFSET: usize = 200;
+/// `MarketHeader.raw_base_units_per_base_unit`
+const RAW_BASE_UNITS_OFFSET: usize = 312;
+/// Size of the `MarketHeader`, the `FIFOMarket` starts right after it
+const MARKET_HEADER_SIZE: usize = 576;
+/// `FIFOMarket.taker_fee_bps`, after 256 bytes of padding and three `u64` fields
+const TAKER_FEE_BPS_OFFSET: usize = MARKET_HEADER_SIZE + 280;
+/// Number of bytes at the start of a market account holding every decoded field
+pub(crate) const MARKET_METADATA_SIZE: usize = TAKER_FEE_BPS_OFFSET + 8;
+
+/// Reads `N` bytes at `offset`
+fn read<const N: usize>(data: &[u8], offset: usize) -> Option<[u8; N]> {
+    data.get(offset..offset.checked_add(N)?)?.try_into().ok()
+}
+
+/// Reads a little endian `u32` at `offset` as an `i32` column
+fn read_u32(data: &[u8], offset: usize) -> Option<i32> {
+    i32::try_from(u32::from_le_bytes(read(data, offset)?)).ok()
+}
+
+/// Reads a little endian `u64` at `offset` as an `i64` column
+fn read_u64(data: &[u8], offset: usize) -> Option<i64> {
+    i64::try_from(u64::from_le_bytes(read(data, offset)?)).ok()
+}
+
+/// Reads a public key at `offset` as a base58 column
+fn read_pubkey(data: &[u8], offset: usize) -> Option<String> {
+    Some(Pubkey::new_from_array(read(data, offset)?).to_string())
+}
+
+/// Decodes the metadata of the market stored at `market_pubkey`, `data` is the whole
+/// market account
+fn decode(market_pubkey: &Pubkey, data: &[u8]) -> Option<NewMarket> {
+    Some(NewMarket {
+        market_pubkey: market_pubkey.to_string(),
+        base_mint: read_pubkey(data, BASE_MINT_OFFSET)?,
+        quote_mint: read_pubkey(data, QUOTE_MINT_OFFSET)?,
+        base_decimals: read_u32(data, BASE_DECIMALS_OFFSET)?,
+        quote_decimals: read_u32(data, QUOTE_DECIMALS_OFFSET)?,
+        base_lot_size: read_u64(data, BASE_LOT_SIZE_OFFSET)?,
+        quote_lot_size: read_u64(data, QUOTE_LOT_SIZE_OFFSET)?,
+        tick_size_in_quote_atoms_per_base_unit: read_u64(data, TICK_SIZE_OFFSET)?,
+        raw_base_units_per_base_unit: read_u32(data, RAW_BASE_UNITS_OFFSET)?,
+        taker_fee_bps: read_u64(data, TAKER_FEE_BPS_OFFSET)
+            .and_then(|fee| i32::try_from(fee).ok())?,
+    })
+}
+
+/// Decodes the metadata of a market from its account
+///
+/// # Errors
+///
+/// `VybeDaemonError::InvalidMarketAccount` if the account isn't a Phoenix market
+pub(crate) fn decode_market(
+    market_pubkey: &Pubkey,
+    owner: &Pubkey,
+    data: &[u8],
+) -> Result<NewMarket, VybeDaemonError> {
+    if *owner != PHOENIX_PROGRAM_ID {
+        return Err(VybeDaemonError::InvalidMarketAccount(format!(
+            "{market_pubkey} is owned by {owner}, not the Phoenix program"
+        )));
+    }
+    decode(market_pubkey, data).ok_or_else(|| {
+        VybeDaemonError::InvalidMarketAccount(format!(
+            "{market_pubkey} is too small ({} bytes) to be a Phoenix market",
+            data.len()
+        ))
+    })
+}
+
+/// The price levels of both sides of a book, best price first
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Ladder {
+    /// Bids, highest price first
+    pub bids: Vec<PriceLevel>,
+    /// Asks, lowest price first
+    pub asks: Vec<PriceLevel>,
+}
+
+/// Converts the levels of one side of a Phoenix ladder into database levels
+fn price_levels(levels: &[phoenix::state::markets::LadderOrder]) -> Option<Vec<PriceLevel>> {
+    levels
+        .iter()
+        .map(|level| {
+            Some(PriceLevel {
+                price_in_ticks: i64::try_from(level.price_in_ticks).ok()?,
+                size_in_base_lots: i64::try_from(level.size_in_base_lots).ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Splits a market account into its header and the bytes of its `FIFOMarket`
+///
+/// # Errors
+///
+/// `VybeDaemonError::InvalidMarketAccount` if the account isn't a Phoenix market
+fn split_market<'a>(
+    market_pubkey: &Pubkey,
+    owner: &Pubkey,
+    data: &'a [u8],
+) -> Result<(MarketHeader, &'a [u8]), VybeDaemonError> {
+    if *owner != PHOENIX_PROGRAM_ID {
+        return Err(invalid_market(
+            market_pubkey,
+            &format!("is owned by {owner}, not the Phoenix program"),
+        ));
+    }
+    let (header, book) = data.split_at_checked(MARKET_HEADER_SIZE).ok_or_else(|| {
+        invalid_market(
+            market_pubkey,
+            &format!("is too small ({} bytes) to hold a book", data.len()),
+        )
+    })?;
+    let header = bytemuck::try_pod_read_unaligned::<MarketHeader>(header)
+        .map_err(|e| invalid_market(market_pubkey, &format!("has an unreadable header: {e}")))?;
+    Ok((header, book))
+}
+
+/// `VybeDaemonError::InvalidMarketAccount` for a market account that isn't usable
+fn invalid_market(market_pubkey: &Pubkey, reason: &str) -> VybeDaemonError {
+    VybeDaemonError::InvalidMarketAccount(format!("{market_pubkey} {reason}"))
+}
+
+/// Reads the top `depth` price levels of each side of the book from a market account
+///
+/// # Errors
+///
+/// `VybeDaemonError::InvalidMarketAccount` if the account isn't a Phoenix market
+pub(crate) fn decode_ladder(
+    market_pubkey: &Pubkey,
+    owner: &Pubkey,
+    data: &[u8],
+    depth: u64,
+) -> Result<Ladder, VybeDaemonError> {
+    let (header, book) = split_market(market_pubkey, owner, data)?;
+    let market = load_with_dispatch(&header.market_size_params, book)
+        .map_err(|e| invalid_market(market_pubkey, &format!("has an unreadable book: {e}")))?;
+    let ladder = market.inner.get_ladder(depth);
+    Ok(Ladder {
+        bids: price_levels(&ladder.bids)
+            .ok_or_else(|| invalid_market(market_pubkey, "has an oversized bid"))?,
+        asks: price_levels(&ladder.asks)
+            .ok_or_else(|| invalid_market(market_pubkey, "has an oversized ask"))?,
+    })
+}
+
+/// Reads every resting order from a market account into a book reflecting every instruction
+/// before the market's current sequence number
+///
+/// # Errors
+///
+/// `VybeDaemonError::InvalidMarketAccount` if the account isn't a Phoenix market
+pub(crate) fn decode_book(
+    market_pubkey: &Pubkey,
+    owner: &Pubkey,
+    data: &[u8],
+) -> Result<OrderBook, VybeDaemonError> {
+    let (header, book) = split_market(market_pubkey, owner, data)?;
+    let market = load_with_dispatch(&header.market_size_params, book)
+        .map_err(|e| invalid_market(market_pubkey, &format!("has an unreadable book: {e}")))?;
+    let market = market.inner;
+    let oversized = || invalid_market(market_pubkey, "has an oversized order");
+    let mut orders = Vec::new();
+    for side in [Side::Bid, Side::Ask] {
+        for (order_id, order) in market.get_book(side).iter() {
+            let trader_index = u32::try_from(order.trader_index).map_err(|_| oversized())?;
+            orders.push(BookOrder {
+                // Bids keep their inverted sequence numbers, matching the extracted events
+                order_sequence_number: order_id.order_sequence_number as i64,
+                maker: market.get_trader_id_from_index(trader_index).to_string(),
+                side,
+                price_in_ticks: i64::try_from(order_id.price_in_ticks.as_u64())
+                    .map_err(|_| oversized())?,
+                base_lots: i64::try_from(order.num_base_lots.as_u64()).map_err(|_| oversized())?,
+                placed_at: None,
+            });
+        }
+    }
+    let next_sequence_number =
+        i64::try_from(header.market_sequence_number).map_err(|_| oversized())?;
+    Ok(OrderBook::with_orders(
+        &market_pubkey.to_string(),
+        next_sequence_number,
+        orders,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{
+            decode_book, decode_ladder, decode_market, BASE_DECIMALS_OFFSET, BASE_LOT_SIZE_OFFSET,
+            BASE_MINT_OFFSET, PHOENIX_PROGRAM_ID, QUOTE_DECIMALS_OFFSET, QUOTE_LOT_SIZE_OFFSET,
+            QUOTE_MINT_OFFSET, RAW_BASE_UNITS_OFFSET, TAKER_FEE_BPS_OFFSET, TICK_SIZE_OFFSET,
+        },
+        crate::error::VybeDaemonError,
+        solana_sdk::pubkey::Pubkey,
+    };
+
+    /// Writes `bytes` into the account at `offset`
+    fn write(data: &mut [u8], offset: usize, bytes: &[u8]) {
+        if let Some(field) = data.get_mut(offset..offset + bytes.len()) {
+            field.copy_from_slice(bytes);
+        }
+    }
+
+    #[test]
+    fn decode_market_account() {
+        let market = Pubkey::new_unique();
+        let base_mint = Pubkey::new_unique();
+        let quote_mint = Pubkey::new_unique();
+        let mut data = vec![0_u8; TAKER_FEE_BPS_OFFSET + 64];
+        write(&mut data, BASE_DECIMALS_OFFSET, &9_u32.to_le_bytes());
+        write(&mut data, BASE_MINT_OFFSET, base_mint.as_ref());
+        write(
+            &mut data,
+            BASE_LOT_SIZE_OFFSET,
+            &1_000_000_u64.to_le_bytes(),
+        );
+        write(&mut data, QUOTE_DECIMALS_OFFSET, &6_u32.to_le_bytes());
+        write(&mut data, QUOTE_MINT_OFFSET, quote_mint.as_ref());
+        write(&mut data, QUOTE_LOT_SIZE_OFFSET, &1_u64.to_le_bytes());
+        write(&mut data, TICK_SIZE_OFFSET, &1000_u64.to_le_bytes());
+        write(&mut data, RAW_BASE_UNITS_OFFSET, &1_u32.to_le_bytes());
+        write(&mut data, TAKER_FEE_BPS_OFFSET, &2_u64.to_le_bytes());
+
+        let decoded = decode_market(&market, &PHOENIX_PROGRAM_ID, &data).expect("a Phoenix market");
+        assert_eq!(decoded.market_pubkey, market.to_string());
+        assert_eq!(decoded.base_mint, base_mint.to_string());
+        assert_eq!(decoded.quote_mint, quote_mint.to_string());
+        assert_eq!(decoded.base_decimals, 9);
+        assert_eq!(decoded.quote_decimals, 6);
+        assert_eq!(decoded.base_lot_size, 1_000_000);
+        assert_eq!(decoded.quote_lot_size, 1);
+        assert_eq!(decoded.tick_size_in_quote_atoms_per_base_unit, 1000);
+        assert_eq!(decoded.raw_base_units_per_base_unit, 1);
+        assert_eq!(decoded.taker_fee_bps, 2);
+
+        assert!(matches!(
+            decode_market(&market, &Pubkey::new_unique(), &data),
+            Err(VybeDaemonError::InvalidMarketAccount(_))
+        ));
+        assert!(matches!(
+            decode_market(&market, &PHOENIX_PROGRAM_ID, &data[..TAKER_FEE_BPS_OFFSET]),
+            Err(VybeDaemonError::InvalidMarketAccount(_))
+        ));
+    }
+
+    #[test]
+    fn decode_ladder_rejects_foreign_and_short_accounts() {
+        let market = Pubkey::new_unique();
+        let data = vec![0_u8; TAKER_FEE_BPS_OFFSET];
+        assert!(matches!(
+            decode_ladder(&market, &Pubkey::new_unique(), &data, 10),
+            Err(VybeDaemonError::InvalidMarketAccount(_))
+        ));
+        assert!(matches!(
+            decode_ladder(&market, &PHOENIX_PROGRAM_ID, &data[..100], 10),
+            Err(VybeDaemonError::InvalidMarketAccount(_))
+        ));
+        assert!(matches!(
+            decode_book(&market, &Pubkey::new_unique(), &data),
+            Err(VybeDaemonError::InvalidMarketAccount(_))
+        ));
+        // an all zero header has no market size params any book is loaded with
+        assert!(matches!(
+            decode_ladder(&market, &PHOENIX_PROGRAM_ID, &data, 10),
+            Err(VybeDaemonError::InvalidMarketAccount(_))
+        ));
+    }
+}
@@ -0,0 +1,399 @@
+//! Prometheus metrics of the daemon, served in the text exposition format on `/metrics`.
+//! Metrics are recorded from wherever the work happens (extractor, workers and the RPC
+//! transport) into one process wide registry, recording never fails the extraction.
+
+use {
+    crate::extractor::VybeResult,
+    prometheus::{
+        exponential_buckets, Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec,
+        Opts, Registry, TextEncoder,
+    },
+    std::{
+        net::SocketAddr,
+        sync::LazyLock,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    },
+    tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+        task::JoinHandle,
+    },
+    tracing::{debug, error, info},
+    vn_database_core::InsertedEvents,
+};
+
+/// Prefix of every metric name
+const NAMESPACE: &str = "vybe";
+
+/// Longest request line read from a scraper, the headers after it are ignored
+const MAX_REQUEST_LINE: u64 = 8192;
+
+/// Ingestion lag buckets in seconds, from one second up to about an hour
+const LAG_BUCKETS: [f64; 13] = [
+    1.0, 2.0, 5.0, 10.0, 20.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1200.0, 1800.0, 3600.0,
+];
+
+/// Every metric the daemon exports
+struct DaemonMetrics {
+    /// Registry the metrics are gathered from
+    registry: Registry,
+    /// Signatures listed by `getSignaturesForAddress` above the cursor, by market
+    signatures_fetched: IntCounterVec,
+    /// Transactions fetched and decoded, by market
+    transactions_parsed: IntCounterVec,
+    /// Transactions given up on for a reason retrying won't fix, by market
+    transactions_failed: IntCounterVec,
+    /// Transactions recorded as dead letters, by market
+    dead_letters_recorded: IntCounterVec,
+    /// Market events of every kind (fills included) inserted into the database, by market
+    events_inserted: IntCounterVec,
+    /// Market events that were already stored and skipped, by market
+    duplicates_skipped: IntCounterVec,
+    /// Trade fills inserted into the database, by market
+    fills_inserted: IntCounterVec,
+    /// Trade fills that were already stored and skipped, by market
+    duplicate_fills_skipped: IntCounterVec,
+    /// Time between sending an RPC request and its response, retries included, by method
+    rpc_latency: HistogramVec,
+    /// Time requests waited on the rate limiter of their endpoint, by endpoint
+    rate_limiter_wait: HistogramVec,
+    /// Time between an event's block time and its insertion, by market
+    ingestion_lag: HistogramVec,
+    /// Slot of the newest processed transaction, by market
+    last_processed_slot: IntGaugeVec,
+}
+
+impl DaemonMetrics {
+    /// Creates and registers every metric
+    fn new() -> prometheus::Result<Self> {
+        let registry = Registry::new();
+        let counter = |name: &str, help: &str, label: &str| {
+            let counter = IntCounterVec::new(Opts::new(name, help).namespace(NAMESPACE), &[label])?;
+            registry.register(Box::new(counter.clone()))?;
+            Ok::<_, prometheus::Error>(counter)
+        };
+        let histogram = |name: &str, help: &str, label: &str, buckets: Vec<f64>| {
+            let histogram = HistogramVec::new(
+                HistogramOpts::new(name, help)
+                    .namespace(NAMESPACE)
+                    .buckets(buckets),
+                &[label],
+            )?;
+            registry.register(Box::new(histogram.clone()))?;
+            Ok::<_, prometheus::Error>(histogram)
+        };
+        // 1ms up to about 16s
+        let latency_buckets = exponential_buckets(0.001, 2.0, 15)?;
+
+        let last_processed_slot = IntGaugeVec::new(
+            Opts::new(
+                "last_processed_slot",
+                "Slot of the newest processed transaction",
+            )
+            .namespace(NAMESPACE),
+            &["market"],
+        )?;
+        registry.register(Box::new(last_processed_slot.clone()))?;
+
+        Ok(Self {
+            signatures_fetched: counter(
+                "signatures_fetched_total",
+                "Signatures listed above the market's cursor",
+                "market",
+            )?,
+            transactions_parsed: counter(
+                "transactions_parsed_total",
+                "Transactions fetched and decoded",
+                "market",
+            )?,
+            transactions_failed: counter(
+                "transactions_failed_total",
+                "Transactions given up on for a reason retrying won't fix",
+                "market",
+            )?,
+            dead_letters_recorded: counter(
+                "dead_letters_recorded_total",
+                "Transactions recorded as dead letters, failed attempts of a retry included",
+                "market",
+            )?,
+            events_inserted: counter(
+                "events_inserted_total",
+                "Market events, fills included, inserted into the database",
+                "market",
+            )?,
+            duplicates_skipped: counter(
+                "duplicate_events_skipped_total",
+                "Market events that were already stored",
+                "market",
+            )?,
+            fills_inserted: counter(
+                "fills_inserted_total",
+                "Trade fills inserted into the database",
+                "market",
+            )?,
+            duplicate_fills_skipped: counter(
+                "duplicate_fills_skipped_total",
+                "Trade fills that were already stored",
+                "market",
+            )?,
+            rpc_latency: histogram(
+                "rpc_request_duration_seconds",
+                "RPC request latency, rate limiter waits and 429 retries included",
+                "method",
+                latency_buckets.clone(),
+            )?,
+            rate_limiter_wait: histogram(
+                "rate_limiter_wait_seconds",
+                "Time a request waited on its endpoint's rate limiter",
+                "endpoint",
+                latency_buckets,
+            )?,
+            ingestion_lag: histogram(
+                "ingestion_lag_seconds",
+                "Time between an event's block time and its insertion",
+                "market",
+                LAG_BUCKETS.to_vec(),
+            )?,
+            last_processed_slot,
+            registry,
+        })
+    }
+}
+
+/// The process wide metrics, `None` if they couldn't be registered
+static METRICS: LazyLock<Option<DaemonMetrics>> = LazyLock::new(|| {
+    DaemonMetrics::new()
+        .inspect_err(|e| error!("Metrics are disabled: {e}"))
+        .ok()
+});
+
+/// Counts the signatures listed above a market's cursor
+pub(crate) fn record_signatures(market: &str, count: usize) {
+    if let Some(metrics) = METRICS.as_ref() {
+        metrics
+            .signatures_fetched
+            .with_label_values(&[market])
+            .inc_by(count as u64);
+    }
+}
+
+/// Counts the transactions of a market that were decoded and the ones given up on
+pub(crate) fn record_transactions(market: &str, parsed: usize, failed: usize) {
+    if let Some(metrics) = METRICS.as_ref() {
+        metrics
+            .transactions_parsed
+            .with_label_values(&[market])
+            .inc_by(parsed as u64);
+        metrics
+            .transactions_failed
+            .with_label_values(&[market])
+            .inc_by(failed as u64);
+    }
+}
+
+/// Counts the transactions of a market recorded as dead letters
+pub(crate) fn record_dead_letters(market: &str, count: usize) {
+    if let Some(metrics) = METRICS.as_ref() {
+        metrics
+            .dead_letters_recorded
+            .with_label_values(&[market])
+            .inc_by(count as u64);
+    }
+}
+
+/// Records a batch of a market's events being committed: how many events and fills were new,
+/// how many were already stored, how long after their block time (Unix seconds) they were
+/// inserted and the slot the market's cursor moved to
+pub(crate) fn record_commit(
+    market: &str,
+    inserted: InsertedEvents,
+    extracted: usize,
+    extracted_fills: usize,
+    block_times: impl IntoIterator<Item = i64>,
+    slot: u64,
+) {
+    let Some(metrics) = METRICS.as_ref() else {
+        return;
+    };
+    metrics
+        .events_inserted
+        .with_label_values(&[market])
+        .inc_by(inserted.total as u64);
+    metrics
+        .duplicates_skipped
+        .with_label_values(&[market])
+        .inc_by(extracted.saturating_sub(inserted.total) as u64);
+    metrics
+        .fills_inserted
+        .with_label_values(&[market])
+        .inc_by(inserted.fills as u64);
+    metrics
+        .duplicate_fills_skipped
+        .with_label_values(&[market])
+        .inc_by(extracted_fills.saturating_sub(inserted.fills) as u64);
+    let now = SystemTime::now();
+    let ingestion_lag = metrics.ingestion_lag.with_label_values(&[market]);
+    for block_time in block_times
+        .into_iter()
+        .filter_map(|time| u64::try_from(time).ok())
+    {
+        let lag = now
+            .duration_since(UNIX_EPOCH + Duration::from_secs(block_time))
+            .unwrap_or_default();
+        ingestion_lag.observe(lag.as_secs_f64());
+    }
+    metrics
+        .last_processed_slot
+        .with_label_values(&[market])
+        .set(i64::try_from(slot).unwrap_or(i64::MAX));
+}
+
+/// Records how long an RPC request of `method` took
+pub(crate) fn observe_rpc_latency(method: &str, elapsed: Duration) {
+    if let Some(metrics) = METRICS.as_ref() {
+        metrics
+            .rpc_latency
+            .with_label_values(&[method])
+            .observe(elapsed.as_secs_f64());
+    }
+}
+
+/// Records how long a request waited on the rate limiter of `endpoint` (a redacted url)
+pub(crate) fn observe_rate_limiter_wait(endpoint: &str, waited: Duration) {
+    if let Some(metrics) = METRICS.as_ref() {
+        metrics
+            .rate_limiter_wait
+            .with_label_values(&[endpoint])
+            .observe(waited.as_secs_f64());
+    }
+}
+
+/// Every metric in the Prometheus text exposition format
+fn gather() -> String {
+    let Some(metrics) = METRICS.as_ref() else {
+        return String::new();
+    };
+    let mut buffer = vec![];
+    if let Err(e) = TextEncoder::new().encode(&metrics.registry.gather(), &mut buffer) {
+        error!("Failed to encode the metrics: {e}");
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
+
+/// Answers one scrape, `GET /metrics` gets the metrics and anything else a 404.
+/// The request line is read up to its `\r\n`, however many reads it arrives in.
+async fn respond(mut stream: TcpStream) {
+    let mut request_line = String::new();
+    if let Err(e) = BufReader::new((&mut stream).take(MAX_REQUEST_LINE))
+        .read_line(&mut request_line)
+        .await
+    {
+        debug!("Failed to read a metrics scrape: {e}");
+        return;
+    }
+    let (status, body) = match request_line.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+        ["GET", "/metrics"] => ("200 OK", gather()),
+        _ => ("404 Not Found", "Not found\n".to_owned()),
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    if let Err(e) = stream.write_all(response.as_bytes()).await {
+        debug!("Failed to answer a metrics scrape: {e}");
+    }
+}
+
+/// Starts serving the metrics on `http://<addr>/metrics`, the listener runs on its own task
+/// until the returned handle is aborted
+///
+/// # Errors
+///
+/// `VybeDaemonError::Io` if `addr` can't be bound
+pub async fn serve_metrics(addr: SocketAddr) -> VybeResult<JoinHandle<()>> {
+    let listener = TcpListener::bind(addr).await?;
+    info!(
+        "Serving metrics on http://{}/metrics",
+        listener.local_addr()?
+    );
+    Ok(tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(respond(stream));
+                }
+                Err(e) => debug!("Failed to accept a metrics scrape: {e}"),
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{record_commit, record_signatures, serve_metrics},
+        std::{net::SocketAddr, time::Duration},
+        tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::TcpStream,
+        },
+        vn_database_core::InsertedEvents,
+    };
+
+    /// Sends a `GET` for `path` and returns the whole response
+    async fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.expect("metrics listener");
+        stream
+            .write_all(format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").as_bytes())
+            .await
+            .expect("send request");
+        let mut response = String::new();
+        stream
+            .read_to_string(&mut response)
+            .await
+            .expect("read response");
+        response
+    }
+
+    #[tokio::test]
+    async fn serve_recorded_metrics() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("free port");
+        let addr = listener.local_addr().expect("local address");
+        drop(listener);
+        let server = serve_metrics(addr).await.expect("metrics listener");
+
+        record_signatures("metrics-test-market", 3);
+        let inserted = InsertedEvents { fills: 1, total: 2 };
+        record_commit("metrics-test-market", inserted, 3, 2, [0_i64], 42);
+        let response = get(addr, "/metrics").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(
+            response.contains(r#"vybe_signatures_fetched_total{market="metrics-test-market"} 3"#)
+        );
+        assert!(response
+            .contains(r#"vybe_duplicate_events_skipped_total{market="metrics-test-market"} 1"#));
+        assert!(response.contains(r#"vybe_fills_inserted_total{market="metrics-test-market"} 1"#));
+        assert!(response
+            .contains(r#"vybe_duplicate_fills_skipped_total{market="metrics-test-market"} 1"#));
+        assert!(response.contains(r#"vybe_last_processed_slot{market="metrics-test-market"} 42"#));
+
+        assert!(get(addr, "/").await.starts_with("HTTP/1.1 404 Not Found"));
+
+        // A request line split across reads is still routed
+        let mut stream = TcpStream::connect(addr).await.expect("metrics listener");
+        stream.write_all(b"GET /met").await.expect("send request");
+        stream.flush().await.expect("send request");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        stream
+            .write_all(b"rics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .expect("send request");
+        let mut response = String::new();
+        stream
+            .read_to_string(&mut response)
+            .await
+            .expect("read response");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        server.abort();
+    }
+}
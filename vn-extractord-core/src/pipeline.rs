@@ -0,0 +1,521 @@
+//! Staged extraction pass: a signature fetcher, a bounded pool of transaction decoders and the
+//! batching database writer, connected by bounded channels. When the database or the RPC
+//! provider is slow the channels fill up and the stages before it wait, instead of every
+//! transaction being requested at once and held in memory.
+
+use {
+    crate::{
+        error::VybeDaemonError,
+        extractor::{
+            FailedTransaction, SequenceMark, SignatureCursor, SignatureInfo, VybeResult,
+            VybeTradeFillExtractor,
+        },
+        metrics,
+        rpc::RpcPool,
+    },
+    ellipsis_client::transaction_utils::parse_transaction,
+    phoenix_sdk::sdk_client::PhoenixEvent,
+    solana_client::rpc_config::RpcTransactionConfig,
+    solana_sdk::signature::Signature,
+    solana_transaction_status::UiTransactionEncoding,
+    std::{
+        collections::{BTreeMap, HashSet, VecDeque},
+        sync::Arc,
+    },
+    tokio::{
+        sync::{
+            mpsc::{self, error::TryRecvError},
+            Mutex, OwnedSemaphorePermit, Semaphore,
+        },
+        task::JoinSet,
+    },
+    tokio_util::task::AbortOnDropHandle,
+    tracing::{error, warn},
+    vn_database_core::models::{DeadLetterKind, MarketEventKind},
+};
+
+/// Maximum number of transactions written in one database transaction
+const WRITE_BATCH_SIZE: usize = 100;
+
+/// Signature pages kept in memory while a catch-up is listed back to the cursor,
+/// newer pages are listed again once the older ones were handed to the decoders
+const MAX_BUFFERED_PAGES: usize = 4;
+
+/// Transactions handed to the decoders that haven't been written yet, in multiples of the
+/// parse concurrency. Transactions are written in listing order, one slow transaction holds
+/// back the writer but not the decoders, until the window is full.
+const REORDER_WINDOW_FACTOR: usize = 4;
+
+/// Where the signatures of a pipeline come from
+pub(crate) enum Source {
+    /// Every signature newer than the extractor's cursor
+    Cursor,
+    /// The given signatures, in order
+    Signatures(Vec<Signature>),
+}
+
+/// A signature on its way to the decoders, it holds a place in the reorder window
+/// until the writer takes its transaction
+struct Listed {
+    /// Position in listing order
+    index: usize,
+    /// Signature of the transaction
+    signature: Signature,
+    /// Slot the transaction landed in, when it was listed from the cursor
+    slot: Option<u64>,
+    /// Place in the reorder window
+    _permit: OwnedSemaphorePermit,
+}
+
+/// A transaction fetched and decoded, `None` when its Phoenix events couldn't be decoded
+struct Decoded {
+    /// The listed signature
+    listed: Listed,
+    /// Market events of the transaction, every kind
+    result: VybeResult<Option<Vec<PhoenixEvent>>>,
+}
+
+/// Puts items that complete in any order back in their listing order
+#[derive(Debug)]
+struct InOrder<T> {
+    /// Completed items waiting for the ones listed before them
+    pending: BTreeMap<usize, T>,
+    /// Index of the next item to release
+    next: usize,
+}
+
+impl<T> Default for InOrder<T> {
+    fn default() -> Self {
+        Self {
+            pending: BTreeMap::new(),
+            next: 0,
+        }
+    }
+}
+
+impl<T> InOrder<T> {
+    /// Adds a completed item
+    fn insert(&mut self, index: usize, item: T) {
+        self.pending.insert(index, item);
+    }
+
+    /// Releases the next item in listing order, once it completed
+    fn pop(&mut self) -> Option<T> {
+        let item = self.pending.remove(&self.next)?;
+        self.next += 1;
+        Some(item)
+    }
+}
+
+/// Transactions written to the database together, in listing order
+#[derive(Debug, Default)]
+pub struct ExtractionBatch {
+    /// Market events of the extracted kinds, sorted by `sequence_number`
+    pub events: Vec<PhoenixEvent>,
+    /// Transactions that failed for good, their events are missing from `events`
+    pub failed: Vec<FailedTransaction>,
+    /// Sequence numbers of every Phoenix event of the batch, whatever its kind
+    pub sequences: Vec<SequenceMark>,
+    /// Signatures of the batch's transactions, oldest first
+    pub signatures: Vec<Signature>,
+    /// Newest transaction of the batch, `None` when the signatures were given without their slot
+    pub cursor: Option<SignatureCursor>,
+}
+
+/// A running extraction pass, see `VybeTradeFillExtractor::pipeline`. The fetcher and every
+/// decoder run on their own task, they are aborted when the pipeline is dropped so an
+/// abandoned pass leaves nothing running.
+pub struct Pipeline {
+    /// Base58 encoded public key of the market
+    market: String,
+    /// Kinds of market events that are extracted
+    event_kinds: Vec<MarketEventKind>,
+    /// Lists the signatures, `None` once it has been joined
+    fetcher: Option<AbortOnDropHandle<VybeResult<()>>>,
+    /// Fetch and decode the listed transactions
+    decoders: JoinSet<()>,
+    /// Decoded transactions, in the order they completed
+    decoded: mpsc::Receiver<Decoded>,
+    /// Decoded transactions waiting for the ones listed before them
+    in_order: InOrder<Decoded>,
+    /// Transient failure met after the transactions of a batch, returned by the next call
+    error: Option<VybeDaemonError>,
+    /// Nothing more is written after a transient failure
+    stopped: bool,
+}
+
+impl Pipeline {
+    /// Starts the fetcher and the decoders, at most `parse_concurrency` of them
+    pub(crate) fn spawn(extractor: &VybeTradeFillExtractor, source: Source) -> Self {
+        let parse_concurrency = (*extractor.parse_concurrency()).max(1);
+        let concurrency = match &source {
+            Source::Cursor => parse_concurrency,
+            Source::Signatures(signatures) => signatures.len().clamp(1, parse_concurrency),
+        };
+        let window = Arc::new(Semaphore::new(
+            parse_concurrency.saturating_mul(REORDER_WINDOW_FACTOR),
+        ));
+        let (listed_tx, listed_rx) = mpsc::channel(concurrency);
+        let (decoded_tx, decoded_rx) = mpsc::channel(concurrency);
+
+        let fetcher = AbortOnDropHandle::new(tokio::spawn(fetch(
+            extractor.clone(),
+            source,
+            window,
+            listed_tx,
+        )));
+        let listed_rx = Arc::new(Mutex::new(listed_rx));
+        let mut decoders = JoinSet::new();
+        for _ in 0..concurrency {
+            decoders.spawn(decode(
+                Arc::clone(extractor.rpc()),
+                Arc::clone(&listed_rx),
+                decoded_tx.clone(),
+            ));
+        }
+
+        Self {
+            market: extractor.market_pubkey().to_string(),
+            event_kinds: extractor.event_kinds().clone(),
+            fetcher: Some(fetcher),
+            decoders,
+            decoded: decoded_rx,
+            in_order: InOrder::default(),
+            error: None,
+            stopped: false,
+        }
+    }
+
+    /// Waits for the next batch of transactions in listing order, the writer stores each batch
+    /// before asking for the next one so a slow database slows down the decoders and the fetcher.
+    /// A batch holds up to `WRITE_BATCH_SIZE` transactions, it is returned early when no
+    /// other transaction is decoded yet so that events aren't held back.
+    ///
+    /// Transactions that fail for a reason retrying won't fix are logged and returned in
+    /// `ExtractionBatch::failed`, a transient failure ends the pass after the transactions
+    /// listed before it so that nothing is skipped while the provider is down.
+    ///
+    /// # Errors
+    ///
+    /// `VybeDaemonError::SolanaClient` if the provider kept failing, see `VybeDaemonError::is_transient`
+    /// `VybeDaemonError::CircuitOpen` while the provider is given time to recover
+    /// `VybeDaemonError::TokioJoin`
+    ///
+    /// # Returns
+    ///
+    /// The next batch, `None` once every transaction has been returned
+    pub async fn next_batch(&mut self) -> VybeResult<Option<ExtractionBatch>> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        if self.stopped {
+            return Ok(None);
+        }
+
+        let mut batch = ExtractionBatch::default();
+        loop {
+            while let Some(decoded) = self.in_order.pop() {
+                if let Err(e) = self.add(&mut batch, decoded) {
+                    self.stopped = true;
+                    if batch.signatures.is_empty() {
+                        return Err(e);
+                    }
+                    self.error = Some(e);
+                    return Ok(Some(self.finish(batch)));
+                }
+                if batch.signatures.len() >= WRITE_BATCH_SIZE {
+                    return Ok(Some(self.finish(batch)));
+                }
+            }
+
+            let decoded = if batch.signatures.is_empty() {
+                self.decoded.recv().await
+            } else {
+                match self.decoded.try_recv() {
+                    Ok(decoded) => Some(decoded),
+                    Err(TryRecvError::Empty) => return Ok(Some(self.finish(batch))),
+                    Err(TryRecvError::Disconnected) => None,
+                }
+            };
+            match decoded {
+                Some(decoded) => self.in_order.insert(decoded.listed.index, decoded),
+                None if batch.signatures.is_empty() => {
+                    self.join().await?;
+                    return Ok(None);
+                }
+                None => return Ok(Some(self.finish(batch))),
+            }
+        }
+    }
+
+    /// Adds the next transaction in listing order to the batch,
+    /// a transient failure is returned instead
+    fn add(&self, batch: &mut ExtractionBatch, decoded: Decoded) -> VybeResult<()> {
+        let Decoded { listed, result } = decoded;
+        let signature = listed.signature;
+        match result {
+            // A transaction without Phoenix events decodes to an empty list
+            Ok(Some(events)) => {
+                batch.sequences.extend(events.iter().map(SequenceMark::of));
+                batch.events.extend(events.into_iter().filter(|event| {
+                    self.event_kinds
+                        .contains(&MarketEventKind::of(&event.details))
+                }));
+            }
+            Ok(None) => batch.failed.push(FailedTransaction {
+                signature,
+                kind: DeadLetterKind::Decode,
+                error: "the transaction's Phoenix events couldn't be decoded".to_owned(),
+            }),
+            Err(e) if e.is_transient() => return Err(e),
+            Err(e) => batch.failed.push(FailedTransaction {
+                signature,
+                kind: DeadLetterKind::Fetch,
+                error: e.to_string(),
+            }),
+        }
+        batch.signatures.push(signature);
+        batch.cursor = listed.slot.map(|slot| SignatureCursor { signature, slot });
+        Ok(())
+    }
+
+    /// Sorts the batch's events and reports its transactions
+    fn finish(&self, mut batch: ExtractionBatch) -> ExtractionBatch {
+        batch.events.sort_by_key(|event| event.sequence_number);
+        for failed in &batch.failed {
+            error!(
+                "{}: giving up on transaction {}: {}",
+                self.market, failed.signature, failed.error
+            );
+        }
+        metrics::record_transactions(
+            &self.market,
+            batch.signatures.len().saturating_sub(batch.failed.len()),
+            batch.failed.len(),
+        );
+        batch
+    }
+
+    /// Waits for the stages once every decoded transaction was returned,
+    /// returning why the fetcher failed or a decoder panicked
+    async fn join(&mut self) -> VybeResult<()> {
+        if let Some(fetcher) = self.fetcher.take() {
+            fetcher.await??;
+        }
+        while let Some(decoder) = self.decoders.join_next().await {
+            decoder?;
+        }
+        Ok(())
+    }
+}
+
+/// Hands listed signatures to the decoders in listing order, each one waits for a place in
+/// the reorder window
+struct ListedSender {
+    /// Places in the reorder window
+    window: Arc<Semaphore>,
+    /// Channel to the decoders
+    listed: mpsc::Sender<Listed>,
+    /// Position of the next signature in listing order
+    index: usize,
+}
+
+impl ListedSender {
+    /// Sends the next signature, `false` once the pipeline was dropped
+    async fn send(&mut self, signature: Signature, slot: Option<u64>) -> bool {
+        let Ok(permit) = Arc::clone(&self.window).acquire_owned().await else {
+            return false;
+        };
+        let next = Listed {
+            index: self.index,
+            signature,
+            slot,
+            _permit: permit,
+        };
+        self.index += 1;
+        self.listed.send(next).await.is_ok()
+    }
+}
+
+/// Signature fetcher stage: lists the signatures and hands them to the decoders oldest first
+async fn fetch(
+    extractor: VybeTradeFillExtractor,
+    source: Source,
+    window: Arc<Semaphore>,
+    listed: mpsc::Sender<Listed>,
+) -> VybeResult<()> {
+    let mut sender = ListedSender {
+        window,
+        listed,
+        index: 0,
+    };
+    match source {
+        Source::Cursor => fetch_from_cursor(&extractor, &mut sender).await,
+        Source::Signatures(signatures) => {
+            for signature in signatures {
+                if !sender.send(signature, None).await {
+                    break;
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Lists every signature newer than the extractor's cursor, paging backwards from the chain tip
+/// with `before` until the cursor (`until`) is reached, and hands each page to the decoders as
+/// soon as every older page was. Without a cursor only the newest page is listed, history
+/// before the daemon started is the job of a backfill.
+///
+/// Transactions are written oldest first, so a long catch-up can't be decoded while it is
+/// listed. Only the `MAX_BUFFERED_PAGES` oldest pages listed are kept, newer pages keep the
+/// signature they were listed before and are listed again once the older ones were handed over.
+///
+/// Below the finalized commitment the cursor's transaction may have been rolled back, in
+/// which case `until` is never reached, paging also stops at signatures older than the
+/// cursor's slot.
+async fn fetch_from_cursor(
+    extractor: &VybeTradeFillExtractor,
+    sender: &mut ListedSender,
+) -> VybeResult<()> {
+    let cursor = *extractor.cursor();
+    let until = cursor.map(|cursor| cursor.signature);
+    let cursor_slot = cursor.map_or(0, |cursor| cursor.slot);
+    // Both newest first, the pages that are kept with the signature they were listed before
+    let mut buffered = VecDeque::new();
+    let mut dropped = vec![];
+    let mut before = None;
+    loop {
+        let page = extractor.get_signature_page(before, until).await?;
+        let passed_cursor = page.signatures.iter().any(|info| info.slot < cursor_slot);
+        // A short page means we reached either the cursor or the start of the history
+        let done = until.is_none() || page.exhausted || page.signatures.is_empty() || passed_cursor;
+        let oldest = page.signatures.last().map(|info| info.signature);
+        buffered.push_back((before, page.signatures));
+        if buffered.len() > MAX_BUFFERED_PAGES {
+            dropped.extend(buffered.pop_front().map(|(before, _)| before));
+        }
+        if done {
+            break;
+        }
+        warn!(
+            "More than {} new signatures, paging backwards..",
+            extractor.signature_page_size()
+        );
+        before = oldest;
+    }
+
+    let market = extractor.market_pubkey().to_string();
+    // Providers may list a signature twice across (or within) pages, decode it once
+    let mut previous = HashSet::new();
+    while let Some((_, signatures)) = buffered.pop_back() {
+        if !send_page(sender, &market, signatures, cursor_slot, &mut previous).await {
+            return Ok(());
+        }
+    }
+    while let Some(before) = dropped.pop() {
+        let page = extractor.get_signature_page(before, until).await?;
+        if !send_page(sender, &market, page.signatures, cursor_slot, &mut previous).await {
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+/// Hands a page of signatures (newest first) to the decoders oldest first, skipping the
+/// signatures older than the cursor's slot and the ones already in the `previous` page.
+/// Returns `false` once the pipeline was dropped.
+async fn send_page(
+    sender: &mut ListedSender,
+    market: &str,
+    signatures: Vec<SignatureInfo>,
+    cursor_slot: u64,
+    previous: &mut HashSet<Signature>,
+) -> bool {
+    let mut sent = HashSet::new();
+    for info in signatures.into_iter().rev() {
+        if info.slot < cursor_slot
+            || previous.contains(&info.signature)
+            || !sent.insert(info.signature)
+        {
+            continue;
+        }
+        if !sender.send(info.signature, Some(info.slot)).await {
+            return false;
+        }
+    }
+    metrics::record_signatures(market, sent.len());
+    *previous = sent;
+    true
+}
+
+/// Transaction decoder stage: fetches and decodes listed transactions until the fetcher is done,
+/// the requests go through the RPC pool so they are rate limited, retried and failed over
+async fn decode(
+    rpc: Arc<RpcPool>,
+    listed: Arc<Mutex<mpsc::Receiver<Listed>>>,
+    decoded: mpsc::Sender<Decoded>,
+) {
+    loop {
+        let Some(next) = listed.lock().await.recv().await else {
+            break;
+        };
+        let result = decode_transaction(&rpc, next.signature).await;
+        // The pipeline was dropped
+        if decoded
+            .send(Decoded {
+                listed: next,
+                result,
+            })
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+/// Fetches a transaction and decodes its Phoenix events
+async fn decode_transaction(
+    rpc: &RpcPool,
+    signature: Signature,
+) -> VybeResult<Option<Vec<PhoenixEvent>>> {
+    let commitment = rpc.commitment().config();
+    let tx = rpc
+        .call(|sdk| async move {
+            sdk.client
+                .get_transaction_with_config(
+                    &signature,
+                    RpcTransactionConfig {
+                        encoding: Some(UiTransactionEncoding::Json),
+                        commitment: Some(commitment),
+                        max_supported_transaction_version: Some(0),
+                    },
+                )
+                .await
+        })
+        .await?;
+    Ok(rpc
+        .client()
+        .core
+        .parse_events_from_transaction(&parse_transaction(tx)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::InOrder;
+
+    #[test]
+    fn release_in_listing_order() {
+        let mut in_order = InOrder::default();
+        in_order.insert(1, "second");
+        in_order.insert(2, "third");
+        assert_eq!(in_order.pop(), None);
+
+        in_order.insert(0, "first");
+        assert_eq!(in_order.pop(), Some("first"));
+        assert_eq!(in_order.pop(), Some("second"));
+        assert_eq!(in_order.pop(), Some("third"));
+        assert_eq!(in_order.pop(), None);
+    }
+}
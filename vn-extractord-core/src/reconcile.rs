@@ -0,0 +1,213 @@
+//! Reconciliation of provisional market events. Events extracted below the finalized commitment
+//! are confirmed once their transaction is finalized, or deleted when the slot it landed in
+//! never finalized, so near real-time data still converges to the canonical chain.
+
+use {
+    crate::{extractor::VybeResult, rpc::RpcPool, shutdown::Shutdown},
+    solana_sdk::{commitment_config::CommitmentConfig, signature::Signature},
+    solana_transaction_status::TransactionStatus,
+    std::{str::FromStr, sync::Arc, time::Duration},
+    tracing::{debug, info, warn},
+    vn_database_core::{models::ProvisionalTransaction, VybeDatabase, VybePool},
+};
+
+/// How often provisional events are reconciled
+const RECONCILE_INTERVAL_SECS: u64 = 10;
+
+/// `getSignatureStatuses` accepts at most 256 signatures per request
+const SIGNATURE_STATUS_BATCH: i64 = 256;
+
+/// A transaction that is unknown to the cluster this many slots after the finalized slot
+/// passed its slot has been rolled back, the margin covers RPC nodes lagging behind
+const ROLLBACK_MARGIN_SLOTS: u64 = 150;
+
+/// What happened to a batch of provisional transactions
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Reconciliation {
+    /// Transactions that reached the finalized commitment, with the slot they were finalized in
+    finalized: Vec<ProvisionalTransaction>,
+    /// Signatures of transactions whose slot never finalized
+    rolled_back: Vec<String>,
+}
+
+/// Settles the transactions of a batch, given their signature statuses (in the same order)
+/// and the newest finalized slot. Transactions that are neither are left for a later pass.
+fn reconcile_statuses(
+    provisional: &[ProvisionalTransaction],
+    statuses: &[Option<TransactionStatus>],
+    finalized_slot: u64,
+) -> Reconciliation {
+    let mut reconciliation = Reconciliation::default();
+    for (transaction, status) in provisional.iter().zip(statuses) {
+        match status {
+            Some(status) if status.satisfies_commitment(CommitmentConfig::finalized()) => {
+                reconciliation.finalized.push(ProvisionalTransaction {
+                    signature: transaction.signature.clone(),
+                    slot: status.slot as i64,
+                });
+            }
+            None if u64::try_from(transaction.slot)
+                .unwrap_or_default()
+                .saturating_add(ROLLBACK_MARGIN_SLOTS)
+                < finalized_slot =>
+            {
+                reconciliation
+                    .rolled_back
+                    .push(transaction.signature.clone());
+            }
+            _ => {}
+        }
+    }
+    reconciliation
+}
+
+/// Periodically settles every provisional market event, shares the database pool with the
+/// market workers so it can run on a separate task next to them.
+pub(crate) struct Reconciler {
+    /// RPC endpoints shared with the market workers
+    rpc: Arc<RpcPool>,
+    /// PG database connection and interface
+    db: VybeDatabase,
+    /// Stops the reconciliation loop once the daemon shuts down
+    shutdown: Shutdown,
+}
+
+impl Reconciler {
+    /// Creates a new reconciler checking connections out of the daemon's pool
+    pub(crate) fn new(rpc: Arc<RpcPool>, database: &VybePool, shutdown: Shutdown) -> Self {
+        Self {
+            rpc,
+            db: VybeDatabase::from_pool(database),
+            shutdown,
+        }
+    }
+
+    /// Reconcile inside a loop until shutdown, transient RPC failures skip a pass.
+    /// Events left provisional at shutdown are reconciled after the next start.
+    pub(crate) async fn run(mut self) -> VybeResult<()> {
+        info!("Reconciling provisional market events..");
+        let mut interval = tokio::time::interval(Duration::from_secs(RECONCILE_INTERVAL_SECS));
+
+        let shutdown = self.shutdown.clone();
+        while shutdown.or_requested(interval.tick()).await.is_some() {
+            match shutdown.drain(self.reconcile()).await {
+                Some(Ok(())) | None => {}
+                Some(Err(e)) if e.is_transient() => {
+                    warn!("{e}, reconciling provisional events again later..");
+                }
+                Some(Err(e)) => return Err(e),
+            }
+        }
+        info!("Stopped reconciling provisional market events");
+        Ok(())
+    }
+
+    /// Runs a single reconciliation pass over the oldest provisional transactions
+    async fn reconcile(&mut self) -> VybeResult<()> {
+        let provisional = self
+            .db
+            .get_provisional_transactions(SIGNATURE_STATUS_BATCH)?;
+        if provisional.is_empty() {
+            return Ok(());
+        }
+        let signatures = provisional
+            .iter()
+            .map(|transaction| Signature::from_str(&transaction.signature))
+            .collect::<Result<Vec<Signature>, _>>()?;
+        let signatures = signatures.as_slice();
+
+        let finalized_slot = self
+            .rpc
+            .call(|sdk| async move {
+                sdk.client
+                    .get_slot_with_commitment(CommitmentConfig::finalized())
+                    .await
+            })
+            .await?;
+        let statuses = self
+            .rpc
+            .call(|sdk| async move {
+                sdk.client
+                    .get_signature_statuses_with_history(signatures)
+                    .await
+                    .map(|response| response.value)
+            })
+            .await?;
+
+        let reconciliation = reconcile_statuses(&provisional, &statuses, finalized_slot);
+        if !reconciliation.finalized.is_empty() {
+            let finalized = self.db.finalize_transactions(&reconciliation.finalized)?;
+            debug!(
+                "Finalized {finalized} market event(s) of {} transaction(s)",
+                reconciliation.finalized.len()
+            );
+        }
+        if !reconciliation.rolled_back.is_empty() {
+            let deleted = self
+                .db
+                .delete_provisional_transactions(&reconciliation.rolled_back)?;
+            warn!(
+                "Deleted {deleted} market event(s) of {} transaction(s) whose slot never finalized",
+                reconciliation.rolled_back.len()
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{reconcile_statuses, ProvisionalTransaction},
+        solana_transaction_status::{TransactionConfirmationStatus, TransactionStatus},
+    };
+
+    /// Builds a signature status the way `getSignatureStatuses` returns it
+    fn status(slot: u64, confirmation_status: TransactionConfirmationStatus) -> TransactionStatus {
+        let finalized = confirmation_status == TransactionConfirmationStatus::Finalized;
+        TransactionStatus {
+            slot,
+            confirmations: if finalized { None } else { Some(1) },
+            status: Ok(()),
+            err: None,
+            confirmation_status: Some(confirmation_status),
+        }
+    }
+
+    #[test]
+    fn settle_provisional_transactions() {
+        let provisional = ["finalized", "confirmed", "dropped", "rolled-back"]
+            .iter()
+            .map(|signature| ProvisionalTransaction {
+                signature: (*signature).to_owned(),
+                slot: 1000,
+            })
+            .collect::<Vec<_>>();
+        let statuses = [
+            Some(status(1002, TransactionConfirmationStatus::Finalized)),
+            Some(status(1000, TransactionConfirmationStatus::Confirmed)),
+            None,
+            None,
+        ];
+
+        // Within the margin unknown transactions may still show up
+        let reconciliation = reconcile_statuses(&provisional, &statuses, 1100);
+        assert_eq!(reconciliation.rolled_back, Vec::<String>::new());
+
+        let reconciliation = reconcile_statuses(&provisional[..2], &statuses[..2], 1200);
+        assert_eq!(
+            reconciliation.finalized,
+            vec![ProvisionalTransaction {
+                signature: "finalized".to_owned(),
+                slot: 1002,
+            }]
+        );
+        assert!(reconciliation.rolled_back.is_empty());
+
+        let reconciliation = reconcile_statuses(&provisional[2..], &statuses[2..], 1200);
+        assert_eq!(
+            reconciliation.rolled_back,
+            vec!["dropped".to_owned(), "rolled-back".to_owned()]
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! Retries of transient RPC failures with jittered exponential backoff, and a circuit breaker
+//! that pauses extraction while the provider is down instead of hammering it.
+
+use {
+    rand::Rng,
+    std::{
+        sync::Mutex,
+        time::{Duration, Instant},
+    },
+    tracing::{info, warn},
+};
+
+/// Number of times a request is attempted before giving up
+const DEFAULT_MAX_ATTEMPTS: u32 = 4;
+
+/// Backoff before the first retry
+const DEFAULT_BASE_DELAY_MS: u64 = 250;
+
+/// Backoff never grows beyond this
+const DEFAULT_MAX_DELAY_MS: u64 = 10_000;
+
+/// Number of consecutive failed requests that opens the circuit
+const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+
+/// How long the circuit stays open before a request is let through to probe the provider
+const DEFAULT_COOLDOWN_SECS: u64 = 30;
+
+/// How transient failures are retried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Number of times a request is attempted, including the first attempt
+    pub max_attempts: u32,
+    /// Backoff before the first retry, doubled on every following retry
+    pub base_delay: Duration,
+    /// Upper bound of the backoff
+    pub max_delay: Duration,
+    /// Number of consecutive failed requests after which extraction is paused
+    pub failure_threshold: u32,
+    /// How long extraction is paused once the failure threshold is reached
+    pub cooldown: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            base_delay: Duration::from_millis(DEFAULT_BASE_DELAY_MS),
+            max_delay: Duration::from_millis(DEFAULT_MAX_DELAY_MS),
+            failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+            cooldown: Duration::from_secs(DEFAULT_COOLDOWN_SECS),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Upper bound of the backoff after `attempt` failed attempts (starting at 1)
+    fn backoff_ceiling(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(u32::BITS - 1);
+        self.base_delay
+            .saturating_mul(2_u32.saturating_pow(exponent))
+            .min(self.max_delay)
+    }
+
+    /// Backoff after `attempt` failed attempts, a random duration up to the exponential
+    /// ceiling ("full jitter") so that concurrent requests don't retry in lockstep
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let ceiling = u64::try_from(self.backoff_ceiling(attempt).as_millis()).unwrap_or(u64::MAX);
+        Duration::from_millis(rand::thread_rng().gen_range(0..=ceiling))
+    }
+}
+
+/// State of the circuit breaker
+#[derive(Debug)]
+struct BreakerState {
+    /// Requests that failed in a row, after every retry
+    consecutive_failures: u32,
+    /// Requests are refused until then
+    open_until: Option<Instant>,
+}
+
+/// Counts requests that failed after every retry. Once `failure_threshold` of them failed in a
+/// row the circuit opens and requests are refused for `cooldown`, afterwards a single probe
+/// closes it again on success or reopens it on failure.
+#[derive(Debug)]
+pub(crate) struct CircuitBreaker {
+    /// Failures in a row that open the circuit
+    failure_threshold: u32,
+    /// How long the circuit stays open
+    cooldown: Duration,
+    /// The lock is never held across an await point
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    /// Creates a closed circuit
+    pub(crate) fn new(policy: &RetryPolicy) -> Self {
+        Self {
+            failure_threshold: policy.failure_threshold.max(1),
+            cooldown: policy.cooldown,
+            state: Mutex::new(BreakerState {
+                consecutive_failures: 0,
+                open_until: None,
+            }),
+        }
+    }
+
+    /// How long requests are still refused, `None` when they may be sent
+    pub(crate) fn open_for(&self) -> Option<Duration> {
+        let state = self.state.lock().ok()?;
+        state
+            .open_until
+            .map(|open_until| open_until.saturating_duration_since(Instant::now()))
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    /// A request succeeded, closes the circuit
+    pub(crate) fn record_success(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            if state.open_until.take().is_some() {
+                info!("RPC provider recovered, resuming extraction");
+            }
+            state.consecutive_failures = 0;
+        }
+    }
+
+    /// A request failed after every retry, opens the circuit once the threshold is reached
+    pub(crate) fn record_failure(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+            if state.consecutive_failures >= self.failure_threshold {
+                warn!(
+                    "{} RPC request(s) failed in a row, pausing extraction for {:?}",
+                    state.consecutive_failures, self.cooldown
+                );
+                state.open_until = Some(Instant::now() + self.cooldown);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{CircuitBreaker, RetryPolicy},
+        std::time::Duration,
+    };
+
+    #[test]
+    fn backoff_grows_exponentially_up_to_the_limit() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff_ceiling(1), Duration::from_millis(250));
+        assert_eq!(policy.backoff_ceiling(3), Duration::from_millis(1000));
+        assert_eq!(policy.backoff_ceiling(100), Duration::from_secs(10));
+        assert!(policy.backoff(3) <= Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn circuit_opens_after_consecutive_failures() {
+        let breaker = CircuitBreaker::new(&RetryPolicy {
+            failure_threshold: 2,
+            ..RetryPolicy::default()
+        });
+
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+        assert_eq!(breaker.open_for(), None);
+
+        breaker.record_failure();
+        assert!(breaker
+            .open_for()
+            .is_some_and(|remaining| remaining <= Duration::from_secs(30)));
+
+        breaker.record_success();
+        assert_eq!(breaker.open_for(), None);
+    }
+}
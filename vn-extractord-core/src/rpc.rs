@@ -0,0 +1,576 @@
+//! Provider agnostic RPC configuration, and an ordered pool of endpoints that fails over
+//! to the next endpoint when the active one returns errors or times out.
+
+use {
+    crate::{
+        error::VybeDaemonError,
+        extractor::VybeResult,
+        fixture::{FixtureSender, FIXTURE_SCHEME},
+        limiter::{RateLimit, RateLimitedSender, RateLimiter, RateLimiterStats},
+        retry::{CircuitBreaker, RetryPolicy},
+    },
+    ellipsis_client::EllipsisClient,
+    phoenix_sdk::sdk_client::SDKClient,
+    reqwest::StatusCode,
+    solana_client::{
+        client_error::{ClientError, ClientErrorKind},
+        nonblocking::rpc_client::RpcClient,
+        rpc_client::RpcClientConfig,
+        rpc_custom_error::{
+            JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY,
+            JSON_RPC_SERVER_ERROR_TRANSACTION_HISTORY_NOT_AVAILABLE,
+        },
+        rpc_request::RpcError,
+    },
+    solana_sdk::{commitment_config::CommitmentConfig, signer::keypair::Keypair},
+    std::{
+        collections::HashMap,
+        fmt,
+        future::Future,
+        io,
+        path::{Path, PathBuf},
+        str::FromStr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::{Duration, Instant},
+    },
+    tracing::{debug, info, warn},
+    vn_config::ExtractorConfig,
+};
+
+/// Helius Remote Procedure Call endpoint for Solana, the API key is appended
+const HELIUS_RPC_ENDPOINT: &str = "https://mainnet.helius-rpc.com/?api-key=";
+
+/// Helius WebSocket (pubsub) endpoint for Solana, the API key is appended
+const HELIUS_WS_ENDPOINT: &str = "wss://mainnet.helius-rpc.com/?api-key=";
+
+/// Request budget of Helius endpoints
+const HELIUS_REQUESTS_PER_SECOND: u32 = 25;
+
+/// Default timeout of a single RPC request
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+/// How long the pool stays on a fallback endpoint before trying the primary again
+const PRIMARY_RETRY_INTERVAL_SECS: u64 = 60;
+
+/// JSON-RPC internal error code, returned by overloaded or misbehaving nodes
+const JSON_RPC_INTERNAL_ERROR: i64 = -32603;
+
+/// How settled a block has to be before the market events of its transactions are extracted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Commitment {
+    /// The block has been processed by the node. `getTransaction` and
+    /// `getSignaturesForAddress` don't serve processed blocks, so this extracts at `Confirmed`.
+    Processed,
+    /// The block has been voted on by a supermajority of the cluster, skipped slots can
+    /// still roll it back. Events are stored as provisional until they are finalized.
+    #[default]
+    Confirmed,
+    /// The block is rooted and can't be rolled back
+    Finalized,
+}
+
+impl Commitment {
+    /// Whether extracted events can never be rolled back
+    pub fn is_final(self) -> bool {
+        self == Self::Finalized
+    }
+
+    /// The commitment RPC requests are sent with
+    pub(crate) fn config(self) -> CommitmentConfig {
+        match self {
+            Self::Processed | Self::Confirmed => CommitmentConfig::confirmed(),
+            Self::Finalized => CommitmentConfig::finalized(),
+        }
+    }
+
+    /// Name of the commitment level as used by the JSON-RPC api
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Processed => "processed",
+            Self::Confirmed => "confirmed",
+            Self::Finalized => "finalized",
+        }
+    }
+}
+
+impl FromStr for Commitment {
+    type Err = VybeDaemonError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "processed" => Ok(Self::Processed),
+            "confirmed" => Ok(Self::Confirmed),
+            "finalized" => Ok(Self::Finalized),
+            _ => Err(VybeDaemonError::InvalidCommitment(s.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for Commitment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Where to reach Solana, any JSON-RPC provider (Helius, Triton, QuickNode, a private node) works,
+/// as does a directory of recorded transactions (`file://<dir>`) to replay them offline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcConfig {
+    /// Primary JSON-RPC url
+    pub url: String,
+    /// WebSocket url, derived from `url` when `None`
+    pub ws_url: Option<String>,
+    /// JSON-RPC urls tried in order when the primary fails
+    pub fallback_urls: Vec<String>,
+    /// Timeout of a single request
+    pub timeout: Duration,
+    /// Request budget of every endpoint, `None` sends requests as fast as they come
+    pub rate_limit: Option<RateLimit>,
+    /// Request budgets of individual endpoints by url, overriding `rate_limit`
+    pub endpoint_rate_limits: HashMap<String, RateLimit>,
+    /// How failed requests are retried, and when extraction pauses
+    pub retry: RetryPolicy,
+    /// How settled blocks have to be before they are extracted
+    pub commitment: Commitment,
+    /// Every fetched transaction is recorded to `<record_dir>/<signature>.json` when set,
+    /// such a directory can be replayed with `RpcConfig::fixtures`
+    pub record_dir: Option<PathBuf>,
+}
+
+impl RpcConfig {
+    /// Creates a configuration for a single JSON-RPC url without fallbacks
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_owned(),
+            ws_url: None,
+            fallback_urls: vec![],
+            timeout: Duration::from_secs(DEFAULT_TIMEOUT_SECS),
+            rate_limit: None,
+            endpoint_rate_limits: HashMap::new(),
+            retry: RetryPolicy::default(),
+            commitment: Commitment::default(),
+            record_dir: None,
+        }
+    }
+
+    /// Creates a configuration that replays the transactions recorded in `dir` without a network
+    pub fn fixtures(dir: &Path) -> Self {
+        Self::new(&format!("{FIXTURE_SCHEME}{}", dir.display()))
+    }
+
+    /// Creates a configuration for Helius mainnet with the provided API key,
+    /// limited to the Helius request budget
+    pub fn helius(api_key: &str) -> Self {
+        Self {
+            ws_url: Some(format!("{HELIUS_WS_ENDPOINT}{api_key}")),
+            rate_limit: Some(RateLimit::per_second(HELIUS_REQUESTS_PER_SECOND)),
+            ..Self::new(&format!("{HELIUS_RPC_ENDPOINT}{api_key}"))
+        }
+    }
+
+    /// Creates the configuration of the `[extractor]` section, an explicit `rpc_url` wins
+    /// over the Helius `api_key`
+    ///
+    /// # Errors
+    ///
+    /// `VybeDaemonError::NoRpcEndpoint` if neither `rpc_url` nor `api_key` is set
+    /// `VybeDaemonError::InvalidCommitment` if the commitment level is unknown
+    pub fn from_config(config: &ExtractorConfig) -> VybeResult<Self> {
+        let mut rpc_config = match (config.rpc_url.as_deref(), config.api_key.as_deref()) {
+            (Some(url), _) => Self::new(url),
+            (None, Some(api_key)) => Self::helius(api_key),
+            (None, None) => return Err(VybeDaemonError::NoRpcEndpoint),
+        };
+        if let Some(ws_url) = config.ws_url.as_ref() {
+            rpc_config.ws_url = Some(ws_url.clone());
+        }
+        rpc_config
+            .fallback_urls
+            .clone_from(&config.fallback_rpc_urls);
+        rpc_config.timeout = Duration::from_secs(config.rpc_timeout_secs);
+        rpc_config.commitment = Commitment::from_str(&config.commitment)?;
+        rpc_config.record_dir.clone_from(&config.record_dir);
+        if let Some(requests_per_second) = config.rpc_rate_limit {
+            rpc_config.rate_limit = Some(RateLimit::per_second(requests_per_second));
+        }
+        rpc_config.endpoint_rate_limits = config
+            .endpoint_rate_limits
+            .iter()
+            .map(|(url, requests_per_second)| {
+                (url.clone(), RateLimit::per_second(*requests_per_second))
+            })
+            .collect();
+        Ok(rpc_config)
+    }
+
+    /// The request budget of an endpoint
+    pub fn rate_limit_for(&self, url: &str) -> Option<RateLimit> {
+        self.endpoint_rate_limits
+            .get(url)
+            .copied()
+            .or(self.rate_limit)
+    }
+
+    /// The WebSocket url, either the configured one or the primary url with
+    /// `http(s)://` replaced by `ws(s)://`
+    pub fn ws_url(&self) -> String {
+        if let Some(ws_url) = self.ws_url.as_ref() {
+            return ws_url.clone();
+        }
+        if let Some(rest) = self.url.strip_prefix("https://") {
+            format!("wss://{rest}")
+        } else if let Some(rest) = self.url.strip_prefix("http://") {
+            format!("ws://{rest}")
+        } else {
+            self.url.clone()
+        }
+    }
+}
+
+/// Strips the query string (where providers put API keys) from a url so it can be logged
+pub(crate) fn redact_url(url: &str) -> &str {
+    url.split_once('?').map_or(url, |(base, _)| base)
+}
+
+/// Whether the error means the endpoint itself is failing (timeouts, connection errors,
+/// 429 and 5xx responses, unhealthy nodes) rather than the request being invalid,
+/// only those fail over and are retried. Any other HTTP status (a wrong API key or url)
+/// and undecodable responses are permanent.
+pub(crate) fn is_endpoint_failure(error: &ClientError) -> bool {
+    match error.kind() {
+        ClientErrorKind::Io(e) => matches!(
+            e.kind(),
+            io::ErrorKind::TimedOut
+                | io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::NotConnected
+        ),
+        ClientErrorKind::Reqwest(e) => {
+            e.is_timeout()
+                || e.is_connect()
+                || e.status().is_some_and(|status| {
+                    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+                })
+        }
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. }) => matches!(
+            *code,
+            JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY
+                | JSON_RPC_SERVER_ERROR_TRANSACTION_HISTORY_NOT_AVAILABLE
+                | JSON_RPC_INTERNAL_ERROR
+        ),
+        _ => false,
+    }
+}
+
+/// A single JSON-RPC endpoint and its Phoenix sdk client
+struct RpcEndpoint {
+    /// JSON-RPC url of the endpoint
+    url: String,
+    /// Atomically ref counted client connected to `url`
+    sdk_client: Arc<SDKClient>,
+    /// Budget every request to `url` waits on
+    limiter: Arc<RateLimiter>,
+}
+
+/// Ordered list of endpoints, the primary first. Requests go to the active endpoint,
+/// when it fails the next endpoint becomes active, after a while the primary is tried again.
+/// When every endpoint fails the request is retried with backoff, and once too many requests
+/// failed in a row the circuit breaker refuses requests until the provider had time to recover.
+pub struct RpcPool {
+    /// Primary endpoint followed by the fallbacks, never empty
+    endpoints: Vec<RpcEndpoint>,
+    /// How failed requests are retried
+    retry: RetryPolicy,
+    /// Refuses requests while the provider is down
+    breaker: CircuitBreaker,
+    /// Index of the endpoint requests are sent to
+    active: AtomicUsize,
+    /// When the pool last moved away from the primary endpoint
+    failed_over_at: Mutex<Instant>,
+    /// Commitment every request is sent with
+    commitment: Commitment,
+}
+
+impl RpcPool {
+    /// Creates a Phoenix sdk client for the primary and every fallback endpoint,
+    /// every request of a client goes through the rate limiter of its endpoint.
+    /// `file://` endpoints answer from recorded transactions instead.
+    ///
+    /// # Errors
+    ///
+    /// `VybeDaemonError::SolanaClient` if an HTTP client can't be built
+    /// `VybeDaemonError::PhoenixClient` if a client can't be created
+    pub async fn connect(config: &RpcConfig) -> VybeResult<Self> {
+        let mut endpoints = vec![];
+        for url in std::iter::once(&config.url).chain(config.fallback_urls.iter()) {
+            let limiter = Arc::new(RateLimiter::new(config.rate_limit_for(url)));
+            let client_config = RpcClientConfig::with_commitment(config.commitment.config());
+            let rpc_client = match url.strip_prefix(FIXTURE_SCHEME) {
+                Some(dir) => RpcClient::new_sender(FixtureSender::new(dir.into()), client_config),
+                None => RpcClient::new_sender(
+                    RateLimitedSender::new(
+                        url.clone(),
+                        config.timeout,
+                        Arc::clone(&limiter),
+                        config.record_dir.clone(),
+                    )?,
+                    client_config,
+                ),
+            };
+            let ellipsis_client = EllipsisClient::from_rpc(rpc_client, &Keypair::new())
+                .map_err(|e| VybeDaemonError::PhoenixClient(e.to_string()))?;
+            let sdk_client = SDKClient::new_from_ellipsis_client(ellipsis_client)
+                .await
+                .map_err(|e| VybeDaemonError::PhoenixClient(e.to_string()))?;
+            debug!("Connected to solana provider {}", redact_url(url));
+            endpoints.push(RpcEndpoint {
+                url: url.clone(),
+                sdk_client: Arc::new(sdk_client),
+                limiter,
+            });
+        }
+        info!(
+            "Using {} with {} fallback endpoint(s) at {} commitment",
+            redact_url(&config.url),
+            config.fallback_urls.len(),
+            config.commitment
+        );
+        if config.commitment == Commitment::Processed {
+            warn!("Transactions can't be fetched at processed commitment, extracting at confirmed");
+        }
+
+        Ok(Self {
+            endpoints,
+            retry: config.retry,
+            breaker: CircuitBreaker::new(&config.retry),
+            active: AtomicUsize::new(0),
+            failed_over_at: Mutex::new(Instant::now()),
+            commitment: config.commitment,
+        })
+    }
+
+    /// Commitment every request is sent with
+    pub fn commitment(&self) -> Commitment {
+        self.commitment
+    }
+
+    /// The client of the active endpoint
+    pub fn client(&self) -> Arc<SDKClient> {
+        let (_, endpoint) = self.active_endpoint();
+        Arc::clone(&endpoint.sdk_client)
+    }
+
+    /// What the rate limiter of every endpoint has done so far, by redacted url
+    pub fn rate_limiter_stats(&self) -> Vec<(String, RateLimiterStats)> {
+        self.endpoints
+            .iter()
+            .map(|endpoint| {
+                (
+                    redact_url(&endpoint.url).to_owned(),
+                    endpoint.limiter.stats(),
+                )
+            })
+            .collect()
+    }
+
+    /// How long requests are still refused by the circuit breaker, `None` when the provider is up
+    pub fn open_for(&self) -> Option<Duration> {
+        self.breaker.open_for()
+    }
+
+    /// Sends a request, failing over to the next endpoint on an endpoint failure. When every
+    /// endpoint failed the request is retried after a jittered exponential backoff.
+    ///
+    /// # Errors
+    ///
+    /// `VybeDaemonError::CircuitOpen` while the circuit breaker refuses requests
+    /// `VybeDaemonError::SolanaClient` with the last error when every attempt failed,
+    /// or immediately when the request itself is invalid
+    pub async fn call<T, F, Fut>(&self, request: F) -> VybeResult<T>
+    where
+        F: Fn(Arc<SDKClient>) -> Fut,
+        Fut: Future<Output = Result<T, ClientError>>,
+    {
+        if let Some(remaining) = self.breaker.open_for() {
+            return Err(VybeDaemonError::CircuitOpen(remaining));
+        }
+
+        let mut attempt = 0_u32;
+        loop {
+            attempt += 1;
+            match self.call_endpoints(&request).await {
+                Ok(value) => {
+                    self.breaker.record_success();
+                    return Ok(value);
+                }
+                Err(e) if e.is_transient() && attempt < self.retry.max_attempts => {
+                    let backoff = self.retry.backoff(attempt);
+                    debug!("Attempt {attempt} failed, retrying in {backoff:?}: {e}");
+                    tokio::time::sleep(backoff).await;
+                }
+                Err(e) => {
+                    if e.is_transient() {
+                        self.breaker.record_failure();
+                    }
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// Sends a request to the active endpoint, on an endpoint failure the request is sent
+    /// to the next endpoint until every endpoint has been tried once.
+    async fn call_endpoints<T, F, Fut>(&self, request: &F) -> VybeResult<T>
+    where
+        F: Fn(Arc<SDKClient>) -> Fut,
+        Fut: Future<Output = Result<T, ClientError>>,
+    {
+        let mut last_error = None;
+        for _ in 0..self.endpoints.len() {
+            let (index, endpoint) = self.active_endpoint();
+            match request(Arc::clone(&endpoint.sdk_client)).await {
+                Ok(value) => return Ok(value),
+                Err(e) if is_endpoint_failure(&e) => {
+                    warn!("{} failed: {e}", redact_url(&endpoint.url));
+                    self.fail_over(index);
+                    last_error = Some(e);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Err(last_error.map_or(VybeDaemonError::NoRpcEndpoint, VybeDaemonError::from))
+    }
+
+    /// Moves away from a failed endpoint, unless another request already did
+    fn fail_over(&self, failed: usize) {
+        let next = failed.checked_add(1).unwrap_or_default() % self.endpoints.len();
+        if self
+            .active
+            .compare_exchange(failed, next, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+        {
+            if let Ok(mut failed_over_at) = self.failed_over_at.lock() {
+                *failed_over_at = Instant::now();
+            }
+            if let Some(endpoint) = self.endpoints.get(next) {
+                warn!("Failing over to {}", redact_url(&endpoint.url));
+            }
+        }
+    }
+
+    /// The active endpoint and its index, moves back to the primary once it had time to recover
+    fn active_endpoint(&self) -> (usize, &RpcEndpoint) {
+        let mut index = self.active.load(Ordering::SeqCst);
+        if index != 0 {
+            let recovered = self.failed_over_at.lock().is_ok_and(|failed_over_at| {
+                failed_over_at.elapsed() >= Duration::from_secs(PRIMARY_RETRY_INTERVAL_SECS)
+            });
+            if recovered
+                && self
+                    .active
+                    .compare_exchange(index, 0, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+            {
+                info!("Trying the primary endpoint again");
+                index = 0;
+            }
+        }
+        match self.endpoints.get(index) {
+            Some(endpoint) => (index, endpoint),
+            None => (0, &self.endpoints[0]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{is_endpoint_failure, redact_url, Commitment, RpcConfig, VybeDaemonError},
+        solana_client::{
+            client_error::{ClientError, ClientErrorKind},
+            rpc_request::{RpcError, RpcResponseErrorData},
+        },
+    };
+
+    #[test]
+    fn derive_websocket_url() {
+        assert_eq!(
+            RpcConfig::new("https://rpc.example.com/abc").ws_url(),
+            "wss://rpc.example.com/abc"
+        );
+        assert_eq!(
+            RpcConfig::new("http://127.0.0.1:8899").ws_url(),
+            "ws://127.0.0.1:8899"
+        );
+
+        let mut config = RpcConfig::new("http://127.0.0.1:8899");
+        config.ws_url = Some("ws://127.0.0.1:8900".to_owned());
+        assert_eq!(config.ws_url(), "ws://127.0.0.1:8900");
+        assert_eq!(
+            RpcConfig::helius("secret").ws_url(),
+            "wss://mainnet.helius-rpc.com/?api-key=secret"
+        );
+    }
+
+    #[test]
+    fn parse_commitment() {
+        assert_eq!(
+            "finalized".parse::<Commitment>().ok(),
+            Some(Commitment::Finalized)
+        );
+        assert_eq!(
+            " Confirmed".parse::<Commitment>().ok(),
+            Some(Commitment::Confirmed)
+        );
+        assert!(matches!(
+            "recent".parse::<Commitment>(),
+            Err(VybeDaemonError::InvalidCommitment(_))
+        ));
+        assert!(!Commitment::Processed.is_final());
+        assert_eq!(
+            Commitment::Processed.config(),
+            Commitment::Confirmed.config()
+        );
+    }
+
+    #[test]
+    fn redact_api_keys() {
+        assert_eq!(
+            redact_url("https://mainnet.helius-rpc.com/?api-key=secret"),
+            "https://mainnet.helius-rpc.com/"
+        );
+        assert_eq!(redact_url("http://127.0.0.1:8899"), "http://127.0.0.1:8899");
+    }
+
+    #[test]
+    fn classify_endpoint_failures() {
+        let response_error = |code: i64| {
+            ClientError::from(ClientErrorKind::RpcError(RpcError::RpcResponseError {
+                code,
+                message: String::new(),
+                data: RpcResponseErrorData::Empty,
+            }))
+        };
+
+        assert!(is_endpoint_failure(&ClientError::from(
+            ClientErrorKind::Io(std::io::Error::from(std::io::ErrorKind::TimedOut))
+        )));
+        assert!(!is_endpoint_failure(&ClientError::from(
+            ClientErrorKind::Io(std::io::Error::from(std::io::ErrorKind::PermissionDenied))
+        )));
+        assert!(is_endpoint_failure(&response_error(-32005)));
+        assert!(!is_endpoint_failure(&response_error(-32602)));
+        assert!(!is_endpoint_failure(&ClientError::from(
+            ClientErrorKind::RpcError(RpcError::RpcRequestError(
+                "Failed to deserialize RPC error response".to_owned()
+            ))
+        )));
+        assert!(!is_endpoint_failure(&ClientError::from(
+            ClientErrorKind::Custom("invalid".to_owned())
+        )));
+    }
+}
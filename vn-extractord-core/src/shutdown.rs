@@ -0,0 +1,140 @@
+//! Graceful shutdown of the daemon. SIGINT or SIGTERM cancel a token shared by every task:
+//! no new extraction pass starts, and a pass in flight gets a deadline to store its events
+//! together with the market's cursor. A pass abandoned at the deadline stored nothing, the
+//! next start resumes from the stored cursor, so nothing is lost or stored twice.
+
+use {
+    std::{future::Future, time::Duration},
+    tokio_util::sync::CancellationToken,
+    tracing::{info, warn},
+};
+
+/// How long a pass in flight may take to finish once shutdown is requested
+const DRAIN_TIMEOUT_SECS: u64 = 30;
+
+/// Shutdown request shared by the daemon's tasks
+#[derive(Debug, Clone)]
+pub(crate) struct Shutdown {
+    /// Cancelled once shutdown is requested
+    token: CancellationToken,
+    /// How long a pass in flight may take to finish once shutdown is requested
+    drain_timeout: Duration,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new(
+            CancellationToken::new(),
+            Duration::from_secs(DRAIN_TIMEOUT_SECS),
+        )
+    }
+}
+
+impl Shutdown {
+    /// Creates a shutdown request that is triggered by cancelling `token`
+    pub(crate) fn new(token: CancellationToken, drain_timeout: Duration) -> Self {
+        Self {
+            token,
+            drain_timeout,
+        }
+    }
+
+    /// The token that triggers the shutdown
+    pub(crate) fn token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+
+    /// Whether shutdown has been requested, no new pass should start
+    pub(crate) fn is_requested(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// Runs a pass to completion, once shutdown is requested it gets the drain timeout to
+    /// finish. `None` when the pass was abandoned at the deadline.
+    pub(crate) async fn drain<T>(&self, pass: impl Future<Output = T>) -> Option<T> {
+        tokio::pin!(pass);
+        tokio::select! {
+            biased;
+            output = &mut pass => Some(output),
+            () = self.token.cancelled() => {
+                tokio::time::timeout(self.drain_timeout, pass).await.ok()
+            }
+        }
+    }
+
+    /// Waits for `future` unless shutdown is requested first, `None` when it was
+    pub(crate) async fn or_requested<T>(&self, future: impl Future<Output = T>) -> Option<T> {
+        tokio::select! {
+            biased;
+            () = self.token.cancelled() => None,
+            output = future => Some(output),
+        }
+    }
+
+    /// Sleeps for `duration`, waking up early when shutdown is requested
+    pub(crate) async fn sleep(&self, duration: Duration) {
+        self.or_requested(tokio::time::sleep(duration)).await;
+    }
+
+    /// Requests shutdown on the first SIGINT (ctrl-c) or SIGTERM
+    pub(crate) async fn listen_for_signals(&self) {
+        tokio::select! {
+            () = self.token.cancelled() => return,
+            () = terminate_signal() => info!("Received a termination signal"),
+        }
+        info!(
+            "Shutting down, passes in flight have {:?} to finish..",
+            self.drain_timeout
+        );
+        self.token.cancel();
+    }
+}
+
+/// Resolves on SIGINT or SIGTERM, only on SIGINT on platforms without SIGTERM.
+/// A signal that can't be listened for is logged and never resolves.
+async fn terminate_signal() {
+    #[cfg(unix)]
+    let sigterm = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                warn!("Can't listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let sigterm = std::future::pending::<()>();
+
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => {
+            if let Err(e) = result {
+                warn!("Can't listen for SIGINT: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+        () = sigterm => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::Shutdown, std::time::Duration, tokio_util::sync::CancellationToken};
+
+    #[tokio::test]
+    async fn drain_passes_in_flight() {
+        let shutdown = Shutdown::new(CancellationToken::new(), Duration::from_millis(50));
+        assert_eq!(shutdown.drain(async { 1_u8 }).await, Some(1));
+
+        shutdown.token().cancel();
+        assert!(shutdown.is_requested());
+        // A pass finishing before the deadline is kept, a slower one is abandoned
+        let quick = tokio::time::sleep(Duration::from_millis(10));
+        assert_eq!(shutdown.drain(quick).await, Some(()));
+        let slow = tokio::time::sleep(Duration::from_secs(10));
+        assert_eq!(shutdown.drain(slow).await, None);
+        assert_eq!(shutdown.or_requested(async { 1_u8 }).await, None);
+    }
+}
@@ -0,0 +1,609 @@
+//! Outputs of extracted market events. A market worker stores every batch in postgres together
+//! with its cursor first, which skips events it already stores, and only then writes it to the
+//! file sinks. When a file sink fails the cursor stays put and the batch is written again on
+//! the next pass, each file sink skipping the instructions it already holds, so no sink holds
+//! an event twice. A crash between the two can leave the batch in flight out of the files.
+//! A batch postgres can't store is recorded as dead letters and left out of the files, the
+//! retried dead letters are only stored in postgres.
+
+use {
+    crate::{
+        error::VybeDaemonError,
+        extractor::{SignatureCursor, VybeResult},
+    },
+    parquet::{
+        basic::Compression,
+        file::{properties::WriterProperties, writer::SerializedFileWriter},
+        record::RecordWriter,
+    },
+    parquet_derive::ParquetRecordWriter,
+    serde::Serialize,
+    std::{
+        fmt,
+        fs::{self, File, OpenOptions},
+        io::{self, Write},
+        path::{Path, PathBuf},
+        str::FromStr,
+        sync::Arc,
+    },
+    vn_database_core::{
+        models::{
+            NewFeeEvent, NewFillSummary, NewMarketEvents, NewOrderEviction, NewOrderPlace,
+            NewOrderReduce, NewSyncCheckpoint, NewTimeInForceEvent, NewTradeFill,
+        },
+        InsertedEvents, VybeDatabase,
+    },
+};
+
+/// Directory the file sinks write to when none is chosen
+const DEFAULT_SINK_DIR: &str = "events";
+
+/// A batch of a market's events and the cursor of the newest transaction they came from
+#[derive(Debug)]
+pub struct SinkBatch<'a> {
+    /// Base58 encoded public key of the market
+    pub market: &'a str,
+    /// Events extracted from the transactions up to the cursor
+    pub events: &'a NewMarketEvents,
+    /// Newest transaction the events were extracted from
+    pub cursor: SignatureCursor,
+}
+
+impl SinkBatch<'_> {
+    /// The market's checkpoint once the batch is stored
+    pub(crate) fn checkpoint(&self) -> NewSyncCheckpoint {
+        NewSyncCheckpoint {
+            market_pubkey: self.market.to_owned(),
+            last_signature: self.cursor.signature.to_string(),
+            last_slot: self.cursor.slot as i64,
+        }
+    }
+}
+
+/// Somewhere extracted market events are written
+pub trait EventSink: Send {
+    /// Name of the sink in logs, e.g. `jsonl`
+    fn name(&self) -> &'static str;
+
+    /// Writes a batch of events, they are handed to the destination once this returns
+    ///
+    /// # Errors
+    ///
+    /// The error of the destination, the market's cursor doesn't move and the next pass
+    /// writes the batch again
+    ///
+    /// # Returns
+    ///
+    /// The number of events written, events the destination already held aren't counted
+    fn write(&mut self, batch: &SinkBatch<'_>) -> VybeResult<usize>;
+}
+
+/// The kinds of sinks a market can write to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SinkKind {
+    /// The database, which also keeps the checkpoints, dead letters and sequence gaps
+    Postgres,
+    /// A newline-delimited JSON file per market, `<dir>/<market>.jsonl`
+    JsonLines,
+    /// A Parquet file of trade fills per batch, in `<dir>/<market>/`
+    Parquet,
+    /// Newline-delimited JSON on stdout, nothing is stored
+    Stdout,
+}
+
+impl SinkKind {
+    /// Every kind of sink
+    pub const ALL: [Self; 4] = [Self::Postgres, Self::JsonLines, Self::Parquet, Self::Stdout];
+
+    /// Name used on the command line and in the configuration, e.g. `jsonl`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Postgres => "postgres",
+            Self::JsonLines => "jsonl",
+            Self::Parquet => "parquet",
+            Self::Stdout => "stdout",
+        }
+    }
+}
+
+impl FromStr for SinkKind {
+    type Err = VybeDaemonError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| VybeDaemonError::InvalidSink(s.to_owned()))
+    }
+}
+
+impl fmt::Display for SinkKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The sinks every market writes to, and the directory of the file sinks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sinks {
+    /// Kinds of sinks, without duplicates
+    kinds: Vec<SinkKind>,
+    /// Directory the jsonl and parquet sinks write to
+    dir: PathBuf,
+}
+
+impl Default for Sinks {
+    /// Only the database
+    fn default() -> Self {
+        Self::new(&[SinkKind::Postgres], DEFAULT_SINK_DIR)
+    }
+}
+
+impl Sinks {
+    /// Writes to every one of `kinds`, the file sinks write into `dir`
+    pub fn new(kinds: &[SinkKind], dir: impl Into<PathBuf>) -> Self {
+        let mut unique = vec![];
+        for kind in kinds {
+            if !unique.contains(kind) {
+                unique.push(*kind);
+            }
+        }
+        Self {
+            kinds: unique,
+            dir: dir.into(),
+        }
+    }
+
+    /// Kinds of sinks every market writes to
+    pub fn kinds(&self) -> &[SinkKind] {
+        &self.kinds
+    }
+
+    /// Whether events are stored in the database. Only then are checkpoints, dead letters and
+    /// sequence gaps kept, without it every start extracts from the newest transactions.
+    pub fn uses_database(&self) -> bool {
+        self.kinds.contains(&SinkKind::Postgres)
+    }
+
+    /// Opens the sinks of a market, except the postgres one the worker opens itself since
+    /// its connection also holds the market's state
+    pub(crate) fn open_files(&self, market: &str) -> VybeResult<Vec<FileSink>> {
+        let mut sinks = vec![];
+        for kind in &self.kinds {
+            match kind {
+                SinkKind::Postgres => {}
+                SinkKind::JsonLines => {
+                    sinks.push(FileSink::new(JsonLinesSink::create(&self.dir, market)?));
+                }
+                SinkKind::Parquet => {
+                    sinks.push(FileSink::new(ParquetSink::create(&self.dir, market)?));
+                }
+                SinkKind::Stdout => sinks.push(FileSink::new(JsonLinesSink::stdout())),
+            }
+        }
+        Ok(sinks)
+    }
+}
+
+/// A sink other than postgres, and the highest sequence number it was handed. A batch written
+/// again after another sink failed only hands it the instructions it doesn't hold yet.
+pub(crate) struct FileSink {
+    /// Where the events are written
+    sink: Box<dyn EventSink>,
+    /// Highest sequence number of the instructions written so far, `None` before the first
+    written_through: Option<i64>,
+}
+
+impl FileSink {
+    /// Wraps a sink that has nothing written yet
+    fn new(sink: impl EventSink + 'static) -> Self {
+        Self {
+            sink: Box::new(sink),
+            written_through: None,
+        }
+    }
+
+    /// Name of the sink in logs
+    pub(crate) fn name(&self) -> &'static str {
+        self.sink.name()
+    }
+
+    /// Writes the events of a batch that are newer than the ones written so far
+    ///
+    /// # Errors
+    ///
+    /// The error of the destination, the batch is written again on the next pass
+    ///
+    /// # Returns
+    ///
+    /// The number of events written
+    pub(crate) fn write(&mut self, batch: &SinkBatch<'_>) -> VybeResult<usize> {
+        let Some(last) = batch.events.last_sequence_number() else {
+            return Ok(0);
+        };
+        let written = match self.written_through {
+            Some(through) if last <= through => 0,
+            Some(through) => self.sink.write(&SinkBatch {
+                events: &batch.events.newer_than(through),
+                ..*batch
+            })?,
+            None => self.sink.write(batch)?,
+        };
+        self.written_through = Some(last);
+        Ok(written)
+    }
+}
+
+/// Stores events together with the market's checkpoint in a single transaction
+pub struct PostgresSink {
+    /// PG database connection and interface
+    db: VybeDatabase,
+}
+
+impl PostgresSink {
+    /// Stores events over `db`
+    pub fn new(db: VybeDatabase) -> Self {
+        Self { db }
+    }
+
+    /// The connection, also used for the market's checkpoint, dead letters and sequence gaps
+    pub(crate) fn db(&mut self) -> &mut VybeDatabase {
+        &mut self.db
+    }
+
+    /// Stores a batch with the market's checkpoint, like `write`, and tells the inserted
+    /// fills apart from the other events
+    ///
+    /// # Errors
+    ///
+    /// `VybeDaemonError::Database`, nothing is stored and the cursor doesn't move
+    pub(crate) fn commit(&mut self, batch: &SinkBatch<'_>) -> VybeResult<InsertedEvents> {
+        Ok(self
+            .db
+            .commit_market_events(batch.events, &batch.checkpoint())?)
+    }
+}
+
+impl EventSink for PostgresSink {
+    fn name(&self) -> &'static str {
+        SinkKind::Postgres.as_str()
+    }
+
+    fn write(&mut self, batch: &SinkBatch<'_>) -> VybeResult<usize> {
+        self.commit(batch).map(|inserted| inserted.total)
+    }
+}
+
+/// Writes every event as a line of JSON tagged with its kind, e.g. `{"kind":"fill",...}`
+pub struct JsonLinesSink<W> {
+    /// Where the lines are written
+    writer: W,
+    /// Name of the sink in logs
+    name: &'static str,
+}
+
+impl JsonLinesSink<File> {
+    /// Appends to `<dir>/<market>.jsonl`, the directory and the file are created when missing
+    ///
+    /// # Errors
+    ///
+    /// `VybeDaemonError::Io` if the file can't be opened
+    pub fn create(dir: &Path, market: &str) -> VybeResult<Self> {
+        fs::create_dir_all(dir)?;
+        let writer = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(format!("{market}.jsonl")))?;
+        Ok(Self {
+            writer,
+            name: SinkKind::JsonLines.as_str(),
+        })
+    }
+}
+
+impl JsonLinesSink<io::Stdout> {
+    /// Writes to stdout, for dry runs
+    pub fn stdout() -> Self {
+        Self {
+            writer: io::stdout(),
+            name: SinkKind::Stdout.as_str(),
+        }
+    }
+}
+
+impl<W: Write + Send> EventSink for JsonLinesSink<W> {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn write(&mut self, batch: &SinkBatch<'_>) -> VybeResult<usize> {
+        let mut lines = vec![];
+        for record in EventRecord::all(batch.events) {
+            serde_json::to_writer(&mut lines, &record).map_err(io::Error::from)?;
+            lines.push(b'\n');
+        }
+        // A single write keeps the lines of markets sharing stdout apart
+        self.writer.write_all(&lines)?;
+        self.writer.flush()?;
+        Ok(batch.events.len())
+    }
+}
+
+/// Writes the trade fills of every batch to a Parquet file of its own, named after the slot
+/// and sequence number of its first fill so the files sort in extraction order.
+/// Only fills are written, the configuration rejects it when other kinds are extracted.
+pub struct ParquetSink {
+    /// Directory of the market's files
+    dir: PathBuf,
+}
+
+impl ParquetSink {
+    /// Writes into `<dir>/<market>/`, the directory is created when missing
+    ///
+    /// # Errors
+    ///
+    /// `VybeDaemonError::Io` if the directory can't be created
+    pub fn create(dir: &Path, market: &str) -> VybeResult<Self> {
+        let dir = dir.join(market);
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+}
+
+impl EventSink for ParquetSink {
+    fn name(&self) -> &'static str {
+        SinkKind::Parquet.as_str()
+    }
+
+    fn write(&mut self, batch: &SinkBatch<'_>) -> VybeResult<usize> {
+        let Some(first) = batch.events.fills.first() else {
+            return Ok(0);
+        };
+        let rows = batch
+            .events
+            .fills
+            .iter()
+            .map(FillRow::from)
+            .collect::<Vec<FillRow<'_>>>();
+        let path = self.dir.join(format!(
+            "fills-{:012}-{:012}.parquet",
+            first.slot, first.sequence_number
+        ));
+        // Written next to its final path and renamed, readers never see a partial file
+        let partial = path.with_extension("parquet.partial");
+
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let mut writer = SerializedFileWriter::new(
+            File::create(&partial)?,
+            rows.as_slice().schema()?,
+            Arc::new(properties),
+        )?;
+        let mut row_group = writer.next_row_group()?;
+        rows.as_slice().write_to_row_group(&mut row_group)?;
+        row_group.close()?;
+        writer.close()?;
+        fs::rename(&partial, &path)?;
+        Ok(rows.len())
+    }
+}
+
+/// A trade fill as written by the parquet sink
+#[derive(ParquetRecordWriter)]
+struct FillRow<'a> {
+    /// Base58 encoded public key of the market
+    market_pubkey: &'a str,
+    /// Base58 encoded signature of the transaction that emitted the fill
+    signature: &'a str,
+    /// The slot the transaction landed in
+    slot: i64,
+    /// The market's sequence number of the instruction that emitted the fill
+    sequence_number: i64,
+    /// Index of the fill among the events of its instruction
+    event_index: i64,
+    /// Unix timestamp (in seconds) of the fill
+    event_timestamp: i64,
+    /// Price of the fill in ticks
+    price_in_ticks: i64,
+    /// Base lots filled
+    base_lots_filled: i64,
+    /// Sequence number of the maker order that was filled
+    order_sequence_number: i64,
+    /// Base58 encoded public key of the maker order owner
+    maker: &'a str,
+    /// Base58 encoded public key of the taker
+    taker: &'a str,
+    /// Side of the maker order that was filled
+    side_filled: &'a str,
+    /// Quote lots exchanged, `None` when the market's header wasn't known
+    quote_lots_filled: Option<i64>,
+    /// Extracted below the finalized commitment
+    provisional: bool,
+}
+
+impl<'a> From<&'a NewTradeFill> for FillRow<'a> {
+    fn from(fill: &'a NewTradeFill) -> Self {
+        Self {
+            market_pubkey: &fill.market_pubkey,
+            signature: &fill.signature,
+            slot: fill.slot,
+            sequence_number: fill.sequence_number,
+            event_index: fill.event_index,
+            event_timestamp: fill.event_timestamp,
+            price_in_ticks: fill.price_in_ticks,
+            base_lots_filled: fill.base_lots_filled,
+            order_sequence_number: fill.order_sequence_number,
+            maker: &fill.maker,
+            taker: &fill.taker,
+            side_filled: &fill.side_filled,
+            quote_lots_filled: fill.quote_lots_filled,
+            provisional: fill.provisional,
+        }
+    }
+}
+
+/// A market event as written by the JSON sinks, tagged with the name of its kind
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum EventRecord<'a> {
+    /// A trade fill
+    Fill(&'a NewTradeFill),
+    /// A placed order
+    Place(&'a NewOrderPlace),
+    /// A reduced order
+    Reduce(&'a NewOrderReduce),
+    /// An evicted order
+    Evict(&'a NewOrderEviction),
+    /// A fill summary
+    FillSummary(&'a NewFillSummary),
+    /// Collected fees
+    Fee(&'a NewFeeEvent),
+    /// An order expiry
+    TimeInForce(&'a NewTimeInForceEvent),
+}
+
+impl<'a> EventRecord<'a> {
+    /// Every event of a batch, grouped by kind
+    fn all(events: &'a NewMarketEvents) -> impl Iterator<Item = Self> + 'a {
+        events
+            .fills
+            .iter()
+            .map(Self::Fill)
+            .chain(events.places.iter().map(Self::Place))
+            .chain(events.reduces.iter().map(Self::Reduce))
+            .chain(events.evictions.iter().map(Self::Evict))
+            .chain(events.fill_summaries.iter().map(Self::FillSummary))
+            .chain(events.fees.iter().map(Self::Fee))
+            .chain(events.time_in_force.iter().map(Self::TimeInForce))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{EventSink, FileSink, JsonLinesSink, SinkBatch, SinkKind, Sinks},
+        crate::extractor::SignatureCursor,
+        serde_json::Value,
+        solana_sdk::signature::Signature,
+        vn_database_core::models::{NewFeeEvent, NewMarketEvents, NewTradeFill},
+    };
+
+    #[test]
+    fn write_json_lines() {
+        let events = NewMarketEvents {
+            fills: vec![NewTradeFill {
+                event_timestamp: 1_700_000_000,
+                price_in_ticks: 150,
+                base_lots_filled: 10,
+                market_pubkey: "market".to_owned(),
+                signature: "signature".to_owned(),
+                slot: 100,
+                sequence_number: 7,
+                event_index: 0,
+                provisional: false,
+                order_sequence_number: 42,
+                maker: "maker".to_owned(),
+                taker: "taker".to_owned(),
+                side_filled: "ask".to_owned(),
+                quote_lots_filled: Some(1_500),
+            }],
+            fees: vec![NewFeeEvent {
+                market_pubkey: "market".to_owned(),
+                event_timestamp: 1_700_000_000,
+                fees_collected_in_quote_lots: 5,
+                signature: "signature".to_owned(),
+                slot: 100,
+                sequence_number: 7,
+                event_index: 1,
+                provisional: false,
+            }],
+            ..NewMarketEvents::default()
+        };
+        let mut sink = JsonLinesSink {
+            writer: vec![],
+            name: "test",
+        };
+        let batch = SinkBatch {
+            market: "market",
+            events: &events,
+            cursor: SignatureCursor {
+                signature: Signature::default(),
+                slot: 100,
+            },
+        };
+        assert_eq!(sink.write(&batch).expect("written"), 2);
+
+        let lines = String::from_utf8(sink.writer).expect("utf-8");
+        let records = lines
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).expect("a JSON object per line"))
+            .collect::<Vec<Value>>();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["kind"], "fill");
+        assert_eq!(records[0]["price_in_ticks"], 150);
+        assert_eq!(records[1]["kind"], "fee");
+        assert_eq!(records[1]["fees_collected_in_quote_lots"], 5);
+    }
+
+    #[test]
+    fn skip_written_events() {
+        /// A batch of `events` up to the same cursor
+        fn batch(events: &NewMarketEvents) -> SinkBatch<'_> {
+            SinkBatch {
+                market: "market",
+                events,
+                cursor: SignatureCursor {
+                    signature: Signature::default(),
+                    slot: 110,
+                },
+            }
+        }
+
+        let fees = |sequence_numbers: &[i64]| NewMarketEvents {
+            fees: sequence_numbers
+                .iter()
+                .map(|&sequence_number| NewFeeEvent {
+                    market_pubkey: "market".to_owned(),
+                    event_timestamp: 1_700_000_000,
+                    fees_collected_in_quote_lots: 5,
+                    signature: format!("signature-{sequence_number}"),
+                    slot: 100 + sequence_number,
+                    sequence_number,
+                    event_index: 0,
+                    provisional: false,
+                })
+                .collect(),
+            ..NewMarketEvents::default()
+        };
+        let mut sink = FileSink::new(JsonLinesSink {
+            writer: vec![],
+            name: "test",
+        });
+
+        let written = fees(&[7, 8]);
+        assert_eq!(sink.write(&batch(&written)).expect("written"), 2);
+        // Written again after another sink failed, with an instruction that landed since
+        let retried = fees(&[7, 8, 9]);
+        assert_eq!(retried.last_sequence_number(), Some(9));
+        assert_eq!(sink.write(&batch(&retried)).expect("written"), 1);
+        assert_eq!(sink.write(&batch(&written)).expect("written"), 0);
+    }
+
+    #[test]
+    fn parse_sinks() {
+        let kinds = ["postgres", " JSONL", "parquet", "jsonl"]
+            .iter()
+            .map(|kind| kind.parse::<SinkKind>())
+            .collect::<Result<Vec<SinkKind>, _>>()
+            .expect("known sinks");
+        let sinks = Sinks::new(&kinds, "events");
+        assert_eq!(
+            sinks.kinds(),
+            [SinkKind::Postgres, SinkKind::JsonLines, SinkKind::Parquet]
+        );
+        assert!(sinks.uses_database());
+        assert!(!Sinks::new(&[SinkKind::Stdout], "events").uses_database());
+        assert!("kafka".parse::<SinkKind>().is_err());
+    }
+}
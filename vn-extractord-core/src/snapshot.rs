@@ -0,0 +1,233 @@
+//! Periodic L2 order book snapshots. The top price levels of both sides of a market's book are
+//! read from its on-chain account and stored with the slot they were read at in the
+//! `orderbook_snapshots` and `orderbook_levels` tables. Every market is snapshot at its own
+//! interval, a failed snapshot is logged and taken again at the next interval.
+
+use {
+    crate::{
+        extractor::{VybeResult, VybeTradeFillExtractor},
+        rpc::RpcPool,
+        shutdown::Shutdown,
+    },
+    solana_sdk::pubkey::Pubkey,
+    std::{collections::HashMap, sync::Arc, time::Duration},
+    tokio::{sync::mpsc, time::Instant},
+    tracing::{debug, info, warn},
+    vn_config::DEFAULT_SNAPSHOT_DEPTH,
+    vn_database_core::{VybeDatabase, VybePool},
+};
+
+/// How often the snapshotter checks which markets are due
+const SNAPSHOT_TICK_MILLIS: u64 = 1000;
+
+/// Which markets have their order book snapshot, how often and how deep
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotSchedule {
+    /// Interval of every market without an interval of its own, `None` skips them
+    interval: Option<Duration>,
+    /// Intervals of individual markets
+    market_intervals: HashMap<Pubkey, Duration>,
+    /// Number of price levels stored per side
+    depth: u64,
+}
+
+impl Default for SnapshotSchedule {
+    fn default() -> Self {
+        Self {
+            interval: None,
+            market_intervals: HashMap::new(),
+            depth: DEFAULT_SNAPSHOT_DEPTH,
+        }
+    }
+}
+
+impl SnapshotSchedule {
+    /// Snapshots every market at `interval` (none when `None`) storing `depth` levels per side
+    pub fn new(interval: Option<Duration>, depth: u64) -> Self {
+        Self {
+            interval,
+            market_intervals: HashMap::new(),
+            depth: depth.max(1),
+        }
+    }
+
+    /// Snapshots a single market at its own interval
+    ///
+    /// # Errors
+    ///
+    /// `VybeDaemonError::ParsePubkey` if `market_addr` isn't a valid address
+    pub fn set_market_interval(&mut self, market_addr: &str, interval: Duration) -> VybeResult<()> {
+        self.market_intervals
+            .insert(Pubkey::try_from(market_addr)?, interval);
+        Ok(())
+    }
+
+    /// How often `market` is snapshot, `None` when it isn't
+    pub fn interval(&self, market: &Pubkey) -> Option<Duration> {
+        self.market_intervals.get(market).copied().or(self.interval)
+    }
+
+    /// Whether no market is snapshot at all
+    pub fn is_empty(&self) -> bool {
+        self.interval.is_none() && self.market_intervals.is_empty()
+    }
+}
+
+/// When a market is snapshot next
+struct SnapshotTimer {
+    /// Reads the market's account
+    extractor: VybeTradeFillExtractor,
+    /// Time between two snapshots
+    interval: Duration,
+    /// When the next snapshot is due
+    next: Instant,
+}
+
+/// Periodically snapshots the order book of every scheduled market, shares the database pool
+/// with the market workers so it can run on a separate task next to them.
+pub(crate) struct Snapshotter {
+    /// RPC endpoints shared with the market workers
+    rpc: Arc<RpcPool>,
+    /// PG database connection and interface
+    db: VybeDatabase,
+    /// Stops the loop once the daemon shuts down
+    shutdown: Shutdown,
+    /// Which markets are snapshot
+    schedule: SnapshotSchedule,
+    /// Markets being snapshot, by address
+    timers: HashMap<Pubkey, SnapshotTimer>,
+    /// Every market the daemon extracts, including the ones discovered while running
+    markets: mpsc::UnboundedReceiver<Pubkey>,
+}
+
+impl Snapshotter {
+    /// Creates a new snapshotter checking connections out of the daemon's pool,
+    /// the markets it is sent are snapshot when they are scheduled
+    pub(crate) fn new(
+        rpc: Arc<RpcPool>,
+        database: &VybePool,
+        shutdown: Shutdown,
+        markets: mpsc::UnboundedReceiver<Pubkey>,
+    ) -> Self {
+        Self {
+            rpc,
+            db: VybeDatabase::from_pool(database),
+            shutdown,
+            schedule: SnapshotSchedule::default(),
+            timers: HashMap::new(),
+            markets,
+        }
+    }
+
+    /// Chooses which markets are snapshot, nothing is by default
+    pub(crate) fn set_schedule(&mut self, schedule: SnapshotSchedule) {
+        self.schedule = schedule;
+    }
+
+    /// Whether any market is scheduled, the snapshotter doesn't need to run otherwise
+    pub(crate) fn is_scheduled(&self) -> bool {
+        !self.schedule.is_empty()
+    }
+
+    /// Snapshot the due markets inside a loop until shutdown. A snapshot that fails is logged
+    /// and taken again at the market's next interval, it never stops the daemon.
+    pub(crate) async fn run(mut self) -> VybeResult<()> {
+        info!("Taking order book snapshots..");
+        let mut tick = tokio::time::interval(Duration::from_millis(SNAPSHOT_TICK_MILLIS));
+
+        let shutdown = self.shutdown.clone();
+        while shutdown.or_requested(tick.tick()).await.is_some() {
+            self.schedule_new_markets()?;
+            if shutdown.drain(self.snapshot_due_markets()).await.is_none() {
+                break;
+            }
+        }
+        info!("Stopped taking order book snapshots");
+        Ok(())
+    }
+
+    /// Starts a timer for every market received since the last tick that is scheduled,
+    /// its first snapshot is due right away
+    fn schedule_new_markets(&mut self) -> VybeResult<()> {
+        while let Ok(market) = self.markets.try_recv() {
+            let Some(interval) = self.schedule.interval(&market) else {
+                continue;
+            };
+            if self.timers.contains_key(&market) {
+                continue;
+            }
+            let extractor =
+                VybeTradeFillExtractor::with_rpc(Arc::clone(&self.rpc), &market.to_string())?;
+            self.timers.insert(
+                market,
+                SnapshotTimer {
+                    extractor,
+                    interval,
+                    next: Instant::now(),
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Snapshots every market whose snapshot is due
+    async fn snapshot_due_markets(&mut self) {
+        let now = Instant::now();
+        for (market, timer) in &mut self.timers {
+            if timer.next > now {
+                continue;
+            }
+            timer.next = now + timer.interval;
+            let snapshot = match timer
+                .extractor
+                .get_orderbook_snapshot(self.schedule.depth)
+                .await
+            {
+                Ok(snapshot) => snapshot,
+                Err(e) => {
+                    warn!("{e}, snapshotting the order book of {market} again later..");
+                    continue;
+                }
+            };
+            match self.db.record_orderbook_snapshot(&snapshot) {
+                Ok(_) => debug!(
+                    "Snapshot the order book of {market} at slot {}: {} bid(s), {} ask(s)",
+                    snapshot.slot,
+                    snapshot.bids.len(),
+                    snapshot.asks.len()
+                ),
+                Err(e) => warn!("{e}, storing the order book of {market} again later.."),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::SnapshotSchedule, solana_sdk::pubkey::Pubkey, std::time::Duration};
+
+    #[test]
+    fn market_intervals_override_the_default() {
+        let market = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+
+        let mut schedule = SnapshotSchedule::new(None, 10);
+        assert!(schedule.is_empty());
+        schedule
+            .set_market_interval(&market.to_string(), Duration::from_secs(5))
+            .expect("valid address");
+        assert!(!schedule.is_empty());
+        assert_eq!(schedule.interval(&market), Some(Duration::from_secs(5)));
+        assert_eq!(schedule.interval(&other), None);
+
+        let mut schedule = SnapshotSchedule::new(Some(Duration::from_secs(60)), 10);
+        schedule
+            .set_market_interval(&market.to_string(), Duration::from_secs(5))
+            .expect("valid address");
+        assert_eq!(schedule.interval(&market), Some(Duration::from_secs(5)));
+        assert_eq!(schedule.interval(&other), Some(Duration::from_secs(60)));
+        assert!(schedule
+            .set_market_interval("not a market", Duration::from_secs(5))
+            .is_err());
+    }
+}
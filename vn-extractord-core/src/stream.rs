@@ -0,0 +1,202 @@
+//! Push based ingestion, streams the signatures of new market transactions over a Solana
+//! WebSocket `logsSubscribe` subscription instead of polling `get_signatures_for_address`.
+
+use {
+    crate::{
+        error::VybeDaemonError,
+        extractor::{SignatureCursor, VybeResult},
+    },
+    futures::StreamExt,
+    solana_client::{
+        nonblocking::pubsub_client::PubsubClient,
+        rpc_config::{RpcTransactionLogsConfig, RpcTransactionLogsFilter},
+    },
+    solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature},
+    std::str::FromStr,
+    tokio::sync::{mpsc, oneshot},
+    tracing::{debug, error},
+};
+
+/// Number of streamed signatures buffered while the previous ones are being decoded
+const SIGNATURE_CHANNEL_CAPACITY: usize = 1000;
+
+/// Subscribes to the logs of every transaction that mentions the market at the given
+/// commitment and forwards the signature (and slot) of each successful one. The subscription
+/// runs on its own task, the returned channel is closed when the WebSocket disconnects, which
+/// is the caller's cue to fill the gap by polling and subscribe again.
+///
+/// Returns once the subscription has been confirmed by the server, so that nothing that lands
+/// after this call returns can be missed.
+///
+/// # Errors
+///
+/// `VybeDaemonError::PubsubClient` if the connection or the subscription fails
+/// `VybeDaemonError::SubscriptionClosed` if the subscription task stopped unexpectedly
+pub(crate) async fn subscribe_signatures(
+    ws_url: &str,
+    market_pubkey: Pubkey,
+    commitment: CommitmentConfig,
+) -> VybeResult<mpsc::Receiver<SignatureCursor>> {
+    let ws_url = ws_url.to_owned();
+    let (ready_sender, ready_receiver) = oneshot::channel();
+    let (sender, receiver) = mpsc::channel(SIGNATURE_CHANNEL_CAPACITY);
+
+    tokio::spawn(async move {
+        let client = match PubsubClient::new(&ws_url).await {
+            Ok(client) => client,
+            Err(e) => {
+                let _ = ready_sender.send(Err(e));
+                return;
+            }
+        };
+        let (mut notifications, _unsubscribe) = match client
+            .logs_subscribe(
+                RpcTransactionLogsFilter::Mentions(vec![market_pubkey.to_string()]),
+                RpcTransactionLogsConfig {
+                    commitment: Some(commitment),
+                },
+            )
+            .await
+        {
+            Ok(subscription) => subscription,
+            Err(e) => {
+                let _ = ready_sender.send(Err(e));
+                return;
+            }
+        };
+        let _ = ready_sender.send(Ok(()));
+        debug!("Subscribed to the logs of {market_pubkey}");
+
+        while let Some(response) = notifications.next().await {
+            // Failed transactions never emit Phoenix events
+            if response.value.err.is_some() {
+                continue;
+            }
+            match Signature::from_str(&response.value.signature) {
+                Ok(signature) => {
+                    let cursor = SignatureCursor {
+                        signature,
+                        slot: response.context.slot,
+                    };
+                    if sender.send(cursor).await.is_err() {
+                        break;
+                    }
+                }
+                Err(e) => error!("Failed to parse a streamed Signature: {e}"),
+            }
+        }
+        debug!("Logs subscription of {market_pubkey} ended");
+    });
+
+    match ready_receiver.await {
+        Ok(Ok(())) => Ok(receiver),
+        Ok(Err(e)) => Err(VybeDaemonError::PubsubClient(e)),
+        Err(_) => Err(VybeDaemonError::SubscriptionClosed),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{subscribe_signatures, VybeDaemonError},
+        futures::{SinkExt, StreamExt},
+        serde_json::{json, Value},
+        solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature},
+        tokio::net::TcpListener,
+        tokio_tungstenite::{accept_async, tungstenite::Message},
+    };
+
+    /// Subscription id the mock server hands out
+    const SUBSCRIPTION_ID: u64 = 7;
+
+    /// Builds a `logsNotification` message the way a Solana RPC node sends it
+    fn logs_notification(signature: &Signature, slot: u64, err: Value) -> Message {
+        Message::Text(
+            json!({
+                "jsonrpc": "2.0",
+                "method": "logsNotification",
+                "params": {
+                    "result": {
+                        "context": { "slot": slot },
+                        "value": { "signature": signature.to_string(), "err": err, "logs": [] }
+                    },
+                    "subscription": SUBSCRIPTION_ID
+                }
+            })
+            .to_string(),
+        )
+    }
+
+    #[tokio::test]
+    async fn stream_signatures_from_mock_websocket() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind mock websocket server");
+        let addr = listener.local_addr().expect("mock websocket address");
+        let market = Pubkey::new_unique();
+        let failed = Signature::new_unique();
+        let succeeded = Signature::new_unique();
+
+        let server = tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.expect("accept connection");
+            let mut ws = accept_async(tcp).await.expect("websocket handshake");
+
+            let request = match ws.next().await {
+                Some(Ok(Message::Text(text))) => serde_json::from_str::<Value>(&text).ok(),
+                _ => None,
+            }
+            .unwrap_or_default();
+            assert_eq!(request["method"], "logsSubscribe");
+            assert_eq!(request["params"][0]["mentions"][0], market.to_string());
+
+            let confirmation =
+                json!({ "jsonrpc": "2.0", "result": SUBSCRIPTION_ID, "id": request["id"] });
+            let _ = ws.send(Message::Text(confirmation.to_string())).await;
+            let _ = ws
+                .send(logs_notification(
+                    &failed,
+                    10,
+                    json!({ "InstructionError": [0, "InvalidArgument"] }),
+                ))
+                .await;
+            let _ = ws
+                .send(logs_notification(&succeeded, 11, Value::Null))
+                .await;
+            let _ = ws.close(None).await;
+        });
+
+        let mut receiver = subscribe_signatures(
+            &format!("ws://{addr}"),
+            market,
+            CommitmentConfig::confirmed(),
+        )
+        .await
+        .expect("subscribe to the mock websocket server");
+        let streamed = receiver.recv().await;
+        assert_eq!(streamed.map(|cursor| cursor.signature), Some(succeeded));
+        assert_eq!(streamed.map(|cursor| cursor.slot), Some(11));
+
+        // The server hung up, the channel must close so the caller can fill the gap
+        assert!(receiver.recv().await.is_none());
+        server.await.expect("mock websocket server");
+    }
+
+    #[tokio::test]
+    async fn subscribe_fails_without_server() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind unused port");
+        let addr = listener.local_addr().expect("unused address");
+        drop(listener);
+
+        assert!(matches!(
+            subscribe_signatures(
+                &format!("ws://{addr}"),
+                Pubkey::new_unique(),
+                CommitmentConfig::confirmed()
+            )
+            .await,
+            Err(VybeDaemonError::PubsubClient(_))
+        ));
+    }
+}
@@ -0,0 +1,547 @@
+//! A single market's extraction loop, the daemon runs one of these per market
+
+use {
+    crate::{
+        backfill::{self, BackfillRange, BackfillReport},
+        book::{BookTracker, ChainBook, SharedOrderBook},
+        error::VybeDaemonError,
+        extractor::{
+            FailedTransaction, SequenceMark, SignatureCursor, VybeResult, VybeTradeFillExtractor,
+        },
+        gaps::SequenceTracker,
+        metrics,
+        shutdown::Shutdown,
+        sink::{FileSink, PostgresSink, SinkBatch, Sinks},
+        stream::subscribe_signatures,
+    },
+    phoenix_sdk::sdk_client::PhoenixEvent,
+    solana_sdk::{pubkey::Pubkey, signature::Signature},
+    std::{collections::BTreeSet, str::FromStr, time::Duration},
+    tracing::{error, info, warn},
+    vn_config::DEFAULT_POLL_INTERVAL_MS,
+    vn_database_core::{
+        models::{DeadLetterKind, MarketEventKind, NewDeadLetter, NewMarketEvent, NewMarketEvents},
+        units::MarketUnits,
+        InsertedEvents, VybeDatabase, VybePool,
+    },
+};
+
+/// How long a streaming market worker waits before subscribing again after a failure,
+/// and how long a worker pauses after a transient RPC failure when the circuit is closed
+const RECONNECT_DELAY_MS: u64 = 1000;
+
+/// Extracts one market, owns its own sinks and shares the database pool so workers can run on
+/// separate tasks
+pub(crate) struct MarketWorker {
+    /// Phoenix sdk and Helius interface for this market
+    trade_fill_extractor: VybeTradeFillExtractor,
+    /// Stores events with the market's checkpoint, and keeps its dead letters and sequence gaps,
+    /// `None` when the database isn't one of the sinks
+    postgres: Option<PostgresSink>,
+    /// Every other sink the market's events are written to, once the postgres one stored them
+    sinks: Vec<FileSink>,
+    /// Stops the extraction loops once the daemon shuts down
+    shutdown: Shutdown,
+    /// How long the worker waits between extraction passes
+    poll_interval: Duration,
+    /// Detects gaps in the market's sequence numbers as events are stored
+    sequences: SequenceTracker,
+    /// Keeps the market's live order book, `None` when it isn't tracked
+    book: Option<BookTracker>,
+    /// Converts the market's prices and sizes, `None` until `store_market` read its header
+    units: Option<MarketUnits>,
+}
+
+impl MarketWorker {
+    /// Creates a new worker writing to `sinks`, checking connections out of the daemon's
+    /// `database` pool when the database is one of them. Then if the market has a stored
+    /// checkpoint the extractor resumes from it and gaps are detected from the highest stored
+    /// sequence number.
+    pub(crate) fn new(
+        mut trade_fill_extractor: VybeTradeFillExtractor,
+        database: Option<&VybePool>,
+        sinks: &Sinks,
+        shutdown: Shutdown,
+    ) -> VybeResult<Self> {
+        let market = trade_fill_extractor.market_pubkey().to_string();
+        let file_sinks = sinks.open_files(&market)?;
+        let Some(database) = database else {
+            return Ok(Self {
+                trade_fill_extractor,
+                postgres: None,
+                sinks: file_sinks,
+                shutdown,
+                poll_interval: Duration::from_millis(DEFAULT_POLL_INTERVAL_MS),
+                sequences: SequenceTracker::default(),
+                book: None,
+                units: None,
+            });
+        };
+
+        let mut db = VybeDatabase::from_pool(database);
+        if let Some(checkpoint) = db.get_checkpoint(&market)? {
+            info!(
+                "Resuming {market} from signature {} (slot {})",
+                checkpoint.last_signature, checkpoint.last_slot
+            );
+            trade_fill_extractor.set_cursor(SignatureCursor {
+                signature: Signature::from_str(&checkpoint.last_signature)?,
+                slot: u64::try_from(checkpoint.last_slot).unwrap_or_default(),
+            });
+        }
+
+        let last_sequence = db
+            .get_last_sequence_number(&market)?
+            .map(|(sequence_number, slot)| SequenceMark {
+                sequence_number: u64::try_from(sequence_number).unwrap_or_default(),
+                slot: u64::try_from(slot).unwrap_or_default(),
+            });
+
+        Ok(Self {
+            trade_fill_extractor,
+            postgres: Some(PostgresSink::new(db)),
+            sinks: file_sinks,
+            shutdown,
+            poll_interval: Duration::from_millis(DEFAULT_POLL_INTERVAL_MS),
+            sequences: SequenceTracker::new(last_sequence),
+            book: None,
+            units: None,
+        })
+    }
+
+    /// Reads the market's on-chain metadata and stores it, fills reference their market's
+    /// row so this runs before anything is extracted. A market's fee can change,
+    /// so it is read again every time the daemon starts. Without the database it is only logged.
+    /// Its units are kept to derive the quote lots of every fill.
+    pub(crate) async fn store_market(&mut self) -> VybeResult<()> {
+        let market = self.trade_fill_extractor.get_market().await?;
+        if let Some(postgres) = &mut self.postgres {
+            postgres.db().upsert_market(&market)?;
+        }
+        let units = MarketUnits::from(&market);
+        info!(
+            "{}: base mint {} ({} decimals), quote mint {} ({} decimals), tick size {}, lot size {}, taker fee {} bps",
+            market.market_pubkey,
+            market.base_mint,
+            market.base_decimals,
+            market.quote_mint,
+            market.quote_decimals,
+            units.tick_size().unwrap_or_default(),
+            units.base_quantity(1).unwrap_or_default(),
+            market.taker_fee_bps
+        );
+        self.units = Some(units);
+        Ok(())
+    }
+
+    /// Address of the extracted market
+    pub(crate) fn market_pubkey(&self) -> &Pubkey {
+        self.trade_fill_extractor.market_pubkey()
+    }
+
+    /// Chooses which kinds of market events are stored
+    pub(crate) fn set_event_kinds(&mut self, event_kinds: &[MarketEventKind]) {
+        self.trade_fill_extractor.set_event_kinds(event_kinds);
+    }
+
+    /// Uses the units of a market header read elsewhere, e.g. by discovery, instead of
+    /// `store_market`, to derive the quote lots of every fill
+    pub(crate) fn set_units(&mut self, units: MarketUnits) {
+        self.units = Some(units);
+    }
+
+    /// Chooses how long the worker waits between extraction passes
+    pub(crate) fn set_poll_interval(&mut self, poll_interval: Duration) {
+        self.poll_interval = poll_interval;
+    }
+
+    /// Chooses how many transactions are parsed concurrently and how many signatures are
+    /// listed per request
+    pub(crate) fn set_parse_limits(
+        &mut self,
+        parse_concurrency: usize,
+        signature_page_size: usize,
+    ) {
+        self.trade_fill_extractor
+            .set_parse_limits(parse_concurrency, signature_page_size);
+    }
+
+    /// Keeps the market's live order book from the events it extracts, the book is seeded from
+    /// the market account and checked against it every `check_interval`
+    pub(crate) fn track_order_book(&mut self, check_interval: Duration) -> SharedOrderBook {
+        let tracker = BookTracker::new(check_interval);
+        let book = tracker.shared();
+        self.book = Some(tracker);
+        book
+    }
+
+    /// Backfill this market's history inside `range` into the database, transient RPC failures
+    /// pause the backfill which then resumes from its stored progress.
+    pub(crate) async fn backfill(&mut self, range: BackfillRange) -> VybeResult<BackfillReport> {
+        loop {
+            let Some(postgres) = &mut self.postgres else {
+                return Err(VybeDaemonError::DatabaseRequired("Backfilling"));
+            };
+            match backfill::backfill(&self.trade_fill_extractor, postgres.db(), range).await {
+                Ok(report) => return Ok(report),
+                Err(e) => self.pause(e).await?,
+            }
+        }
+    }
+
+    /// Extract the market inside a loop until shutdown, transient RPC failures pause the loop.
+    /// A pass in flight at shutdown is drained, its events are stored with the cursor.
+    pub(crate) async fn run(mut self) -> VybeResult<()> {
+        info!(
+            "Extracting market {}",
+            self.trade_fill_extractor.market_pubkey()
+        );
+
+        let shutdown = self.shutdown.clone();
+        while !shutdown.is_requested() {
+            match shutdown.drain(self.poll()).await {
+                Some(Ok(_)) => shutdown.sleep(self.poll_interval).await,
+                Some(Err(e)) => self.pause(e).await?,
+                None => self.abandoned(),
+            }
+        }
+        self.stopped();
+        Ok(())
+    }
+
+    /// Extract the market inside a loop until shutdown, new transactions are pushed over a
+    /// WebSocket logs subscription. Whenever the subscription is (re)established the cursor
+    /// based polling runs once to fill the gap since the last processed signature.
+    /// At shutdown no new signature is taken from the subscription, the one in flight is drained.
+    pub(crate) async fn stream(mut self, ws_url: String) -> VybeResult<()> {
+        let market_pubkey = *self.trade_fill_extractor.market_pubkey();
+        info!("Streaming market {market_pubkey}");
+
+        let shutdown = self.shutdown.clone();
+        while !shutdown.is_requested() {
+            // Subscribe before polling, anything landing in between is both polled and
+            // streamed, the streamed duplicate is skipped below.
+            let subscription = shutdown
+                .or_requested(subscribe_signatures(
+                    &ws_url,
+                    market_pubkey,
+                    self.trade_fill_extractor.rpc().commitment().config(),
+                ))
+                .await;
+            let mut signatures = match subscription {
+                Some(Ok(signatures)) => signatures,
+                Some(Err(e)) => {
+                    warn!("{market_pubkey}: {e}, polling until the subscription is back..");
+                    match shutdown.drain(self.poll()).await {
+                        Some(Ok(_)) => {
+                            shutdown
+                                .sleep(Duration::from_millis(RECONNECT_DELAY_MS))
+                                .await;
+                        }
+                        Some(Err(e)) => self.pause(e).await?,
+                        None => self.abandoned(),
+                    }
+                    continue;
+                }
+                None => break,
+            };
+            let polled = match shutdown.drain(self.poll()).await {
+                Some(Ok(polled)) => polled,
+                Some(Err(e)) => {
+                    self.pause(e).await?;
+                    continue;
+                }
+                None => {
+                    self.abandoned();
+                    break;
+                }
+            };
+
+            while let Some(Some(cursor)) = shutdown.or_requested(signatures.recv()).await {
+                if polled.contains(&cursor.signature) {
+                    continue;
+                }
+                // The cursor only moves once the transaction is stored, after a transient
+                // failure the polling below picks it up again.
+                let extraction =
+                    shutdown.drain(self.trade_fill_extractor.extract_events([cursor.signature]));
+                match extraction.await {
+                    Some(Ok(decoded)) => {
+                        self.commit(decoded.events, &decoded.sequences, &decoded.failed, cursor)?;
+                        self.check_order_book().await;
+                    }
+                    Some(Err(e)) => {
+                        self.pause(e).await?;
+                        break;
+                    }
+                    None => {
+                        self.abandoned();
+                        break;
+                    }
+                }
+            }
+            if !shutdown.is_requested() {
+                warn!("{market_pubkey}: stream interrupted, filling the gap by polling..");
+            }
+        }
+        self.stopped();
+        Ok(())
+    }
+
+    /// Waits out a transient RPC failure, for as long as the circuit breaker is open,
+    /// any other error is returned.
+    async fn pause(&self, error: VybeDaemonError) -> VybeResult<()> {
+        if !error.is_transient() {
+            return Err(error);
+        }
+        let pause = self
+            .trade_fill_extractor
+            .rpc()
+            .open_for()
+            .unwrap_or(Duration::from_millis(RECONNECT_DELAY_MS));
+        warn!(
+            "{}: {error}, pausing extraction for {pause:?}",
+            self.trade_fill_extractor.market_pubkey()
+        );
+        self.shutdown.sleep(pause).await;
+        Ok(())
+    }
+
+    /// Logs a pass that didn't finish before the drain deadline, nothing of it was stored
+    fn abandoned(&self) {
+        warn!(
+            "{}: abandoned the extraction pass in flight, the next start resumes from the stored cursor",
+            self.trade_fill_extractor.market_pubkey()
+        );
+    }
+
+    /// Logs where the market stopped, the cursor is stored with the last committed events
+    fn stopped(&self) {
+        let market = self.trade_fill_extractor.market_pubkey();
+        match self.trade_fill_extractor.cursor() {
+            Some(cursor) => info!(
+                "Stopped extracting market {market} at signature {} (slot {})",
+                cursor.signature, cursor.slot
+            ),
+            None => info!("Stopped extracting market {market}"),
+        }
+    }
+
+    /// Runs a single extraction pass from the cursor, each batch of the pipeline is stored
+    /// with its cursor before the next one is taken. The pass stops at the first batch that
+    /// couldn't be stored, the next pass starts over from the stored cursor.
+    /// Returns the signatures that were decoded.
+    async fn poll(&mut self) -> VybeResult<Vec<Signature>> {
+        let mut pipeline = self.trade_fill_extractor.pipeline();
+        let mut signatures = vec![];
+        while let Some(batch) = pipeline.next_batch().await? {
+            signatures.extend(batch.signatures);
+            if let Some(cursor) = batch.cursor {
+                if !self.commit(batch.events, &batch.sequences, &batch.failed, cursor)? {
+                    break;
+                }
+            }
+        }
+        self.check_order_book().await;
+        Ok(signatures)
+    }
+
+    /// Reads the book of the market account when the live order book is due to be seeded or
+    /// checked, a failed read is logged and retried at the next interval
+    async fn check_order_book(&mut self) {
+        let Some(tracker) = self.book.as_mut().filter(|tracker| tracker.is_due()) else {
+            return;
+        };
+        match self.trade_fill_extractor.get_order_book().await {
+            Ok((slot, book)) => tracker.observe(ChainBook { slot, book }),
+            Err(e) => {
+                warn!(
+                    "{}: {e}, reading the order book again later..",
+                    self.trade_fill_extractor.market_pubkey()
+                );
+                tracker.postpone();
+            }
+        }
+    }
+
+    /// Applies events that are committed to the live order book, extracted up to `cursor_slot`
+    fn track_events(&mut self, events: &NewMarketEvents, cursor_slot: u64) {
+        if let Some(tracker) = &mut self.book {
+            tracker.apply(events, cursor_slot);
+        }
+    }
+
+    /// Records the gaps in front of newly stored sequence numbers, the gap filler backfills them.
+    /// Without the database they are only logged.
+    fn record_gaps(&mut self, market: &str, sequences: &[SequenceMark]) {
+        let gaps = self.sequences.observe(market, sequences);
+        if gaps.is_empty() {
+            return;
+        }
+        for gap in &gaps {
+            warn!(
+                "{market}: sequence numbers {}..={} are missing, backfilling slots {}..={}",
+                gap.first_missing, gap.last_missing, gap.from_slot, gap.to_slot
+            );
+        }
+        let Some(postgres) = &mut self.postgres else {
+            return;
+        };
+        if let Err(e) = postgres.db().record_sequence_gaps(&gaps) {
+            error!(
+                "{market}: failed to record {} sequence gap(s): {e}",
+                gaps.len()
+            );
+        }
+    }
+
+    /// Stores the events with the market's signature cursor in one transaction, then writes
+    /// them to every other sink, the cursor only moves forward in memory once they are all
+    /// written. Below the finalized commitment the events are stored as provisional.
+    /// Without the database the cursor only moves in memory.
+    ///
+    /// Transactions that failed to be fetched or decoded are recorded as dead letters first.
+    /// When the events can't be inserted their transactions are recorded as dead letters
+    /// together with the cursor instead, the retrier stores their events later in postgres
+    /// only. They aren't written to the other sinks, and the live order book that misses them
+    /// is seeded again.
+    /// When another sink fails the cursor stays, the next pass writes the batch again and
+    /// every sink skips the events it already holds.
+    /// Once the events are stored the gaps in front of their sequence numbers are recorded,
+    /// and events whose cursor moved are applied to the live order book.
+    /// Returns whether the cursor moved.
+    fn commit(
+        &mut self,
+        events: Vec<PhoenixEvent>,
+        sequences: &[SequenceMark],
+        failed: &[FailedTransaction],
+        cursor: SignatureCursor,
+    ) -> VybeResult<bool> {
+        let market = self.trade_fill_extractor.market_pubkey().to_string();
+        if !failed.is_empty() {
+            let letters = failed
+                .iter()
+                .map(|failed| failed.dead_letter(&market))
+                .collect::<Vec<NewDeadLetter>>();
+            match &mut self.postgres {
+                Some(postgres) => {
+                    if let Err(e) = postgres.db().record_dead_letters(&letters) {
+                        error!(
+                            "{market}: failed to record {} dead letter(s): {e}",
+                            letters.len()
+                        );
+                        return Ok(false);
+                    }
+                    metrics::record_dead_letters(&market, letters.len());
+                }
+                None => {
+                    for letter in &letters {
+                        warn!(
+                            "{market}: skipped transaction {}: {}",
+                            letter.signature, letter.error_message
+                        );
+                    }
+                }
+            }
+        }
+
+        let extracted = events.len();
+        let block_times = events
+            .iter()
+            .map(|event| event.timestamp)
+            .collect::<Vec<i64>>();
+        let signatures = events
+            .iter()
+            .map(|event| event.signature)
+            .collect::<BTreeSet<Signature>>();
+        let mut new_events = events
+            .into_iter()
+            .map(NewMarketEvent::try_from)
+            .collect::<Result<NewMarketEvents, _>>()?;
+        if !self.trade_fill_extractor.rpc().commitment().is_final() {
+            new_events.mark_provisional();
+        }
+        if let Some(units) = &self.units {
+            new_events.derive_quote_lots(units);
+        }
+        let batch = SinkBatch {
+            market: &market,
+            events: &new_events,
+            cursor,
+        };
+        let stored = match &mut self.postgres {
+            None => Some(InsertedEvents {
+                fills: new_events.fills.len(),
+                total: extracted,
+            }),
+            Some(postgres) => match postgres.commit(&batch) {
+                Ok(inserted) => {
+                    if inserted.total > 0 {
+                        info!(
+                            "Successfully created {} market event entries ({} trade fill(s)) for {market}..",
+                            inserted.total, inserted.fills
+                        );
+                    }
+                    Some(inserted)
+                }
+                Err(e) => {
+                    error!("{market}: {e}");
+                    let letters = signatures
+                        .iter()
+                        .map(|signature| {
+                            NewDeadLetter::new(
+                                &market,
+                                &signature.to_string(),
+                                DeadLetterKind::Insert,
+                                e.to_string(),
+                            )
+                        })
+                        .collect::<Vec<NewDeadLetter>>();
+                    match postgres
+                        .db()
+                        .commit_dead_letters(&letters, &batch.checkpoint())
+                    {
+                        Ok(recorded) => {
+                            metrics::record_dead_letters(&market, recorded);
+                            warn!("{market}: recorded {recorded} transaction(s) as dead letters, moving on..");
+                            None
+                        }
+                        Err(e) => {
+                            error!("{market}: failed to record dead letters: {e}");
+                            return Ok(false);
+                        }
+                    }
+                }
+            },
+        };
+
+        let Some(inserted) = stored else {
+            // Dead letters are only retried into postgres, the other sinks and the order book
+            // never see their events
+            self.trade_fill_extractor.set_cursor(cursor);
+            if let Some(tracker) = &mut self.book {
+                tracker.reset();
+            }
+            return Ok(true);
+        };
+        for sink in &mut self.sinks {
+            if let Err(e) = sink.write(&batch) {
+                error!("{market}: failed to write to the {} sink: {e}", sink.name());
+                return Ok(false);
+            }
+        }
+
+        self.trade_fill_extractor.set_cursor(cursor);
+        self.track_events(&new_events, cursor.slot);
+        metrics::record_commit(
+            &market,
+            inserted,
+            extracted,
+            new_events.fills.len(),
+            block_times,
+            cursor.slot,
+        );
+        self.record_gaps(&market, sequences);
+        Ok(true)
+    }
+}
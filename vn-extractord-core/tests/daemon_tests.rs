@@ -0,0 +1,201 @@
+//! End to end tests of the daemon against the mock JSON-RPC server of `vn-test-support`.
+//! These tests require a postgresql database to be running locally with
+//! credentials that match those found the .env file in the root of the project
+//!
+//! These tests are ran with `just itest` the database is migrated and cleared afterwards
+
+#[cfg(feature = "integration_tests")]
+use {
+    solana_sdk::{pubkey::Pubkey, signature::Signature},
+    std::{str::FromStr, time::Duration},
+    vn_database_core::{
+        models::{NewMarketEvents, NewSyncCheckpoint},
+        VybeDatabase,
+    },
+    vn_extractord_core::{MarketFilter, RetryPolicy, RpcConfig, VybeDaemon},
+    vn_test_support::{MockFailure, MockRpcServer, MockSignature},
+};
+
+/// Slot of the checkpoint the daemon resumes from
+#[cfg(feature = "integration_tests")]
+const CHECKPOINT_SLOT: u64 = 300_000_000;
+
+/// Owner of every Phoenix market account
+#[cfg(feature = "integration_tests")]
+const PHOENIX_PROGRAM_ID: &str = "PhoeNiXZ8ByJGLkxNfZRnkUfjvmuYqLR89jjFHGqdXY";
+
+/// Mint of wrapped SOL
+#[cfg(feature = "integration_tests")]
+const SOL_MINT: &str = "So11111111111111111111111111111111111111112";
+
+/// A Phoenix market account of `base_mint` against USDC with the SOL/USDC lot and tick sizes
+/// and a 2 bps taker fee, fields are written at their offsets in the `MarketHeader` and
+/// `FIFOMarket`
+#[cfg(feature = "integration_tests")]
+fn phoenix_market_account(base_mint: &str) -> Vec<u8> {
+    let base = Pubkey::from_str(base_mint).expect("mint");
+    let usdc = Pubkey::from_str("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").expect("mint");
+    let mut data = vec![0_u8; 1024];
+    for (offset, bytes) in [
+        (40, 9_u32.to_le_bytes().to_vec()),
+        (48, base.to_bytes().to_vec()),
+        (112, 1_000_000_u64.to_le_bytes().to_vec()),
+        (120, 6_u32.to_le_bytes().to_vec()),
+        (128, usdc.to_bytes().to_vec()),
+        (192, 1_u64.to_le_bytes().to_vec()),
+        (200, 1000_u64.to_le_bytes().to_vec()),
+        (312, 1_u32.to_le_bytes().to_vec()),
+        (856, 2_u64.to_le_bytes().to_vec()),
+    ] {
+        data[offset..offset + bytes.len()].copy_from_slice(&bytes);
+    }
+    data
+}
+
+/// Lists and serves a new memo transaction mentioning `market`, landed in `slot`
+#[cfg(feature = "integration_tests")]
+fn add_transaction(server: &MockRpcServer, market: &str, slot: u64) -> MockSignature {
+    let signature = MockSignature::new(&Signature::new_unique().to_string(), slot);
+    server.add_transaction(
+        market,
+        signature.clone(),
+        MockRpcServer::transaction(&signature, &[market]),
+    );
+    signature
+}
+
+#[cfg(feature = "integration_tests")]
+#[tokio::test]
+async fn daemon_run_against_mock_rpc_test() {
+    let server = MockRpcServer::start().await.expect("mock server");
+    let market = Pubkey::new_unique().to_string();
+    server.set_account(
+        &market,
+        MockRpcServer::account(PHOENIX_PROGRAM_ID, &phoenix_market_account(SOL_MINT)),
+    );
+    let checkpoint = add_transaction(&server, &market, CHECKPOINT_SLOT);
+    let mut newest = checkpoint.clone();
+    for offset in 1..=1200_u64 {
+        newest = add_transaction(&server, &market, CHECKPOINT_SLOT + offset);
+        if offset % 100 == 0 {
+            server.push_signature(&market, newest.clone());
+        }
+    }
+    server.fail_requests("getSignaturesForAddress", MockFailure::HttpStatus(503), 1);
+    server.fail_requests("getTransaction", MockFailure::TooManyRequests(Some(1)), 2);
+
+    let db = &mut VybeDatabase::new().expect("database connection");
+    db.commit_market_events(
+        &NewMarketEvents::default(),
+        &NewSyncCheckpoint {
+            market_pubkey: market.clone(),
+            last_signature: checkpoint.signature.clone(),
+            last_slot: CHECKPOINT_SLOT as i64,
+        },
+    )
+    .expect("seed checkpoint");
+
+    let rpc_config = RpcConfig {
+        retry: RetryPolicy {
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(50),
+            ..RetryPolicy::default()
+        },
+        ..RpcConfig::new(&server.url())
+    };
+    let mut daemon = VybeDaemon::new(&rpc_config, &[market.clone()])
+        .await
+        .expect("daemon on the mock server");
+    // Give the daemon time for a few passes, then shut it down like SIGTERM would
+    let shutdown = daemon.shutdown_token();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        shutdown.cancel();
+    });
+    tokio::time::timeout(Duration::from_secs(60), daemon.run())
+        .await
+        .expect("daemon drained before the deadline")
+        .expect("daemon shut down cleanly");
+
+    let stored_market = db
+        .get_market(&market)
+        .expect("read market")
+        .expect("stored market");
+    assert_eq!(
+        stored_market
+            .units()
+            .tick_size()
+            .map(|tick| tick.to_string()),
+        Some("0.001".to_owned())
+    );
+    assert_eq!(stored_market.taker_fee_bps, 2);
+
+    let stored = db
+        .get_checkpoint(&market)
+        .expect("read checkpoint")
+        .expect("stored checkpoint");
+    assert_eq!(stored.last_signature, newest.signature);
+    assert_eq!(stored.last_slot, newest.slot as i64);
+    // Every transaction is fetched once, duplicates included, plus the throttled attempts
+    assert_eq!(server.requests("getTransaction"), 1202);
+    let throttled = daemon
+        .rate_limiter_stats()
+        .iter()
+        .map(|(_, stats)| stats.throttled)
+        .sum::<u64>();
+    assert_eq!(throttled, 2);
+}
+
+#[cfg(feature = "integration_tests")]
+#[tokio::test]
+async fn daemon_discover_markets_test() {
+    let server = MockRpcServer::start().await.expect("mock server");
+    let sol_market = Pubkey::new_unique().to_string();
+    let other_market = Pubkey::new_unique().to_string();
+    let other_mint = Pubkey::new_unique().to_string();
+    server.set_account(
+        &sol_market,
+        MockRpcServer::account(PHOENIX_PROGRAM_ID, &phoenix_market_account(SOL_MINT)),
+    );
+    server.set_account(
+        &other_market,
+        MockRpcServer::account(PHOENIX_PROGRAM_ID, &phoenix_market_account(&other_mint)),
+    );
+    // A seat is too small to be a market, accounts of other programs aren't listed
+    server.set_account(
+        &Pubkey::new_unique().to_string(),
+        MockRpcServer::account(PHOENIX_PROGRAM_ID, &[0_u8; 128]),
+    );
+    server.set_account(
+        &Pubkey::new_unique().to_string(),
+        MockRpcServer::account(
+            &Pubkey::new_unique().to_string(),
+            &phoenix_market_account(SOL_MINT),
+        ),
+    );
+
+    let mut daemon = VybeDaemon::new(&RpcConfig::new(&server.url()), &[])
+        .await
+        .expect("daemon on the mock server");
+    let filter = MarketFilter::mints(&[SOL_MINT.to_owned()]).expect("valid mint");
+    let added = daemon.discover(&filter).await.expect("discovery");
+    assert_eq!(added, vec![sol_market.clone()]);
+    assert_eq!(server.requests("getProgramAccounts"), 1);
+
+    // Every market is stored, only the allowed one is extracted
+    let db = &mut VybeDatabase::new().expect("database connection");
+    for market in [&sol_market, &other_market] {
+        let stored = db
+            .get_market(market)
+            .expect("read market")
+            .expect("stored market");
+        assert_eq!(stored.taker_fee_bps, 2);
+    }
+
+    // Scanning again doesn't extract a market twice
+    let added = daemon
+        .discover(&MarketFilter::All)
+        .await
+        .expect("discovery");
+    assert_eq!(added, vec![other_market]);
+}
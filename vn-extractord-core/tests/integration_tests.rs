@@ -1,6 +1,232 @@
 //! Integration tests for (Online and Offline) for solana blockchain transaction extraction library
+//!
+//! The offline tests replay the transactions recorded in `tests/fixtures`, record more with
+//! `vn-extractord --record-dir <dir>` and replay them with `--rpc-url file://<dir>`.
+//! The fixtures are four SOL/USDC Phoenix transactions: a bid placed, an ask placed on a full
+//! book evicting another ask, a swap filling the bid and the cancel of what is left of it.
 
-#[test]
-fn integration_test_one() {
-    assert_eq!(2_i32, 2_i32);
+use {
+    solana_sdk::signature::Signature,
+    std::{path::PathBuf, str::FromStr},
+    vn_database_core::models::{NewMarketEvent, NewMarketEvents},
+    vn_extractord_core::{MarketEventKind, RpcConfig, SignatureCursor, VybeTradeFillExtractor},
+};
+
+/// Market every fixture transaction mentions, SOL/USDC
+const FIXTURE_MARKET: &str = "4DoNfFBfF7UokCC2FQzriy7yHK6DY6NVdYpuekQ5pRgg";
+
+/// Oldest fixture transaction, maker A rests a bid, landed in slot 325000000
+const OLDER_SIGNATURE: &str =
+    "Rf7PVDuRHaeBEo8VBCp7E4meor6Vy7P1R2tvMiydBkYiPzYfneXofkGrkoggXvBJpLeh4Awjp9LqpXxAnGhLWQv";
+
+/// Maker B rests an ask on a full book evicting maker C's, landed in slot 325000001
+const EVICT_SIGNATURE: &str =
+    "3kZ15Q87p4gbJdkonZBZUh6zHiiDANd7H8Weq9GVe1Yb23TJbxejQ8oi4YYHdkb9akupmUNHkD5AAWTKGG2ibie1";
+
+/// A taker sells into maker A's bid, landed in slot 325000003
+const FILL_SIGNATURE: &str =
+    "4FMbS6BNQJse4ZGTFcbGxdwZ7Sjn8VCZxUo11tAq2bo5gDzc3suCF4Kq1Dnvs78vaBvxyXHEXMAekpJbqusAx65Z";
+
+/// Newest fixture transaction, maker A cancels the rest of the bid, landed in slot 325000004
+const NEWER_SIGNATURE: &str =
+    "3Hx2zMqJbmndSj4rfFGj5sDqH2dFagwd7aaLTZ4jr2V4Ns2vdx2oF1wEjqimxVByXvQrqRdUKv1GZbRQMVXA5Gco";
+
+/// Owner of the filled and cancelled bid
+const MAKER_A: &str = "62hTV1sP6egBZwCcGxgRtoJfACaCPsnkJBozXoB6R9KQ";
+
+/// Owner of the resting ask
+const MAKER_B: &str = "7jwbAo1Ue9aPQXYioUtwXPjZXeALJJPdeBo8Z1EFc7Gj";
+
+/// Owner of the evicted ask
+const MAKER_C: &str = "2SYwaMYxtDHPVnkfE5JgNsPPkXSfoCKqJSfGgbWnFVif";
+
+/// Signer of the swap
+const TAKER: &str = "FqT1wHQuwdFwkj4y8MuXhxrq4HTh8Cee2P81XdXfpbpy";
+
+/// Order sequence number of maker A's bid, bids carry inverted sequence numbers
+const BID: i64 = !700;
+
+/// Extractor replaying the recorded fixtures
+async fn fixture_extractor() -> VybeTradeFillExtractor {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    VybeTradeFillExtractor::connect(&RpcConfig::fixtures(&dir), FIXTURE_MARKET)
+        .await
+        .expect("replay extractor")
+}
+
+/// Parses a fixture signature
+fn signature(signature: &str) -> Signature {
+    Signature::from_str(signature).expect("fixture signature")
+}
+
+#[tokio::test]
+async fn replay_recorded_transactions() {
+    let mut extractor = fixture_extractor().await;
+
+    let extraction = extractor.extract().await.expect("offline extraction");
+    assert_eq!(
+        extraction.signatures,
+        vec![
+            signature(OLDER_SIGNATURE),
+            signature(EVICT_SIGNATURE),
+            signature(FILL_SIGNATURE),
+            signature(NEWER_SIGNATURE)
+        ]
+    );
+    assert_eq!(
+        extraction.cursor,
+        Some(SignatureCursor {
+            signature: signature(NEWER_SIGNATURE),
+            slot: 325_000_004,
+        })
+    );
+    assert!(extraction.failed.is_empty());
+
+    // Every event, in the order it happened on the market
+    let order = extraction
+        .events
+        .iter()
+        .map(|event| (event.slot, event.sequence_number, event.event_index))
+        .collect::<Vec<(u64, u64, u64)>>();
+    assert_eq!(
+        order,
+        [
+            (325_000_000, 5000, 0),
+            (325_000_001, 5001, 0),
+            (325_000_001, 5001, 1),
+            (325_000_003, 5002, 0),
+            (325_000_003, 5002, 1),
+            (325_000_004, 5003, 0),
+        ]
+    );
+    assert_eq!(extraction.sequences.len(), 6);
+
+    // Nothing newer than the cursor has been recorded
+    if let Some(cursor) = extraction.cursor {
+        extractor.set_cursor(cursor);
+    }
+    let extraction = extractor.extract().await.expect("offline extraction");
+    assert!(extraction.signatures.is_empty());
+    assert_eq!(extraction.cursor, None);
+}
+
+#[tokio::test]
+async fn decode_recorded_events() {
+    let extractor = fixture_extractor().await;
+
+    let events = extractor
+        .extract()
+        .await
+        .expect("offline extraction")
+        .events
+        .into_iter()
+        .map(NewMarketEvent::try_from)
+        .collect::<Result<NewMarketEvents, _>>()
+        .expect("known event kinds");
+    assert_eq!(events.len(), 6);
+
+    let placed = events.places.first().expect("maker A's bid");
+    assert_eq!(placed.market_pubkey, FIXTURE_MARKET);
+    assert_eq!(placed.signature, OLDER_SIGNATURE);
+    assert_eq!(placed.slot, 325_000_000);
+    assert_eq!(placed.event_timestamp, 1_740_956_436);
+    assert_eq!(placed.sequence_number, 5000);
+    assert_eq!(placed.maker, MAKER_A);
+    assert_eq!(placed.order_sequence_number, BID);
+    assert_eq!(placed.client_order_id, "1");
+    assert_eq!(placed.price_in_ticks, 177_000);
+    assert_eq!(placed.base_lots_placed, 50);
+    let ask = events.places.get(1).expect("maker B's ask");
+    assert_eq!(
+        (ask.maker.as_str(), ask.order_sequence_number),
+        (MAKER_B, 701)
+    );
+
+    let evicted = events.evictions.first().expect("maker C's ask");
+    assert_eq!(evicted.signature, EVICT_SIGNATURE);
+    assert_eq!(evicted.maker, MAKER_C);
+    assert_eq!(evicted.order_sequence_number, 650);
+    assert_eq!(evicted.price_in_ticks, 177_900);
+    assert_eq!(evicted.base_lots_evicted, 5);
+
+    let fill = events.fills.first().expect("the swap's fill");
+    assert_eq!(fill.signature, FILL_SIGNATURE);
+    assert_eq!(fill.sequence_number, 5002);
+    assert_eq!(fill.maker, MAKER_A);
+    assert_eq!(fill.taker, TAKER);
+    assert_eq!(fill.order_sequence_number, BID);
+    assert_eq!(fill.side_filled, "bid");
+    assert_eq!(fill.price_in_ticks, 177_000);
+    assert_eq!(fill.base_lots_filled, 20);
+    let summary = events.fill_summaries.first().expect("the swap's summary");
+    assert_eq!(summary.client_order_id, "9");
+    assert_eq!(summary.total_base_lots_filled, 20);
+    assert_eq!(summary.total_quote_lots_filled_including_fees, 3_540_000);
+    assert_eq!(summary.total_quote_lots_fees, 1770);
+
+    let reduced = events.reduces.first().expect("maker A's cancel");
+    assert_eq!(reduced.signature, NEWER_SIGNATURE);
+    assert_eq!(reduced.maker, MAKER_A);
+    assert_eq!(reduced.order_sequence_number, BID);
+    assert_eq!(reduced.base_lots_removed, 30);
+    assert_eq!(reduced.base_lots_remaining, 0);
+    assert!(reduced.is_full_cancel);
+}
+
+#[tokio::test]
+async fn filter_recorded_event_kinds() {
+    let mut extractor = fixture_extractor().await;
+    extractor.set_event_kinds(&[MarketEventKind::Fill, MarketEventKind::Reduce]);
+
+    let extraction = extractor.extract().await.expect("offline extraction");
+    // Gap detection still sees the sequence numbers of the skipped kinds
+    assert_eq!(extraction.sequences.len(), 6);
+    let events = extraction
+        .events
+        .into_iter()
+        .map(NewMarketEvent::try_from)
+        .collect::<Result<NewMarketEvents, _>>()
+        .expect("known event kinds");
+    assert_eq!(events.len(), 2);
+    assert_eq!(events.fills.len(), 1);
+    assert_eq!(events.reduces.len(), 1);
+}
+
+#[tokio::test]
+async fn page_recorded_signatures() {
+    let extractor = fixture_extractor().await;
+
+    let page = extractor
+        .get_signature_page(Some(signature(NEWER_SIGNATURE)), None)
+        .await
+        .expect("offline signature page");
+    // Newest first
+    assert_eq!(page.signatures.len(), 3);
+    assert_eq!(
+        page.signatures
+            .first()
+            .map(|info| (info.signature, info.slot)),
+        Some((signature(FILL_SIGNATURE), 325_000_003))
+    );
+    assert_eq!(
+        page.signatures.last().map(|info| info.signature),
+        Some(signature(OLDER_SIGNATURE))
+    );
+    assert!(page.exhausted);
+}
+
+#[tokio::test]
+async fn report_unrecorded_transactions() {
+    let extractor = fixture_extractor().await;
+    let missing = Signature::new_unique();
+
+    let decoded = extractor
+        .extract_events([missing])
+        .await
+        .expect("a missing transaction fails on its own");
+    assert!(decoded.events.is_empty());
+    assert_eq!(
+        decoded.failed.first().map(|failed| failed.signature),
+        Some(missing)
+    );
 }
@@ -0,0 +1,196 @@
+//! End to end tests of the extractor against the scriptable mock JSON-RPC server of
+//! `vn-test-support`: signature pagination, transient failures, rate limiting and
+//! duplicate signatures.
+
+use {
+    solana_sdk::{pubkey::Pubkey, signature::Signature},
+    std::{str::FromStr, time::Duration},
+    vn_extractord_core::{
+        DeadLetterKind, RetryPolicy, RpcConfig, SignatureCursor, VybeTradeFillExtractor,
+    },
+    vn_test_support::{MockFailure, MockRpcServer, MockSignature},
+};
+
+/// Slot of the transaction the extractor's cursor points at
+const CURSOR_SLOT: u64 = 300_000_000;
+
+/// Lists and serves a new memo transaction mentioning `market`, landed in `slot`
+fn add_transaction(server: &MockRpcServer, market: &str, slot: u64) -> MockSignature {
+    let signature = MockSignature::new(&Signature::new_unique().to_string(), slot);
+    server.add_transaction(
+        market,
+        signature.clone(),
+        MockRpcServer::transaction(&signature, &[market]),
+    );
+    signature
+}
+
+/// Parses a scripted signature
+fn signature(signature: &MockSignature) -> Signature {
+    Signature::from_str(&signature.signature).expect("scripted signature")
+}
+
+/// Extractor for `market` on the mock server, retrying quickly, resuming from the
+/// cursor transaction
+async fn mock_extractor(
+    server: &MockRpcServer,
+    market: &str,
+    cursor: &MockSignature,
+) -> VybeTradeFillExtractor {
+    let rpc_config = RpcConfig {
+        retry: RetryPolicy {
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(50),
+            ..RetryPolicy::default()
+        },
+        ..RpcConfig::new(&server.url())
+    };
+    let mut extractor = VybeTradeFillExtractor::connect(&rpc_config, market)
+        .await
+        .expect("mock extractor");
+    extractor.set_cursor(SignatureCursor {
+        signature: signature(cursor),
+        slot: cursor.slot,
+    });
+    extractor
+}
+
+#[tokio::test]
+async fn page_back_to_the_cursor() {
+    let server = MockRpcServer::start().await.expect("mock server");
+    let market = Pubkey::new_unique().to_string();
+    let cursor = add_transaction(&server, &market, CURSOR_SLOT);
+    let new = (1..=1500_u64)
+        .map(|offset| add_transaction(&server, &market, CURSOR_SLOT + offset))
+        .collect::<Vec<_>>();
+    let extractor = mock_extractor(&server, &market, &cursor).await;
+
+    let extraction = extractor.extract().await.expect("mock extraction");
+    assert_eq!(
+        extraction.signatures,
+        new.iter().map(signature).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        extraction.cursor.map(|cursor| cursor.slot),
+        Some(CURSOR_SLOT + 1500)
+    );
+    assert!(extraction.failed.is_empty());
+    // A full page of 1000 signatures, then the 500 left above the cursor
+    assert_eq!(server.requests("getSignaturesForAddress"), 2);
+    assert_eq!(server.requests("getTransaction"), 1500);
+}
+
+#[tokio::test]
+async fn stream_a_long_catch_up_oldest_first() {
+    let server = MockRpcServer::start().await.expect("mock server");
+    let market = Pubkey::new_unique().to_string();
+    let cursor = add_transaction(&server, &market, CURSOR_SLOT);
+    let new = (1..=95_u64)
+        .map(|offset| add_transaction(&server, &market, CURSOR_SLOT + offset))
+        .collect::<Vec<_>>();
+    let mut extractor = mock_extractor(&server, &market, &cursor).await;
+    extractor.set_parse_limits(4, 10);
+
+    let extraction = extractor.extract().await.expect("mock extraction");
+    assert_eq!(
+        extraction.signatures,
+        new.iter().map(signature).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        extraction.cursor.map(|cursor| cursor.slot),
+        Some(CURSOR_SLOT + 95)
+    );
+    // 10 pages back to the cursor, the 6 newest are listed again after the 4 oldest
+    assert_eq!(server.requests("getSignaturesForAddress"), 16);
+    assert_eq!(server.requests("getTransaction"), 95);
+}
+
+#[tokio::test]
+async fn retry_transient_failures() {
+    let server = MockRpcServer::start().await.expect("mock server");
+    let market = Pubkey::new_unique().to_string();
+    let cursor = add_transaction(&server, &market, CURSOR_SLOT);
+    let new = add_transaction(&server, &market, CURSOR_SLOT + 1);
+    let extractor = mock_extractor(&server, &market, &cursor).await;
+    server.fail_requests("getSignaturesForAddress", MockFailure::HttpStatus(500), 2);
+    server.fail_requests("getTransaction", MockFailure::TooManyRequests(Some(1)), 1);
+
+    let extraction = extractor.extract().await.expect("mock extraction");
+    assert_eq!(extraction.signatures, vec![signature(&new)]);
+    assert!(extraction.failed.is_empty());
+    assert_eq!(server.requests("getSignaturesForAddress"), 3);
+    assert_eq!(server.requests("getTransaction"), 2);
+    let throttled = extractor
+        .rpc()
+        .rate_limiter_stats()
+        .iter()
+        .map(|(_, stats)| stats.throttled)
+        .sum::<u64>();
+    assert_eq!(throttled, 1);
+}
+
+#[tokio::test]
+async fn fail_on_rejected_credentials() {
+    let server = MockRpcServer::start().await.expect("mock server");
+    let market = Pubkey::new_unique().to_string();
+    let cursor = add_transaction(&server, &market, CURSOR_SLOT);
+    add_transaction(&server, &market, CURSOR_SLOT + 1);
+    let extractor = mock_extractor(&server, &market, &cursor).await;
+    server.fail_requests("getSignaturesForAddress", MockFailure::HttpStatus(401), 1);
+
+    let error = extractor
+        .extract()
+        .await
+        .expect_err("a wrong API key isn't retried");
+    assert!(!error.is_transient());
+    assert_eq!(server.requests("getSignaturesForAddress"), 1);
+}
+
+#[tokio::test]
+async fn report_rejected_transactions() {
+    let server = MockRpcServer::start().await.expect("mock server");
+    let market = Pubkey::new_unique().to_string();
+    let cursor = add_transaction(&server, &market, CURSOR_SLOT);
+    let rejected = add_transaction(&server, &market, CURSOR_SLOT + 1);
+    let extractor = mock_extractor(&server, &market, &cursor).await;
+    server.fail_requests(
+        "getTransaction",
+        MockFailure::RpcError {
+            code: -32602,
+            message: "Invalid params".to_owned(),
+        },
+        1,
+    );
+
+    let extraction = extractor
+        .extract()
+        .await
+        .expect("a rejected transaction fails on its own");
+    assert_eq!(
+        extraction
+            .failed
+            .iter()
+            .map(|failed| (failed.signature, failed.kind))
+            .collect::<Vec<_>>(),
+        vec![(signature(&rejected), DeadLetterKind::Fetch)]
+    );
+    assert_eq!(server.requests("getTransaction"), 1);
+}
+
+#[tokio::test]
+async fn decode_duplicate_signatures_once() {
+    let server = MockRpcServer::start().await.expect("mock server");
+    let market = Pubkey::new_unique().to_string();
+    let cursor = add_transaction(&server, &market, CURSOR_SLOT);
+    let duplicate = add_transaction(&server, &market, CURSOR_SLOT + 1);
+    server.push_signature(&market, duplicate.clone());
+    let newest = add_transaction(&server, &market, CURSOR_SLOT + 2);
+    let extractor = mock_extractor(&server, &market, &cursor).await;
+
+    let extraction = extractor.extract().await.expect("mock extraction");
+    assert_eq!(
+        extraction.signatures,
+        vec![signature(&duplicate), signature(&newest)]
+    );
+    assert_eq!(server.requests("getTransaction"), 2);
+}
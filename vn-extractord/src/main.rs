@@ -2,27 +2,257 @@
 
 use {
     anyhow::Result,
-    clap::Parser,
+    clap::{Parser, Subcommand},
+    std::{net::SocketAddr, path::PathBuf, str::FromStr},
     tracing::{error, info, Level},
     tracing_subscriber::EnvFilter,
-    vn_extractord_core::VybeDaemon,
+    vn_config::{ExtractorConfig, VybeConfig},
+    vn_extractord_core::{
+        serve_metrics, BackfillRange, Commitment, HistoryBound, MarketEventKind, SinkKind,
+        VybeDaemon,
+    },
 };
 
-/// Mainnet address of active SOL/USDC Market
+/// Mainnet address of active SOL/USDC Market, extracted when no market is given and
+/// markets aren't discovered
 ///
 /// From <https://ellipsis-labs.gitbook.io/phoenix-dex/tRIkEFlLUzWK9uKO3W2V/getting-started/technical-overview/market-addresses>
 const PHOENIX_SOLUSDC_MARKET_ADDRESS: &str = "4DoNfFBfF7UokCC2FQzriy7yHK6DY6NVdYpuekQ5pRgg";
 
-/// Simple cli implementation
+/// Simple cli implementation, flags override the configuration file and the environment
 #[derive(Parser)]
 #[command(author, version, about)]
 struct Args {
-    /// Helium RPC API Key
+    /// TOML configuration file, `VYBE_CONFIG` when omitted, see `vybe.example.toml`
     #[arg(short, long)]
-    api_key: String,
-    /// Log level (e.g., error, warn, info, debug, trace)
-    #[arg(short, long, default_value = "info")]
-    log_level: String,
+    config: Option<PathBuf>,
+    /// Helius RPC API Key, shorthand for a Helius mainnet `--rpc-url`
+    #[arg(short, long)]
+    api_key: Option<String>,
+    /// JSON-RPC url of any Solana provider (your own node, Triton, QuickNode, ...),
+    /// or `file://<dir>` to replay the transactions recorded with `--record-dir` offline
+    #[arg(long)]
+    rpc_url: Option<String>,
+    /// WebSocket url used by the stream mode, derived from the RPC url when omitted
+    #[arg(long)]
+    ws_url: Option<String>,
+    /// JSON-RPC url to fail over to when the active endpoint errors or times out,
+    /// repeat the flag or comma separate to add several, they are tried in order
+    #[arg(long = "fallback-rpc-url", value_delimiter = ',')]
+    fallback_rpc_urls: Vec<String>,
+    /// Timeout of a single RPC request in seconds, 30 by default
+    #[arg(long)]
+    rpc_timeout: Option<u64>,
+    /// Requests per second sent to each RPC endpoint, 25 with `--api-key`, unlimited otherwise
+    #[arg(long)]
+    rpc_rate_limit: Option<u32>,
+    /// Requests per second sent to one RPC endpoint as `<url>=<requests per second>`,
+    /// overrides `--rpc-rate-limit`, repeat the flag for several endpoints
+    #[arg(long = "endpoint-rate-limit", value_parser = parse_endpoint_rate_limit)]
+    endpoint_rate_limits: Vec<(String, u32)>,
+    /// Record every fetched transaction to `<dir>/<signature>.json`, to replay it later
+    #[arg(long)]
+    record_dir: Option<PathBuf>,
+    /// Commitment level transactions are extracted at (processed, confirmed, finalized),
+    /// below finalized events are stored as provisional until they are finalized or rolled back,
+    /// confirmed by default
+    #[arg(long)]
+    commitment: Option<Commitment>,
+    /// Phoenix market address to extract, repeat the flag or comma separate to extract several,
+    /// the SOL/USDC market when omitted without `--discover`
+    #[arg(short, long = "market", value_delimiter = ',')]
+    markets: Vec<String>,
+    /// Scan the Phoenix program for every market, store their metadata and extract them
+    /// next to the `--market` ones, the scan is repeated while running to pick up new listings
+    #[arg(long)]
+    discover: bool,
+    /// Only extract discovered markets trading one of these mints (base or quote), repeat the
+    /// flag or comma separate to allow several, every discovered market when omitted
+    #[arg(long = "discover-mint", value_delimiter = ',')]
+    discover_mints: Vec<String>,
+    /// How often the Phoenix program is scanned again for new markets, in seconds,
+    /// 3600 by default
+    #[arg(long)]
+    discovery_interval: Option<u64>,
+    /// Kinds of market events to store (fill, place, reduce, evict, fill_summary, fee, time_in_force),
+    /// repeat the flag or comma separate to store several, every kind is stored when omitted
+    #[arg(long = "event-kind", value_delimiter = ',')]
+    event_kinds: Vec<MarketEventKind>,
+    /// Where extracted events are written (postgres, jsonl, parquet, stdout), repeat the flag or
+    /// comma separate to write to several, postgres by default. Only postgres keeps the cursors,
+    /// without it every start extracts from the newest transactions. parquet only writes fills
+    /// and needs `--event-kind fill`
+    #[arg(long = "sink", value_delimiter = ',')]
+    sinks: Vec<SinkKind>,
+    /// Directory the jsonl (`<dir>/<market>.jsonl`) and parquet (`<dir>/<market>/*.parquet`)
+    /// sinks write to, `events` by default
+    #[arg(long)]
+    sink_dir: Option<PathBuf>,
+    /// Print the extracted events to stdout as JSON lines instead of writing them anywhere,
+    /// the database isn't touched and logs go to stderr
+    #[arg(long, conflicts_with = "sinks")]
+    dry_run: bool,
+    /// Snapshot the top of every market's order book into the database every this many seconds,
+    /// no snapshots are taken when omitted
+    #[arg(long)]
+    snapshot_interval: Option<u64>,
+    /// Snapshot one market's order book at its own interval as `<market>=<seconds>`,
+    /// overrides `--snapshot-interval`, repeat the flag for several markets
+    #[arg(long = "market-snapshot-interval", value_parser = parse_market_snapshot_interval)]
+    market_snapshot_intervals: Vec<(String, u64)>,
+    /// Number of price levels stored per side of an order book snapshot, 20 by default
+    #[arg(long)]
+    snapshot_depth: Option<u64>,
+    /// Keep the L3 order book of every market in memory from the place, reduce, evict and fill
+    /// events, seeded from and checked against the market accounts
+    #[arg(long)]
+    track_order_books: bool,
+    /// How often a tracked order book is checked against its market account, in seconds,
+    /// 60 by default
+    #[arg(long)]
+    book_check_interval: Option<u64>,
+    /// Serve Prometheus metrics on `http://<address>/metrics`, e.g. `127.0.0.1:9184`
+    #[arg(long)]
+    metrics_addr: Option<SocketAddr>,
+    /// How long each market waits between extraction passes in milliseconds, 200 by default
+    #[arg(long)]
+    poll_interval_ms: Option<u64>,
+    /// How many transactions of a pass are fetched and parsed concurrently, 100 by default
+    #[arg(long)]
+    parse_concurrency: Option<usize>,
+    /// How many signatures are listed per request, at most and by default 1000
+    #[arg(long)]
+    signature_page_size: Option<usize>,
+    /// Log level (e.g., error, warn, info, debug, trace), info by default
+    #[arg(short, long)]
+    log_level: Option<String>,
+    /// What to do, continuously extract new transactions when omitted
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Daemon modes
+#[derive(Subcommand)]
+enum Command {
+    /// Continuously extract new transactions by polling
+    Run,
+    /// Continuously extract new transactions pushed over a WebSocket logs subscription,
+    /// polling only fills the gap after a disconnect
+    Stream,
+    /// Walk the market's history backwards and store every fill between two bounds,
+    /// a bound is either a slot number or an RFC 3339 time (e.g. 2025-03-02T00:00:00Z)
+    Backfill {
+        /// Oldest slot or time to backfill
+        #[arg(long)]
+        from: String,
+        /// Newest slot or time to backfill
+        #[arg(long)]
+        to: String,
+    },
+}
+
+impl Args {
+    /// Overrides the `[extractor]` settings with the flags that were given, an explicit
+    /// `--rpc-url` wins over a configured Helius API key and the other way around
+    fn apply(&self, config: &mut ExtractorConfig) {
+        if let Some(rpc_url) = self.rpc_url.as_ref() {
+            config.rpc_url = Some(rpc_url.clone());
+        } else if let Some(api_key) = self.api_key.as_ref() {
+            config.api_key = Some(api_key.clone());
+            config.rpc_url = None;
+        }
+        override_with(&mut config.ws_url, self.ws_url.clone().map(Some));
+        override_list(&mut config.fallback_rpc_urls, &self.fallback_rpc_urls);
+        override_with(&mut config.rpc_timeout_secs, self.rpc_timeout);
+        override_with(&mut config.rpc_rate_limit, self.rpc_rate_limit.map(Some));
+        config
+            .endpoint_rate_limits
+            .extend(self.endpoint_rate_limits.iter().cloned());
+        override_with(
+            &mut config.commitment,
+            self.commitment.map(|commitment| commitment.to_string()),
+        );
+        override_with(&mut config.record_dir, self.record_dir.clone().map(Some));
+        override_list(&mut config.markets, &self.markets);
+        config.discover |= self.discover;
+        override_list(&mut config.discover_mints, &self.discover_mints);
+        override_with(&mut config.discovery_interval_secs, self.discovery_interval);
+        override_list(
+            &mut config.event_kinds,
+            &self
+                .event_kinds
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<String>>(),
+        );
+        if self.dry_run {
+            config.sinks = vec![SinkKind::Stdout.to_string()];
+        } else {
+            override_list(
+                &mut config.sinks,
+                &self
+                    .sinks
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<String>>(),
+            );
+        }
+        override_with(&mut config.sink_dir, self.sink_dir.clone());
+        override_with(
+            &mut config.snapshot_interval_secs,
+            self.snapshot_interval.map(Some),
+        );
+        config
+            .snapshot_intervals
+            .extend(self.market_snapshot_intervals.iter().cloned());
+        override_with(&mut config.snapshot_depth, self.snapshot_depth);
+        config.track_order_books |= self.track_order_books;
+        override_with(
+            &mut config.book_check_interval_secs,
+            self.book_check_interval,
+        );
+        override_with(&mut config.metrics_addr, self.metrics_addr.map(Some));
+        override_with(&mut config.poll_interval_ms, self.poll_interval_ms);
+        override_with(&mut config.parse_concurrency, self.parse_concurrency);
+        override_with(&mut config.signature_page_size, self.signature_page_size);
+        override_with(&mut config.log_level, self.log_level.clone());
+    }
+}
+
+/// Replaces a setting with the value of a flag that was given
+fn override_with<T>(setting: &mut T, flag: Option<T>) {
+    if let Some(value) = flag {
+        *setting = value;
+    }
+}
+
+/// Replaces a list setting with the values of a repeatable flag that was given
+fn override_list(setting: &mut Vec<String>, flag: &[String]) {
+    if !flag.is_empty() {
+        *setting = flag.to_vec();
+    }
+}
+
+/// Parses `<url>=<requests per second>`, the url itself may contain `=` in its query string
+fn parse_endpoint_rate_limit(s: &str) -> Result<(String, u32), String> {
+    let (url, requests_per_second) = s
+        .rsplit_once('=')
+        .ok_or_else(|| format!("expected <url>=<requests per second>, got '{s}'"))?;
+    let requests_per_second = requests_per_second
+        .parse()
+        .map_err(|e| format!("invalid requests per second '{requests_per_second}': {e}"))?;
+    Ok((url.to_owned(), requests_per_second))
+}
+
+/// Parses `<market>=<seconds>`
+fn parse_market_snapshot_interval(s: &str) -> Result<(String, u64), String> {
+    let (market, seconds) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected <market>=<seconds>, got '{s}'"))?;
+    let seconds = seconds
+        .parse()
+        .map_err(|e| format!("invalid snapshot interval '{seconds}': {e}"))?;
+    Ok((market.to_owned(), seconds))
 }
 
 /// Converts cli argument string log level to tracing `Level`
@@ -38,9 +268,24 @@ fn convert_log_level(level_str: &str) -> Level {
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Parse command line arguments.
+    // Parse command line arguments on top of the configuration file and the environment.
     let args = Args::parse();
-    let level = convert_log_level(&args.log_level);
+    let mut config = VybeConfig::load(args.config.as_deref())?;
+    args.apply(&mut config.extractor);
+    if config.extractor.markets.is_empty() && !config.extractor.discover {
+        config.extractor.markets = vec![PHOENIX_SOLUSDC_MARKET_ADDRESS.to_owned()];
+    }
+    config.extractor.validate()?;
+    if config.extractor.uses_database() {
+        config.database.validate()?;
+    }
+    let level = convert_log_level(&config.extractor.log_level);
+    // Events printed to stdout aren't mixed with the logs
+    let prints_events = config
+        .extractor
+        .sinks
+        .iter()
+        .any(|sink| sink.parse::<SinkKind>().ok() == Some(SinkKind::Stdout));
 
     // Filter out the noise from 3rd party libraries
     let filter = EnvFilter::builder()
@@ -53,19 +298,47 @@ async fn main() -> Result<()> {
         .add_directive("solana_rpc_client=info".parse()?)
         .add_directive("h2=info".parse()?);
 
-    tracing_subscriber::fmt()
-        .with_env_filter(filter)
-        .compact()
-        .init();
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter).compact();
+    if prints_events {
+        subscriber.with_writer(std::io::stderr).init();
+    } else {
+        subscriber.init();
+    }
+
+    if let Some(addr) = config.extractor.metrics_addr {
+        // Dropping the handle detaches the listener, it is scraped until the process exits
+        let _listener = serve_metrics(addr).await?;
+    }
 
-    let vdaemon =
-        &mut VybeDaemon::new(args.api_key.as_str(), PHOENIX_SOLUSDC_MARKET_ADDRESS).await?;
+    let vdaemon = &mut VybeDaemon::from_config(&config).await?;
 
-    info!("Starting the vybe-network daemon");
-    if let Err(e) = vdaemon.run().await {
-        error!("{e}");
+    match args.command.unwrap_or(Command::Run) {
+        Command::Run => {
+            info!("Starting the vybe-network daemon");
+            if let Err(e) = vdaemon.run().await {
+                error!("{e}");
+            }
+            info!("vybe daemon stopped");
+        }
+        Command::Stream => {
+            info!("Starting the vybe-network daemon in streaming mode");
+            if let Err(e) = vdaemon.stream().await {
+                error!("{e}");
+            }
+            info!("vybe daemon stopped");
+        }
+        Command::Backfill { from, to } => {
+            let range =
+                BackfillRange::new(HistoryBound::from_str(&from)?, HistoryBound::from_str(&to)?)?;
+            info!("Starting backfill from {from} to {to}");
+            for report in vdaemon.backfill(range).await? {
+                info!(
+                    "Backfill of {} finished, {} signature(s) processed, {} fill(s) inserted",
+                    report.market_pubkey, report.signatures_processed, report.fills_inserted
+                );
+            }
+        }
     }
-    info!("Shutting down vybe daemon");
 
     Ok(())
 }
@@ -3,41 +3,230 @@ use {
     actix_web::{get, web, App, HttpResponse, HttpServer, Responder},
     anyhow::Result,
     clap::Parser,
-    serde::Serialize,
-    std::sync::{Arc, Mutex},
-    tracing::{info, Level},
+    rust_decimal::Decimal,
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::HashMap,
+        net::SocketAddr,
+        path::PathBuf,
+        str::FromStr,
+        sync::{Arc, Mutex},
+    },
+    tracing::{info, warn, Level},
     tracing_subscriber::EnvFilter,
-    vn_database_core::{models::TradeFill, VybeDatabase},
+    vn_config::VybeConfig,
+    vn_database_core::{
+        models::{Market, Orderbook, OrderbookSnapshot, PriceLevel, SequenceGapStatus, TradeFill},
+        units::MarketUnits,
+        VybeDatabase, VybeDatabaseError,
+    },
 };
 
-/// Enpoint
-const SERVER: &str = "127.0.0.1:8080";
-
-/// Solanas tick size
-const TICK_SIZE: f64 = 0.001;
-
-/// Simple cli implementation
+/// Simple cli implementation, flags override the configuration file and the environment
 #[derive(Parser)]
 #[command(author, version, about)]
 struct Args {
-    /// Log level (e.g., error, warn, info, debug, trace)
-    #[arg(short, long, default_value = "info")]
-    log_level: String,
+    /// TOML configuration file, `VYBE_CONFIG` when omitted, see `vybe.example.toml`
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+    /// Address the server listens on, 127.0.0.1:8080 by default
+    #[arg(short, long)]
+    bind_addr: Option<SocketAddr>,
+    /// Log level (e.g., error, warn, info, debug, trace), info by default
+    #[arg(short, long)]
+    log_level: Option<String>,
 }
 
-/// Simple open/high/low/close
+/// Simple open/high/low/close, prices are quote tokens per base token and volumes are token
+/// amounts, all exact decimals serialized as strings. The raw ticks and lots are included.
 #[derive(Serialize)]
 struct OhlcResponse {
     /// First price
-    open: f64,
+    open: Decimal,
     /// Highest value
-    high: f64,
+    high: Decimal,
     /// Lowest value
-    low: f64,
+    low: Decimal,
     /// Last price
-    close: f64,
+    close: Decimal,
+    /// First price in ticks
+    open_in_ticks: i64,
+    /// Highest value in ticks
+    high_in_ticks: i64,
+    /// Lowest value in ticks
+    low_in_ticks: i64,
+    /// Last price in ticks
+    close_in_ticks: i64,
+    /// Traded base tokens
+    base_volume: Decimal,
+    /// Traded quote tokens
+    quote_volume: Decimal,
+    /// Traded base lots
+    base_lots_volume: i64,
+}
+
+/// A raw trade fill with its price and size in UI units, which are `None` when the
+/// daemon hasn't read the fill's market yet
+#[derive(Serialize)]
+struct TradeFillResponse {
+    /// The stored fill, with its price in ticks and size in base lots
+    #[serde(flatten)]
+    fill: TradeFill,
+    /// Price in quote tokens per base token
+    price: Option<Decimal>,
+    /// Filled base tokens
+    base_quantity: Option<Decimal>,
+    /// Value of the fill in quote tokens
+    quote_notional: Option<Decimal>,
+}
+
+impl TradeFillResponse {
+    /// Converts a fill with its market's units
+    fn new(fill: TradeFill, units: Option<MarketUnits>) -> Self {
+        let units = units.as_ref();
+        Self {
+            price: units.and_then(|units| units.price(fill.price_in_ticks)),
+            base_quantity: units.and_then(|units| units.base_quantity(fill.base_lots_filled)),
+            quote_notional: units
+                .and_then(|units| units.quote_notional(fill.price_in_ticks, fill.base_lots_filled)),
+            fill,
+        }
+    }
+}
+
+/// A price level of an order book snapshot with its price and size in UI units, which are
+/// `None` when the daemon hasn't read the market yet
+#[derive(Serialize)]
+struct PriceLevelResponse {
+    /// The stored level, with its price in ticks and size in base lots
+    #[serde(flatten)]
+    level: PriceLevel,
+    /// Price in quote tokens per base token
+    price: Option<Decimal>,
+    /// Resting base tokens
+    base_quantity: Option<Decimal>,
+}
+
+impl PriceLevelResponse {
+    /// Converts a level with its market's units
+    fn new(level: PriceLevel, units: Option<&MarketUnits>) -> Self {
+        Self {
+            price: units.and_then(|units| units.price(level.price_in_ticks)),
+            base_quantity: units.and_then(|units| units.base_quantity(level.size_in_base_lots)),
+            level,
+        }
+    }
 }
 
+/// An order book snapshot with its levels in raw and UI units, best price first,
+/// and the spread and mid price of its top of book when both sides have orders
+#[derive(Serialize)]
+struct OrderbookResponse {
+    /// The stored snapshot, with the slot and time it was taken at
+    #[serde(flatten)]
+    snapshot: OrderbookSnapshot,
+    /// Bids, highest price first
+    bids: Vec<PriceLevelResponse>,
+    /// Asks, lowest price first
+    asks: Vec<PriceLevelResponse>,
+    /// Best ask minus best bid in ticks
+    spread_in_ticks: Option<i64>,
+    /// Best ask minus best bid in quote tokens per base token
+    spread: Option<Decimal>,
+    /// Halfway between the best bid and the best ask, in quote tokens per base token
+    mid_price: Option<Decimal>,
+}
+
+impl OrderbookResponse {
+    /// Converts a snapshot with its market's units
+    fn new(book: Orderbook, units: Option<&MarketUnits>) -> Self {
+        let best_bid = book.bids.first().map(|level| level.price_in_ticks);
+        let best_ask = book.asks.first().map(|level| level.price_in_ticks);
+        let spread_in_ticks = best_bid
+            .zip(best_ask)
+            .and_then(|(bid, ask)| ask.checked_sub(bid));
+        let top = units
+            .zip(best_bid.zip(best_ask))
+            .and_then(|(units, (bid, ask))| Some((units.price(bid)?, units.price(ask)?)));
+        Self {
+            spread_in_ticks,
+            spread: top.and_then(|(bid, ask)| ask.checked_sub(bid)),
+            mid_price: top
+                .and_then(|(bid, ask)| bid.checked_add(ask))
+                .and_then(|sum| sum.checked_div(Decimal::TWO)),
+            bids: book
+                .bids
+                .into_iter()
+                .map(|level| PriceLevelResponse::new(level, units))
+                .collect(),
+            asks: book
+                .asks
+                .into_iter()
+                .map(|level| PriceLevelResponse::new(level, units))
+                .collect(),
+            snapshot: book.snapshot,
+        }
+    }
+}
+
+/// A trade fill converted to UI units
+struct PricedFill {
+    /// When the fill happened
+    event_timestamp: i64,
+    /// Raw price
+    price_in_ticks: i64,
+    /// Raw size
+    base_lots_filled: i64,
+    /// Price in quote tokens per base token
+    price: Decimal,
+    /// Filled base tokens
+    base_quantity: Decimal,
+    /// Value of the fill in quote tokens
+    quote_notional: Decimal,
+}
+
+/// Optional query parameters shared by the trade fill endpoints
+#[derive(Deserialize)]
+struct MarketQuery {
+    /// Only use trade fills from this market (base58 address), all markets when omitted,
+    /// required by `/ohlc`
+    market: Option<String>,
+    /// Only use trade fills this trader (base58 address) took part in, as maker or taker
+    trader: Option<String>,
+    /// Only use finalized trade fills, provisional fills that may still be rolled back
+    /// are included when omitted
+    #[serde(default)]
+    finalized: bool,
+}
+
+/// Optional query parameters of the `/sequence_gaps` endpoint
+#[derive(Deserialize)]
+struct GapQuery {
+    /// Only the gaps of this market (base58 address), all markets when omitted
+    market: Option<String>,
+    /// Only the gaps with this status, `open`, `closed` or `unresolved`, every status when omitted
+    status: Option<String>,
+}
+
+/// Maximum number of sequence gaps returned by `/sequence_gaps`
+const MAX_SEQUENCE_GAPS: i64 = 1000;
+
+/// Query parameters of the order book endpoints
+#[derive(Deserialize)]
+struct OrderbookQuery {
+    /// The market (base58 address) whose order book is returned
+    market: String,
+    /// `/orderbook/history` only: snapshots taken at or after this Unix timestamp
+    from: Option<i64>,
+    /// `/orderbook/history` only: snapshots taken at or before this Unix timestamp
+    to: Option<i64>,
+    /// `/orderbook/history` only: maximum number of snapshots, at most and by default 1000
+    limit: Option<i64>,
+}
+
+/// Maximum number of snapshots returned by `/orderbook/history`
+const MAX_ORDERBOOK_SNAPSHOTS: i64 = 1000;
+
 /// Generic application state
 struct AppState {
     /// Database abstraction will likely need to be shared, wrap it in the ol' Arc-Mutey
@@ -55,33 +244,119 @@ fn convert_log_level(level_str: &str) -> Level {
     }
 }
 
-/// Calculate OHLC from a slice of `TradeFill` records.
-fn calculate_ohlc(trades: &[TradeFill]) -> Option<(f64, f64, f64, f64)> {
-    if trades.is_empty() {
-        return None;
+/// Calculate OHLC from a slice of `TradeFill` records, every price and size is converted with
+/// the metadata of the fill's market. Fills of markets the daemon hasn't read yet are left out.
+fn calculate_ohlc(
+    trades: &[TradeFill],
+    markets: &HashMap<String, MarketUnits>,
+) -> Option<OhlcResponse> {
+    let mut fills = trades
+        .iter()
+        .filter_map(|t| {
+            let units = markets.get(&t.market_pubkey)?;
+            Some(PricedFill {
+                event_timestamp: t.event_timestamp,
+                price_in_ticks: t.price_in_ticks,
+                base_lots_filled: t.base_lots_filled,
+                price: units.price(t.price_in_ticks)?,
+                base_quantity: units.base_quantity(t.base_lots_filled)?,
+                quote_notional: units.quote_notional(t.price_in_ticks, t.base_lots_filled)?,
+            })
+        })
+        .collect::<Vec<PricedFill>>();
+    if fills.len() < trades.len() {
+        warn!(
+            "Left out {} trade fill(s) of markets without stored metadata",
+            trades.len() - fills.len()
+        );
     }
 
     // The daemon extractor does sort the keys before writing them to the database,
     // but since Solana is a distributed system that won't be reliable, they need to be sorted
     // after we get them here as well.
-    let mut sorted = trades.to_vec();
-    sorted.sort_by_key(|t| t.event_timestamp);
+    fills.sort_by_key(|fill| fill.event_timestamp);
 
-    let open = sorted.first()?.price_in_ticks as f64 * TICK_SIZE;
-    let close = sorted.last()?.price_in_ticks as f64 * TICK_SIZE;
-    let high = sorted.iter().map(|t| t.price_in_ticks).max()? as f64 * TICK_SIZE;
-    let low = sorted.iter().map(|t| t.price_in_ticks).min()? as f64 * TICK_SIZE;
+    let open = fills.first()?;
+    let close = fills.last()?;
+    let high = fills.iter().max_by_key(|fill| fill.price)?;
+    let low = fills.iter().min_by_key(|fill| fill.price)?;
 
-    Some((open, high, low, close))
+    Some(OhlcResponse {
+        open: open.price,
+        high: high.price,
+        low: low.price,
+        close: close.price,
+        open_in_ticks: open.price_in_ticks,
+        high_in_ticks: high.price_in_ticks,
+        low_in_ticks: low.price_in_ticks,
+        close_in_ticks: close.price_in_ticks,
+        base_volume: fills.iter().try_fold(Decimal::ZERO, |sum, fill| {
+            sum.checked_add(fill.base_quantity)
+        })?,
+        quote_volume: fills.iter().try_fold(Decimal::ZERO, |sum, fill| {
+            sum.checked_add(fill.quote_notional)
+        })?,
+        base_lots_volume: fills
+            .iter()
+            .try_fold(0_i64, |sum, fill| sum.checked_add(fill.base_lots_filled))?,
+    })
+}
+
+/// Fetch the units of every stored market, by market address
+fn load_markets(db: &mut VybeDatabase) -> Result<HashMap<String, MarketUnits>, VybeDatabaseError> {
+    Ok(db
+        .get_markets()?
+        .into_iter()
+        .map(|market: Market| (market.market_pubkey.clone(), market.units()))
+        .collect())
+}
+
+/// Fetch the trade fills of the requested market and trader, or every trade fill when neither
+/// is given
+fn load_trade_fills(
+    db: &mut VybeDatabase,
+    query: &MarketQuery,
+) -> Result<Vec<TradeFill>, VybeDatabaseError> {
+    let mut trades = match (query.trader.as_deref(), query.market.as_deref()) {
+        (Some(trader), market) => {
+            let mut trades = db.get_trade_fills_by_trader(trader)?;
+            if let Some(market) = market {
+                trades.retain(|trade| trade.market_pubkey == market);
+            }
+            trades
+        }
+        (None, Some(market)) => db.get_trade_fills_by_market(market)?,
+        (None, None) => db.get_all_trade_fills()?,
+    };
+    if query.finalized {
+        trades.retain(|trade| !trade.provisional);
+    }
+    Ok(trades)
 }
 
-/// Route to fetch all raw trade fills
+/// Route to fetch all trade fills with their raw and UI unit prices and sizes, optionally of
+/// a single market (`?market=<address>`) and only the finalized ones (`?finalized=true`)
 #[get("/trade_fills")]
-async fn get_trade_fills(data: web::Data<AppState>) -> impl Responder {
+async fn get_trade_fills(
+    data: web::Data<AppState>,
+    query: web::Query<MarketQuery>,
+) -> impl Responder {
     if let Ok(mut db) = data.db.lock() {
         // Fetch all trade fill records.
-        match db.get_all_trade_fills() {
-            Ok(trades) => HttpResponse::Ok().json(trades),
+        let trades = match load_trade_fills(&mut db, &query) {
+            Ok(trades) => trades,
+            Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {e}")),
+        };
+        match load_markets(&mut db) {
+            Ok(markets) => HttpResponse::Ok().json(
+                trades
+                    .into_iter()
+                    .map(|fill| {
+                        let units = markets.get(&fill.market_pubkey).copied();
+                        TradeFillResponse::new(fill, units)
+                    })
+                    .collect::<Vec<TradeFillResponse>>(),
+            ),
             Err(e) => HttpResponse::InternalServerError().body(format!("DB error: {e}")),
         }
     } else {
@@ -89,39 +364,152 @@ async fn get_trade_fills(data: web::Data<AppState>) -> impl Responder {
     }
 }
 
-/// Handler for the `/ohlc` endpoint.
+/// Route to fetch the metadata of every market the daemon has read: mints, decimals,
+/// lot sizes, tick size and taker fee
+#[get("/markets")]
+async fn get_markets(data: web::Data<AppState>) -> impl Responder {
+    if let Ok(mut db) = data.db.lock() {
+        match db.get_markets() {
+            Ok(markets) => HttpResponse::Ok().json(markets),
+            Err(e) => HttpResponse::InternalServerError().body(format!("DB error: {e}")),
+        }
+    } else {
+        HttpResponse::InternalServerError().body("Lock error".to_string())
+    }
+}
+
+/// Route to fetch the gaps the daemon found in the markets' sequence numbers, a data quality
+/// signal: `unresolved` gaps are events that are missing even after a targeted backfill.
+/// Optionally of a single market (`?market=<address>`) and a single status (`?status=unresolved`)
+#[get("/sequence_gaps")]
+async fn get_sequence_gaps(
+    data: web::Data<AppState>,
+    query: web::Query<GapQuery>,
+) -> impl Responder {
+    let status = match query
+        .status
+        .as_deref()
+        .map(SequenceGapStatus::from_str)
+        .transpose()
+    {
+        Ok(status) => status,
+        Err(e) => return HttpResponse::BadRequest().body(e.to_string()),
+    };
+    if let Ok(mut db) = data.db.lock() {
+        match db.get_sequence_gaps(query.market.as_deref(), status, MAX_SEQUENCE_GAPS) {
+            Ok(gaps) => HttpResponse::Ok().json(gaps),
+            Err(e) => HttpResponse::InternalServerError().body(format!("DB error: {e}")),
+        }
+    } else {
+        HttpResponse::InternalServerError().body("Lock error".to_string())
+    }
+}
+
+/// Fetch the units of a single stored market, `None` when the daemon hasn't read it yet
+fn load_market_units(
+    db: &mut VybeDatabase,
+    market_pubkey: &str,
+) -> Result<Option<MarketUnits>, VybeDatabaseError> {
+    Ok(db.get_market(market_pubkey)?.as_ref().map(Market::units))
+}
+
+/// Route to fetch the newest order book snapshot of a market (`?market=<address>`),
+/// with its levels in raw and UI units, its spread and its mid price
+#[get("/orderbook")]
+async fn get_orderbook(
+    data: web::Data<AppState>,
+    query: web::Query<OrderbookQuery>,
+) -> impl Responder {
+    let mut db = match data.db.lock() {
+        Ok(guard) => guard,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Lock error: {e}")),
+    };
+    let book = match db.get_latest_orderbook(&query.market) {
+        Ok(Some(book)) => book,
+        Ok(None) => return HttpResponse::NotFound().body("No order book snapshot available"),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {e}")),
+    };
+    match load_market_units(&mut db, &query.market) {
+        Ok(units) => HttpResponse::Ok().json(OrderbookResponse::new(book, units.as_ref())),
+        Err(e) => HttpResponse::InternalServerError().body(format!("DB error: {e}")),
+    }
+}
+
+/// Route to fetch the order book snapshots of a market (`?market=<address>`), newest first,
+/// optionally between two Unix timestamps (`&from=<timestamp>&to=<timestamp>`) and at most
+/// `&limit=<count>` of them
+#[get("/orderbook/history")]
+async fn get_orderbook_history(
+    data: web::Data<AppState>,
+    query: web::Query<OrderbookQuery>,
+) -> impl Responder {
+    let limit = query
+        .limit
+        .unwrap_or(MAX_ORDERBOOK_SNAPSHOTS)
+        .clamp(1, MAX_ORDERBOOK_SNAPSHOTS);
+    let mut db = match data.db.lock() {
+        Ok(guard) => guard,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Lock error: {e}")),
+    };
+    let books = match db.get_orderbook_history(&query.market, query.from, query.to, limit) {
+        Ok(books) => books,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {e}")),
+    };
+    match load_market_units(&mut db, &query.market) {
+        Ok(units) => HttpResponse::Ok().json(
+            books
+                .into_iter()
+                .map(|book| OrderbookResponse::new(book, units.as_ref()))
+                .collect::<Vec<OrderbookResponse>>(),
+        ),
+        Err(e) => HttpResponse::InternalServerError().body(format!("DB error: {e}")),
+    }
+}
+
+/// Handler for the `/ohlc` endpoint of a single market (`?market=<address>`), optionally
+/// only from finalized fills (`?finalized=true`). Prices of different markets don't mix,
+/// so the market is required.
 #[get("/ohlc")]
-async fn get_ohlc(data: web::Data<AppState>) -> impl Responder {
+async fn get_ohlc(data: web::Data<AppState>, query: web::Query<MarketQuery>) -> impl Responder {
+    if query.market.is_none() {
+        return HttpResponse::BadRequest().body("The market query parameter is required");
+    }
     let mut db = match data.db.lock() {
         Ok(guard) => guard,
         Err(e) => return HttpResponse::InternalServerError().body(format!("Lock error: {e}")),
     };
 
     // Fetch all trade fill records.
-    let all_trades = match db.get_all_trade_fills() {
+    let all_trades = match load_trade_fills(&mut db, &query) {
         Ok(trades) => trades,
         Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {e}")),
     };
 
-    match calculate_ohlc(&all_trades) {
-        Some((open, high, low, close)) => {
-            let response = OhlcResponse {
-                open,
-                high,
-                low,
-                close,
-            };
-            HttpResponse::Ok().json(response)
-        }
+    let markets = match load_markets(&mut db) {
+        Ok(markets) => markets,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {e}")),
+    };
+
+    match calculate_ohlc(&all_trades, &markets) {
+        Some(response) => HttpResponse::Ok().json(response),
         None => HttpResponse::NotFound().body("No trade fill data available"),
     }
 }
 
 #[actix_web::main]
 async fn main() -> Result<()> {
-    // Parse command line arguments.
+    // Parse command line arguments on top of the configuration file and the environment.
     let args = Args::parse();
-    let level = convert_log_level(&args.log_level);
+    let mut config = VybeConfig::load(args.config.as_deref())?;
+    if let Some(bind_addr) = args.bind_addr {
+        config.api.bind_addr = bind_addr;
+    }
+    if let Some(log_level) = args.log_level {
+        config.api.log_level = log_level;
+    }
+    config.api.validate()?;
+    config.database.validate()?;
+    let level = convert_log_level(&config.api.log_level);
 
     // Filter out the noise from 3rd party libraries
     let filter = EnvFilter::builder()
@@ -133,19 +521,24 @@ async fn main() -> Result<()> {
         .compact()
         .init();
 
-    let db = VybeDatabase::new()?;
+    let db = VybeDatabase::connect(&config.database)?;
     let shared_app_state = web::Data::new(AppState {
         db: Arc::new(Mutex::new(db)),
     });
 
-    info!("Starting server at http://{SERVER}");
+    let server = config.api.bind_addr;
+    info!("Starting server at http://{server}");
     let _ = HttpServer::new(move || {
         App::new()
             .app_data(shared_app_state.clone())
             .service(get_trade_fills)
+            .service(get_markets)
             .service(get_ohlc)
+            .service(get_sequence_gaps)
+            .service(get_orderbook)
+            .service(get_orderbook_history)
     })
-    .bind(SERVER)?
+    .bind(server)?
     .run()
     .await;
 
@@ -0,0 +1,22 @@
+//! Test helpers shared by the workspace crates.
+//!
+//! `MockRpcServer` is an in-process Solana JSON-RPC endpoint answering from scripted data,
+//! so the extractor, the daemon and the API can be exercised end to end without a network:
+//!
+//! ```no_run
+//! use vn_test_support::{MockFailure, MockRpcServer, MockSignature};
+//!
+//! # async fn example() -> std::io::Result<()> {
+//! let server = MockRpcServer::start().await?;
+//! let market = "4DoNfFBfF7UokCC2FQzriy7yHK6DY6NVdYpuekQ5pRgg";
+//! let signature = MockSignature::new("5h6xBEauJ3PK6SWCZ1PGjBvj8vDdWG3KpwATGy1ARAXFSDwt8GFXM7W5Ncn16wmqokgpiKRLuS83KUxyZyv2sUYv", 42);
+//! server.add_transaction(market, signature.clone(), MockRpcServer::transaction(&signature, &[market]));
+//! server.fail_requests("getSignaturesForAddress", MockFailure::HttpStatus(503), 1);
+//! // point the code under test at `server.url()`
+//! # Ok(())
+//! # }
+//! ```
+
+mod rpc;
+
+pub use rpc::{MockFailure, MockRpcServer, MockSignature};
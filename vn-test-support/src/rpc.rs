@@ -0,0 +1,655 @@
+//! Scriptable mock of a Solana JSON-RPC endpoint. Speaks just enough HTTP/1.1 (keep-alive,
+//! `Content-Length` bodies) for the `reqwest` based transports of the workspace, and answers
+//! `getSignaturesForAddress`, `getTransaction`, `getAccountInfo` and `getProgramAccounts` from
+//! what a test scripted.
+//! Failures (HTTP errors, `429 Too Many Requests`, JSON-RPC errors and slow responses) can be
+//! queued per method to exercise retries, rate limiting and circuit breaking.
+
+use {
+    base64::{engine::general_purpose::STANDARD, Engine},
+    serde_json::{json, Value},
+    std::{
+        collections::{HashMap, VecDeque},
+        io,
+        net::SocketAddr,
+        sync::{Arc, Mutex, MutexGuard},
+        time::Duration,
+    },
+    tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+        task::JoinHandle,
+    },
+    tracing::debug,
+};
+
+/// `getSignaturesForAddress` returns at most this many signatures when no limit is given
+const DEFAULT_SIGNATURE_LIMIT: usize = 1000;
+
+/// JSON-RPC error code of a method the endpoint doesn't know
+const JSON_RPC_METHOD_NOT_FOUND: i64 = -32601;
+
+/// JSON-RPC error code of a request that isn't valid JSON
+const JSON_RPC_PARSE_ERROR: i64 = -32700;
+
+/// Blockhash of every generated transaction, the base58 encoding of 32 zero bytes
+const MOCK_BLOCKHASH: &str = "11111111111111111111111111111111";
+
+/// Program invoked by every generated transaction
+const MEMO_PROGRAM_ID: &str = "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr";
+
+/// How the mock answers a request instead of serving its scripted data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MockFailure {
+    /// `429 Too Many Requests`, with a `Retry-After` header in seconds when set
+    TooManyRequests(Option<u64>),
+    /// Any other HTTP status without a JSON-RPC body, e.g. 500 or 503
+    HttpStatus(u16),
+    /// A JSON-RPC error object in a `200 OK` response
+    RpcError {
+        /// JSON-RPC error code, e.g. -32005 for a node that is behind
+        code: i64,
+        /// Human readable description of the error
+        message: String,
+    },
+    /// The scripted data, sent after a delay to trigger client timeouts
+    Delay(Duration),
+}
+
+/// A signature listed by `getSignaturesForAddress`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockSignature {
+    /// Base58 encoded transaction signature
+    pub signature: String,
+    /// Slot the transaction landed in
+    pub slot: u64,
+    /// Unix timestamp of the block, `None` like nodes that don't know it
+    pub block_time: Option<i64>,
+    /// Whether the transaction failed
+    pub failed: bool,
+}
+
+impl MockSignature {
+    /// A successful transaction landed in `slot`
+    pub fn new(signature: &str, slot: u64) -> Self {
+        Self {
+            signature: signature.to_owned(),
+            slot,
+            block_time: None,
+            failed: false,
+        }
+    }
+
+    /// The `getSignaturesForAddress` entry of the signature
+    fn to_json(&self) -> Value {
+        json!({
+            "signature": self.signature,
+            "slot": self.slot,
+            "err": self.error(),
+            "memo": null,
+            "blockTime": self.block_time,
+            "confirmationStatus": "finalized",
+        })
+    }
+
+    /// Transaction error of a failed transaction, `null` otherwise
+    fn error(&self) -> Value {
+        if self.failed {
+            json!({ "InstructionError": [0_u8, "InvalidInstructionData"] })
+        } else {
+            Value::Null
+        }
+    }
+}
+
+/// Everything a test scripted, and what the server has been asked
+#[derive(Debug, Default)]
+struct MockState {
+    /// Signatures listed for an address, newest first, duplicates are kept as listed
+    signatures: HashMap<String, Vec<MockSignature>>,
+    /// `getTransaction` results by signature
+    transactions: HashMap<String, Value>,
+    /// `getAccountInfo` values by public key
+    accounts: HashMap<String, Value>,
+    /// Failures answered before the scripted data, by method
+    failures: HashMap<String, VecDeque<MockFailure>>,
+    /// Number of requests received, by method
+    requests: HashMap<String, usize>,
+    /// Newest slot of the scripted data, returned by `getSlot`
+    slot: u64,
+}
+
+impl MockState {
+    /// Answers `getSignaturesForAddress`, paging with `before`, `until` and `limit`
+    fn signatures_for_address(&self, params: &Value) -> Value {
+        let listed = params[0]
+            .as_str()
+            .and_then(|address| self.signatures.get(address))
+            .map_or(&[][..], Vec::as_slice);
+        let config = &params[1];
+        let limit = config["limit"]
+            .as_u64()
+            .and_then(|limit| usize::try_from(limit).ok())
+            .unwrap_or(DEFAULT_SIGNATURE_LIMIT);
+
+        let start = match config["before"].as_str() {
+            Some(before) => listed
+                .iter()
+                .position(|listed| listed.signature == before)
+                .map_or(listed.len(), |index| index.saturating_add(1)),
+            None => 0,
+        };
+        let until = config["until"].as_str();
+        let page = listed
+            .iter()
+            .skip(start)
+            .take_while(|listed| Some(listed.signature.as_str()) != until)
+            .take(limit)
+            .map(MockSignature::to_json)
+            .collect();
+        Value::Array(page)
+    }
+
+    /// Answers `getSignatureStatuses`, transactions the mock knows are finalized
+    fn signature_statuses(&self, params: &Value) -> Value {
+        let statuses = params[0]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|signature| {
+                let transaction = signature
+                    .as_str()
+                    .and_then(|signature| self.transactions.get(signature));
+                transaction.map_or(Value::Null, |transaction| {
+                    json!({
+                        "slot": transaction["slot"],
+                        "confirmations": null,
+                        "status": { "Ok": null },
+                        "err": null,
+                        "confirmationStatus": "finalized",
+                    })
+                })
+            })
+            .collect::<Vec<Value>>();
+        self.with_context(&Value::Array(statuses))
+    }
+
+    /// Answers `getProgramAccounts`, every scripted account owned by the program sorted by
+    /// public key, cut to the `dataSlice` when one is given
+    fn program_accounts(&self, params: &Value) -> Value {
+        let slice = &params[1]["dataSlice"];
+        let mut accounts = self
+            .accounts
+            .iter()
+            .filter(|(_, account)| account["owner"] == params[0])
+            .map(|(pubkey, account)| {
+                let mut account = account.clone();
+                if let (Some(offset), Some(length)) =
+                    (slice["offset"].as_u64(), slice["length"].as_u64())
+                {
+                    account["data"][0] = json!(slice_data(&account["data"][0], offset, length));
+                }
+                json!({ "pubkey": pubkey, "account": account })
+            })
+            .collect::<Vec<Value>>();
+        accounts.sort_by(|a, b| a["pubkey"].as_str().cmp(&b["pubkey"].as_str()));
+        Value::Array(accounts)
+    }
+
+    /// Wraps a value the way the `RpcResponse` methods return it
+    fn with_context(&self, value: &Value) -> Value {
+        json!({ "context": { "slot": self.slot }, "value": value })
+    }
+
+    /// The result of a request, or the code and message of a JSON-RPC error
+    fn answer(&self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        match method {
+            "getSignaturesForAddress" => Ok(self.signatures_for_address(params)),
+            "getTransaction" => Ok(params[0]
+                .as_str()
+                .and_then(|signature| self.transactions.get(signature))
+                .cloned()
+                .unwrap_or_default()),
+            "getAccountInfo" => Ok(self.with_context(
+                params[0]
+                    .as_str()
+                    .and_then(|pubkey| self.accounts.get(pubkey))
+                    .unwrap_or(&Value::Null),
+            )),
+            "getProgramAccounts" => Ok(self.program_accounts(params)),
+            "getSignatureStatuses" => Ok(self.signature_statuses(params)),
+            "getSlot" => Ok(json!(self.slot)),
+            "getVersion" => Ok(json!({ "solana-core": "1.18.26", "feature-set": 0_u32 })),
+            _ => Err((
+                JSON_RPC_METHOD_NOT_FOUND,
+                format!("Method not found: {method}"),
+            )),
+        }
+    }
+}
+
+/// Cuts `length` bytes at `offset` out of `base64` encoded account data, the way a
+/// `dataSlice` does, past the end of the data the slice is shorter or empty
+fn slice_data(data: &Value, offset: u64, length: u64) -> String {
+    let data = data
+        .as_str()
+        .and_then(|data| STANDARD.decode(data).ok())
+        .unwrap_or_default();
+    let start = usize::try_from(offset)
+        .unwrap_or(usize::MAX)
+        .min(data.len());
+    let end = start
+        .saturating_add(usize::try_from(length).unwrap_or(usize::MAX))
+        .min(data.len());
+    STANDARD.encode(data.get(start..end).unwrap_or_default())
+}
+
+/// In-process Solana JSON-RPC endpoint bound to a random local port, the server stops
+/// when it is dropped. Scripting goes through `&self`, so a test can change the data
+/// while the code under test is running against `MockRpcServer::url`.
+#[derive(Debug)]
+pub struct MockRpcServer {
+    /// Address the server listens on
+    addr: SocketAddr,
+    /// Scripted data, shared with the connection tasks
+    state: Arc<Mutex<MockState>>,
+    /// Task accepting connections
+    task: JoinHandle<()>,
+}
+
+impl MockRpcServer {
+    /// Starts a server on `127.0.0.1` on a port chosen by the system
+    ///
+    /// # Errors
+    ///
+    /// `io::Error` if no local port can be bound
+    pub async fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(MockState::default()));
+        let task = tokio::spawn(accept(listener, Arc::clone(&state)));
+        debug!("Mock JSON-RPC server listening on {addr}");
+        Ok(Self { addr, state, task })
+    }
+
+    /// JSON-RPC url of the server
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Locks the scripted data, a test that panicked while holding it doesn't poison others
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Lists a signature for `address` as the newest one, listing the same signature again
+    /// reproduces providers returning duplicates
+    pub fn push_signature(&self, address: &str, signature: MockSignature) {
+        let mut state = self.state();
+        state.slot = state.slot.max(signature.slot);
+        state
+            .signatures
+            .entry(address.to_owned())
+            .or_default()
+            .insert(0, signature);
+    }
+
+    /// Lists a signature for `address` as the newest one and serves `transaction`
+    /// as its `getTransaction` result
+    pub fn add_transaction(&self, address: &str, signature: MockSignature, transaction: Value) {
+        self.state()
+            .transactions
+            .insert(signature.signature.clone(), transaction);
+        self.push_signature(address, signature);
+    }
+
+    /// Serves `account` as the `getAccountInfo` value of `pubkey`, and lists it in the
+    /// `getProgramAccounts` result of its owner, whatever encoding the client asks for,
+    /// `MockRpcServer::account` builds a `base64` encoded one
+    pub fn set_account(&self, pubkey: &str, account: Value) {
+        self.state().accounts.insert(pubkey.to_owned(), account);
+    }
+
+    /// Answers the next `count` requests of `method` with `failure`, failures queued
+    /// for the same method are answered in order
+    pub fn fail_requests(&self, method: &str, failure: MockFailure, count: usize) {
+        self.state()
+            .failures
+            .entry(method.to_owned())
+            .or_default()
+            .extend(std::iter::repeat_n(failure, count));
+    }
+
+    /// Number of requests of `method` received so far, failed ones included
+    pub fn requests(&self, method: &str) -> usize {
+        self.state()
+            .requests
+            .get(method)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// A `base64` encoded `getAccountInfo` value of an account owned by `owner`
+    pub fn account(owner: &str, data: &[u8]) -> Value {
+        json!({
+            "lamports": 1_000_000_000_u64,
+            "data": [STANDARD.encode(data), "base64"],
+            "owner": owner,
+            "executable": false,
+            "rentEpoch": 0_u64,
+            "space": data.len(),
+        })
+    }
+
+    /// A successful `json` encoded `getTransaction` result of a memo transaction
+    /// that mentions `accounts`, it carries no Phoenix events
+    pub fn transaction(signature: &MockSignature, accounts: &[&str]) -> Value {
+        let account_keys = accounts
+            .iter()
+            .copied()
+            .chain(std::iter::once(MEMO_PROGRAM_ID))
+            .collect::<Vec<&str>>();
+        json!({
+            "slot": signature.slot,
+            "blockTime": signature.block_time,
+            "transaction": {
+                "signatures": [signature.signature],
+                "message": {
+                    "header": {
+                        "numRequiredSignatures": 1_u8,
+                        "numReadonlySignedAccounts": 0_u8,
+                        "numReadonlyUnsignedAccounts": 1_u8,
+                    },
+                    "accountKeys": account_keys,
+                    "recentBlockhash": MOCK_BLOCKHASH,
+                    "instructions": [{
+                        "programIdIndex": accounts.len(),
+                        "accounts": [],
+                        "data": "",
+                        "stackHeight": null,
+                    }],
+                },
+            },
+            "meta": {
+                "err": signature.error(),
+                "status": if signature.failed {
+                    json!({ "Err": signature.error() })
+                } else {
+                    json!({ "Ok": null })
+                },
+                "fee": 5000_u64,
+                "preBalances": vec![0_u64; account_keys.len()],
+                "postBalances": vec![0_u64; account_keys.len()],
+                "innerInstructions": [],
+                "logMessages": [],
+                "preTokenBalances": [],
+                "postTokenBalances": [],
+                "rewards": [],
+                "loadedAddresses": { "writable": [], "readonly": [] },
+                "computeUnitsConsumed": 0_u64,
+            },
+        })
+    }
+}
+
+impl Drop for MockRpcServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Accepts connections until the server is dropped, each one is served on its own task
+async fn accept(listener: TcpListener, state: Arc<Mutex<MockState>>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(serve(stream, Arc::clone(&state)));
+            }
+            Err(e) => debug!("Mock JSON-RPC server failed to accept a connection: {e}"),
+        }
+    }
+}
+
+/// Answers the requests of a keep-alive connection until the client closes it
+async fn serve(stream: TcpStream, state: Arc<Mutex<MockState>>) {
+    let mut stream = BufReader::new(stream);
+    while let Some(body) = read_request(&mut stream).await {
+        let response = respond(&state, &body).await;
+        if stream.get_mut().write_all(&response).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Reads the body of the next request, `None` once the connection is closed or broken
+async fn read_request(stream: &mut BufReader<TcpStream>) -> Option<Vec<u8>> {
+    let mut content_length = 0;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if stream.read_line(&mut line).await.ok()? == 0 {
+            return None;
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse::<usize>().ok()?;
+            }
+        }
+    }
+    let mut body = vec![0_u8; content_length];
+    stream.read_exact(&mut body).await.ok()?;
+    Some(body)
+}
+
+/// Builds the HTTP response of a request body
+async fn respond(state: &Mutex<MockState>, body: &[u8]) -> Vec<u8> {
+    let Ok(request) = serde_json::from_slice::<Value>(body) else {
+        return rpc_response(
+            &Value::Null,
+            Err((JSON_RPC_PARSE_ERROR, "Parse error".to_owned())),
+        );
+    };
+    let method = request["method"].as_str().unwrap_or_default();
+
+    let failure = {
+        let mut state = state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let requests = state.requests.entry(method.to_owned()).or_default();
+        *requests = requests.saturating_add(1);
+        state.failures.get_mut(method).and_then(VecDeque::pop_front)
+    };
+    match failure {
+        Some(MockFailure::TooManyRequests(retry_after)) => {
+            let header = retry_after.map(|secs| format!("Retry-After: {secs}\r\n"));
+            return http_response(429, "Too Many Requests", header.as_deref(), "");
+        }
+        Some(MockFailure::HttpStatus(status)) => {
+            return http_response(status, "Mock Failure", None, "");
+        }
+        Some(MockFailure::RpcError { code, message }) => {
+            return rpc_response(&request["id"], Err((code, message)));
+        }
+        Some(MockFailure::Delay(delay)) => tokio::time::sleep(delay).await,
+        None => {}
+    }
+
+    let answer = state
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .answer(method, &request["params"]);
+    rpc_response(&request["id"], answer)
+}
+
+/// A `200 OK` JSON-RPC response with either a result or an error
+fn rpc_response(id: &Value, answer: Result<Value, (i64, String)>) -> Vec<u8> {
+    let body = match answer {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message)) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": code, "message": message },
+        }),
+    };
+    http_response(
+        200,
+        "OK",
+        Some("Content-Type: application/json\r\n"),
+        &body.to_string(),
+    )
+}
+
+/// Serializes an HTTP/1.1 response, `headers` are complete `\r\n` terminated lines
+fn http_response(status: u16, reason: &str, headers: Option<&str>, body: &str) -> Vec<u8> {
+    format!(
+        "HTTP/1.1 {status} {reason}\r\n{}Content-Length: {}\r\n\r\n{body}",
+        headers.unwrap_or_default(),
+        body.len()
+    )
+    .into_bytes()
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{MockFailure, MockRpcServer, MockSignature},
+        serde_json::{json, Value},
+        tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::TcpStream,
+        },
+    };
+
+    /// Market the scripted signatures are listed for
+    const MARKET: &str = "Market";
+
+    /// Sends one JSON-RPC request on a fresh connection, returns the status line and body
+    async fn call(server: &MockRpcServer, method: &str, params: Value) -> (String, String) {
+        let body =
+            json!({ "jsonrpc": "2.0", "id": 1_u8, "method": method, "params": params }).to_string();
+        let mut stream = TcpStream::connect(server.addr).await.expect("connect");
+        let request = format!(
+            "POST / HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(request.as_bytes()).await.expect("send");
+        stream.shutdown().await.expect("shutdown");
+        let mut response = String::new();
+        stream
+            .read_to_string(&mut response)
+            .await
+            .expect("response");
+        let (head, body) = response.split_once("\r\n\r\n").unwrap_or_default();
+        let status = head.lines().next().unwrap_or_default().to_owned();
+        (status, body.to_owned())
+    }
+
+    /// Result of a successful JSON-RPC request
+    async fn result(server: &MockRpcServer, method: &str, params: Value) -> Value {
+        let (_, body) = call(server, method, params).await;
+        serde_json::from_str::<Value>(&body).expect("json body")["result"].clone()
+    }
+
+    /// Signatures of a `getSignaturesForAddress` result
+    fn listed(page: &Value) -> Vec<&str> {
+        page.as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|status| status["signature"].as_str())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn page_scripted_signatures() {
+        let server = MockRpcServer::start().await.expect("mock server");
+        for (signature, slot) in [("a", 10), ("b", 11), ("c", 12), ("d", 13)] {
+            server.push_signature(MARKET, MockSignature::new(signature, slot));
+        }
+
+        let page = result(
+            &server,
+            "getSignaturesForAddress",
+            json!([MARKET, { "limit": 2_u16 }]),
+        )
+        .await;
+        assert_eq!(listed(&page), vec!["d", "c"]);
+        let page = result(
+            &server,
+            "getSignaturesForAddress",
+            json!([MARKET, { "before": "d", "until": "a" }]),
+        )
+        .await;
+        assert_eq!(listed(&page), vec!["c", "b"]);
+        assert_eq!(page[0]["slot"], 12_u64);
+        let page = result(&server, "getSignaturesForAddress", json!(["Other"])).await;
+        assert_eq!(listed(&page), Vec::<&str>::new());
+        assert_eq!(server.requests("getSignaturesForAddress"), 3);
+    }
+
+    #[tokio::test]
+    async fn serve_transactions_and_accounts() {
+        let server = MockRpcServer::start().await.expect("mock server");
+        let signature = MockSignature::new("a", 10);
+        server.add_transaction(
+            MARKET,
+            signature.clone(),
+            MockRpcServer::transaction(&signature, &[MARKET]),
+        );
+        server.set_account(MARKET, MockRpcServer::account("Owner", &[1, 2, 3]));
+
+        let transaction = result(&server, "getTransaction", json!(["a"])).await;
+        assert_eq!(
+            transaction["transaction"]["message"]["accountKeys"][0],
+            MARKET
+        );
+        assert!(result(&server, "getTransaction", json!(["missing"]))
+            .await
+            .is_null());
+        let account = result(&server, "getAccountInfo", json!([MARKET])).await;
+        assert_eq!(account["value"]["data"][0], "AQID");
+        assert_eq!(account["context"]["slot"], 10_u64);
+        let owned = result(
+            &server,
+            "getProgramAccounts",
+            json!(["Owner", { "dataSlice": { "offset": 1_u8, "length": 1_u8 } }]),
+        )
+        .await;
+        assert_eq!(owned[0]["pubkey"], MARKET);
+        assert_eq!(owned[0]["account"]["data"][0], "Ag==");
+        let unowned = result(&server, "getProgramAccounts", json!(["Other"])).await;
+        assert_eq!(unowned, json!([]));
+        assert_eq!(result(&server, "getSlot", json!([])).await, 10_u64);
+        let (_, body) = call(&server, "getBlock", json!([10_u64])).await;
+        assert!(body.contains("-32601"));
+    }
+
+    #[tokio::test]
+    async fn answer_queued_failures_first() {
+        let server = MockRpcServer::start().await.expect("mock server");
+        server.fail_requests("getSlot", MockFailure::TooManyRequests(Some(2)), 1);
+        server.fail_requests("getSlot", MockFailure::HttpStatus(503), 1);
+        server.fail_requests(
+            "getSlot",
+            MockFailure::RpcError {
+                code: -32005,
+                message: "Node is behind".to_owned(),
+            },
+            1,
+        );
+
+        let (status, _) = call(&server, "getSlot", json!([])).await;
+        assert_eq!(status, "HTTP/1.1 429 Too Many Requests");
+        let (status, _) = call(&server, "getSlot", json!([])).await;
+        assert!(status.starts_with("HTTP/1.1 503"));
+        let (_, body) = call(&server, "getSlot", json!([])).await;
+        assert!(body.contains("Node is behind"));
+        assert_eq!(result(&server, "getSlot", json!([])).await, 0_u64);
+        assert_eq!(server.requests("getSlot"), 4);
+    }
+}